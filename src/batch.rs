@@ -0,0 +1,261 @@
+use anyhow::Result;
+use futures::stream::{self, StreamExt};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::{fmt, fs, path::Path};
+
+use crate::config::{BatchOptions, BruteforceOptions, Cli};
+use crate::http::HttpContext;
+use crate::twitch::models::VodRecovery;
+use crate::twitch::vods::{exact, from_tracker};
+use crate::util::derive_date_from_url;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum BatchJob {
+    // a TwitchTracker/StreamsCharts stream URL, same as the link mode
+    Tracker {
+        url: String,
+    },
+    // same as the exact mode
+    Exact {
+        username: String,
+        id: i64,
+        timestamp: String,
+    },
+}
+
+impl fmt::Display for BatchJob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tracker { url } => write!(f, "{url}"),
+            Self::Exact {
+                username,
+                id,
+                timestamp,
+            } => write!(f, "{username} {id} ({timestamp})"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BatchResult {
+    pub job: BatchJob,
+    pub found: bool,
+    pub recovery: Option<VodRecovery>,
+    pub error: Option<String>,
+}
+
+fn text_from_value(value: Option<&Value>) -> Option<String> {
+    match value? {
+        Value::String(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn job_from_value(value: &Value) -> Option<BatchJob> {
+    if value.is_string() {
+        return text_from_value(Some(value)).map(|url| BatchJob::Tracker { url });
+    }
+    if let Some(url) = text_from_value(value.get("url")) {
+        return Some(BatchJob::Tracker { url });
+    }
+    let id = text_from_value(value.get("id").or_else(|| value.get("broadcast_id")))?;
+    Some(BatchJob::Exact {
+        username: text_from_value(value.get("username"))?,
+        id: id.parse().ok()?,
+        timestamp: text_from_value(value.get("timestamp"))?,
+    })
+}
+
+// a row of a CSV file (commas, semicolons or tabs), the quotes around a field get dropped
+fn job_from_row(line: &str) -> Option<BatchJob> {
+    let fields: Vec<&str> = line
+        .split([',', ';', '\t'])
+        .map(|f| f.trim().trim_matches('"').trim())
+        .collect();
+    match fields[..] {
+        [url] if url.starts_with("http") => Some(BatchJob::Tracker {
+            url: url.to_string(),
+        }),
+        [username, id, timestamp] => Some(BatchJob::Exact {
+            username: username.to_string(),
+            id: id.parse().ok()?,
+            timestamp: timestamp.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+// a JSON array of URLs and {"username", "id", "timestamp"} objects (the report.json of
+// recover-channel works too), otherwise a line per job - either a tracker URL or a
+// username,id,timestamp row
+pub fn parse_jobs(list: &str) -> Vec<BatchJob> {
+    let mut jobs: Vec<BatchJob> = Vec::new();
+    if let Ok(Value::Array(values)) = serde_json::from_str::<Value>(list) {
+        for value in &values {
+            match job_from_value(value) {
+                Some(job) if !jobs.contains(&job) => jobs.push(job),
+                Some(_) => (),
+                None => warn!("Skipping an entry that isn't a job - {value}"),
+            }
+        }
+        return jobs;
+    }
+
+    for (i, line) in list.lines().map(str::trim).enumerate() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match job_from_row(line) {
+            Some(job) if !jobs.contains(&job) => jobs.push(job),
+            Some(_) => (),
+            // the header of a CSV file
+            None if i == 0 && line.contains(',') => (),
+            None => warn!("Skipping a line that isn't a job - {line}"),
+        }
+    }
+    jobs
+}
+
+async fn run_job(job: &BatchJob, matches: &Cli, http: &HttpContext) -> Result<Option<VodRecovery>> {
+    let options = BruteforceOptions::from(matches);
+    match job {
+        BatchJob::Tracker { url } => {
+            let (proc, data) = derive_date_from_url(url, matches.clone(), http).await?;
+            from_tracker(proc, &data, &options, http).await
+        }
+        BatchJob::Exact {
+            username,
+            id,
+            timestamp,
+        } => exact(username, *id, timestamp, &options, http).await,
+    }
+}
+
+fn save_report(path: &Path, results: &[BatchResult]) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(results)?)?;
+    Ok(())
+}
+
+// runs up to `concurrency` jobs at a time and rewrites the report after every finished job, in
+// the order of the file. a failed job gets its error in the report instead of stopping the rest
+pub async fn run_batch(
+    jobs: &[BatchJob],
+    options: &BatchOptions,
+    matches: &Cli,
+    http: &HttpContext,
+) -> Result<Vec<BatchResult>> {
+    let output = Path::new(&options.output);
+    let total = jobs.len();
+    let mut finished = stream::iter(jobs.iter().enumerate())
+        .map(|(i, job)| async move {
+            if !matches.simple {
+                info!("[{}/{total}] Looking for {job}", i + 1);
+            }
+            match run_job(job, matches, http).await {
+                Ok(recovery) => BatchResult {
+                    job: job.clone(),
+                    found: recovery.is_some(),
+                    recovery,
+                    error: None,
+                },
+                Err(e) => {
+                    warn!("Couldn't go over {job} - {e}");
+                    BatchResult {
+                        job: job.clone(),
+                        found: false,
+                        recovery: None,
+                        error: Some(e.to_string()),
+                    }
+                }
+            }
+        })
+        .buffered(options.concurrency.max(1))
+        .take_until(matches.cancel.cancelled())
+        // boxed to pin the cancellation future and so the borrowed jobs don't keep the future
+        // from being Send
+        .boxed();
+
+    let mut results = Vec::new();
+    while let Some(result) = finished.next().await {
+        results.push(result);
+        save_report(output, &results)?;
+    }
+
+    save_report(output, &results)?;
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+
+    use tempfile::tempdir;
+
+    use crate::config::{BatchOptions, Cli};
+    use crate::http::{Fixtures, HttpContext};
+
+    use super::{parse_jobs, run_batch, BatchJob};
+
+    #[test]
+    fn job_list() {
+        let tracker = BatchJob::Tracker {
+            url: "https://twitchtracker.com/dansgaming/streams/42218705421".to_string(),
+        };
+        let exact = BatchJob::Exact {
+            username: "dansgaming".to_string(),
+            id: 42218705421,
+            timestamp: "2021-06-05 00:50:17".to_string(),
+        };
+        assert_eq!(
+            parse_jobs(
+                "# dansgaming\nhttps://twitchtracker.com/dansgaming/streams/42218705421\n\ndansgaming, 42218705421, 2021-06-05 00:50:17\nnope\n"
+            ),
+            vec![tracker.clone(), exact.clone()],
+            "testing the text list"
+        );
+        assert_eq!(
+            parse_jobs(
+                "username,id,timestamp\n\"dansgaming\",\"42218705421\",\"2021-06-05 00:50:17\"\n"
+            ),
+            vec![exact.clone()],
+            "testing a CSV file"
+        );
+        assert_eq!(
+            parse_jobs(
+                r#"["https://twitchtracker.com/dansgaming/streams/42218705421", {"username": "dansgaming", "broadcast_id": "42218705421", "timestamp": "2021-06-05 00:50:17"}, {"username": "dansgaming"}]"#
+            ),
+            vec![tracker, exact],
+            "testing a JSON file"
+        );
+    }
+
+    #[tokio::test]
+    async fn batch() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("batch.json").to_string_lossy().to_string();
+        let http = HttpContext::default().with_fixtures(Arc::new(Fixtures::new()));
+        let jobs = parse_jobs("mrmouton,39905263305,2022-07-12 1200\n");
+        let options = BatchOptions::default()
+            .output(output.clone())
+            .concurrency(2);
+        let cli = Cli {
+            simple: true,
+            ..Default::default()
+        };
+
+        let results = run_batch(&jobs, &options, &cli, &http).await.unwrap();
+        assert_eq!(results.len(), 1, "testing a result per job");
+        assert!(
+            results[0].error.is_some() && !results[0].found,
+            "testing a failed job"
+        );
+        assert!(
+            fs::read_to_string(&output).unwrap().contains("39905263305"),
+            "testing the report"
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::Result;
+use colored::Colorize;
+use futures::stream::{self, StreamExt};
+use indicatif::ProgressBar;
+use rand::distr::{Alphanumeric, SampleString};
+use reqwest::StatusCode;
+use std::time::{Duration, Instant};
+
+use crate::config::Cli;
+use crate::http::HttpContext;
+use crate::util::{compile_cdn_list, info};
+
+enum Outcome {
+    Expected(Duration),
+    Throttled,
+    Failed,
+}
+
+pub async fn bench(requests: usize, matches: Cli, http: &HttpContext) -> Result<()> {
+    let cdns = compile_cdn_list(matches.cdnfile.clone(), http).await;
+    // a hash that can't exist, so every request is a guaranteed miss just like most bruteforce probes
+    let hash = Alphanumeric
+        .sample_string(&mut rand::rng(), 20)
+        .to_lowercase();
+
+    if !matches.simple {
+        println!(
+            "Sending {} requests to {} CDNs with {} threads...",
+            requests,
+            cdns.len(),
+            matches.threads
+        );
+    }
+
+    let pb = ProgressBar::new(requests as u64);
+    let started = Instant::now();
+
+    let outcomes = stream::iter(0..requests)
+        .map(|i| {
+            let url = format!(
+                "https://{}/{hash}_tbf_0_{i}/chunked/index-dvr.m3u8",
+                cdns[i % cdns.len()]
+            );
+            let pb_clone = pb.clone();
+            async move {
+                let request_started = Instant::now();
+                // no retries, the throttling and the failures are what gets measured
+                let outcome = match http.probe_once(&url).await {
+                    Ok(r) => match r.status() {
+                        StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => {
+                            Outcome::Expected(request_started.elapsed())
+                        }
+                        StatusCode::TOO_MANY_REQUESTS => Outcome::Throttled,
+                        status if status.is_server_error() => Outcome::Throttled,
+                        _ => Outcome::Expected(request_started.elapsed()),
+                    },
+                    Err(_) => Outcome::Failed,
+                };
+                if matches.progressbar {
+                    pb_clone.inc(1);
+                }
+                outcome
+            }
+        })
+        .buffer_unordered(matches.threads)
+        .fold(
+            (0usize, 0usize, 0usize, Duration::ZERO),
+            |acc, outcome| async move {
+                let (expected, throttled, failed, latency) = acc;
+                match outcome {
+                    Outcome::Expected(d) => (expected + 1, throttled, failed, latency + d),
+                    Outcome::Throttled => (expected, throttled + 1, failed, latency),
+                    Outcome::Failed => (expected, throttled, failed + 1, latency),
+                }
+            },
+        )
+        .await;
+
+    pb.finish_and_clear();
+    let elapsed = started.elapsed();
+    let (expected, throttled, failed, latency) = outcomes;
+
+    let rps = requests as f64 / elapsed.as_secs_f64().max(f64::EPSILON);
+    let failure_rate = (throttled + failed) as f64 / requests.max(1) as f64;
+    let avg_latency = latency.as_millis() as f64 / expected.max(1) as f64;
+
+    let suggested_threads = if failure_rate > 0.05 {
+        (matches.threads / 2).max(1)
+    } else if failure_rate > 0.01 {
+        (matches.threads * 3 / 4).max(1)
+    } else {
+        matches.threads
+    };
+    let suggested_rate = (rps * 0.9).floor() as u64;
+
+    if matches.simple {
+        println!("{rps:.0}");
+        return Ok(());
+    }
+
+    info(format!("Finished in {:.2}s", elapsed.as_secs_f64()), false);
+    info(format!("Throughput: {:.0} req/s", rps), false);
+    info(format!("Average latency: {avg_latency:.0}ms"), false);
+    info(
+        format!(
+            "Throttled (429/5xx): {} - Failed: {} - Error rate: {}",
+            throttled,
+            failed,
+            match failure_rate {
+                r if r > 0.05 => format!("{:.2}%", r * 100.0).red(),
+                r if r > 0.01 => format!("{:.2}%", r * 100.0).yellow(),
+                r => format!("{:.2}%", r * 100.0).green(),
+            }
+        ),
+        false,
+    );
+    if suggested_threads == matches.threads && failure_rate <= 0.01 {
+        info(
+            format!(
+                "Suggested settings: --threads {suggested_threads} (you could try going higher), about {suggested_rate} req/s"
+            ),
+            false,
+        );
+    } else {
+        info(
+            format!(
+                "Suggested settings: --threads {suggested_threads}, about {suggested_rate} req/s"
+            ),
+            false,
+        );
+    }
+
+    Ok(())
+}
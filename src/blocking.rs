@@ -0,0 +1,130 @@
+//! Blocking versions of the [`recover`](crate::recover) and [`playlist`](crate::playlist)
+//! functions, for callers without an async runtime.
+//!
+//! Like `reqwest::blocking`, the first call starts a runtime that's kept around for the rest of
+//! the process, so the connection pool of the [`HttpContext`] stays usable between calls. These
+//! must not be called from inside an async runtime, they panic there.
+//!
+//! ```no_run
+//! use tbf::{blocking, BruteforceOptions, HttpContext};
+//!
+//! # fn run() -> anyhow::Result<()> {
+//! let http = HttpContext::default();
+//! let recovery = blocking::exact("dansgaming", 42218705421, "2021-06-05 00:50:17", &BruteforceOptions::default(), &http)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use anyhow::Result;
+use once_cell::sync::OnceCell;
+use std::future::Future;
+use tokio::runtime::{Builder, Runtime};
+
+use crate::config::{BruteforceOptions, ClipOptions, FixOptions, ReportOptions};
+use crate::http::HttpContext;
+use crate::twitch::{
+    clips,
+    models::{FixedPlaylist, ReturnURL, VodRecovery},
+    vods,
+};
+
+static RUNTIME: OnceCell<Runtime> = OnceCell::new();
+
+fn block_on<F: Future>(future: F) -> Result<F::Output> {
+    let runtime = RUNTIME.get_or_try_init(|| {
+        Builder::new_multi_thread()
+            .thread_name("tbf-blocking")
+            .enable_all()
+            .build()
+    })?;
+    Ok(runtime.block_on(future))
+}
+
+/// See [`recover::exact`](crate::recover::exact).
+pub fn exact(
+    username: &str,
+    vod: i64,
+    stamp: &str,
+    options: &BruteforceOptions,
+    http: &HttpContext,
+) -> Result<Option<VodRecovery>> {
+    block_on(vods::exact(username, vod, stamp, options, http))?
+}
+
+/// See [`recover::bruteforce`](crate::recover::bruteforce).
+pub fn bruteforce(
+    username: &str,
+    vod: i64,
+    from: &str,
+    to: &str,
+    options: &BruteforceOptions,
+    http: &HttpContext,
+) -> Result<Option<VodRecovery>> {
+    block_on(vods::bruteforcer(username, vod, from, to, options, http))?
+}
+
+/// See [`recover::live`](crate::recover::live).
+pub fn live(
+    username: &str,
+    options: &BruteforceOptions,
+    http: &HttpContext,
+) -> Result<Option<VodRecovery>> {
+    block_on(vods::live(username, options, http))?
+}
+
+/// See [`recover::clip_bruteforce`](crate::recover::clip_bruteforce).
+pub fn clip_bruteforce(
+    vod: i64,
+    start: i64,
+    end: i64,
+    options: &ClipOptions,
+    http: &HttpContext,
+) -> Result<Option<Vec<ReturnURL>>> {
+    block_on(clips::clip_bruteforce(vod, start, end, options, http))?
+}
+
+/// See [`recover::find_bid_from_clip`](crate::recover::find_bid_from_clip).
+pub fn find_bid_from_clip(
+    clip: String,
+    flags: &ReportOptions,
+    http: &HttpContext,
+) -> Result<Option<(String, i64)>> {
+    block_on(clips::find_bid_from_clip(clip, flags, http))?
+}
+
+/// See [`playlist::fix`](crate::playlist::fix).
+pub fn fix(url: &str, options: &FixOptions, http: &HttpContext) -> Result<FixedPlaylist> {
+    block_on(vods::fix(url, options, http))?
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::config::ReportOptions;
+    use crate::http::{Fixtures, HttpContext};
+
+    use super::find_bid_from_clip;
+
+    #[test]
+    fn blocking_without_runtime() {
+        let http = HttpContext::default().with_fixtures(Arc::new(Fixtures::new().respond(
+            "https://gql.twitch.tv/gql",
+            200,
+            r#"{"data": {"clip": {"broadcaster": {"login": "mrmouton"}, "broadcast": {"id": "39905263305"}}}}"#,
+        )));
+        // called twice to make sure the runtime survives between calls
+        for _ in 0..2 {
+            assert_eq!(
+                find_bid_from_clip(
+                    "SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string(),
+                    &ReportOptions::default(),
+                    &http,
+                )
+                .unwrap(),
+                Some(("mrmouton".to_string(), 39905263305)),
+                "testing a blocking call"
+            );
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+use url::Url;
+
+use crate::offline::split_vod_directory;
+use crate::twitch::models::CDN_URLS;
+use crate::util::{read_cdn_file, remote_cdn_file, CDNEntry, CDNFile};
+
+// where the new CDNs go without --cdnfile
+pub const DEFAULT_CDN_FILE: &str = "cdns.txt";
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct CdnDiscovery {
+    // every CDN host in the input, in the order they came up
+    pub found: Vec<String>,
+    // the ones neither tbf nor the cdnfile knew yet
+    pub added: Vec<String>,
+}
+
+// the host of a URL pointing into a VOD's directory (<hash>_<username>_<id>_<timestamp>), a
+// playlist or a segment of any quality
+pub fn vod_cdn_host(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    if !(url.path().ends_with(".m3u8") || url.path().ends_with(".ts")) {
+        return None;
+    }
+    split_vod_directory(url.path_segments()?.next()?)?;
+    Some(url.host_str()?.to_lowercase())
+}
+
+// the URLs can be anywhere in the text - a list, a log, a chat message or a JSON report
+pub fn extract_cdn_hosts(text: &str) -> Vec<String> {
+    let mut hosts: Vec<String> = Vec::new();
+    let words =
+        text.split(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '<' | '>' | ','));
+    for host in words.filter_map(|w| vod_cdn_host(w.trim_end_matches(['.', ')']))) {
+        if !hosts.contains(&host) {
+            hosts.push(host);
+        }
+    }
+    hosts
+}
+
+// adds the hosts to the cdnfile in the format it's in, a missing file becomes a text file
+fn append_cdns(path: &Path, hosts: &[String]) -> Result<()> {
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    if matches!(extension, Some("txt") | None) {
+        let existing = fs::read_to_string(path).unwrap_or_default();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if !existing.is_empty() && !existing.ends_with('\n') {
+            writeln!(file)?;
+        }
+        for host in hosts {
+            writeln!(file, "{host}")?;
+        }
+        return Ok(());
+    }
+
+    let existing = fs::read_to_string(path).ok();
+    let mut cdn_file: CDNFile = match (extension, existing.as_deref()) {
+        (_, None) => CDNFile::default(),
+        (Some("json"), Some(text)) => serde_json::from_str(text)?,
+        (Some("toml"), Some(text)) => toml::from_str(text)?,
+        (Some("yaml") | Some("yml"), Some(text)) => serde_yaml::from_str(text)?,
+        _ => {
+            return Err(anyhow!(
+                "{} isn't a TXT, JSON, YAML or TOML file",
+                path.display()
+            ))
+        }
+    };
+    cdn_file
+        .cdns
+        .extend(hosts.iter().cloned().map(CDNEntry::Host));
+    let text = match extension {
+        Some("json") => serde_json::to_string_pretty(&cdn_file)?,
+        Some("toml") => toml::to_string(&cdn_file)?,
+        _ => serde_yaml::to_string(&cdn_file)?,
+    };
+    fs::write(path, text)?;
+    Ok(())
+}
+
+// the CDNs of the VOD URLs in the text that aren't in the built-in list or the cdnfile yet get
+// appended to the cdnfile, unless it's a dry run
+pub fn discover_cdns(text: &str, cdnfile: &Path, dry_run: bool) -> Result<CdnDiscovery> {
+    if remote_cdn_file(&cdnfile.to_string_lossy()).is_some() {
+        return Err(anyhow!(
+            "{} is a remote CDN list, new CDNs can only go into a local file",
+            cdnfile.display()
+        ));
+    }
+    let found = extract_cdn_hosts(text);
+    // the disabled CDNs of the file count as known, they're there on purpose
+    let mut known: Vec<String> = CDN_URLS.iter().map(|s| s.to_string()).collect();
+    if cdnfile.exists() {
+        known.extend(
+            read_cdn_file(&cdnfile.to_string_lossy())?
+                .into_iter()
+                .map(|cdn| cdn.host),
+        );
+    }
+    let added: Vec<String> = found
+        .iter()
+        .filter(|host| !known.contains(host))
+        .cloned()
+        .collect();
+    if !dry_run && !added.is_empty() {
+        append_cdns(cdnfile, &added)?;
+    }
+    Ok(CdnDiscovery { found, added })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use tempfile::tempdir;
+
+    use crate::http::HttpContext;
+    use crate::util::compile_cdn_list;
+
+    use super::{discover_cdns, extract_cdn_hosts};
+
+    #[test]
+    fn cdn_hosts() {
+        let text = r#"found it: https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8
+{"url": "https://new-cdn.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/720p60/12.ts"}
+https://D1M7JFOE9ZDC1J.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/160p30/index-dvr.m3u8
+https://twitchtracker.com/dansgaming/streams/42218705421 and https://example.com/index.m3u8"#;
+        assert_eq!(
+            extract_cdn_hosts(text),
+            vec!["d1m7jfoe9zdc1j.cloudfront.net", "new-cdn.cloudfront.net"],
+            "testing the hosts of the VOD URLs"
+        );
+    }
+
+    #[tokio::test]
+    async fn discovery() {
+        let dir = tempdir().unwrap();
+        let text = "https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8 https://new-cdn.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8";
+
+        let txt = dir.path().join("cdns.txt");
+        fs::write(&txt, "other-cdn.cloudfront.net").unwrap();
+        let dry_run = discover_cdns(text, &txt, true).unwrap();
+        assert_eq!(
+            dry_run.added,
+            vec!["new-cdn.cloudfront.net"],
+            "testing a new CDN"
+        );
+        assert_eq!(
+            fs::read_to_string(&txt).unwrap(),
+            "other-cdn.cloudfront.net",
+            "testing a dry run"
+        );
+        discover_cdns(text, &txt, false).unwrap();
+        assert_eq!(
+            fs::read_to_string(&txt).unwrap(),
+            "other-cdn.cloudfront.net\nnew-cdn.cloudfront.net\n",
+            "testing a text file"
+        );
+        assert!(
+            discover_cdns(text, &txt, false).unwrap().added.is_empty(),
+            "testing a CDN that's in the file already"
+        );
+
+        let toml = dir.path().join("cdns.toml");
+        fs::write(
+            &toml,
+            "cdns = [{ host = \"new-cdn.cloudfront.net\", enabled = false }]\n",
+        )
+        .unwrap();
+        assert!(
+            discover_cdns(text, &toml, false).unwrap().added.is_empty(),
+            "testing a disabled CDN"
+        );
+
+        let json = dir.path().join("cdns.json");
+        discover_cdns(text, &json, false).unwrap();
+        assert!(
+            compile_cdn_list(
+                Some(json.to_string_lossy().to_string()),
+                &HttpContext::default()
+            )
+            .await
+            .contains(&"new-cdn.cloudfront.net".to_string()),
+            "testing a new JSON file"
+        );
+
+        assert!(
+            discover_cdns(text, Path::new("https://example.com/cdns.txt"), false).is_err(),
+            "testing a remote cdnfile"
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::Result;
+use log::{info, warn};
+use serde::Serialize;
+use std::{fs, path::Path, time::Duration};
+
+use crate::config::{BruteforceOptions, ChannelOptions, Cli, FixOptions, ReportOptions};
+use crate::http::HttpContext;
+use crate::twitch::vods::{fix, from_tracker};
+use crate::util::{derive_date_from_url, list_channel_streams, sleep_with_jitter};
+
+// one stream of the run, all of them end up in report.json
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct ChannelStream {
+    pub url: String,
+    pub broadcast_id: String,
+    pub found: bool,
+    pub playlist: Option<String>,
+    pub error: Option<String>,
+}
+
+async fn recover_stream(
+    url: &str,
+    directory: &Path,
+    options: &ChannelOptions,
+    matches: &Cli,
+    http: &HttpContext,
+) -> Result<ChannelStream> {
+    let (proc, data) = derive_date_from_url(url, matches.clone(), http).await?;
+    let mut stream = ChannelStream {
+        url: url.to_string(),
+        broadcast_id: data.broadcast_id.clone(),
+        ..Default::default()
+    };
+    let Some(recovery) = from_tracker(proc, &data, &BruteforceOptions::from(matches), http).await?
+    else {
+        return Ok(stream);
+    };
+    stream.found = true;
+
+    fs::create_dir_all(directory)?;
+    fs::write(
+        directory.join("recovery.json"),
+        serde_json::to_string_pretty(&recovery)?,
+    )?;
+    if let (true, Some(first)) = (options.fix, recovery.urls.first()) {
+        let path = directory
+            .join("playlist.m3u8")
+            .to_string_lossy()
+            .to_string();
+        let fix_options = FixOptions::default()
+            .output(path)
+            .report(ReportOptions::from(matches));
+        stream.playlist = Some(fix(&first.url, &fix_options, http).await?.path);
+    }
+    Ok(stream)
+}
+
+// goes over the streams the tracker lists for the channel one at a time, with a pause in
+// between, and leaves a folder per found stream and a report.json in the output folder
+pub async fn recover_channel(
+    username: &str,
+    options: &ChannelOptions,
+    matches: &Cli,
+    http: &HttpContext,
+) -> Result<Vec<ChannelStream>> {
+    let mut urls = list_channel_streams(username, &options.tracker, http).await?;
+    if let Some(limit) = options.limit {
+        urls.truncate(limit);
+    }
+    if !matches.simple {
+        info!("Found {} streams of {username}", urls.len());
+    }
+
+    let output = Path::new(&options.output);
+    fs::create_dir_all(output)?;
+    let mut streams = Vec::new();
+    for (i, url) in urls.iter().enumerate() {
+        if matches.cancel.is_cancelled() {
+            break;
+        }
+        let broadcast_id = url.rsplit('/').next().unwrap_or_default().to_string();
+        let directory = output.join(&broadcast_id);
+        // anything found on an earlier run stays as it is
+        if directory.join("recovery.json").exists() {
+            streams.push(ChannelStream {
+                url: url.clone(),
+                broadcast_id,
+                found: true,
+                playlist: Some(directory.join("playlist.m3u8"))
+                    .filter(|p| p.exists())
+                    .map(|p| p.to_string_lossy().to_string()),
+                error: None,
+            });
+            continue;
+        }
+
+        if !matches.simple {
+            info!("[{}/{}] {url}", i + 1, urls.len());
+        }
+        let stream = match recover_stream(url, &directory, options, matches, http).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Couldn't recover {url} - {e}");
+                ChannelStream {
+                    url: url.clone(),
+                    broadcast_id,
+                    error: Some(e.to_string()),
+                    ..Default::default()
+                }
+            }
+        };
+        streams.push(stream);
+        fs::write(
+            output.join("report.json"),
+            serde_json::to_string_pretty(&streams)?,
+        )?;
+
+        if i + 1 < urls.len() {
+            sleep_with_jitter(Duration::from_millis(options.delay)).await;
+        }
+    }
+
+    fs::write(
+        output.join("report.json"),
+        serde_json::to_string_pretty(&streams)?,
+    )?;
+    Ok(streams)
+}
@@ -0,0 +1,382 @@
+use anyhow::Result;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{fs, path::Path};
+
+use crate::config::ClipBatchOptions;
+use crate::http::HttpContext;
+use crate::limiter::{scan_budget, AdaptiveConcurrency};
+use crate::twitch::clips::clip_bruteforce_shared;
+use crate::twitch::models::ReturnURL;
+
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ClipBatchVod {
+    pub broadcast_id: i64,
+    pub clips: Vec<String>,
+    // a cancelled VOD is left incomplete, so the next run goes over it again
+    pub complete: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ClipBatchReport {
+    pub start: i64,
+    pub end: i64,
+    pub vods: Vec<ClipBatchVod>,
+}
+
+// what a clipforce run went over so far, the offsets are the same for any range of the VOD so a
+// resumed run can go over another range and still skip what's done
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct ClipScanState {
+    pub broadcast_id: i64,
+    // sorted [from, to) ranges that don't touch each other
+    pub checked: Vec<(i64, i64)>,
+    pub clips: Vec<ReturnURL>,
+}
+
+impl ClipScanState {
+    pub fn new(broadcast_id: i64) -> Self {
+        Self {
+            broadcast_id,
+            ..Default::default()
+        }
+    }
+
+    // the state of an earlier run, a fresh one if there's none for this VOD
+    pub fn load(path: &Path, broadcast_id: i64) -> Result<Self> {
+        let state = match fs::read_to_string(path) {
+            Ok(s) => serde_json::from_str::<Self>(&s)?,
+            Err(_) => return Ok(Self::new(broadcast_id)),
+        };
+        if state.broadcast_id != broadcast_id {
+            warn!(
+                "{} is the state of {}, starting over",
+                path.display(),
+                state.broadcast_id
+            );
+            return Ok(Self::new(broadcast_id));
+        }
+        Ok(state)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        // written next to it first, so getting killed halfway doesn't leave a broken file
+        let partial = path.with_extension("partial");
+        fs::write(&partial, serde_json::to_string(self)?)?;
+        fs::rename(partial, path)?;
+        Ok(())
+    }
+
+    pub fn is_checked(&self, offset: i64) -> bool {
+        let i = self.checked.partition_point(|&(_, to)| to <= offset);
+        self.checked.get(i).is_some_and(|&(from, _)| from <= offset)
+    }
+
+    // the offsets come back out of order, so a new one can extend a range, fill the gap
+    // between two or start one of its own
+    pub fn mark(&mut self, offset: i64) {
+        let i = self.checked.partition_point(|&(_, to)| to < offset);
+        match self.checked.get(i).copied() {
+            Some((_, to)) if to == offset => {
+                self.checked[i].1 = offset + 1;
+                if self
+                    .checked
+                    .get(i + 1)
+                    .is_some_and(|&(from, _)| from == offset + 1)
+                {
+                    self.checked[i].1 = self.checked.remove(i + 1).1;
+                }
+            }
+            Some((from, _)) if from <= offset => (),
+            Some((from, _)) if from == offset + 1 => self.checked[i].0 = offset,
+            _ => self.checked.insert(i, (offset, offset + 1)),
+        }
+    }
+}
+
+fn id_from_value(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(n) => n.as_i64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+// one broadcast ID (or a tracker stream URL) per line, or the report.json of recover-channel
+pub fn parse_broadcast_ids(list: &str) -> Vec<i64> {
+    let mut ids: Vec<i64> = Vec::new();
+    if let Ok(Value::Array(streams)) = serde_json::from_str::<Value>(list) {
+        for stream in &streams {
+            match stream.get("broadcast_id").and_then(id_from_value) {
+                Some(id) if !ids.contains(&id) => ids.push(id),
+                Some(_) => (),
+                None => warn!("Skipping a stream without a broadcast ID - {stream}"),
+            }
+        }
+        return ids;
+    }
+
+    for line in list.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let last = line
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or(line);
+        match last.parse::<i64>() {
+            Ok(id) if !ids.contains(&id) => ids.push(id),
+            Ok(_) => (),
+            Err(_) => warn!("Skipping a line that isn't a broadcast ID - {line}"),
+        }
+    }
+    ids
+}
+
+fn save_report(path: &Path, report: &ClipBatchReport) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(report)?)?;
+    Ok(())
+}
+
+// goes over the VODs one at a time with a single concurrency limit, so a CDN throttling one of
+// them slows all the rest down too, and keeps the combined report up to date after every VOD.
+// the VODs finished on an earlier run with the same offsets get skipped
+pub async fn clipforce_batch(
+    ids: &[i64],
+    options: &ClipBatchOptions,
+    http: &HttpContext,
+) -> Result<ClipBatchReport> {
+    let flags = &options.clip.report;
+    let output = Path::new(&options.output);
+    let mut report = match fs::read_to_string(output) {
+        Ok(s) => serde_json::from_str::<ClipBatchReport>(&s)?,
+        Err(_) => ClipBatchReport::default(),
+    };
+    if report.start != options.start || report.end != options.end {
+        report = ClipBatchReport {
+            start: options.start,
+            end: options.end,
+            vods: Vec::new(),
+        };
+    }
+    report.vods.retain(|v| v.complete);
+
+    let budget = scan_budget(options.clip.scan.threads, options.clip.scan.max_memory);
+    let concurrency = AdaptiveConcurrency::new(budget.threads, !options.clip.scan.fixed_threads);
+    for (i, &id) in ids.iter().enumerate() {
+        if options.clip.scan.cancel.is_cancelled() {
+            break;
+        }
+        if report.vods.iter().any(|v| v.broadcast_id == id) {
+            continue;
+        }
+        if !flags.simple {
+            info!("[{}/{}] Looking for the clips of {id}", i + 1, ids.len());
+        }
+
+        let vod = match clip_bruteforce_shared(
+            id,
+            options.start,
+            options.end,
+            &options.clip,
+            &concurrency,
+            http,
+        )
+        .await
+        {
+            Ok(clips) => ClipBatchVod {
+                broadcast_id: id,
+                clips: clips
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|c| c.url)
+                    .collect(),
+                complete: !options.clip.scan.cancel.is_cancelled(),
+                error: None,
+            },
+            Err(e) => {
+                warn!("Couldn't go over the clips of {id} - {e}");
+                ClipBatchVod {
+                    broadcast_id: id,
+                    error: Some(e.to_string()),
+                    ..Default::default()
+                }
+            }
+        };
+        report.vods.push(vod);
+        save_report(output, &report)?;
+    }
+
+    save_report(output, &report)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tempfile::tempdir;
+
+    use crate::config::{ClipBatchOptions, ClipOptions, ReportOptions, ScanOptions};
+    use crate::http::{Fixtures, HttpContext};
+    use crate::twitch::clips::clip_bruteforce;
+    use crate::twitch::models::ReturnURL;
+
+    use super::{clipforce_batch, parse_broadcast_ids, ClipScanState};
+
+    #[test]
+    fn broadcast_id_list() {
+        assert_eq!(
+            parse_broadcast_ids(
+                "# banned channel\n39619965384\n\nhttps://twitchtracker.com/forsen/streams/39600000000/\n39619965384\nnope\n"
+            ),
+            vec![39619965384, 39600000000],
+            "testing the ID list"
+        );
+        assert_eq!(
+            parse_broadcast_ids(
+                r#"[{"url": "", "broadcast_id": "39619965384", "found": true}, {"broadcast_id": 39600000000}]"#
+            ),
+            vec![39619965384, 39600000000],
+            "testing the recover-channel report"
+        );
+    }
+
+    #[tokio::test]
+    async fn clipforce_many() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("clips.json").to_string_lossy().to_string();
+        let http = HttpContext::default().with_fixtures(Arc::new(
+            Fixtures::new()
+                .respond(
+                    "https://clips-media-assets2.twitch.tv/1-offset-2.mp4",
+                    200,
+                    "",
+                )
+                .respond(
+                    "https://clips-media-assets2.twitch.tv/2-offset-0.mp4",
+                    200,
+                    "",
+                ),
+        ));
+        let options = ClipBatchOptions::default()
+            .output(output.clone())
+            .range(0, 3)
+            .clip(
+                ClipOptions::default()
+                    .scan(ScanOptions::default().threads(4))
+                    .report(ReportOptions::default().simple(true)),
+            );
+
+        let report = clipforce_batch(&[1, 2, 3], &options, &http).await.unwrap();
+        assert_eq!(
+            report
+                .vods
+                .iter()
+                .map(|v| (v.broadcast_id, v.clips.clone(), v.complete))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    1,
+                    vec!["https://clips-media-assets2.twitch.tv/1-offset-2.mp4".to_string()],
+                    true
+                ),
+                (
+                    2,
+                    vec!["https://clips-media-assets2.twitch.tv/2-offset-0.mp4".to_string()],
+                    true
+                ),
+                (3, Vec::new(), true),
+            ],
+            "testing the combined report"
+        );
+
+        let fixtures = Arc::new(Fixtures::new());
+        let again = HttpContext::default().with_fixtures(fixtures.clone());
+        let report = clipforce_batch(&[1, 2, 3, 4], &options, &again)
+            .await
+            .unwrap();
+        assert_eq!(
+            (report.vods.len(), fixtures.requested().len()),
+            (4, 3),
+            "testing a second run only going over the new VOD"
+        );
+    }
+
+    #[test]
+    fn scan_state() {
+        let mut state = ClipScanState::new(1);
+        for offset in [5, 3, 9, 4, 8, 7, 4] {
+            state.mark(offset);
+        }
+        assert_eq!(
+            state.checked,
+            vec![(3, 6), (7, 10)],
+            "testing the merged ranges"
+        );
+        state.mark(6);
+        assert_eq!(state.checked, vec![(3, 10)], "testing a filled gap");
+        assert!(
+            state.is_checked(3) && state.is_checked(9) && !state.is_checked(10),
+            "testing the checked offsets"
+        );
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("clipforce_1.json");
+        state.save(&path).unwrap();
+        assert_eq!(
+            ClipScanState::load(&path, 1).unwrap(),
+            state,
+            "testing a saved state"
+        );
+        assert_eq!(
+            ClipScanState::load(&path, 2).unwrap(),
+            ClipScanState::new(2),
+            "testing the state of another VOD"
+        );
+    }
+
+    #[tokio::test]
+    async fn resumed_clipforce() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("clipforce_1.json");
+        let clip = |offset: i64| ReturnURL {
+            url: format!("https://clips-media-assets2.twitch.tv/1-offset-{offset}.mp4"),
+            muted: false,
+            audio_only: false,
+        };
+        ClipScanState {
+            broadcast_id: 1,
+            checked: vec![(0, 2)],
+            clips: vec![clip(1)],
+        }
+        .save(&path)
+        .unwrap();
+
+        let fixtures = Arc::new(Fixtures::new().respond(&clip(3).url, 200, ""));
+        let http = HttpContext::default().with_fixtures(fixtures.clone());
+        let options = ClipOptions::default()
+            .report(ReportOptions::default().simple(true))
+            .state(Some(path.clone()))
+            .resume(true);
+        let clips = clip_bruteforce(1, 0, 4, &options, &http).await.unwrap();
+        assert_eq!(
+            clips,
+            Some(vec![clip(1), clip(3)]),
+            "testing the clips of both runs"
+        );
+        assert_eq!(
+            fixtures.requested().len(),
+            2,
+            "testing only the offsets left getting checked"
+        );
+        assert_eq!(
+            ClipScanState::load(&path, 1).unwrap().checked,
+            vec![(0, 4)],
+            "testing the saved progress"
+        );
+    }
+}
@@ -1,60 +1,102 @@
+#[cfg(feature = "net")]
 use reqwest::header::{InvalidHeaderName, InvalidHeaderValue};
 use std::{error::Error, fmt::Display, num::ParseIntError};
 use time::error::Parse;
 use url::ParseError as UrlPError;
 
+#[cfg(feature = "net")]
 #[derive(Debug)]
 pub enum PlaylistFix {
     Reqwest(reqwest::Error),
     Io(std::io::Error),
     URL,
+    Playlist,
+    Cancelled,
 }
 
+#[cfg(feature = "net")]
 impl From<reqwest::Error> for PlaylistFix {
     fn from(e: reqwest::Error) -> Self {
         Self::Reqwest(e)
     }
 }
 
+#[cfg(feature = "net")]
 impl From<std::io::Error> for PlaylistFix {
     fn from(e: std::io::Error) -> Self {
         Self::Io(e)
     }
 }
 
+#[cfg(feature = "net")]
 impl Display for PlaylistFix {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::Reqwest(e) => write!(f, "couldn't process the url: {e}"),
             Self::Io(e) => write!(f, "io error: {e}"),
             Self::URL => write!(f, "only twitch.tv and cloudfront.net URLs are supported"),
+            Self::Playlist => write!(f, "the URL didn't return an m3u8 playlist"),
+            Self::Cancelled => write!(f, "cancelled, the playlist is incomplete"),
         }
     }
 }
 
+#[cfg(feature = "net")]
 impl Error for PlaylistFix {}
 
+#[cfg(feature = "net")]
+#[derive(Debug)]
+pub enum Download {
+    URL,
+    Playlist,
+    Segment(String, reqwest::Error),
+    Cancelled,
+    Remux(String),
+}
+
+#[cfg(feature = "net")]
+impl Display for Download {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::URL => write!(f, "only twitch.tv and cloudfront.net URLs are supported"),
+            Self::Playlist => write!(f, "the URL didn't return an m3u8 playlist"),
+            Self::Segment(uri, e) => write!(f, "couldn't download the segment {uri}: {e}"),
+            Self::Cancelled => write!(f, "cancelled, the download is incomplete"),
+            Self::Remux(e) => write!(f, "couldn't remux the download: {e}"),
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+impl Error for Download {}
+
 #[derive(Debug)]
 pub enum Vod {
     IntegerParse(ParseIntError),
     StringParse(Parse),
+    #[cfg(feature = "net")]
     HeaderName(InvalidHeaderName),
+    #[cfg(feature = "net")]
     HeaderValue(InvalidHeaderValue),
+    #[cfg(feature = "net")]
     UrlProcess(reqwest::Error),
 }
 
+#[cfg(feature = "net")]
 impl From<InvalidHeaderName> for Vod {
     fn from(e: InvalidHeaderName) -> Self {
         Self::HeaderName(e)
     }
 }
 
+#[cfg(feature = "net")]
 impl From<InvalidHeaderValue> for Vod {
     fn from(e: InvalidHeaderValue) -> Self {
         Self::HeaderValue(e)
     }
 }
 
+#[cfg(feature = "net")]
 impl From<reqwest::Error> for Vod {
     fn from(e: reqwest::Error) -> Self {
         Self::UrlProcess(e)
@@ -78,8 +120,11 @@ impl Display for Vod {
         match self {
             Self::IntegerParse(e) => write!(f, "couldn't parse the unix timestamp: {e}"),
             Self::StringParse(e) => write!(f, "couldn't parse the string timestamp: {e}"),
+            #[cfg(feature = "net")]
             Self::HeaderName(e) => write!(f, "invalid header name: {e}"),
+            #[cfg(feature = "net")]
             Self::HeaderValue(e) => write!(f, "invalid header value: {e}"),
+            #[cfg(feature = "net")]
             Self::UrlProcess(e) => write!(f, "couldn't process the url: {e}"),
         }
     }
@@ -94,6 +139,7 @@ pub enum DeriveDate {
     ScraperAttribute,
     Selector,
     TimestampParser(Vod),
+    #[cfg(feature = "net")]
     UrlProcess(reqwest::Error),
     UrlParse(UrlPError),
     WrongURL(String),
@@ -111,6 +157,7 @@ impl From<UrlPError> for DeriveDate {
     }
 }
 
+#[cfg(feature = "net")]
 impl From<reqwest::Error> for DeriveDate {
     fn from(e: reqwest::Error) -> Self {
         Self::UrlProcess(e)
@@ -125,6 +172,7 @@ impl Display for DeriveDate {
             Self::ScraperAttribute => write!(f, "couldn't find the html attribute"),
             Self::Selector => write!(f, "couldn't parse the selector"),
             Self::TimestampParser(e) => write!(f, "{e}"),
+            #[cfg(feature = "net")]
             Self::UrlProcess(e) => write!(f, "couldn't process the url: {e}"),
             Self::WrongURL(e) => write!(f, "{e}"),
             Self::UrlParse(e) => write!(f, "couldn't parse the url: {e}"),
@@ -134,6 +182,7 @@ impl Display for DeriveDate {
 
 impl Error for DeriveDate {}
 
+#[cfg(feature = "net")]
 #[derive(Debug)]
 pub enum Clip {
     IntegerParse(ParseIntError),
@@ -142,32 +191,38 @@ pub enum Clip {
     HeaderValue(InvalidHeaderValue),
     WrongURL(String),
     UrlProcess(reqwest::Error),
+    NotFound,
 }
 
+#[cfg(feature = "net")]
 impl From<ParseIntError> for Clip {
     fn from(e: ParseIntError) -> Self {
         Self::IntegerParse(e)
     }
 }
 
+#[cfg(feature = "net")]
 impl From<InvalidHeaderName> for Clip {
     fn from(e: InvalidHeaderName) -> Self {
         Self::HeaderName(e)
     }
 }
 
+#[cfg(feature = "net")]
 impl From<InvalidHeaderValue> for Clip {
     fn from(e: InvalidHeaderValue) -> Self {
         Self::HeaderValue(e)
     }
 }
 
+#[cfg(feature = "net")]
 impl From<reqwest::Error> for Clip {
     fn from(e: reqwest::Error) -> Self {
         Self::UrlProcess(e)
     }
 }
 
+#[cfg(feature = "net")]
 impl Display for Clip {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -177,8 +232,76 @@ impl Display for Clip {
             Self::HeaderValue(e) => write!(f, "invalid header value: {e}"),
             Self::WrongURL(e) => write!(f, "{e}"),
             Self::UrlProcess(e) => write!(f, "couldn't process the url: {e}"),
+            Self::NotFound => write!(f, "the clip doesn't exist or got deleted"),
         }
     }
 }
 
+#[cfg(feature = "net")]
 impl Error for Clip {}
+
+#[cfg(feature = "net")]
+#[derive(Debug)]
+pub enum Chat {
+    NotFound,
+    Throttled,
+    Io(std::io::Error),
+}
+
+#[cfg(feature = "net")]
+impl From<std::io::Error> for Chat {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[cfg(feature = "net")]
+impl Display for Chat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "the VOD doesn't exist or its chat isn't stored anymore"),
+            Self::Throttled => write!(f, "got rate limited by Twitch too many times in a row"),
+            Self::Io(e) => write!(f, "io error: {e}"),
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+impl Error for Chat {}
+
+#[cfg(feature = "net")]
+#[derive(Debug)]
+pub enum Update {
+    NoAsset,
+    NoChecksum,
+    ChecksumMismatch { expected: String, actual: String },
+    NoSignature,
+    Signature(String),
+    Extract(String),
+}
+
+#[cfg(feature = "net")]
+impl Display for Update {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoAsset => write!(f, "the release has no build for this platform"),
+            Self::NoChecksum => write!(
+                f,
+                "the release has no checksum for the build, refusing to install it"
+            ),
+            Self::ChecksumMismatch { expected, actual } => write!(
+                f,
+                "the checksum doesn't match (expected {expected}, got {actual}), refusing to install it"
+            ),
+            Self::NoSignature => write!(
+                f,
+                "the release has no signature for the build, refusing to install it"
+            ),
+            Self::Signature(e) => write!(f, "the signature isn't valid ({e}), refusing to install it"),
+            Self::Extract(e) => write!(f, "couldn't unpack the build: {e}"),
+        }
+    }
+}
+
+#[cfg(feature = "net")]
+impl Error for Update {}
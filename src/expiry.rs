@@ -0,0 +1,122 @@
+use anyhow::{anyhow, Result};
+use log::debug;
+use reqwest::StatusCode;
+use serde::Serialize;
+
+use crate::http::HttpContext;
+use crate::offline::playlist_base_url;
+
+const DAY: i64 = 86400;
+// below these many days left a download gets flagged
+const URGENT_DAYS: i64 = 3;
+const SOON_DAYS: i64 = 14;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Urgency {
+    // the playlist doesn't answer anymore
+    Gone,
+    // past the usual retention, it can disappear any moment
+    Overdue,
+    Urgent,
+    Soon,
+    Fine,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ExpiryEstimate {
+    pub stream_start: i64,
+    pub expires_at: i64,
+    // negative once the estimated expiry is in the past
+    pub remaining: i64,
+    // None when the playlist wasn't checked
+    pub available: Option<bool>,
+    pub urgency: Urgency,
+}
+
+// the start of the stream is the last part of the playlist's directory
+// (<hash>_<username>_<id>_<timestamp>)
+pub fn stream_start(url: &str) -> Result<i64> {
+    let (_, directory) = playlist_base_url(url)
+        .ok_or_else(|| anyhow!("Only twitch.tv and cloudfront.net playlist URLs are supported"))?;
+    directory
+        .rsplit('_')
+        .next()
+        .and_then(|t| t.parse::<i64>().ok())
+        .ok_or_else(|| anyhow!("Couldn't find the stream's timestamp in the URL"))
+}
+
+// nothing but a heuristic: the storage usually outlives the VOD page by a lot, and most VODs
+// are observed to stay reachable for about `retention_days` after the stream, but Twitch never
+// promised anything about it and can clean a VOD up earlier (or keep it longer)
+pub fn estimate_expiry(
+    stream_start: i64,
+    now: i64,
+    retention_days: i64,
+    available: Option<bool>,
+) -> ExpiryEstimate {
+    let expires_at = stream_start + retention_days * DAY;
+    let remaining = expires_at - now;
+    let urgency = match available {
+        Some(false) => Urgency::Gone,
+        _ if remaining <= 0 => Urgency::Overdue,
+        _ if remaining < URGENT_DAYS * DAY => Urgency::Urgent,
+        _ if remaining < SOON_DAYS * DAY => Urgency::Soon,
+        _ => Urgency::Fine,
+    };
+    ExpiryEstimate {
+        stream_start,
+        expires_at,
+        remaining,
+        available,
+        urgency,
+    }
+}
+
+// None when the check itself failed, which says nothing about the playlist
+pub async fn check_available(url: &str, http: &HttpContext) -> Option<bool> {
+    match http.send_with_retry(http.client.head(url)).await {
+        Ok(r) => Some(!matches!(
+            r.status(),
+            StatusCode::FORBIDDEN | StatusCode::NOT_FOUND
+        )),
+        Err(e) => {
+            debug!("Couldn't check {url} - {e}");
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_expiry, stream_start, Urgency};
+
+    #[test]
+    fn expiry() {
+        let start = stream_start("https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8").unwrap();
+        assert_eq!(start, 1622854217, "testing the timestamp from the URL");
+
+        let day = 86400;
+        let estimate = estimate_expiry(start, start + 10 * day, 60, Some(true));
+        assert_eq!(
+            (estimate.remaining, estimate.urgency),
+            (50 * day, Urgency::Fine),
+            "testing a fresh VOD"
+        );
+        assert_eq!(
+            estimate_expiry(start, start + 58 * day, 60, None).urgency,
+            Urgency::Urgent,
+            "testing a VOD close to the retention"
+        );
+        assert_eq!(
+            estimate_expiry(start, start + 61 * day, 60, Some(true)).urgency,
+            Urgency::Overdue,
+            "testing a VOD past the retention"
+        );
+        assert_eq!(
+            estimate_expiry(start, start + day, 60, Some(false)).urgency,
+            Urgency::Gone,
+            "testing an unavailable VOD"
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::{fs, path::Path};
+use time::OffsetDateTime;
+use tokio::process::Command;
+
+use crate::config::ReportOptions;
+use crate::error::Download;
+use crate::http::HttpContext;
+use crate::notify::RecoveryEvent;
+use crate::twitch::{
+    models::{MutedRange, VodInfo},
+    muted_ranges, vod_info,
+};
+
+#[derive(Clone, Debug, PartialEq, ValueEnum)]
+pub enum StreamlinkMode {
+    // only print the command so it can be copied
+    Print,
+    // run streamlink right away
+    Run,
+}
+
+// builds the streamlink arguments for either a remote playlist or a fixed playlist on disk
+pub fn streamlink_args(source: &str, output: &str) -> Vec<String> {
+    let source = if source.starts_with("http://") || source.starts_with("https://") {
+        source.to_string()
+    } else {
+        // streamlink needs an explicit scheme to read local playlists
+        let path = Path::new(source);
+        let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        format!("hls://file://{}", path.display())
+    };
+
+    vec![
+        source,
+        "best".to_string(),
+        "-o".to_string(),
+        output.to_string(),
+    ]
+}
+
+fn shell_quote(arg: &str) -> String {
+    if arg
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || "-_./:=?&%".contains(c))
+    {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+pub fn streamlink_command(source: &str, output: &str) -> String {
+    let args: Vec<String> = streamlink_args(source, output)
+        .iter()
+        .map(|a| shell_quote(a))
+        .collect();
+    format!("streamlink {}", args.join(" "))
+}
+
+// prints or runs the streamlink invocation depending on --streamlink, a failing run never fails tbf
+pub async fn emit_streamlink(source: &str, output: &str, flags: &ReportOptions) {
+    let Some(mode) = &flags.streamlink else {
+        return;
+    };
+
+    match mode {
+        StreamlinkMode::Print => {
+            let command = streamlink_command(source, output);
+            if flags.simple {
+                println!("{command}");
+            } else {
+                info!("Streamlink command: {command}");
+            }
+        }
+        StreamlinkMode::Run => {
+            if !flags.simple {
+                info!("Running streamlink, saving to {output}");
+            }
+            match Command::new("streamlink")
+                .args(streamlink_args(source, output))
+                .status()
+                .await
+            {
+                Ok(status) if status.success() => (),
+                Ok(status) => warn!("Streamlink exited with {status}"),
+                Err(e) => warn!("Couldn't run streamlink: {e}"),
+            }
+        }
+    }
+}
+
+// a stream copy into an mp4 with the index at the start, so players can seek before the whole
+// file is there. the AAC in TS has ADTS headers an mp4 can't keep
+pub fn remux_args(input: &str, output: &str) -> Vec<String> {
+    [
+        "-hide_banner",
+        "-loglevel",
+        "error",
+        "-y",
+        "-i",
+        input,
+        "-c",
+        "copy",
+        "-bsf:a",
+        "aac_adtstoasc",
+        "-movflags",
+        "+faststart",
+        output,
+    ]
+    .iter()
+    .map(|a| a.to_string())
+    .collect()
+}
+
+// unlike streamlink this was asked for explicitly, so a failing ffmpeg fails the download
+pub async fn remux(input: &str, output: &str, ffmpeg: &str) -> Result<()> {
+    let status = Command::new(ffmpeg)
+        .args(remux_args(input, output))
+        .status()
+        .await
+        .map_err(|e| Download::Remux(format!("couldn't run {ffmpeg}: {e}")))?;
+    if !status.success() {
+        return Err(Download::Remux(format!("{ffmpeg} exited with {status}")))?;
+    }
+    Ok(())
+}
+
+fn broadcast_date(timestamp: Option<i64>) -> Option<String> {
+    timestamp
+        .and_then(|t| OffsetDateTime::from_unix_timestamp(t).ok())
+        .map(|d| format!("{:04}-{:02}-{:02}", d.year(), u8::from(d.month()), d.day()))
+}
+
+// describes a recovery the way archive.org expects it in its bulk upload tools
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ArchiveMetadata {
+    pub identifier: String,
+    pub file: String,
+    pub title: String,
+    pub creator: String,
+    pub date: Option<String>,
+    pub description: String,
+    pub mediatype: String,
+    pub subject: Vec<String>,
+    pub broadcast_id: i64,
+    pub source: Vec<String>,
+    pub muted_ranges: Vec<MutedRange>,
+}
+
+impl ArchiveMetadata {
+    pub fn new(event: &RecoveryEvent, muted_ranges: Vec<MutedRange>) -> Self {
+        let date = event
+            .timestamp
+            .and_then(|t| OffsetDateTime::from_unix_timestamp(t).ok())
+            .map(|d| format!("{:04}-{:02}-{:02}", d.year(), u8::from(d.month()), d.day()));
+        let title = match &date {
+            Some(date) => format!(
+                "{} - {date} - Twitch VOD {}",
+                event.username, event.broadcast_id
+            ),
+            None => format!("{} - Twitch VOD {}", event.username, event.broadcast_id),
+        };
+
+        Self {
+            identifier: format!("twitch-{}-{}", event.username, event.broadcast_id),
+            file: format!("{}_{}.ts", event.username, event.broadcast_id),
+            title,
+            creator: event.username.clone(),
+            date,
+            description: format!(
+                "Twitch broadcast {} by {}, recovered from the Twitch CDN.",
+                event.broadcast_id, event.username
+            ),
+            mediatype: "movies".to_string(),
+            subject: vec!["twitch".to_string(), event.username.clone()],
+            broadcast_id: event.broadcast_id,
+            source: event.urls.iter().map(|u| u.url.clone()).collect(),
+            muted_ranges,
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        let mut header = vec![
+            "identifier".to_string(),
+            "file".to_string(),
+            "title".to_string(),
+            "creator".to_string(),
+            "date".to_string(),
+            "description".to_string(),
+            "mediatype".to_string(),
+            "external-identifier".to_string(),
+            "muted_ranges".to_string(),
+        ];
+        let mut row = vec![
+            self.identifier.clone(),
+            self.file.clone(),
+            self.title.clone(),
+            self.creator.clone(),
+            self.date.clone().unwrap_or_default(),
+            self.description.clone(),
+            self.mediatype.clone(),
+            format!("twitch:broadcast:{}", self.broadcast_id),
+            self.muted_ranges
+                .iter()
+                .map(|r| format!("{:.0}-{:.0}", r.start, r.end))
+                .collect::<Vec<String>>()
+                .join(";"),
+        ];
+        for (i, subject) in self.subject.iter().enumerate() {
+            header.push(format!("subject[{i}]"));
+            row.push(subject.clone());
+        }
+        for (i, source) in self.source.iter().enumerate() {
+            header.push(format!("source[{i}]"));
+            row.push(source.clone());
+        }
+
+        let escape = |field: &String| {
+            if field.contains([',', '"', '\n']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.clone()
+            }
+        };
+        format!(
+            "{}\n{}\n",
+            header.iter().map(escape).collect::<Vec<String>>().join(","),
+            row.iter().map(escape).collect::<Vec<String>>().join(",")
+        )
+    }
+}
+
+// writes <username>_<id>_meta.json and .csv next to where the media ends up
+pub async fn write_archive_metadata(
+    event: &RecoveryEvent,
+    flags: &ReportOptions,
+    http: &HttpContext,
+) -> Result<()> {
+    let Some(dir) = &flags.archive_metadata else {
+        return Ok(());
+    };
+
+    let metadata = ArchiveMetadata::new(event, muted_ranges(&event.urls[0].url, http).await);
+    let dir = Path::new(dir);
+    fs::create_dir_all(dir)?;
+    let stem = format!("{}_{}_meta", event.username, event.broadcast_id);
+    fs::write(
+        dir.join(format!("{stem}.json")),
+        serde_json::to_string_pretty(&metadata)?,
+    )?;
+    fs::write(dir.join(format!("{stem}.csv")), metadata.to_csv())?;
+
+    if !flags.simple {
+        info!("Archive metadata saved to: {}", dir.join(stem).display());
+    }
+    Ok(())
+}
+
+fn sanitize_filename(name: &str) -> String {
+    name.chars()
+        .filter(|c| !c.is_control())
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c => c,
+        })
+        .collect::<String>()
+        .trim()
+        .trim_end_matches('.')
+        .to_string()
+}
+
+// Streamer - YYYY-MM-DD - Title [id], the layout Plex and Jellyfin pick up without extra agents
+pub fn media_server_name(
+    streamer: &str,
+    date: Option<&str>,
+    title: Option<&str>,
+    broadcast_id: i64,
+) -> String {
+    let mut parts = vec![streamer.to_string()];
+    if let Some(date) = date {
+        parts.push(date.to_string());
+    }
+    parts.push(title.unwrap_or("Twitch VOD").to_string());
+    sanitize_filename(&format!("{} [{broadcast_id}]", parts.join(" - ")))
+}
+
+pub fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn nfo(streamer: &str, date: Option<&str>, title: &str, event: &RecoveryEvent) -> String {
+    let mut nfo =
+        String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<movie>\n");
+    nfo.push_str(&format!("  <title>{}</title>\n", xml_escape(title)));
+    nfo.push_str(&format!(
+        "  <plot>Twitch broadcast {} by {}.</plot>\n",
+        event.broadcast_id,
+        xml_escape(streamer)
+    ));
+    if let Some(date) = date {
+        nfo.push_str(&format!("  <premiered>{date}</premiered>\n"));
+        nfo.push_str(&format!("  <year>{}</year>\n", &date[..4]));
+    }
+    nfo.push_str(&format!(
+        "  <director>{}</director>\n",
+        xml_escape(streamer)
+    ));
+    nfo.push_str("  <studio>Twitch</studio>\n  <genre>Livestream</genre>\n");
+    nfo.push_str(&format!(
+        "  <uniqueid type=\"twitch\" default=\"true\">{}</uniqueid>\n</movie>\n",
+        event.broadcast_id
+    ));
+    nfo
+}
+
+// writes the .nfo for --media-server-naming and returns the file stem the media should use
+pub async fn media_server_export(
+    event: &RecoveryEvent,
+    flags: &ReportOptions,
+    http: &HttpContext,
+) -> Result<Option<String>> {
+    if !flags.media_server_naming {
+        return Ok(None);
+    }
+
+    let info = match vod_info(&event.username, event.broadcast_id, http).await {
+        Ok(info) => info,
+        Err(e) => {
+            debug!("Couldn't get the VOD info from GQL: {e}");
+            VodInfo::default()
+        }
+    };
+    let streamer = info.display_name.as_deref().unwrap_or(&event.username);
+    let date = broadcast_date(event.timestamp);
+    let name = media_server_name(
+        streamer,
+        date.as_deref(),
+        info.title.as_deref(),
+        event.broadcast_id,
+    );
+
+    let title = info.title.as_deref().unwrap_or("Twitch VOD");
+    fs::write(
+        format!("{name}.nfo"),
+        nfo(streamer, date.as_deref(), title, event),
+    )?;
+    if !flags.simple {
+        info!("NFO saved to: {name}.nfo");
+    }
+
+    Ok(Some(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{media_server_name, remux, remux_args, streamlink_command, ArchiveMetadata};
+    use crate::{
+        notify::RecoveryEvent,
+        twitch::models::{MutedRange, ReturnURL},
+    };
+
+    #[test]
+    fn streamlink() {
+        assert_eq!(
+            streamlink_command(
+                "https://d2nvs31859zcd8.cloudfront.net/abc_destiny_1_2/chunked/index-dvr.m3u8",
+                "destiny_1.ts"
+            ),
+            "streamlink https://d2nvs31859zcd8.cloudfront.net/abc_destiny_1_2/chunked/index-dvr.m3u8 best -o destiny_1.ts",
+            "testing remote playlist"
+        );
+        assert_eq!(
+            streamlink_command("/tmp/my vod.m3u8", "it's.ts"),
+            r"streamlink 'hls://file:///tmp/my vod.m3u8' best -o 'it'\''s.ts'",
+            "testing local playlist with quoting"
+        );
+    }
+
+    #[tokio::test]
+    async fn remuxing() {
+        let args = remux_args("vod.ts", "vod.mp4");
+        assert_eq!(
+            (
+                args.first().map(String::as_str),
+                args.last().map(String::as_str)
+            ),
+            (Some("-hide_banner"), Some("vod.mp4")),
+            "testing the output going last"
+        );
+        assert!(
+            args.windows(2).any(|w| w == ["-movflags", "+faststart"]),
+            "testing faststart"
+        );
+        assert!(
+            remux("vod.ts", "vod.mp4", "/nonexistent/ffmpeg")
+                .await
+                .is_err(),
+            "testing a missing ffmpeg"
+        );
+    }
+
+    #[test]
+    fn archive_metadata() {
+        let event = RecoveryEvent {
+            username: "destiny".to_string(),
+            broadcast_id: 39700667438,
+            timestamp: Some(1605781794),
+            urls: vec![ReturnURL {
+                url: "https://vod-secure.twitch.tv/a/chunked/index-dvr.m3u8".to_string(),
+                muted: true,
+                audio_only: false,
+            }],
+        };
+        let metadata = ArchiveMetadata::new(
+            &event,
+            vec![MutedRange {
+                start: 10.0,
+                end: 30.0,
+            }],
+        );
+
+        assert_eq!(
+            metadata.date,
+            Some("2020-11-19".to_string()),
+            "testing metadata date"
+        );
+        assert_eq!(
+            metadata.title, "destiny - 2020-11-19 - Twitch VOD 39700667438",
+            "testing metadata title"
+        );
+        assert_eq!(
+            metadata.to_csv(),
+            "identifier,file,title,creator,date,description,mediatype,external-identifier,muted_ranges,subject[0],subject[1],source[0]\n\
+            twitch-destiny-39700667438,destiny_39700667438.ts,destiny - 2020-11-19 - Twitch VOD 39700667438,destiny,2020-11-19,\"Twitch broadcast 39700667438 by destiny, recovered from the Twitch CDN.\",movies,twitch:broadcast:39700667438,10-30,twitch,destiny,https://vod-secure.twitch.tv/a/chunked/index-dvr.m3u8\n",
+            "testing metadata csv"
+        );
+    }
+
+    #[test]
+    fn media_server_naming() {
+        assert_eq!(
+            media_server_name(
+                "Destiny",
+                Some("2020-11-19"),
+                Some("stream title"),
+                39700667438
+            ),
+            "Destiny - 2020-11-19 - stream title [39700667438]",
+            "testing full name"
+        );
+        assert_eq!(
+            media_server_name("destiny", None, None, 39700667438),
+            "destiny - Twitch VOD [39700667438]",
+            "testing name without info"
+        );
+        assert_eq!(
+            media_server_name("Destiny", Some("2020-11-19"), Some("a/b: c?"), 1),
+            "Destiny - 2020-11-19 - a_b_ c_ [1]",
+            "testing title sanitizing"
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::Result;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::config::ReportOptions;
+use crate::export::xml_escape;
+use crate::notify::RecoveryEvent;
+use crate::util::data_dir;
+
+const HISTORY_FILE: &str = "recoveries.json";
+// the feed only needs the recent entries, older ones get dropped from the history
+const HISTORY_LIMIT: usize = 200;
+const FEED_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedEntry {
+    pub found_at: i64,
+    #[serde(flatten)]
+    pub event: RecoveryEvent,
+}
+
+pub fn load_history() -> Vec<FeedEntry> {
+    data_dir()
+        .and_then(|dir| fs::read_to_string(dir.join(HISTORY_FILE)).ok())
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn write_history(history: &[FeedEntry]) {
+    let Some(dir) = data_dir() else {
+        return;
+    };
+    match serde_json::to_string_pretty(history) {
+        Ok(s) => {
+            if let Err(e) = fs::write(dir.join(HISTORY_FILE), s) {
+                debug!("Couldn't save the recovery history - {e}");
+            }
+        }
+        Err(e) => debug!("Couldn't serialize the recovery history - {e}"),
+    }
+}
+
+fn rfc3339(timestamp: i64) -> String {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .ok()
+        .and_then(|d| d.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+pub fn render_atom(entries: &[FeedEntry]) -> String {
+    let updated = entries.iter().map(|e| e.found_at).max().unwrap_or(0);
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    feed.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    feed.push_str("  <title>tbf - recovered VODs</title>\n");
+    feed.push_str("  <id>urn:tbf:recoveries</id>\n");
+    feed.push_str(&format!("  <updated>{}</updated>\n", rfc3339(updated)));
+
+    // newest first
+    for entry in entries.iter().rev().take(FEED_LIMIT) {
+        let event = &entry.event;
+        let username = xml_escape(&event.username);
+        feed.push_str("  <entry>\n");
+        feed.push_str(&format!(
+            "    <title>{username} - VOD {}</title>\n",
+            event.broadcast_id
+        ));
+        feed.push_str(&format!(
+            "    <id>urn:tbf:vod:{}:{}</id>\n",
+            event.broadcast_id,
+            event.timestamp.unwrap_or_default()
+        ));
+        feed.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            rfc3339(entry.found_at)
+        ));
+        if let Some(timestamp) = event.timestamp {
+            feed.push_str(&format!(
+                "    <published>{}</published>\n",
+                rfc3339(timestamp)
+            ));
+        }
+        feed.push_str(&format!("    <author><name>{username}</name></author>\n"));
+        for url in &event.urls {
+            feed.push_str(&format!("    <link href=\"{}\"/>\n", xml_escape(&url.url)));
+        }
+        let content = event
+            .urls
+            .iter()
+            .map(|u| {
+                if u.muted {
+                    format!("{} (muted)", u.url)
+                } else {
+                    u.url.clone()
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        feed.push_str(&format!(
+            "    <content type=\"text\">{}</content>\n",
+            xml_escape(&content)
+        ));
+        feed.push_str("  </entry>\n");
+    }
+
+    feed.push_str("</feed>\n");
+    feed
+}
+
+// keeps the recovery in the history and refreshes the --feed file
+pub fn record_recovery(event: &RecoveryEvent, flags: &ReportOptions) -> Result<()> {
+    let mut history = load_history();
+    history.push(FeedEntry {
+        found_at: OffsetDateTime::now_utc().unix_timestamp(),
+        event: event.clone(),
+    });
+    if history.len() > HISTORY_LIMIT {
+        history.drain(..history.len() - HISTORY_LIMIT);
+    }
+    write_history(&history);
+
+    if let Some(path) = &flags.feed {
+        fs::write(path, render_atom(&history))?;
+        if !flags.simple {
+            info!("Feed updated: {path}");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_atom, FeedEntry};
+    use crate::{notify::RecoveryEvent, twitch::models::ReturnURL};
+
+    #[test]
+    fn atom_feed() {
+        let entries = vec![FeedEntry {
+            found_at: 1605781900,
+            event: RecoveryEvent {
+                username: "destiny".to_string(),
+                broadcast_id: 39700667438,
+                timestamp: Some(1605781794),
+                urls: vec![ReturnURL {
+                    url: "https://vod-secure.twitch.tv/a/chunked/index-dvr.m3u8?a=1&b=2"
+                        .to_string(),
+                    muted: true,
+                    audio_only: false,
+                }],
+            },
+        }];
+        let feed = render_atom(&entries);
+
+        assert!(
+            feed.contains("<updated>2020-11-19T10:31:40Z</updated>"),
+            "testing feed timestamps"
+        );
+        assert!(
+            feed.contains("<published>2020-11-19T10:29:54Z</published>"),
+            "testing entry stream start"
+        );
+        assert!(
+            feed.contains("<link href=\"https://vod-secure.twitch.tv/a/chunked/index-dvr.m3u8?a=1&amp;b=2\"/>"),
+            "testing url escaping"
+        );
+        assert!(feed.contains("(muted)</content>"), "testing muted marker");
+        assert_eq!(
+            render_atom(&[]).matches("<entry>").count(),
+            0,
+            "testing empty feed"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::{anyhow, Result};
+use clap::crate_name;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use time::OffsetDateTime;
+
+use crate::config::{Cli, HistoryOptions};
+use crate::twitch::models::VodRecovery;
+
+const HISTORY_FILE: &str = "history.db";
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct HistoryEntry {
+    // the mode that found it
+    pub mode: String,
+    pub username: String,
+    pub broadcast_id: i64,
+    pub timestamp: i64,
+    pub urls: Vec<String>,
+    // when it got found
+    pub recorded_at: i64,
+}
+
+// ~/.local/share/tbf/history.db on Linux, next to the rest of the state in util::data_dir;
+// unlike the caches it's not something that can be thrown away
+fn history_path() -> Option<PathBuf> {
+    Some(dirs::data_dir()?.join(crate_name!()).join(HISTORY_FILE))
+}
+
+// --history-db, otherwise history.db in the data folder
+pub fn history_location(matches: &Cli) -> Result<PathBuf> {
+    matches
+        .history_db
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(history_path)
+        .ok_or_else(|| anyhow!("couldn't find the data folder for the history, use --history-db"))
+}
+
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS results (
+                id INTEGER PRIMARY KEY,
+                mode TEXT NOT NULL,
+                username TEXT NOT NULL,
+                broadcast_id INTEGER NOT NULL,
+                timestamp INTEGER NOT NULL,
+                urls TEXT NOT NULL,
+                recorded_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS results_username ON results (username);
+            CREATE INDEX IF NOT EXISTS results_broadcast_id ON results (broadcast_id);",
+        )?;
+        Ok(Self { conn })
+    }
+
+    pub fn record(&self, mode: &str, recovery: &VodRecovery) -> Result<()> {
+        let urls: Vec<&str> = recovery.urls.iter().map(|u| u.url.as_str()).collect();
+        self.conn.execute(
+            "INSERT INTO results (mode, username, broadcast_id, timestamp, urls, recorded_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                mode,
+                recovery.username,
+                recovery.broadcast_id,
+                recovery.timestamp,
+                serde_json::to_string(&urls)?,
+                OffsetDateTime::now_utc().unix_timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    // the newest results first, a limit of 0 returns all of them
+    pub fn query(&self, options: &HistoryOptions) -> Result<Vec<HistoryEntry>> {
+        let mut statement = self.conn.prepare(
+            "SELECT mode, username, broadcast_id, timestamp, urls, recorded_at FROM results
+            WHERE (?1 IS NULL OR username = ?1 COLLATE NOCASE)
+                AND (?2 IS NULL OR broadcast_id = ?2)
+            ORDER BY recorded_at DESC, id DESC
+            LIMIT ?3",
+        )?;
+        let limit = match options.limit {
+            0 => -1,
+            limit => limit as i64,
+        };
+        let entries = statement
+            .query_map(
+                params![options.username, options.broadcast_id, limit],
+                |row| {
+                    let urls: String = row.get(4)?;
+                    Ok(HistoryEntry {
+                        mode: row.get(0)?,
+                        username: row.get(1)?,
+                        broadcast_id: row.get(2)?,
+                        timestamp: row.get(3)?,
+                        urls: serde_json::from_str(&urls).unwrap_or_default(),
+                        recorded_at: row.get(5)?,
+                    })
+                },
+            )?
+            .collect::<rusqlite::Result<Vec<HistoryEntry>>>()?;
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use crate::config::HistoryOptions;
+    use crate::twitch::models::{ReturnURL, VodRecovery};
+
+    use super::History;
+
+    #[test]
+    fn history() {
+        let dir = tempdir().unwrap();
+        let history = History::open(&dir.path().join("tbf").join("history.db")).unwrap();
+        let recovery = |username: &str, id: i64| VodRecovery {
+            username: username.to_string(),
+            broadcast_id: id,
+            timestamp: 1622854217,
+            urls: vec![ReturnURL {
+                url: format!(
+                    "https://d1m7jfoe9zdc1j.cloudfront.net/{username}_{id}/chunked/index-dvr.m3u8"
+                ),
+                muted: false,
+                audio_only: false,
+            }],
+            qualities: Vec::new(),
+            muted_ranges: Vec::new(),
+            elapsed_ms: 0,
+        };
+        history
+            .record("exact", &recovery("dansgaming", 42218705421))
+            .unwrap();
+        history
+            .record("link", &recovery("mrmouton", 39905263305))
+            .unwrap();
+        history
+            .record("bruteforce", &recovery("dansgaming", 42218705422))
+            .unwrap();
+
+        let all = history.query(&HistoryOptions::default()).unwrap();
+        assert_eq!(all.len(), 3, "testing every result");
+        assert_eq!(all[0].broadcast_id, 42218705422, "testing the newest first");
+        assert_eq!(
+            all[0].urls,
+            vec!["https://d1m7jfoe9zdc1j.cloudfront.net/dansgaming_42218705422/chunked/index-dvr.m3u8"],
+            "testing the URLs"
+        );
+
+        let streamer = history
+            .query(&HistoryOptions::default().username(Some("DansGaming".to_string())))
+            .unwrap();
+        assert_eq!(streamer.len(), 2, "testing a streamer's results");
+        let broadcast = history
+            .query(&HistoryOptions::default().broadcast_id(Some(39905263305)))
+            .unwrap();
+        assert_eq!(
+            broadcast
+                .iter()
+                .map(|e| e.mode.as_str())
+                .collect::<Vec<_>>(),
+            vec!["link"],
+            "testing a broadcast's results"
+        );
+        assert_eq!(
+            history
+                .query(&HistoryOptions::default().limit(1))
+                .unwrap()
+                .len(),
+            1,
+            "testing the limit"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use log::{debug, warn};
+use serde::Serialize;
+use serde_json::json;
+use std::{process::Stdio, str::FromStr};
+use strum::{Display, EnumString};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::config::ReportOptions;
+
+#[derive(Clone, Debug, PartialEq, Display, EnumString, Serialize)]
+#[strum(serialize_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum HookStage {
+    // the tracker page got parsed into a username, a broadcast ID and timestamps
+    Derived,
+    // a working playlist got found
+    Hit,
+    // a muted playlist got saved
+    Fixed,
+    // a VOD got downloaded
+    Downloaded,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Hook {
+    pub stage: HookStage,
+    pub command: String,
+}
+
+impl FromStr for Hook {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (stage, command) = s
+            .split_once('=')
+            .ok_or_else(|| "hooks have to look like <stage>=<command>".to_string())?;
+        let stage = HookStage::from_str(stage.trim()).map_err(|_| {
+            format!(
+                "unknown hook stage \"{stage}\" (derived, hit, fixed and downloaded are supported)"
+            )
+        })?;
+        if command.trim().is_empty() {
+            return Err("the hook command can't be empty".to_string());
+        }
+        Ok(Self {
+            stage,
+            command: command.to_string(),
+        })
+    }
+}
+
+// runs every hook configured for the stage with the JSON payload on stdin,
+// a failing hook gets logged but never stops the run
+pub async fn run_hooks<T: Serialize>(stage: HookStage, data: &T, flags: &ReportOptions) {
+    let hooks: Vec<&Hook> = flags.hooks.iter().filter(|h| h.stage == stage).collect();
+    if hooks.is_empty() {
+        return;
+    }
+
+    let payload = json!({ "stage": stage, "data": data }).to_string();
+    for hook in hooks {
+        if let Err(e) = run_hook(&hook.command, &payload).await {
+            warn!("The {stage} hook \"{}\" failed: {e}", hook.command);
+        }
+    }
+}
+
+async fn run_hook(command: &str, payload: &str) -> std::io::Result<()> {
+    let mut child = if cfg!(windows) {
+        Command::new("cmd")
+            .args(["/C", command])
+            .stdin(Stdio::piped())
+            .spawn()?
+    } else {
+        Command::new("sh")
+            .args(["-c", command])
+            .stdin(Stdio::piped())
+            .spawn()?
+    };
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // the hook is free to not read its stdin at all
+        if let Err(e) = stdin.write_all(payload.as_bytes()).await {
+            debug!("Couldn't write the payload to the hook: {e}");
+        }
+    }
+
+    let status = child.wait().await?;
+    if !status.success() {
+        return Err(std::io::Error::other(format!("exited with {status}")));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{Hook, HookStage};
+
+    #[test]
+    fn parse_hooks() {
+        assert_eq!(
+            Hook::from_str("hit=notify-send found").unwrap(),
+            Hook {
+                stage: HookStage::Hit,
+                command: "notify-send found".to_string()
+            },
+            "testing valid hook"
+        );
+        assert_eq!(
+            Hook::from_str("fixed=jq . > out.json").unwrap().stage,
+            HookStage::Fixed,
+            "testing hook command with an equals sign"
+        );
+        assert!(
+            Hook::from_str("hit").is_err(),
+            "testing hook without a command"
+        );
+        assert!(
+            Hook::from_str("found=echo").is_err(),
+            "testing hook with an unknown stage"
+        );
+        assert!(
+            Hook::from_str("hit= ").is_err(),
+            "testing empty hook command"
+        );
+    }
+}
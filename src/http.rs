@@ -0,0 +1,409 @@
+#[cfg(test)]
+mod fixtures;
+mod proxies;
+mod retry;
+
+use log::warn;
+use reqwest::{Proxy, RequestBuilder, Response, StatusCode, Url};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::Semaphore;
+
+use crate::config::{HttpSettings, HttpVersion, RetryPolicy};
+use crate::limiter::RequestPacer;
+use crate::tracker::find_provider;
+#[cfg(test)]
+pub use fixtures::Fixtures;
+pub use proxies::{read_proxy_list, ProxyPool};
+use retry::should_retry;
+
+// the tracker pages can go through a proxy of their own, the CDNs and Twitch through --proxy.
+// the URLs were checked while parsing the arguments
+fn proxy(settings: &HttpSettings) -> Option<Proxy> {
+    if settings.proxy.is_none() && settings.tracker_proxy.is_none() {
+        return None;
+    }
+    let proxy = settings.proxy.as_deref().and_then(|p| Url::parse(p).ok());
+    let tracker_proxy = settings
+        .tracker_proxy
+        .as_deref()
+        .and_then(|p| Url::parse(p).ok())
+        .or_else(|| proxy.clone());
+    Some(Proxy::custom(move |url| {
+        route_proxy(url, &proxy, &tracker_proxy)
+    }))
+}
+
+fn route_proxy(url: &Url, proxy: &Option<Url>, tracker_proxy: &Option<Url>) -> Option<Url> {
+    if url.host_str().and_then(find_provider).is_some() {
+        tracker_proxy.clone()
+    } else {
+        proxy.clone()
+    }
+}
+
+pub fn build_client(settings: &HttpSettings) -> reqwest::Client {
+    let builder = reqwest::Client::builder()
+        .pool_max_idle_per_host(settings.pool_max_idle)
+        .pool_idle_timeout(Duration::from_secs(settings.pool_idle_timeout))
+        .tcp_keepalive(Duration::from_secs(settings.tcp_keepalive))
+        .tcp_nodelay(true)
+        // hickory keeps its own in-process cache, so every CDN host only gets resolved once per TTL
+        .hickory_dns(!settings.system_dns);
+
+    let builder = match proxy(settings) {
+        Some(proxy) => builder.proxy(proxy),
+        None => builder,
+    };
+
+    let builder = match settings.http_version {
+        HttpVersion::Auto => builder,
+        HttpVersion::Http1 => builder.http1_only(),
+        HttpVersion::Http2 => builder.http2_prior_knowledge().http2_adaptive_window(true),
+    };
+
+    builder.build().expect("Couldn't build the HTTP client")
+}
+
+// the client and the limits that go with it, built once in main and passed down to everything sending requests
+#[derive(Clone, Debug)]
+pub struct HttpContext {
+    pub client: reqwest::Client,
+    settings: HttpSettings,
+    host_limits: Arc<Mutex<HashMap<String, Arc<Semaphore>>>>,
+    // caps the outstanding probes across everything sharing the context, set by the server
+    request_limit: Option<Arc<Semaphore>>,
+    proxy_pool: Option<Arc<ProxyPool>>,
+    pacer: Arc<RequestPacer>,
+    #[cfg(test)]
+    fixtures: Option<Arc<Fixtures>>,
+}
+
+impl Default for HttpContext {
+    fn default() -> Self {
+        Self::new(HttpSettings::default())
+    }
+}
+
+impl HttpContext {
+    pub fn new(settings: HttpSettings) -> Self {
+        Self::with_client(build_client(&settings), settings)
+    }
+
+    // lets callers bring their own client (proxies, timeouts, a mock server in the tests)
+    pub fn with_client(client: reqwest::Client, settings: HttpSettings) -> Self {
+        Self {
+            client,
+            host_limits: Arc::new(Mutex::new(HashMap::new())),
+            request_limit: None,
+            proxy_pool: (!settings.proxy_pool.is_empty())
+                .then(|| Arc::new(ProxyPool::new(&settings))),
+            pacer: Arc::new(RequestPacer::new(settings.max_rps, settings.backoff)),
+            settings,
+            #[cfg(test)]
+            fixtures: None,
+        }
+    }
+
+    // answers every request from the fixtures instead of going to the network
+    #[cfg(test)]
+    pub fn with_fixtures(mut self, fixtures: Arc<Fixtures>) -> Self {
+        self.fixtures = Some(fixtures);
+        self
+    }
+
+    pub fn with_request_limit(mut self, max_requests: usize) -> Self {
+        self.request_limit = Some(Arc::new(Semaphore::new(max_requests.max(1))));
+        self
+    }
+
+    // the stream cap only means something with HTTP/2, the per-host cap applies to everything
+    fn host_limit(&self, url: &str) -> Option<Arc<Semaphore>> {
+        let max_streams = self
+            .settings
+            .max_streams
+            .filter(|_| self.settings.http_version != HttpVersion::Http1);
+        let max_requests = match (max_streams, self.settings.max_per_host) {
+            (Some(streams), Some(per_host)) => streams.min(per_host),
+            (streams, per_host) => streams.or(per_host)?,
+        };
+        let host = Url::parse(url).ok()?.host_str()?.to_string();
+
+        let mut limits = self.host_limits.lock().unwrap();
+        Some(
+            limits
+                .entry(host)
+                .or_insert_with(|| Arc::new(Semaphore::new(max_requests.max(1))))
+                .clone(),
+        )
+    }
+
+    // everything sending requests goes through here, so the tests can swap the network for fixtures
+    pub async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        #[cfg(test)]
+        if let Some(fixtures) = &self.fixtures {
+            return Ok(fixtures.reply(&request.build()?));
+        }
+        request.send().await
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.settings.retry
+    }
+
+    // send, but a network error, a 429 or a 5xx gets the request sent again by the retry policy.
+    // for the requests that can be repeated (the GQL queries, the playlists), a request with a
+    // streamed body can't be copied and only goes out once
+    pub async fn send_with_retry(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let policy = self.retry_policy();
+        let mut attempt = 1;
+        loop {
+            let Some(copy) = request.try_clone().filter(|_| attempt < policy.attempts) else {
+                return self.send(request).await;
+            };
+            let resp = self.send(copy).await;
+            if !should_retry(&resp) {
+                return resp;
+            }
+            policy.wait(attempt).await;
+            attempt += 1;
+        }
+    }
+
+    // the probes get retried the same way, every attempt waits for its own permits (and picks
+    // its own proxy), so a retry doesn't hold up the other probes while it waits
+    pub async fn probe(&self, url: &str) -> reqwest::Result<Response> {
+        let policy = self.retry_policy();
+        let mut attempt = 1;
+        loop {
+            let resp = self.probe_once(url).await;
+            if attempt >= policy.attempts || !should_retry(&resp) {
+                return resp;
+            }
+            policy.wait(attempt).await;
+            attempt += 1;
+        }
+    }
+
+    // sends a GET request for the probes (through the next proxy of the pool, if there is one),
+    // keeping the amount of concurrent requests per host and the outstanding requests overall in
+    // check. the host permit comes first, so a probe waiting for a busy host doesn't hold up the
+    // probes to the other ones
+    pub async fn probe_once(&self, url: &str) -> reqwest::Result<Response> {
+        let _permit = match self.host_limit(url) {
+            Some(limit) => limit.acquire_owned().await.ok(),
+            None => None,
+        };
+        let _request_permit = match &self.request_limit {
+            Some(limit) => limit.acquire().await.ok(),
+            None => None,
+        };
+        self.pacer.wait().await;
+        let proxy = self
+            .proxy_pool
+            .as_ref()
+            .and_then(|pool| Some((pool, pool.pick()?)));
+        let resp = match proxy {
+            Some((pool, i)) => {
+                let resp = self.send(pool.client(i).get(url)).await;
+                pool.report(
+                    i,
+                    matches!(&resp, Ok(r) if r.status() != StatusCode::PROXY_AUTHENTICATION_REQUIRED),
+                );
+                resp
+            }
+            None => self.send(self.client.get(url)).await,
+        };
+        if let Ok(r) = &resp {
+            crate::metrics::record_request(r.content_length().unwrap_or_default());
+            let status = r.status();
+            if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                if let Some(pause) = self.pacer.throttled() {
+                    warn!(
+                        "Getting throttled, pausing the requests for {}s",
+                        pause.as_secs()
+                    );
+                }
+            }
+        }
+        resp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use reqwest::Url;
+
+    use crate::config::{HttpSettings, HttpVersion, RetryPolicy};
+
+    use super::{route_proxy, Fixtures, HttpContext, ProxyPool};
+
+    #[test]
+    fn proxies() {
+        let url = |u: &str| Url::parse(u).unwrap();
+        let proxy = Some(url("socks5h://127.0.0.1:1080"));
+        let tracker_proxy = Some(url("http://127.0.0.1:8080"));
+        assert_eq!(
+            route_proxy(
+                &url("https://twitchtracker.com/forsen/streams/1"),
+                &proxy,
+                &tracker_proxy
+            ),
+            tracker_proxy,
+            "testing a tracker page"
+        );
+        assert_eq!(
+            route_proxy(
+                &url("https://d1m7jfoe9zdc1j.cloudfront.net/a/chunked/1.ts"),
+                &proxy,
+                &tracker_proxy
+            ),
+            proxy,
+            "testing a CDN"
+        );
+    }
+
+    #[test]
+    fn proxy_pool() {
+        let settings = HttpSettings {
+            proxy_pool: vec![
+                "http://127.0.0.1:8080".to_string(),
+                "socks5://127.0.0.1:1080".to_string(),
+            ],
+            ..Default::default()
+        };
+        let pool = ProxyPool::new(&settings);
+        assert_eq!(
+            (pool.pick(), pool.pick(), pool.pick()),
+            (Some(0), Some(1), Some(0)),
+            "testing the round robin"
+        );
+
+        for _ in 0..5 {
+            pool.report(1, false);
+        }
+        assert_eq!(
+            (pool.pick(), pool.pick()),
+            (Some(0), Some(0)),
+            "testing a dropped proxy"
+        );
+        for _ in 0..4 {
+            pool.report(0, false);
+        }
+        pool.report(0, true);
+        pool.report(0, false);
+        assert_eq!(pool.pick(), Some(0), "testing the failures in a row");
+    }
+
+    #[test]
+    fn host_limits() {
+        let settings = HttpSettings {
+            http_version: HttpVersion::Http1,
+            max_streams: Some(100),
+            max_per_host: Some(20),
+            ..Default::default()
+        };
+        let http = HttpContext::new(settings.clone());
+        let first =
+            http.host_limit("https://d1m7jfoe9zdc1j.cloudfront.net/a/chunked/index-dvr.m3u8");
+        let again =
+            http.host_limit("https://d1m7jfoe9zdc1j.cloudfront.net/b/chunked/index-dvr.m3u8");
+        let other = http.host_limit("https://vod-secure.twitch.tv/a/chunked/index-dvr.m3u8");
+        assert_eq!(
+            first.as_ref().map(|l| l.available_permits()),
+            Some(20),
+            "testing the per-host cap over HTTP/1.1"
+        );
+        assert!(
+            Arc::ptr_eq(first.as_ref().unwrap(), again.as_ref().unwrap())
+                && !Arc::ptr_eq(first.as_ref().unwrap(), other.as_ref().unwrap()),
+            "testing a limit per host"
+        );
+
+        let http2 = HttpContext::new(HttpSettings {
+            http_version: HttpVersion::Http2,
+            max_per_host: Some(200),
+            ..settings
+        });
+        assert_eq!(
+            http2
+                .host_limit("https://vod-secure.twitch.tv/a/chunked/index-dvr.m3u8")
+                .map(|l| l.available_permits()),
+            Some(100),
+            "testing the lower of the two caps"
+        );
+        assert!(
+            HttpContext::default()
+                .host_limit("https://vod-secure.twitch.tv/")
+                .is_none(),
+            "testing no cap"
+        );
+    }
+
+    #[tokio::test]
+    async fn retries() {
+        let url = "https://gql.twitch.tv/gql";
+        let context = |attempts, fixtures: &Arc<Fixtures>| {
+            HttpContext::new(HttpSettings {
+                retry: RetryPolicy {
+                    attempts,
+                    delay: 0,
+                    ..Default::default()
+                },
+                backoff: false,
+                ..Default::default()
+            })
+            .with_fixtures(fixtures.clone())
+        };
+
+        let fixtures = Arc::new(
+            Fixtures::new()
+                .respond(url, 503, "")
+                .respond(url, 200, "{}"),
+        );
+        let http = context(5, &fixtures);
+        let resp = http
+            .send_with_retry(http.client.post(url).body("{}"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200, "testing a retried request");
+        assert_eq!(fixtures.requested().len(), 2, "testing the attempts");
+
+        let fixtures = Arc::new(Fixtures::new().respond(url, 503, ""));
+        let resp = context(3, &fixtures).probe(url).await.unwrap();
+        assert_eq!(resp.status(), 503, "testing the last attempt");
+        assert_eq!(
+            fixtures.requested().len(),
+            3,
+            "testing the attempts of a probe"
+        );
+
+        let fixtures = Arc::new(Fixtures::new());
+        context(3, &fixtures).probe(url).await.unwrap();
+        assert_eq!(
+            fixtures.requested().len(),
+            1,
+            "testing a 404 not getting retried"
+        );
+    }
+
+    #[test]
+    fn retry_backoff() {
+        let policy = RetryPolicy {
+            delay: 100,
+            factor: 2.0,
+            max_delay: 300,
+            ..Default::default()
+        };
+        assert_eq!(
+            [1, 2, 3].map(|attempt| policy.backoff(attempt).as_millis()),
+            [100, 200, 300],
+            "testing the delay growing up to the cap"
+        );
+    }
+}
@@ -0,0 +1,51 @@
+use reqwest::{Request, Response};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+// recorded responses for the offline tests, keyed by the full URL,
+// anything without a fixture gets an empty 404 like a missing VOD would
+#[derive(Debug, Default)]
+pub struct Fixtures {
+    // responding to the same URL more than once queues the responses, the last one sticks
+    responses: Mutex<HashMap<String, VecDeque<(u16, String)>>>,
+    requested: Mutex<Vec<String>>,
+}
+
+impl Fixtures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn respond(mut self, url: &str, status: u16, body: impl Into<String>) -> Self {
+        self.responses
+            .get_mut()
+            .unwrap()
+            .entry(url.to_string())
+            .or_default()
+            .push_back((status, body.into()));
+        self
+    }
+
+    // every URL that was asked for, in order
+    pub fn requested(&self) -> Vec<String> {
+        self.requested.lock().unwrap().clone()
+    }
+
+    pub(super) fn reply(&self, request: &Request) -> Response {
+        let url = request.url().to_string();
+        let (status, body) = match self.responses.lock().unwrap().get_mut(&url) {
+            Some(queue) if queue.len() > 1 => queue.pop_front().unwrap(),
+            Some(queue) => queue.front().cloned().unwrap(),
+            None => (404, String::new()),
+        };
+        self.requested.lock().unwrap().push(url);
+
+        ::http::Response::builder()
+            .status(status)
+            .body(body)
+            .expect("Couldn't build the fixture response")
+            .into()
+    }
+}
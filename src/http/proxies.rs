@@ -0,0 +1,109 @@
+use anyhow::{anyhow, Result};
+use log::warn;
+use rand::Rng;
+use std::{
+    fs,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use super::build_client;
+use crate::config::{parse_proxy, HttpSettings, ProxyRotation};
+
+// a proxy failing this many requests in a row gets dropped from the rotation
+const MAX_PROXY_FAILURES: usize = 5;
+
+#[derive(Debug)]
+struct PoolProxy {
+    url: String,
+    client: reqwest::Client,
+    failures: AtomicUsize,
+    dropped: AtomicBool,
+}
+
+// the probes of the scans get spread over these, every proxy has its own client (and so its
+// own connection pool) since reqwest only has one proxy per client
+#[derive(Debug)]
+pub struct ProxyPool {
+    proxies: Vec<PoolProxy>,
+    rotation: ProxyRotation,
+    next: AtomicUsize,
+}
+
+impl ProxyPool {
+    pub fn new(settings: &HttpSettings) -> Self {
+        let proxies = settings
+            .proxy_pool
+            .iter()
+            .map(|url| PoolProxy {
+                url: url.clone(),
+                client: build_client(&HttpSettings {
+                    proxy: Some(url.clone()),
+                    tracker_proxy: None,
+                    proxy_pool: Vec::new(),
+                    ..settings.clone()
+                }),
+                failures: AtomicUsize::new(0),
+                dropped: AtomicBool::new(false),
+            })
+            .collect();
+        Self {
+            proxies,
+            rotation: settings.proxy_rotation.clone(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    // the proxy the next request goes through, None once every one of them got dropped
+    pub fn pick(&self) -> Option<usize> {
+        let alive: Vec<usize> = (0..self.proxies.len())
+            .filter(|&i| !self.proxies[i].dropped.load(Ordering::Relaxed))
+            .collect();
+        if alive.is_empty() {
+            return None;
+        }
+        let n = match self.rotation {
+            ProxyRotation::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed) % alive.len(),
+            ProxyRotation::Random => rand::rng().random_range(0..alive.len()),
+        };
+        Some(alive[n])
+    }
+
+    pub fn client(&self, proxy: usize) -> &reqwest::Client {
+        &self.proxies[proxy].client
+    }
+
+    // only failures in a row count, a proxy that works now and then stays in
+    pub fn report(&self, proxy: usize, ok: bool) {
+        let proxy = &self.proxies[proxy];
+        if ok {
+            proxy.failures.store(0, Ordering::Relaxed);
+            return;
+        }
+        let failures = proxy.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= MAX_PROXY_FAILURES && !proxy.dropped.swap(true, Ordering::Relaxed) {
+            warn!(
+                "Dropping the proxy {} after {failures} failed requests in a row",
+                proxy.url
+            );
+            if self.pick().is_none() {
+                warn!("Every proxy got dropped, going on without them");
+            }
+        }
+    }
+}
+
+// a proxy URL per line, empty lines and the ones starting with # are skipped
+pub fn read_proxy_list(path: &str) -> Result<Vec<String>> {
+    let list = fs::read_to_string(path)?;
+    let proxies = list
+        .lines()
+        .map(str::trim)
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| parse_proxy(line).map_err(|e| anyhow!("{path}:{}: {e}", i + 1)))
+        .collect::<Result<Vec<String>>>()?;
+    if proxies.is_empty() {
+        return Err(anyhow!("{path} doesn't have any proxies"));
+    }
+    Ok(proxies)
+}
@@ -0,0 +1,32 @@
+use rand::Rng;
+use reqwest::{Response, StatusCode};
+use std::time::Duration;
+
+use crate::config::RetryPolicy;
+
+// network errors and the statuses that go away on their own; a 403 or a 404 is an answer
+pub fn should_retry(resp: &reqwest::Result<Response>) -> bool {
+    match resp {
+        Ok(r) => r.status() == StatusCode::TOO_MANY_REQUESTS || r.status().is_server_error(),
+        Err(_) => true,
+    }
+}
+
+impl RetryPolicy {
+    // the wait after the nth failed attempt, before the jitter
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let factor = self.factor.max(1.0).powi(attempt.saturating_sub(1) as i32);
+        Duration::from_millis(((self.delay as f64 * factor) as u64).min(self.max_delay))
+    }
+
+    pub async fn wait(&self, attempt: usize) {
+        let base = self.backoff(attempt);
+        let max_jitter = (base.as_millis() as f64 * self.jitter.clamp(0.0, 1.0)) as u64;
+        let jitter = if max_jitter > 0 {
+            rand::rng().random_range(0..=max_jitter)
+        } else {
+            0
+        };
+        tokio::time::sleep(base + Duration::from_millis(jitter)).await;
+    }
+}
@@ -0,0 +1,252 @@
+use once_cell::sync::OnceCell;
+use std::env;
+
+type Catalog = &'static [(&'static str, &'static str)];
+
+// the text of the interactive mode, {name} gets replaced by the value with that name. a key
+// missing from a translation falls back to English
+const EN: Catalog = &[
+    ("menu.select", "Select the application mode (or paste a tracker, clip or playlist link):"),
+    ("menu.no_match", "Nothing matches \"{query}\", press Enter to see every mode"),
+    ("menu.page", "Page {page}/{pages} (n/p to switch)"),
+    ("menu.hint_help", "?<number> for help on a mode"),
+    ("menu.hint_search", "type to search"),
+    ("menu.hint_clear", "Enter to clear the search"),
+    ("menu.recognized", "That looks like {kind}, going with {mode}"),
+    ("menu.no_mode", "Couldn't select the specified mode"),
+    ("prompt.username", "Please enter the streamer's username:"),
+    ("prompt.vod_id", "Please enter the VOD/broadcast ID:"),
+    ("prompt.timestamp", "Please enter the timestamp:"),
+    ("prompt.first_timestamp", "Please enter the first timestamp: [year]-[month]-[day] [hour]:[minute]:[second]"),
+    ("prompt.last_timestamp", "Please enter the last timestamp: [year]-[month]-[day] [hour]:[minute]:[second]"),
+    ("prompt.parse_input", "Please paste the link, slug, ID or timestamp:"),
+    ("prompt.tracker_url", "Please enter the TwitchTracker or StreamsCharts URL:"),
+    ("prompt.first_known_id", "Please enter the broadcast ID of the first known stream:"),
+    ("prompt.first_known_date", "Please enter the start of the first known stream:"),
+    ("prompt.second_known_id", "Please enter the broadcast ID of the second known stream:"),
+    ("prompt.second_known_date", "Please enter the start of the second known stream:"),
+    ("prompt.guess_date", "Please enter the start of the stream to guess the ID for:"),
+    ("prompt.clip", "Please enter the clip's URL (twitch.tv/%username%/clip/%slug% and clips.twitch.tv/%slug% are both supported) or the slug (\"GentleAthleticWombatHoneyBadger-ohJAsKzGinIgFUx2\" for example):"),
+    ("prompt.clip_start", "Please enter the starting timestamp (in seconds):"),
+    ("prompt.clip_end", "Please enter the end timestamp (in seconds):"),
+    ("prompt.id_file", "Please enter the path of the file with the broadcast IDs:"),
+    ("prompt.batch_file", "Please enter the path of the file with the jobs (tracker URLs or username,id,timestamp rows):"),
+    ("prompt.playlist_url", "Please enter Twitch VOD m3u8 playlist URL (only twitch.tv and cloudfront.net URLs are supported):"),
+    ("prompt.chat_vod_id", "Please enter the VOD ID:"),
+    ("prompt.video_id", "Please enter the VOD ID or URL (twitch.tv/videos/%id%):"),
+    ("prompt.chat_json", "Please enter the path of the chat JSON:"),
+    ("prompt.bench_requests", "Please enter the amount of requests to send:"),
+    ("prompt.vod_urls", "Please paste the VOD URLs (or the path of a file with them):"),
+    ("prompt.serve_address", "Please enter the address to listen on (default is 127.0.0.1:8080):"),
+    ("question.fix_playlist", "Do you want to download the fixed playlist?"),
+    ("question.install_update", "Install {tag} over the current binary?"),
+    ("status.fix_failed", "Failed to fix playlist: {error}"),
+    ("tui.search", "Search the modes or paste a tracker, clip or playlist link"),
+    ("tui.no_match", "Nothing matches \"{query}\""),
+    ("tui.recognized", "That looks like {kind}, Enter goes with {mode}"),
+    ("tui.menu_keys", "↑/↓ select, Enter open, Esc quit"),
+    ("tui.form_keys", "Tab/↑/↓ move, Space toggle, Enter run, Esc back"),
+    ("tui.running_keys", "Esc stop"),
+    ("tui.results_keys", "↑/↓ scroll, Enter/c copy, a copy all, Esc back"),
+    ("tui.starting", "starting"),
+    ("tui.stopping", "stopping"),
+    ("tui.hits", "Found so far: {count}"),
+    ("tui.found", "Found {count} URLs"),
+    ("tui.nothing_found", "Couldn't find anything"),
+    ("tui.failed", "Failed: {error}"),
+    ("tui.copied", "Copied to the clipboard"),
+    ("tui.press_enter", "Press Enter to go back to the menu"),
+];
+
+const RU: Catalog = &[
+    ("menu.select", "Выберите режим (или вставьте ссылку на трекер, клип или плейлист):"),
+    ("menu.no_match", "Ничего не найдено по запросу \"{query}\", нажмите Enter, чтобы увидеть все режимы"),
+    ("menu.page", "Страница {page}/{pages} (n/p для переключения)"),
+    ("menu.hint_help", "?<номер> для справки по режиму"),
+    ("menu.hint_search", "введите текст для поиска"),
+    ("menu.hint_clear", "Enter сбрасывает поиск"),
+    ("menu.recognized", "Похоже, это {kind}, запускаю «{mode}»"),
+    ("menu.no_mode", "Не удалось выбрать указанный режим"),
+    ("prompt.username", "Введите никнейм стримера:"),
+    ("prompt.vod_id", "Введите ID VOD/трансляции:"),
+    ("prompt.timestamp", "Введите время начала:"),
+    ("prompt.first_timestamp", "Введите первую отметку времени: [год]-[месяц]-[день] [час]:[минута]:[секунда]"),
+    ("prompt.last_timestamp", "Введите последнюю отметку времени: [год]-[месяц]-[день] [час]:[минута]:[секунда]"),
+    ("prompt.parse_input", "Вставьте ссылку, slug, ID или отметку времени:"),
+    ("prompt.tracker_url", "Введите ссылку на TwitchTracker или StreamsCharts:"),
+    ("prompt.first_known_id", "Введите ID первой известной трансляции:"),
+    ("prompt.first_known_date", "Введите время начала первой известной трансляции:"),
+    ("prompt.second_known_id", "Введите ID второй известной трансляции:"),
+    ("prompt.second_known_date", "Введите время начала второй известной трансляции:"),
+    ("prompt.guess_date", "Введите время начала трансляции, ID которой нужно угадать:"),
+    ("prompt.clip", "Введите ссылку на клип (поддерживаются twitch.tv/%username%/clip/%slug% и clips.twitch.tv/%slug%) или его slug (например, \"GentleAthleticWombatHoneyBadger-ohJAsKzGinIgFUx2\"):"),
+    ("prompt.clip_start", "Введите начальную отметку (в секундах):"),
+    ("prompt.clip_end", "Введите конечную отметку (в секундах):"),
+    ("prompt.id_file", "Введите путь к файлу с ID трансляций:"),
+    ("prompt.batch_file", "Введите путь к файлу с заданиями (ссылки на трекер или строки username,id,timestamp):"),
+    ("prompt.playlist_url", "Введите ссылку на m3u8-плейлист VOD (поддерживаются только ссылки twitch.tv и cloudfront.net):"),
+    ("prompt.chat_vod_id", "Введите ID VOD:"),
+    ("prompt.video_id", "Введите ID или ссылку на VOD (twitch.tv/videos/%id%):"),
+    ("prompt.chat_json", "Введите путь к JSON-файлу чата:"),
+    ("prompt.bench_requests", "Введите количество запросов:"),
+    ("prompt.vod_urls", "Вставьте ссылки на VOD (или путь к файлу с ними):"),
+    ("prompt.serve_address", "Введите адрес для сервера (по умолчанию 127.0.0.1:8080):"),
+    ("question.fix_playlist", "Скачать исправленный плейлист?"),
+    ("question.install_update", "Установить {tag} вместо текущей версии?"),
+    ("status.fix_failed", "Не удалось исправить плейлист: {error}"),
+    ("tui.search", "Поиск по режимам или ссылка на трекер, клип или плейлист"),
+    ("tui.no_match", "Ничего не найдено по запросу \"{query}\""),
+    ("tui.recognized", "Похоже, это {kind}, Enter запустит «{mode}»"),
+    ("tui.menu_keys", "↑/↓ выбор, Enter открыть, Esc выход"),
+    ("tui.form_keys", "Tab/↑/↓ перемещение, пробел переключить, Enter запустить, Esc назад"),
+    ("tui.running_keys", "Esc остановить"),
+    ("tui.results_keys", "↑/↓ прокрутка, Enter/c копировать, a копировать все, Esc назад"),
+    ("tui.starting", "запуск"),
+    ("tui.stopping", "остановка"),
+    ("tui.hits", "Найдено: {count}"),
+    ("tui.found", "Найдено ссылок: {count}"),
+    ("tui.nothing_found", "Ничего не найдено"),
+    ("tui.failed", "Ошибка: {error}"),
+    ("tui.copied", "Скопировано в буфер обмена"),
+    ("tui.press_enter", "Нажмите Enter, чтобы вернуться в меню"),
+    ("category.recovery", "Восстановление VOD"),
+    ("category.clips", "Клипы"),
+    ("category.playlists", "Плейлисты"),
+    ("category.chat", "Чат"),
+    ("category.tools", "Инструменты"),
+    ("mode.exact", "Точный режим"),
+    ("mode.bruteforce", "Перебор"),
+    ("mode.hash", "Отладка хеша"),
+    ("mode.parse", "Разбор ввода"),
+    ("mode.link", "Режим ссылки"),
+    ("mode.vod", "Поиск VOD"),
+    ("mode.analyze", "Анализ ссылки"),
+    ("mode.guess-id", "Угадать ID трансляции"),
+    ("mode.recover-channel", "Восстановление канала"),
+    ("mode.batch", "Пакетное восстановление"),
+    ("mode.history", "История восстановления"),
+    ("mode.live", "Текущий стрим"),
+    ("mode.clip", "Клип"),
+    ("mode.clipforce", "Перебор клипов"),
+    ("mode.clipforce-batch", "Перебор клипов нескольких VOD"),
+    ("mode.clips-of-vod", "Клипы трансляции"),
+    ("mode.fix", "Исправить плейлист"),
+    ("mode.download", "Скачать VOD"),
+    ("mode.expiry", "Срок хранения"),
+    ("mode.chat", "Скачать чат"),
+    ("mode.monitor", "Мониторинг плейлистов"),
+    ("mode.subtitles", "Субтитры из чата"),
+    ("mode.replay", "Воспроизведение чата"),
+    ("mode.bench", "Бенчмарк"),
+    ("mode.discover-cdns", "Поиск новых CDN"),
+    ("mode.serve", "Режим сервера"),
+    ("mode.update", "Проверить обновления"),
+];
+
+pub const LANGUAGES: &[(&str, Catalog)] = &[("en", EN), ("ru", RU)];
+
+static LANGUAGE: OnceCell<Catalog> = OnceCell::new();
+
+// "ru_RU.UTF-8" and "ru-RU" are both "ru"
+fn language_code(locale: &str) -> String {
+    locale
+        .split(['_', '-', '.', '@'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn find_catalog(locale: &str) -> Option<Catalog> {
+    let code = language_code(locale);
+    LANGUAGES
+        .iter()
+        .find(|(lang, _)| *lang == code)
+        .map(|(_, catalog)| *catalog)
+}
+
+// --lang, otherwise the system's language (the same variables gettext looks at), English if
+// there's no catalog for it
+pub fn set_language(lang: Option<&str>) {
+    let catalog = lang
+        .map(str::to_string)
+        .into_iter()
+        .chain(
+            ["LC_ALL", "LC_MESSAGES", "LANG"]
+                .iter()
+                .filter_map(|v| env::var(v).ok()),
+        )
+        .find(|l| !l.is_empty())
+        .and_then(|l| find_catalog(&l))
+        .unwrap_or(EN);
+    let _ = LANGUAGE.set(catalog);
+}
+
+fn lookup(catalog: Catalog, key: &str) -> Option<&'static str> {
+    catalog
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map(|(_, text)| *text)
+}
+
+// only the keys a translation has, for the names that are English in the code already
+pub fn try_t(key: &str) -> Option<&'static str> {
+    lookup(LANGUAGE.get().copied().unwrap_or(EN), key)
+}
+
+pub fn t(key: &str) -> String {
+    try_t(key)
+        .or_else(|| lookup(EN, key))
+        .unwrap_or(key)
+        .to_string()
+}
+
+pub fn t_with(key: &str, values: &[(&str, &str)]) -> String {
+    let mut text = t(key);
+    for (name, value) in values {
+        text = text.replace(&format!("{{{name}}}"), value);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_catalog, language_code, lookup, t_with, EN, LANGUAGES};
+
+    #[test]
+    fn catalogs() {
+        for (lang, catalog) in LANGUAGES {
+            for (key, text) in catalog.iter() {
+                // the names are English in the code, only the translations have them
+                if key.starts_with("mode.") || key.starts_with("category.") {
+                    continue;
+                }
+                let english =
+                    lookup(EN, key).unwrap_or_else(|| panic!("{lang} has an unknown key {key}"));
+                for placeholder in [
+                    "{query}", "{page}", "{pages}", "{kind}", "{mode}", "{tag}", "{error}",
+                    "{count}",
+                ] {
+                    assert_eq!(
+                        english.contains(placeholder),
+                        text.contains(placeholder),
+                        "testing {placeholder} in {lang}:{key}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn languages() {
+        assert_eq!(language_code("ru_RU.UTF-8"), "ru", "testing a POSIX locale");
+        assert_eq!(language_code("pt-BR"), "pt", "testing a BCP 47 tag");
+        assert!(find_catalog("RU").is_some(), "testing a known language");
+        assert!(find_catalog("C").is_none(), "testing the C locale");
+        assert_eq!(
+            t_with("menu.page", &[("page", "1"), ("pages", "2")]),
+            "Page 1/2 (n/p to switch)",
+            "testing the placeholders"
+        );
+        assert_eq!(t_with("nope", &[]), "nope", "testing a missing key");
+    }
+}
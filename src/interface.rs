@@ -1,17 +1,57 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use colored::Colorize;
-use log::error;
-use std::io::stdin;
-use strum::{EnumMessage, IntoEnumIterator};
+use log::{error, info, warn};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::json;
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, stdin, IsTerminal, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use time::OffsetDateTime;
 
-use crate::config::{Cli, Commands, ProcessingType};
+use crate::batch::{parse_jobs, run_batch};
+use crate::bench::bench;
+use crate::cdns::{discover_cdns, DEFAULT_CDN_FILE};
+use crate::channel::recover_channel;
+use crate::clipforce::{clipforce_batch, parse_broadcast_ids};
+use crate::config::{
+    BatchOptions, BruteforceOptions, ChannelOptions, ChatOptions, Cli, ClipBatchOptions,
+    ClipOptions, Commands, DownloadOptions, FixOptions, HistoryOptions, MonitorOptions,
+    OutputFormat, ProcessingType, ReportOptions, ScanOptions,
+};
+use crate::expiry::{check_available, estimate_expiry, stream_start, Urgency};
+use crate::history::{history_location, History};
+use crate::hooks::{run_hooks, HookStage};
+use crate::http::HttpContext;
+use crate::i18n::{set_language, t, t_with};
+use crate::matrix::availability_matrix;
+use crate::menu::{mode_help, mode_name, parse_menu_input, Menu, MenuInput};
+use crate::metrics;
+use crate::monitor::monitor;
+use crate::offline::{guess_broadcast_id, hash_breakdown, parse_timestamp, split_range};
+use crate::recognize::recognize;
+use crate::replay::{replay, ReplayOptions};
+use crate::server::{serve, ServerLimits};
+use crate::stats::{chat_stats, stats_summary, write_time_series};
+use crate::subtitles::{subtitle_path, write_subtitles, SubtitleFormat, SubtitleOptions};
+use crate::tracker::{analyze, StreamType};
+use crate::tui::tui_interface;
 use crate::twitch::{
-    clips::{clip_bruteforce, find_bid_from_clip},
-    models::ReturnURL,
-    vods::{bruteforcer, exact, fix, live},
+    chat::{chat_path, download_chat, ChatFilter},
+    clips::{
+        clip_bruteforce, clip_qualities, clips_of_broadcast, find_bid_from_clip,
+        stream_start_window,
+    },
+    irc::capture_live_chat,
+    lookup_video,
+    models::{ReturnURL, VodRecovery},
+    vods::{bruteforcer, download, exact, fix, from_tracker, live, watch_live},
 };
-use crate::update::update;
-use crate::util::derive_date_from_url;
+use crate::update::{background_update_check, update, update_notice};
+use crate::util::{self, confirm, derive_date_from_url};
 
 impl Commands {
     fn fill_out_values(&mut self) -> Result<()> {
@@ -23,12 +63,12 @@ impl Commands {
             } => {
                 let mut vod = String::new();
 
-                ask_for_value("Please enter the streamer's username:", username);
+                ask_for_value(&t("prompt.username"), username);
 
-                ask_for_value("Please enter the VOD/broadcast ID:", &mut vod);
+                ask_for_value(&t("prompt.vod_id"), &mut vod);
                 *id = vod.parse::<i64>()?;
 
-                ask_for_value("Please enter the timestamp:", stamp);
+                ask_for_value(&t("prompt.timestamp"), stamp);
 
                 Ok(())
             }
@@ -40,143 +80,1051 @@ impl Commands {
             } => {
                 let mut vod = String::new();
 
-                ask_for_value("Please enter the streamer's username:", username);
+                ask_for_value(&t("prompt.username"), username);
 
-                ask_for_value("Please enter the VOD/broadcast ID:", &mut vod);
+                ask_for_value(&t("prompt.vod_id"), &mut vod);
                 *id = vod.parse::<i64>()?;
 
-                ask_for_value("Please enter the first timestamp: [year]-[month]-[day] [hour]:[minute]:[second]", from);
-                ask_for_value("Please enter the last timestamp: [year]-[month]-[day] [hour]:[minute]:[second]", to);
+                ask_for_value(&t("prompt.first_timestamp"), from);
+                let mut last = String::new();
+                ask_for_value(&t("prompt.last_timestamp"), &mut last);
+                // left empty when the first one is a range already
+                *to = (!last.is_empty()).then_some(last);
 
                 Ok(())
             }
+            Self::Hash {
+                username,
+                id,
+                stamp,
+                ..
+            } => {
+                let mut vod = String::new();
+
+                ask_for_value(&t("prompt.username"), username);
+
+                ask_for_value(&t("prompt.vod_id"), &mut vod);
+                *id = vod.parse::<i64>()?;
+
+                ask_for_value(&t("prompt.timestamp"), stamp);
+
+                Ok(())
+            }
+            Self::Parse { input, .. } => {
+                ask_for_value(&t("prompt.parse_input"), input);
+                Ok(())
+            }
             Self::Link { url } => {
-                ask_for_value("Please enter the TwitchTracker or StreamsCharts URL:", url);
+                ask_for_value(&t("prompt.tracker_url"), url);
                 Ok(())
             }
-            Self::Live { username } => {
-                ask_for_value("Please enter the streamer's username:", username);
+            Self::Vod { id } => {
+                let mut input = String::new();
+
+                ask_for_value(&t("prompt.video_id"), &mut input);
+                *id = match recognize(&input).to_command(&input) {
+                    Some(Commands::Vod { id }) => id,
+                    _ => input.trim().parse::<i64>()?,
+                };
+
                 Ok(())
             }
-            Self::Clip { clip } => {
-                ask_for_value("Please enter the clip's URL (twitch.tv/%username%/clip/%slug% and clips.twitch.tv/%slug% are both supported) or the slug (\"GentleAthleticWombatHoneyBadger-ohJAsKzGinIgFUx2\" for example):", clip);
+            Self::Analyze { url, .. } => {
+                ask_for_value(&t("prompt.tracker_url"), url);
                 Ok(())
             }
-            Self::Clipforce { id, start, end } => {
+            Self::GuessId {
+                first_id,
+                first_date,
+                second_id,
+                second_date,
+                date,
+                ..
+            } => {
+                let mut first_id_string = String::new();
+                let mut second_id_string = String::new();
+
+                ask_for_value(&t("prompt.first_known_id"), &mut first_id_string);
+                *first_id = first_id_string.parse::<i64>()?;
+                ask_for_value(&t("prompt.first_known_date"), first_date);
+
+                ask_for_value(&t("prompt.second_known_id"), &mut second_id_string);
+                *second_id = second_id_string.parse::<i64>()?;
+                ask_for_value(&t("prompt.second_known_date"), second_date);
+
+                ask_for_value(&t("prompt.guess_date"), date);
+
+                Ok(())
+            }
+            Self::RecoverChannel { username, .. } => {
+                ask_for_value(&t("prompt.username"), username);
+                Ok(())
+            }
+            Self::Batch { file, .. } => {
+                ask_for_value(&t("prompt.batch_file"), file);
+                Ok(())
+            }
+            // everything found so far, narrowing it down is up to the command line
+            Self::History { .. } => Ok(()),
+            Self::Live { username, .. } => {
+                ask_for_value(&t("prompt.username"), username);
+                Ok(())
+            }
+            Self::Clip { clip, .. } => {
+                ask_for_value(&t("prompt.clip"), clip);
+                Ok(())
+            }
+            Self::Clipforce { id, start, end, .. } => {
                 let mut id_string = String::new();
                 let mut start_string = String::new();
                 let mut end_string = String::new();
 
-                ask_for_value("Please enter the VOD/broadcast ID:", &mut id_string);
+                ask_for_value(&t("prompt.vod_id"), &mut id_string);
                 *id = id_string.parse::<i64>()?;
 
-                ask_for_value(
-                    "Please enter the starting timestamp (in seconds):",
-                    &mut start_string,
-                );
+                ask_for_value(&t("prompt.clip_start"), &mut start_string);
                 *start = start_string.parse::<i64>()?;
 
-                ask_for_value(
-                    "Please enter the end timestamp (in seconds):",
-                    &mut end_string,
-                );
+                ask_for_value(&t("prompt.clip_end"), &mut end_string);
                 *end = end_string.parse::<i64>()?;
 
                 Ok(())
             }
+            Self::ClipforceBatch { file, .. } => {
+                ask_for_value(&t("prompt.id_file"), file);
+                Ok(())
+            }
+            Self::ClipsOfVod { username, id, .. } => {
+                let mut id_string = String::new();
+
+                ask_for_value(&t("prompt.username"), username);
+
+                ask_for_value(&t("prompt.vod_id"), &mut id_string);
+                *id = id_string.parse::<i64>()?;
+
+                Ok(())
+            }
             Self::Fix { url, .. } => {
-                ask_for_value("Please enter Twitch VOD m3u8 playlist URL (only twitch.tv and cloudfront.net URLs are supported):", url);
+                ask_for_value(&t("prompt.playlist_url"), url);
                 Ok(())
             }
-            Self::Update => Ok(()),
+            Self::Download { url, .. } => {
+                ask_for_value(&t("prompt.playlist_url"), url);
+                Ok(())
+            }
+            Self::Expiry { url, .. } => {
+                ask_for_value(&t("prompt.playlist_url"), url);
+                Ok(())
+            }
+            Self::Chat { id, .. } => {
+                let mut id_string = String::new();
+
+                ask_for_value(&t("prompt.chat_vod_id"), &mut id_string);
+                *id = id_string.parse::<i64>()?;
+
+                Ok(())
+            }
+            Self::Monitor { .. } => Ok(()),
+            Self::Subtitles { input, .. } => {
+                ask_for_value(&t("prompt.chat_json"), input);
+                Ok(())
+            }
+            Self::Replay { input, .. } => {
+                ask_for_value(&t("prompt.chat_json"), input);
+                Ok(())
+            }
+            Self::Bench { requests } => {
+                let mut requests_string = String::new();
+
+                ask_for_value(&t("prompt.bench_requests"), &mut requests_string);
+                *requests = requests_string.parse::<usize>()?;
+
+                Ok(())
+            }
+            Self::DiscoverCdns { inputs, .. } => {
+                let mut input = String::new();
+                ask_for_value(&t("prompt.vod_urls"), &mut input);
+                *inputs = vec![input];
+                Ok(())
+            }
+            Self::Serve {
+                address, max_jobs, ..
+            } => {
+                ask_for_value(&t("prompt.serve_address"), address);
+                if address.is_empty() {
+                    *address = "127.0.0.1:8080".to_string();
+                }
+                if *max_jobs == 0 {
+                    *max_jobs = 2;
+                }
+                Ok(())
+            }
+            Self::Update { .. } => Ok(()),
         }
     }
 
-    pub async fn execute(&self, matches: Cli) -> Result<Option<Vec<ReturnURL>>> {
+    pub async fn execute(
+        &self,
+        matches: Cli,
+        http: &HttpContext,
+    ) -> Result<Option<Vec<ReturnURL>>> {
         match self {
             Self::Exact {
                 username,
                 id,
                 stamp,
-            } => exact(username.as_str(), *id, stamp.as_str(), matches).await,
+            } => {
+                let recovery = exact(
+                    username.as_str(),
+                    *id,
+                    stamp.as_str(),
+                    &BruteforceOptions::from(&matches),
+                    http,
+                )
+                .await?;
+                Ok(report_recovery(self, username, recovery, None, &matches, http).await)
+            }
             Self::Bruteforce {
                 username,
                 id,
                 from,
                 to,
-            } => bruteforcer(username.as_str(), *id, from.as_str(), to.as_str(), matches).await,
+            } => {
+                let (from, to) = split_range(from, to.as_deref())?;
+                let recovery = bruteforcer(
+                    username.as_str(),
+                    *id,
+                    from,
+                    to,
+                    &BruteforceOptions::from(&matches),
+                    http,
+                )
+                .await?;
+                Ok(report_recovery(self, username, recovery, None, &matches, http).await)
+            }
+            Self::Hash {
+                username,
+                id,
+                stamp,
+                json,
+            } => {
+                let timestamp = parse_timestamp(stamp)?;
+                let breakdown = hash_breakdown(
+                    username,
+                    *id,
+                    timestamp,
+                    &util::compile_cdn_list(matches.cdnfile.clone(), http).await,
+                );
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&breakdown)?);
+                    return Ok(None);
+                }
+
+                util::info(format!("SHA1 input: {}", breakdown.input), matches.simple);
+                util::info(format!("SHA1: {}", breakdown.sha1), matches.simple);
+                util::info(
+                    format!("Hash (the first 20 characters): {}", breakdown.hash),
+                    matches.simple,
+                );
+                if !matches.simple {
+                    info!("Playlist URLs:");
+                }
+                for url in breakdown.urls {
+                    util::url(&url, matches.simple);
+                }
+                Ok(None)
+            }
+            Self::Parse { input, json } => {
+                let recognized = recognize(input);
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&recognized)?);
+                    return Ok(None);
+                }
+
+                util::info(
+                    format!("Recognized as: {}", recognized.kind.describe()),
+                    matches.simple,
+                );
+                if let Some(command) = &recognized.command {
+                    util::info(format!("Handled by: {command}"), matches.simple);
+                }
+                for (key, value) in &recognized.values {
+                    util::info(format!("{key}: {value}"), matches.simple);
+                }
+                Ok(None)
+            }
             Self::Link { url } => {
-                let (proc, data) = match derive_date_from_url(url, matches.clone()).await {
+                let (proc, data) = match derive_date_from_url(url, matches.clone(), http).await {
                     Ok(a) => a,
                     Err(e) => {
                         return Err(e)?;
                     }
                 };
+                run_hooks(
+                    HookStage::Derived,
+                    &json!({ "url": url, "processing_type": proc, "data": data }),
+                    &ReportOptions::from(&matches),
+                )
+                .await;
+
+                let processing_type = proc.clone();
+                let recovery =
+                    from_tracker(proc, &data, &BruteforceOptions::from(&matches), http).await?;
+                Ok(report_recovery(
+                    self,
+                    &data.username,
+                    recovery,
+                    Some(&processing_type),
+                    &matches,
+                    http,
+                )
+                .await)
+            }
+            Self::Vod { id } => {
+                let Some((username, broadcast_id, timestamp)) = lookup_video(*id, http).await?
+                else {
+                    return Err(anyhow!(
+                        "Twitch doesn't know the video {id}, if it got deleted try the link mode instead"
+                    ));
+                };
+                if !matches.simple {
+                    info!("The video {id} is the broadcast {broadcast_id} of {username}");
+                }
+
+                let recovery = exact(
+                    &username,
+                    broadcast_id,
+                    &timestamp.to_string(),
+                    &BruteforceOptions::from(&matches),
+                    http,
+                )
+                .await?;
+                Ok(report_recovery(self, &username, recovery, None, &matches, http).await)
+            }
+            Self::Analyze { url, json } => {
+                let (proc, data) = derive_date_from_url(url, matches.clone(), http).await?;
+                let analysis = analyze(proc, &data)?;
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&analysis)?);
+                    return Ok(None);
+                }
+
+                util::info(format!("Username: {}", analysis.username), matches.simple);
+                util::info(
+                    format!("Broadcast ID: {}", analysis.broadcast_id),
+                    matches.simple,
+                );
+                util::info(
+                    match analysis.processing_type {
+                        ProcessingType::Exact => "Processing type: exact".to_string(),
+                        ProcessingType::Bruteforce => {
+                            "Processing type: bruteforce (approximate timestamps)".to_string()
+                        }
+                    },
+                    matches.simple,
+                );
+                util::info(
+                    format!("Start: {}", utc_date(analysis.start)),
+                    matches.simple,
+                );
+                if let Some(end) = analysis.end {
+                    util::info(format!("End: {}", utc_date(end)), matches.simple);
+                }
+                if let Some(duration) = analysis.duration {
+                    util::info(
+                        format!(
+                            "Duration: {}h {:02}m {:02}s",
+                            duration / 3600,
+                            duration / 60 % 60,
+                            duration % 60
+                        ),
+                        matches.simple,
+                    );
+                }
+                match analysis.stream_type {
+                    StreamType::Live => (),
+                    StreamType::Rerun => util::info(
+                        "Stream type: rerun (there's no VOD of its own to recover)".to_string(),
+                        matches.simple,
+                    ),
+                    StreamType::Premiere => util::info(
+                        "Stream type: premiere (stored under the upload time, before the start)"
+                            .to_string(),
+                        matches.simple,
+                    ),
+                }
+                Ok(None)
+            }
+            Self::GuessId {
+                first_id,
+                first_date,
+                second_id,
+                second_date,
+                date,
+                tolerance,
+            } => {
+                let guess = guess_broadcast_id(
+                    (*first_id, parse_timestamp(first_date)?),
+                    (*second_id, parse_timestamp(second_date)?),
+                    parse_timestamp(date)?,
+                    *tolerance / 100.0,
+                )?;
+                util::info(
+                    format!("Estimated broadcast ID: {}", guess.estimate),
+                    matches.simple,
+                );
+                util::info(
+                    format!("Likely range: {} - {}", guess.low, guess.high),
+                    matches.simple,
+                );
+                if !matches.simple {
+                    info!(
+                        "That's about {} IDs per hour between the known streams",
+                        guess.per_hour.round()
+                    );
+                }
+                Ok(None)
+            }
+            Self::RecoverChannel {
+                username,
+                output,
+                tracker,
+                limit,
+                delay,
+                fix,
+            } => {
+                let options = ChannelOptions {
+                    output: output.clone().unwrap_or_else(|| username.clone()),
+                    tracker: tracker.clone(),
+                    limit: *limit,
+                    delay: *delay,
+                    fix: *fix,
+                };
+                let streams = recover_channel(username, &options, &matches, http).await?;
+                if !matches.simple {
+                    info!(
+                        "Found {} of {} streams, the report is in: {}",
+                        streams.iter().filter(|s| s.found).count(),
+                        streams.len(),
+                        Path::new(&options.output).join("report.json").display()
+                    );
+                }
+                Ok(None)
+            }
+            Self::Batch {
+                file,
+                output,
+                concurrency,
+            } => {
+                let jobs = parse_jobs(&read_list(file)?);
+                let options = BatchOptions {
+                    output: output.clone(),
+                    concurrency: *concurrency,
+                };
+                let results = run_batch(&jobs, &options, &matches, http).await?;
+                if !matches.simple {
+                    info!(
+                        "Found {} of {} jobs ({} failed), the report is in: {}",
+                        results.iter().filter(|r| r.found).count(),
+                        results.len(),
+                        results.iter().filter(|r| r.error.is_some()).count(),
+                        options.output
+                    );
+                }
+                Ok(None)
+            }
+            Self::History {
+                username,
+                id,
+                limit,
+                json,
+            } => {
+                let options = HistoryOptions::default()
+                    .username(username.clone())
+                    .broadcast_id(*id)
+                    .limit(*limit);
+                let entries = History::open(&history_location(&matches)?)?.query(&options)?;
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&entries)?);
+                    return Ok(None);
+                }
+
+                if entries.is_empty() && !matches.simple {
+                    info!("Nothing in the history yet, the results get saved with --history");
+                }
+                for entry in &entries {
+                    if !matches.simple {
+                        info!(
+                            "{} {} - started {}, found with {} on {}",
+                            entry.username.bright_green(),
+                            entry.broadcast_id,
+                            utc_date(entry.timestamp),
+                            entry.mode,
+                            utc_date(entry.recorded_at)
+                        );
+                    }
+                    for url in &entry.urls {
+                        util::url(url, matches.simple);
+                    }
+                }
+                Ok(None)
+            }
+            Self::Live {
+                username,
+                chat,
+                chat_output,
+                watch,
+                interval,
+                keep_watching,
+                output,
+            } => {
+                let options = BruteforceOptions::from(&matches);
+                let mut urls = None;
+                let mut last = None;
+                loop {
+                    let recovery = if *watch {
+                        if !matches.simple {
+                            info!("Waiting for {username} to go live");
+                        }
+                        let interval = Duration::from_secs((*interval).max(1));
+                        match watch_live(username, interval, last, &options, http).await? {
+                            Some(recovery) => Some(recovery),
+                            // cancelled
+                            None => break,
+                        }
+                    } else {
+                        live(username.as_str(), &options, http).await?
+                    };
+                    let stream = recovery.as_ref().map(|r| (r.broadcast_id, r.timestamp));
+                    if let (Some(path), Some(recovery)) = (output, &recovery) {
+                        append_urls(path, recovery)?;
+                    }
+                    urls = report_recovery(self, username, recovery, None, &matches, http).await;
+
+                    // waits until the stream is over, so the next check is already looking for
+                    // the next stream
+                    if let (true, Some((vod, start))) = (*chat, stream) {
+                        let options = ChatOptions::default()
+                            .retry_delay(matches.retry_delay)
+                            .cancel(matches.cancel.clone())
+                            .report(ReportOptions::from(&matches));
+                        let options = match chat_output {
+                            Some(path) => options.output(path),
+                            None => options,
+                        };
+                        let log = capture_live_chat(username, vod, start, &options, http).await?;
+                        if !matches.simple {
+                            info!(
+                                "Saved {} messages to: {}",
+                                log.comments.len(),
+                                chat_path(vod, chat_output)
+                            );
+                        }
+                    }
 
-                match proc {
-                    ProcessingType::Exact => {
-                        exact(
-                            data.username.as_str(),
-                            match data.broadcast_id.parse::<i64>() {
-                                Ok(b) => b,
-                                Err(e) => {
-                                    return Err(e)?;
+                    last = stream.map(|(vod, _)| vod);
+                    if !(*watch && *keep_watching) {
+                        break;
+                    }
+                }
+                Ok(urls)
+            }
+            Self::Clip { clip, direct } => {
+                if *direct {
+                    let qualities = clip_qualities(clip.clone(), http).await?;
+                    if matches.output_format == OutputFormat::Json {
+                        print_json(&json!({ "mode": "clip", "direct": qualities }));
+                    } else {
+                        if !matches.simple {
+                            info!("The clip itself in {} qualities:", qualities.len());
+                        }
+                        for quality in &qualities {
+                            match matches.simple {
+                                true => util::url(&quality.url, true),
+                                false => {
+                                    info!("{} - {}", quality.quality.green(), quality.url)
                                 }
-                            },
-                            data.start_date.as_str(),
-                            matches.clone(),
-                        )
-                        .await
+                            }
+                        }
                     }
-                    ProcessingType::Bruteforce => {
-                        let end_date = match data.end_date {
-                            Some(d) => d,
-                            None => {
-                                error!("Couldn't get the end date for the bruteforce method");
-                                return Ok(None);
+                }
+                match find_bid_from_clip(clip.clone(), &ReportOptions::from(&matches), http).await {
+                    Ok(r) => match r {
+                        Some((username, vod)) => {
+                            let url = format!("https://twitchtracker.com/{username}/streams/{vod}");
+                            let (_, data) =
+                                match derive_date_from_url(&url, matches.clone(), http).await {
+                                    Ok(a) => a,
+                                    Err(e) => Err(e)?,
+                                };
+
+                            let recovery = exact(
+                                &username,
+                                vod,
+                                &data.start_date,
+                                &BruteforceOptions::from(&matches),
+                                http,
+                            )
+                            .await?;
+                            Ok(
+                                report_recovery(self, &username, recovery, None, &matches, http)
+                                    .await,
+                            )
+                        }
+                        None => {
+                            if matches.output_format == OutputFormat::Json {
+                                print_json(&json!({ "mode": "clip", "found": false }));
                             }
-                        };
+                            Ok(None)
+                        }
+                    },
+                    Err(e) => Err(e)?,
+                }
+            }
+            Self::Clipforce {
+                id,
+                start,
+                end,
+                state,
+                resume,
+            } => {
+                let state = state
+                    .clone()
+                    .unwrap_or_else(|| format!("clipforce_{id}.json"));
+                let options = ClipOptions::from(&matches)
+                    .state(Some(PathBuf::from(state)))
+                    .resume(*resume);
+                let clips = clip_bruteforce(*id, *start, *end, &options, http).await?;
+                if matches.output_format == OutputFormat::Json {
+                    let found = clips.clone().unwrap_or_default();
+                    print_json(&json!({
+                        "mode": "clipforce",
+                        "found": !found.is_empty(),
+                        "broadcast_id": id,
+                        "start": start,
+                        "end": end,
+                        "clips": found,
+                    }));
+                } else if matches!(&clips, Some(c) if c.is_empty()) && !matches.simple {
+                    info!("{}", "Couldn't find anything :(".red());
+                }
+                Ok(clips)
+            }
+            Self::ClipsOfVod {
+                username,
+                id,
+                recover,
+            } => {
+                let clips = clips_of_broadcast(username, *id, http).await?;
+                let window = stream_start_window(&clips);
+                if matches.output_format == OutputFormat::Json && !*recover {
+                    print_json(&json!({
+                        "mode": "clips-of-vod",
+                        "broadcast_id": id,
+                        "clips": clips,
+                        "start": window.map(|(from, to)| json!({ "from": from, "to": to })),
+                    }));
+                    return Ok(None);
+                }
+                if matches.output_format != OutputFormat::Json {
+                    if !matches.simple {
+                        info!("Found {} clips of {id}", clips.len());
+                    }
+                    for clip in &clips {
+                        let offset = clip
+                            .offset
+                            .map(|o| o.to_string())
+                            .unwrap_or_else(|| "?".to_string());
+                        util::info(format!("{offset}s - {}", clip.slug), matches.simple);
+                    }
+                }
+
+                let Some((from, to)) = window else {
+                    if !matches.simple {
+                        info!("None of the clips has an offset in the stream, can't tell when it started");
+                    }
+                    return Ok(None);
+                };
+                if !matches.simple {
+                    match from == to {
+                        true => info!("The stream started at {}", utc_date(from)),
+                        false => info!(
+                            "The stream started between {} and {}",
+                            utc_date(from),
+                            utc_date(to)
+                        ),
+                    }
+                }
+                if !*recover {
+                    return Ok(None);
+                }
+
+                let options = BruteforceOptions::from(&matches);
+                let recovery = match from == to {
+                    true => exact(username, *id, &from.to_string(), &options, http).await?,
+                    false => {
                         bruteforcer(
-                            data.username.as_str(),
-                            match data.broadcast_id.parse::<i64>() {
-                                Ok(b) => b,
-                                Err(e) => return Err(e)?,
-                            },
-                            data.start_date.as_str(),
-                            end_date.as_str(),
-                            matches.clone(),
+                            username,
+                            *id,
+                            &from.to_string(),
+                            &to.to_string(),
+                            &options,
+                            http,
                         )
-                        .await
+                        .await?
                     }
+                };
+                Ok(report_recovery(self, username, recovery, None, &matches, http).await)
+            }
+            Self::ClipforceBatch {
+                file,
+                start,
+                end,
+                output,
+            } => {
+                let ids = parse_broadcast_ids(&fs::read_to_string(file)?);
+                let options = ClipBatchOptions {
+                    output: output.clone(),
+                    start: *start,
+                    end: *end,
+                    clip: ClipOptions::from(&matches),
+                };
+                let report = clipforce_batch(&ids, &options, http).await?;
+                if !matches.simple {
+                    info!(
+                        "Found {} clips in {} of {} VODs, the report is in: {}",
+                        report.vods.iter().map(|v| v.clips.len()).sum::<usize>(),
+                        report.vods.iter().filter(|v| !v.clips.is_empty()).count(),
+                        report.vods.len(),
+                        options.output
+                    );
                 }
+                Ok(None)
             }
-            Self::Live { username } => live(username.as_str(), matches).await,
-            Self::Clip { clip } => match find_bid_from_clip(clip.clone(), matches.clone()).await {
-                Ok(r) => match r {
-                    Some((username, vod)) => {
-                        let url = format!("https://twitchtracker.com/{username}/streams/{vod}");
-                        let (_, data) = match derive_date_from_url(&url, matches.clone()).await {
-                            Ok(a) => a,
-                            Err(e) => Err(e)?,
-                        };
-
-                        exact(&username, vod, &data.start_date, matches).await
-                    }
-                    None => Ok(None),
-                },
-                Err(e) => Err(e)?,
-            },
-            Self::Clipforce { id, start, end } => clip_bruteforce(*id, *start, *end, matches).await,
             Self::Fix { url, output, slow } => {
-                if let Err(e) = fix(url.as_str(), output.clone(), *slow, matches).await {
-                    error!("Failed to fix playlist: {e}");
+                let options = FixOptions {
+                    output: output.clone(),
+                    old_method: *slow,
+                    scan: ScanOptions::from(&matches),
+                    report: ReportOptions::from(&matches),
+                };
+                let fixed = fix(url.as_str(), &options, http).await?;
+                if !matches.simple {
+                    info!(
+                        "Playlist fixed and saved to: {} ({}/{} segments muted)",
+                        fixed.path, fixed.muted, fixed.segments
+                    );
                 }
                 // this might not be the right way to this
                 // but i want to combine everything into one method
                 Ok(None)
             }
-            Self::Update => {
-                match update(matches).await {
+            Self::Download {
+                url,
+                output,
+                remux,
+                ffmpeg,
+            } => {
+                let options = DownloadOptions {
+                    output: output.clone(),
+                    remux: remux.then(|| ffmpeg.clone()),
+                    scan: ScanOptions::from(&matches),
+                    report: ReportOptions::from(&matches),
+                };
+                let downloaded = download(url.as_str(), &options, http).await?;
+                if !matches.simple {
+                    info!(
+                        "VOD downloaded to: {} ({} segments, {} of them muted, {:.1} MB)",
+                        downloaded.path,
+                        downloaded.segments,
+                        downloaded.muted,
+                        downloaded.bytes as f64 / 1_000_000.0
+                    );
+                }
+                if let (false, Some(remuxed)) = (matches.simple, &downloaded.remuxed) {
+                    info!("Remuxed into: {remuxed}");
+                }
+                Ok(None)
+            }
+            Self::Expiry {
+                url,
+                retention,
+                no_check,
+                json,
+            } => {
+                let start = stream_start(url)?;
+                let available = if *no_check {
+                    None
+                } else {
+                    check_available(url, http).await
+                };
+                let estimate = estimate_expiry(
+                    start,
+                    OffsetDateTime::now_utc().unix_timestamp(),
+                    *retention,
+                    available,
+                );
+                if *json {
+                    println!("{}", serde_json::to_string_pretty(&estimate)?);
+                    return Ok(None);
+                }
+
+                util::info(
+                    format!("Stream start: {}", utc_date(estimate.stream_start)),
+                    matches.simple,
+                );
+                util::info(
+                    format!("Likely expiry: {}", utc_date(estimate.expires_at)),
+                    matches.simple,
+                );
+                let days = estimate.remaining as f64 / 86400.0;
+                let verdict = match estimate.urgency {
+                    Urgency::Gone => "It's not available anymore".red().to_string(),
+                    Urgency::Overdue => format!(
+                        "It's {:.1} days past the usual retention, download it right away",
+                        -days
+                    )
+                    .red()
+                    .to_string(),
+                    Urgency::Urgent => format!("{days:.1} days left, download it right away")
+                        .red()
+                        .to_string(),
+                    Urgency::Soon => format!("{days:.1} days left, download it soon")
+                        .yellow()
+                        .to_string(),
+                    Urgency::Fine => format!("{days:.1} days left").green().to_string(),
+                };
+                util::info(verdict, matches.simple);
+                if !matches.simple {
+                    info!(
+                        "This is only an estimate - Twitch can remove a VOD's files at any point"
+                    );
+                }
+                Ok(None)
+            }
+            Self::Chat {
+                id,
+                output,
+                format,
+                no_emotes,
+                users,
+                contains,
+                regex,
+                start,
+                end,
+                stats,
+                stats_output,
+                delay,
+                channel,
+                from,
+                to,
+            } => {
+                let window = match from {
+                    Some(from) => {
+                        let from = parse_timestamp(from)?;
+                        let to = match to {
+                            Some(to) => parse_timestamp(to)?,
+                            // the longest a stream can be before Twitch cuts it
+                            None => from + 48 * 60 * 60,
+                        };
+                        Some((from, to))
+                    }
+                    None => None,
+                };
+                let options = ChatOptions {
+                    output: output.clone(),
+                    format: *format,
+                    emotes: !*no_emotes,
+                    filter: ChatFilter {
+                        users: users.clone(),
+                        contains: contains.clone(),
+                        regex: match regex {
+                            Some(regex) => Some(Regex::new(regex)?),
+                            None => None,
+                        },
+                        start: *start,
+                        end: *end,
+                    },
+                    page_delay: *delay,
+                    retry_delay: matches.retry_delay,
+                    log_instances: matches.log_instances.clone(),
+                    channel: channel.clone(),
+                    window,
+                    cancel: matches.cancel.clone(),
+                    report: ReportOptions::from(&matches),
+                };
+                let log = download_chat(*id, &options, http).await?;
+                if !matches.simple {
+                    info!(
+                        "Saved {} messages to: {}",
+                        log.comments.len(),
+                        chat_path(*id, output)
+                    );
+                }
+                if *stats || stats_output.is_some() {
+                    let chat_stats = chat_stats(&log);
+                    if *stats {
+                        for line in stats_summary(&chat_stats) {
+                            util::info(line, matches.simple);
+                        }
+                    }
+                    if let Some(path) = stats_output {
+                        write_time_series(path, &chat_stats)?;
+                        if !matches.simple {
+                            info!("Saved the messages per minute to: {path}");
+                        }
+                    }
+                }
+                Ok(None)
+            }
+            Self::Monitor {
+                file,
+                interval,
+                webhook,
+                once,
+            } => {
+                let options = MonitorOptions {
+                    interval: *interval,
+                    webhook: webhook.clone(),
+                    once: *once,
+                    cancel: matches.cancel.clone(),
+                    report: ReportOptions::from(&matches),
+                };
+                monitor(file, &options, http).await?;
+                Ok(None)
+            }
+            Self::Subtitles {
+                input,
+                output,
+                format,
+                max_lines,
+                duration,
+                group,
+                offset,
+                vod_start,
+            } => {
+                let format = match (format, output) {
+                    (Some(format), _) => *format,
+                    (None, Some(output)) => SubtitleFormat::from_path(output),
+                    (None, None) => SubtitleFormat::default(),
+                };
+                let path = subtitle_path(input, output, format);
+                let options = SubtitleOptions {
+                    format,
+                    max_lines: *max_lines,
+                    duration: *duration,
+                    group: *group,
+                    offset: *offset,
+                    vod_start: match vod_start {
+                        Some(stamp) => Some(parse_timestamp(stamp)?),
+                        None => None,
+                    },
+                };
+                let messages = write_subtitles(input, &path, &options)?;
+                if !matches.simple {
+                    info!("Wrote {messages} messages as subtitles to: {path}");
+                }
+                Ok(None)
+            }
+            Self::Replay {
+                input,
+                speed,
+                start,
+                wait,
+            } => {
+                let options = ReplayOptions {
+                    speed: *speed,
+                    start: *start,
+                    // nothing to wait for in a script
+                    wait: *wait && !matches.yes,
+                    simple: matches.simple,
+                };
+                let messages = replay(input, &options).await?;
+                if !matches.simple {
+                    info!("Replayed {messages} messages");
+                }
+                Ok(None)
+            }
+            Self::Bench { requests } => {
+                bench(*requests, matches, http).await?;
+                Ok(None)
+            }
+            Self::DiscoverCdns { inputs, dry_run } => {
+                // a path of a file to read, otherwise the URLs themselves
+                let mut text = String::new();
+                for input in inputs {
+                    if input == "-" || Path::new(input).is_file() {
+                        text.push_str(&read_list(input)?);
+                    } else {
+                        text.push_str(input);
+                    }
+                    text.push('\n');
+                }
+                let cdnfile = matches
+                    .cdnfile
+                    .clone()
+                    .unwrap_or_else(|| DEFAULT_CDN_FILE.to_string());
+                let discovery = discover_cdns(&text, Path::new(&cdnfile), *dry_run)?;
+                if discovery.found.is_empty() {
+                    return Err(anyhow!("Couldn't find any VOD URLs in the input"));
+                }
+                if discovery.added.is_empty() {
+                    util::info(
+                        format!(
+                            "All {} CDNs in there are known already",
+                            discovery.found.len()
+                        ),
+                        matches.simple,
+                    );
+                    return Ok(None);
+                }
+                for host in &discovery.added {
+                    util::url(host, matches.simple);
+                }
+                if *dry_run {
+                    util::info(
+                        format!("{} of the CDNs aren't known yet", discovery.added.len()),
+                        matches.simple,
+                    );
+                } else {
+                    util::info(
+                        format!("Added {} new CDNs to {cdnfile}", discovery.added.len()),
+                        matches.simple,
+                    );
+                    if matches.cdnfile.is_none() && !matches.simple {
+                        info!("Pass --cdnfile {cdnfile} to have the scans go over them too");
+                    }
+                }
+                Ok(None)
+            }
+            Self::Serve {
+                address,
+                max_jobs,
+                job_threads,
+                max_requests,
+                rate_limit,
+                api_keys,
+            } => {
+                serve(
+                    address,
+                    ServerLimits {
+                        max_jobs: *max_jobs,
+                        job_threads: *job_threads,
+                        max_requests: *max_requests,
+                        rate_limit: *rate_limit,
+                        api_keys: api_keys.clone(),
+                    },
+                    matches,
+                    http,
+                )
+                .await?;
+                Ok(None)
+            }
+            Self::Update {
+                install,
+                public_key,
+            } => {
+                match update(matches, *install, public_key.as_deref(), http).await {
                     Ok(_) => (),
                     Err(e) => return Err(e)?,
                 }
@@ -186,6 +1134,225 @@ impl Commands {
     }
 }
 
+// the core functions only return what they found, printing it is up to the interface
+fn utc_date(timestamp: i64) -> String {
+    match OffsetDateTime::from_unix_timestamp(timestamp) {
+        Ok(d) => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC ({timestamp})",
+            d.year(),
+            u8::from(d.month()),
+            d.day(),
+            d.hour(),
+            d.minute(),
+            d.second()
+        ),
+        Err(_) => timestamp.to_string(),
+    }
+}
+
+// the document --output-format json prints for the recovery modes instead of the log lines
+#[derive(Serialize)]
+struct RecoveryOutput<'a> {
+    mode: String,
+    found: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    processing_type: Option<&'a ProcessingType>,
+    #[serde(flatten)]
+    recovery: Option<&'a VodRecovery>,
+}
+
+fn print_json(value: &impl Serialize) {
+    match serde_json::to_string_pretty(value) {
+        Ok(s) => println!("{s}"),
+        Err(e) => error!("Couldn't write the JSON output: {e}"),
+    }
+}
+
+// a file, or stdin for -
+fn read_list(path: &str) -> Result<String> {
+    match path {
+        "-" => Ok(io::read_to_string(stdin())?),
+        _ => Ok(fs::read_to_string(path)?),
+    }
+}
+
+// link and clip read their inputs from stdin with -, for piping URLs or slugs from other tools
+fn reads_stdin(command: &Commands) -> bool {
+    match command {
+        Commands::Link { url } => url == "-",
+        Commands::Clip { clip, .. } => clip == "-",
+        _ => false,
+    }
+}
+
+// runs the command once per line of stdin, a failed line doesn't stop the rest
+async fn run_stdin(sub: &Commands, matches: &Cli, http: &HttpContext) -> Result<()> {
+    let (mut total, mut failed) = (0, 0);
+    for line in stdin().lines() {
+        let line = line?;
+        let input = line.trim();
+        if input.is_empty() || input.starts_with('#') {
+            continue;
+        }
+        let command = match sub {
+            Commands::Link { .. } => Commands::Link {
+                url: input.to_string(),
+            },
+            Commands::Clip { direct, .. } => Commands::Clip {
+                clip: input.to_string(),
+                direct: *direct,
+            },
+            _ => sub.clone(),
+        };
+        total += 1;
+        if let Err(e) = command.execute(matches.clone(), http).await {
+            error!("Couldn't go over {input} - {e}");
+            failed += 1;
+        }
+        if matches.cancel.is_cancelled() {
+            break;
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        _ => Err(anyhow!("{failed} of {total} inputs failed")),
+    }
+}
+
+// for the monitor mode or anything else reading a URL per line
+fn append_urls(path: &str, recovery: &VodRecovery) -> Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    for url in &recovery.urls {
+        writeln!(file, "{}", url.url)?;
+    }
+    Ok(())
+}
+
+// a broken history shouldn't get in the way of the result that was just found
+fn save_to_history(sub: &Commands, recovery: &VodRecovery, matches: &Cli) {
+    let saved = history_location(matches)
+        .and_then(|path| History::open(&path)?.record(&sub.to_subcommand(), recovery));
+    if let Err(e) = saved {
+        warn!("Couldn't save the result to the history - {e}");
+    }
+}
+
+async fn report_recovery(
+    sub: &Commands,
+    username: &str,
+    recovery: Option<VodRecovery>,
+    processing_type: Option<&ProcessingType>,
+    matches: &Cli,
+    http: &HttpContext,
+) -> Option<Vec<ReturnURL>> {
+    if let (true, Some(recovery)) = (matches.history, &recovery) {
+        if matches!(
+            sub,
+            Commands::Exact { .. }
+                | Commands::Bruteforce { .. }
+                | Commands::Clip { .. }
+                | Commands::Link { .. }
+                | Commands::Vod { .. }
+                | Commands::ClipsOfVod { .. }
+        ) {
+            save_to_history(sub, recovery, matches);
+        }
+    }
+
+    if matches.output_format == OutputFormat::Json {
+        print_json(&RecoveryOutput {
+            mode: sub.to_subcommand(),
+            found: recovery.is_some(),
+            processing_type,
+            recovery: recovery.as_ref(),
+        });
+        return recovery.map(|r| r.urls);
+    }
+
+    let Some(recovery) = recovery else {
+        if !matches.simple {
+            info!("{}", "Couldn't find anything :(".red());
+        }
+        return None;
+    };
+
+    if !matches.simple && recovery.username != username {
+        info!(
+            "Matched using the username variant \"{}\" instead of \"{}\"",
+            recovery.username.yellow(),
+            username
+        );
+    }
+
+    if !matches.simple && recovery.urls.iter().all(|u| u.audio_only) {
+        info!(
+            "{}, the playlists have no video",
+            "Only the audio is left of this VOD".yellow()
+        );
+    }
+
+    // which CDN has which quality instead of a flat list, so it's clear where to download from
+    // and which CDN entries are stale; the simple output stays a plain list for scripts
+    let matrix = if matches.simple {
+        None
+    } else {
+        let mut qualities: Vec<String> = recovery
+            .qualities
+            .iter()
+            .map(|q| q.quality.clone())
+            .collect();
+        if qualities.is_empty() {
+            qualities.push("chunked".to_string());
+        }
+        let cdns = util::compile_cdn_list(matches.cdnfile.clone(), http).await;
+        availability_matrix(&recovery.urls[0].url, &cdns, &qualities, http).await
+    };
+    match matrix {
+        Some(matrix) => {
+            info!(
+                "Got the URL and it {} on Twitch servers: {}",
+                "was available".green(),
+                recovery.urls[0].url
+            );
+            info!("Availability per CDN and quality:");
+            for line in matrix.render() {
+                info!("{line}");
+            }
+            if let Some(cdn) = matrix.best_cdn() {
+                info!("Every quality is available on {}", cdn.green());
+            }
+        }
+        None => {
+            if !matches.simple {
+                info!(
+                    "Got the URL and it {} on Twitch servers. Here are the valid URLs:",
+                    "was available".green()
+                );
+            }
+            for url in &recovery.urls {
+                util::url(&url.url, matches.simple);
+            }
+        }
+    }
+
+    if !matches.simple {
+        for report in &recovery.qualities {
+            let muted = format!("{}/{} segments muted", report.muted, report.total);
+            info!(
+                "{} - {}",
+                report.quality,
+                if report.muted > 0 {
+                    muted.yellow()
+                } else {
+                    muted.green()
+                }
+            );
+        }
+    }
+
+    Some(recovery.urls)
+}
+
 pub fn trim_newline(s: &mut String) {
     if s.ends_with('\n') {
         s.pop();
@@ -196,37 +1363,49 @@ pub fn trim_newline(s: &mut String) {
 }
 
 fn ask_for_value(desc: &str, buf: &mut String) {
-    println!("{}", desc.bright_blue());
+    util::prompt(desc);
     stdin().read_line(buf).expect("Failed to read line.");
     trim_newline(buf);
 }
 
-async fn try_to_fix(valid_urls: Vec<ReturnURL>, matches: Cli) {
-    if !valid_urls.is_empty() && valid_urls[0].muted {
-        let mut response = String::new();
-
-        ask_for_value(
-            "Do you want to download the fixed playlist? (Y/n)",
-            &mut response,
-        );
-
-        match response.to_lowercase().as_str() {
-            "y" | "" => {
-                let fix_command = Commands::Fix {
-                    url: valid_urls[0].url.clone(),
-                    output: None,
-                    slow: false,
-                };
-                if let Err(e) = fix_command.execute(matches).await {
-                    error!("Failed to fix playlist: {e}");
-                }
-            }
-            _ => (),
+async fn try_to_fix(valid_urls: Vec<ReturnURL>, matches: Cli, http: &HttpContext) {
+    if !valid_urls.is_empty()
+        && valid_urls[0].muted
+        && confirm(&t("question.fix_playlist"), true, matches.yes)
+    {
+        let fix_command = Commands::Fix {
+            url: valid_urls[0].url.clone(),
+            output: None,
+            slow: false,
         };
+        if let Err(e) = fix_command.execute(matches, http).await {
+            error!(
+                "{}",
+                t_with("status.fix_failed", &[("error", &e.to_string())])
+            );
+        }
     }
 }
 
-pub async fn main_interface(mut matches: Cli) {
+// a mode run on the plain terminal, asking for its values first unless they're all there
+pub(crate) async fn run_plain(mut sub: Commands, ask: bool, matches: Cli, http: &HttpContext) {
+    if ask {
+        if let Err(e) = sub.fill_out_values() {
+            error!("{e}");
+            return;
+        }
+    }
+    let valid_urls = match sub.execute(matches.clone(), http).await {
+        Ok(u) => u.unwrap_or_default(),
+        Err(e) => {
+            error!("{e}");
+            return;
+        }
+    };
+    try_to_fix(valid_urls, matches, http).await;
+}
+
+pub async fn main_interface(mut matches: Cli, http: &HttpContext) {
     // forcing the progress bar option on
     matches = Cli {
         progressbar: true,
@@ -234,55 +1413,123 @@ pub async fn main_interface(mut matches: Cli) {
         ..matches
     };
 
+    // the TUI needs a terminal to draw on, piped input gets the plain menu
+    if stdin().is_terminal() && io::stdout().is_terminal() {
+        if let Err(e) = tui_interface(matches, http).await {
+            error!("{e}");
+        }
+        return;
+    }
+
+    let mut menu = Menu::default();
     loop {
         let mut mode = String::new();
 
-        println!("{}", "Select the application mode:".green());
-        for (i, com) in Commands::iter().enumerate() {
-            let selector = match com.to_selector() {
-                Some(str) => str,
-                None => (i + 1).to_string(),
-            };
-            let name = com.to_short_desc();
-
-            match com.show_description() {
-                true => println!(
-                    "[{}] {} - {}",
-                    selector.yellow(),
-                    name.bright_green(),
-                    com.get_documentation()
-                        .unwrap_or("<error - couldn't get mode description>")
-                        .italic()
-                ),
-                false => println!("[{}] {}", selector.yellow(), name.bright_green()),
-            }
+        println!("{}", t("menu.select").green());
+        for line in menu.render() {
+            println!("{line}");
         }
 
         stdin().read_line(&mut mode).expect("Failed to read line.");
         trim_newline(&mut mode);
 
-        match Commands::from_selector(mode) {
-            Some(mut sub) => {
-                if let Err(e) = sub.fill_out_values() {
-                    error!("{e}");
-                    continue;
-                }
-                let valid_urls = match sub.execute(matches.clone()).await {
-                    Ok(u) => match u {
-                        Some(u) => u,
-                        None => Vec::new(),
-                    },
-                    Err(e) => {
-                        error!("{e}");
+        let selected = match parse_menu_input(&mode) {
+            MenuInput::Select(sub) => Some((sub, false)),
+            MenuInput::Help(sub) => {
+                println!("{}", mode_help(&sub));
+                continue;
+            }
+            MenuInput::NextPage => {
+                menu.next_page();
+                continue;
+            }
+            MenuInput::PreviousPage => {
+                menu.previous_page();
+                continue;
+            }
+            MenuInput::Search(query) => {
+                menu.search(&query);
+                continue;
+            }
+            MenuInput::ClearSearch => {
+                menu.clear_search();
+                continue;
+            }
+            // a pasted link goes straight to the command handling it, everything it needs is in
+            // there, anything else is a search
+            MenuInput::Other(input) => {
+                let recognized = recognize(&input);
+                match recognized.to_command(&input) {
+                    Some(sub) => {
+                        info!(
+                            "{}",
+                            t_with(
+                                "menu.recognized",
+                                &[
+                                    ("kind", recognized.kind.describe()),
+                                    ("mode", &mode_name(&sub)),
+                                ],
+                            )
+                        );
+                        Some((sub, true))
+                    }
+                    None => {
+                        menu.search(&input);
                         continue;
                     }
-                };
-                try_to_fix(valid_urls, matches.clone()).await;
+                }
             }
+        };
+
+        match selected {
+            Some((sub, pasted)) => run_plain(sub, !pasted, matches.clone(), http).await,
             None => {
-                error!("Couldn't select the specified mode");
+                error!("{}", t("menu.no_mode"));
                 continue;
             }
         }
     }
 }
+
+// what the binary runs after setting up the terminal and the logger
+pub async fn run(mut matches: Cli, http: &HttpContext) -> Result<()> {
+    set_language(matches.lang.as_deref());
+    // the simple output prints the results to stdout, which is where the JSON document goes
+    if matches.output_format == OutputFormat::Json {
+        matches.simple = false;
+    }
+    // nothing but the URLs, so the simple output without its extra lines
+    if matches.urls_only {
+        matches.simple = true;
+        util::set_urls_only(true);
+    }
+    // the simple output is for scripts, the server never finishes and update checks by itself
+    let check_updates = !matches.no_update_check
+        && !matches.simple
+        && matches.output_format == OutputFormat::Text
+        && !matches!(
+            matches.command,
+            Some(Commands::Update { .. } | Commands::Serve { .. })
+        );
+    let update_check = check_updates.then(|| tokio::spawn(background_update_check(http.clone())));
+
+    let result = match matches.command {
+        Some(ref sub) if reads_stdin(sub) => run_stdin(sub, &matches, http).await,
+        Some(ref sub) => sub.execute(matches.clone(), http).await.map(|_| ()),
+        None => {
+            main_interface(matches.clone(), http).await;
+            Ok(())
+        }
+    };
+    metrics::write_snapshot(&matches);
+
+    // the run never waits for the check, a slow one just gets dropped
+    if let Some(check) = update_check {
+        if !check.is_finished() {
+            check.abort();
+        } else if let Ok(Some(tag)) = check.await {
+            info!("{}", update_notice(&tag).yellow());
+        }
+    }
+    result
+}
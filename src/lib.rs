@@ -0,0 +1,174 @@
+//! Finds VOD playlists on Twitch.
+//!
+//! Everything sending requests takes an [`HttpContext`], build one per process and share it so
+//! the connection pool and the per-host limits are shared too. The options structs all have
+//! sensible defaults and builder-style setters.
+//!
+//! ```no_run
+//! use tbf::{recover, BruteforceOptions, HttpContext};
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let http = HttpContext::default();
+//! let options = BruteforceOptions::default();
+//! if let Some(recovery) = recover::exact("dansgaming", 42218705421, "2021-06-05 00:50:17", &options, &http).await? {
+//!     for url in recovery.urls {
+//!         println!("{}", url.url);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Nothing gets printed: the results are returned, the progress goes to a [`Progress`] if you
+//! pass one and the diagnostics go through the `log` crate. `console` in [`ReportOptions`]
+//! turns on the progress bar and the output the CLI shows.
+//!
+//! Only the items re-exported here are covered by semver, the rest of the crate can change in
+//! any release.
+//!
+//! Everything that needs the network is behind the default `net` feature. Without it only
+//! [`offline`] and [`tracker`] are left, which also build for wasm32.
+
+#[cfg(feature = "net")]
+pub(crate) mod batch;
+#[cfg(feature = "net")]
+pub(crate) mod bench;
+#[cfg(feature = "net")]
+pub mod blocking;
+#[cfg(feature = "net")]
+pub(crate) mod cdns;
+#[cfg(feature = "net")]
+pub(crate) mod channel;
+#[cfg(feature = "net")]
+pub(crate) mod clipforce;
+#[cfg(feature = "net")]
+pub(crate) mod config;
+pub mod error;
+#[cfg(feature = "net")]
+pub(crate) mod expiry;
+#[cfg(feature = "net")]
+pub(crate) mod export;
+#[cfg(feature = "net")]
+pub(crate) mod feed;
+#[cfg(feature = "net")]
+pub(crate) mod history;
+#[cfg(feature = "net")]
+pub(crate) mod hooks;
+#[cfg(feature = "net")]
+pub(crate) mod http;
+#[cfg(feature = "net")]
+pub(crate) mod i18n;
+#[cfg(feature = "net")]
+pub(crate) mod interface;
+#[cfg(feature = "net")]
+pub(crate) mod limiter;
+#[cfg(feature = "net")]
+pub(crate) mod matrix;
+#[cfg(feature = "net")]
+pub(crate) mod menu;
+#[cfg(feature = "net")]
+pub(crate) mod metrics;
+#[cfg(feature = "net")]
+pub(crate) mod monitor;
+#[cfg(feature = "net")]
+pub(crate) mod notify;
+pub mod offline;
+#[cfg(feature = "net")]
+pub(crate) mod progress;
+#[cfg(feature = "net")]
+pub(crate) mod recognize;
+#[cfg(feature = "net")]
+pub(crate) mod replay;
+#[cfg(feature = "net")]
+pub(crate) mod server;
+#[cfg(feature = "net")]
+pub(crate) mod spill;
+#[cfg(feature = "net")]
+pub(crate) mod stats;
+#[cfg(feature = "net")]
+pub(crate) mod subtitles;
+pub mod tracker;
+#[cfg(feature = "net")]
+pub(crate) mod tui;
+#[cfg(feature = "net")]
+pub(crate) mod twitch;
+#[cfg(feature = "net")]
+pub(crate) mod update;
+#[cfg(feature = "net")]
+pub(crate) mod util;
+
+#[cfg(feature = "net")]
+pub use config::{
+    BruteforceOptions, ClipOptions, DownloadOptions, FixOptions, HttpSettings, HttpVersion,
+    ReportOptions, RetryPolicy, ScanOptions,
+};
+#[cfg(feature = "net")]
+pub use export::StreamlinkMode;
+#[cfg(feature = "net")]
+pub use hooks::{Hook, HookStage};
+#[cfg(feature = "net")]
+pub use http::HttpContext;
+#[cfg(feature = "net")]
+pub use progress::{Progress, ProgressEvent};
+pub use tracker::ProcessingType;
+
+#[cfg(feature = "net")]
+/// Finding the playlists of VODs and clips.
+///
+/// ```no_run
+/// use tbf::{recover, BruteforceOptions, HttpContext};
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let http = HttpContext::default();
+/// let recovery = recover::bruteforce(
+///     "dansgaming",
+///     42218705421,
+///     "2021-06-05 00:45:00",
+///     "2021-06-05 00:55:00",
+///     &BruteforceOptions::default(),
+///     &http,
+/// )
+/// .await?;
+/// # Ok(())
+/// # }
+/// ```
+pub mod recover {
+    pub use crate::twitch::clips::{clip_bruteforce, clip_qualities, find_bid_from_clip};
+    pub use crate::twitch::lookup_video;
+    pub use crate::twitch::vods::{bruteforcer as bruteforce, exact, live};
+}
+
+#[cfg(feature = "net")]
+/// Making the playlists of muted VODs playable and downloading them.
+///
+/// ```no_run
+/// use tbf::{playlist, FixOptions, HttpContext};
+///
+/// # async fn run() -> anyhow::Result<()> {
+/// let options = FixOptions::default().output("fixed.m3u8");
+/// let fixed = playlist::fix("https://d1m7jfoe9zdc1j.cloudfront.net/.../chunked/index-dvr.m3u8", &options, &HttpContext::default()).await?;
+/// println!("{} of {} segments were muted", fixed.muted, fixed.segments);
+/// # Ok(())
+/// # }
+/// ```
+pub mod playlist {
+    pub use crate::twitch::vods::{download, fix};
+}
+
+#[cfg(feature = "net")]
+/// The values the functions in [`recover`](crate::recover) and [`playlist`](crate::playlist) return.
+pub mod models {
+    pub use crate::twitch::models::{
+        ClipQuality, DownloadedVod, FixedPlaylist, MutedRange, MutedSegments, PathLayout,
+        ReturnURL, VodRecovery,
+    };
+}
+
+// the binary's entry points, not part of the stable API
+#[cfg(feature = "net")]
+#[doc(hidden)]
+pub mod cli {
+    pub use crate::config::{parse_cli, Cli, OutputFormat};
+    pub use crate::http::read_proxy_list;
+    pub use crate::interface::run;
+}
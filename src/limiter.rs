@@ -0,0 +1,509 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+// how long the requests have to go through without any throttling before the concurrency creeps back up
+const CLEAN_PERIOD: Duration = Duration::from_secs(30);
+// throttling right after a decrease is most likely coming from the requests sent before it
+const DECREASE_COOLDOWN: Duration = Duration::from_secs(5);
+
+// this many throttled responses within the window count as the CDNs throttling, a lone 429 or 503
+// is just noise
+const BURST_SIZE: usize = 3;
+const BURST_WINDOW: Duration = Duration::from_secs(1);
+// the first pause once the CDNs start throttling, doubled whenever they keep at it right after
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+// rough per-item costs used to fit a scan into the memory budget
+const BYTES_PER_REQUEST: u64 = 64 * 1024;
+const BYTES_PER_CANDIDATE: u64 = 512;
+const DEFAULT_QUEUE_PER_THREAD: usize = 4;
+
+#[derive(Debug, PartialEq)]
+pub struct ScanBudget {
+    // requests in flight, at least one or the scan would never start
+    pub threads: usize,
+    // candidates generated ahead of the requests
+    pub queue: usize,
+    // how many of the pending candidates stay in memory before the rest goes to disk,
+    // None keeps all of them in memory
+    pub spill_after: Option<usize>,
+}
+
+// a scan only ever holds the requests in flight and the queue of candidates waiting for them,
+// so fitting into the budget means shrinking those two (most of it going to the requests).
+// the candidates that don't fit into the queue get spilled to disk
+pub fn scan_budget(threads: usize, max_memory: Option<u64>) -> ScanBudget {
+    let Some(max_memory) = max_memory else {
+        let threads = threads.max(1);
+        return ScanBudget {
+            threads,
+            queue: threads * DEFAULT_QUEUE_PER_THREAD,
+            spill_after: None,
+        };
+    };
+
+    let bytes = max_memory * 1024 * 1024;
+    let max_threads = ((bytes * 4 / 5) / BYTES_PER_REQUEST).max(1) as usize;
+    let max_queue = ((bytes / 5) / BYTES_PER_CANDIDATE).max(1) as usize;
+
+    let threads = match threads {
+        0 => max_threads,
+        t => t.min(max_threads),
+    };
+    let queue = (threads * DEFAULT_QUEUE_PER_THREAD).min(max_queue);
+    ScanBudget {
+        threads,
+        queue,
+        spill_after: Some(queue),
+    }
+}
+
+// token bucket per client, every client can do `per_minute` actions in a burst
+// and gets them back gradually over a minute
+pub struct RateLimiter {
+    per_minute: u32,
+    buckets: Mutex<HashMap<String, (f64, Instant)>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self {
+            per_minute: per_minute.max(1),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // takes a token for the client or returns how long it has to wait for the next one
+    pub fn check(&self, client: &str) -> Result<(), Duration> {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let capacity = self.per_minute as f64;
+        let per_second = capacity / 60.0;
+        let mut buckets = self.buckets.lock().unwrap();
+        let (tokens, last) = buckets.entry(client.to_string()).or_insert((capacity, now));
+
+        *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * per_second).min(capacity);
+        *last = now;
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - *tokens) / per_second))
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PacerState {
+    next_slot: Option<Instant>,
+    burst: usize,
+    burst_start: Option<Instant>,
+    // the length of the last pause
+    delay: Duration,
+    paused_until: Option<Instant>,
+}
+
+// spaces the requests out to at most `max_rps` a second and pauses all of them once a burst of
+// throttled responses comes in, twice as long as before if it happens again right after a pause
+#[derive(Debug)]
+pub struct RequestPacer {
+    interval: Option<Duration>,
+    backoff: bool,
+    state: Mutex<PacerState>,
+}
+
+impl RequestPacer {
+    pub fn new(max_rps: Option<f64>, backoff: bool) -> Self {
+        Self {
+            interval: max_rps
+                .filter(|rps| *rps > 0.0)
+                .map(|rps| Duration::from_secs_f64(1.0 / rps)),
+            backoff,
+            state: Mutex::new(PacerState::default()),
+        }
+    }
+
+    pub async fn wait(&self) {
+        let delay = self.reserve_at(Instant::now());
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    // how long the request has to wait, its slot gets taken right away
+    fn reserve_at(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+        let mut start = now;
+        if let Some(until) = state.paused_until {
+            start = start.max(until);
+        }
+        if let Some(interval) = self.interval {
+            if let Some(slot) = state.next_slot {
+                start = start.max(slot);
+            }
+            state.next_slot = Some(start + interval);
+        }
+        start - now
+    }
+
+    // returns the pause if this response started one
+    pub fn throttled(&self) -> Option<Duration> {
+        self.throttled_at(Instant::now())
+    }
+
+    fn throttled_at(&self, now: Instant) -> Option<Duration> {
+        if !self.backoff {
+            return None;
+        }
+        let mut state = self.state.lock().unwrap();
+        // the requests sent before the pause are still coming back
+        if state.paused_until.is_some_and(|until| now < until) {
+            return None;
+        }
+        if state
+            .burst_start
+            .is_none_or(|start| now.duration_since(start) > BURST_WINDOW)
+        {
+            state.burst_start = Some(now);
+            state.burst = 0;
+        }
+        state.burst += 1;
+        if state.burst < BURST_SIZE {
+            return None;
+        }
+
+        let again = state
+            .paused_until
+            .is_some_and(|until| now.duration_since(until) < state.delay * 2);
+        state.delay = if again {
+            (state.delay * 2).min(MAX_BACKOFF)
+        } else {
+            INITIAL_BACKOFF
+        };
+        state.paused_until = Some(now + state.delay);
+        state.burst = 0;
+        state.burst_start = None;
+        Some(state.delay)
+    }
+}
+
+// caps the amount of requests in flight below the configured thread count,
+// halving it when the CDNs start throttling and slowly raising it back after a clean period
+pub struct AdaptiveConcurrency {
+    semaphore: Semaphore,
+    max: usize,
+    limit: AtomicUsize,
+    // permits that have to be taken out of circulation once they're released
+    debt: AtomicUsize,
+    last_change: Mutex<Option<Instant>>,
+    // milliseconds from created to the last change plus one, 0 before the first one,
+    // so the successes can be turned away without taking the lock
+    changed_at: AtomicU64,
+    created: Instant,
+    enabled: bool,
+}
+
+pub struct ConcurrencyPermit<'a> {
+    permit: Option<SemaphorePermit<'a>>,
+    debt: &'a AtomicUsize,
+}
+
+impl Drop for ConcurrencyPermit<'_> {
+    fn drop(&mut self) {
+        if let Some(permit) = self.permit.take() {
+            if self
+                .debt
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |d| d.checked_sub(1))
+                .is_ok()
+            {
+                permit.forget();
+            }
+        }
+    }
+}
+
+impl AdaptiveConcurrency {
+    // a max of 0 means there's no limit, so there's nothing to tune either
+    pub fn new(max: usize, enabled: bool) -> Self {
+        Self {
+            semaphore: Semaphore::new(max.min(Semaphore::MAX_PERMITS)),
+            max,
+            limit: AtomicUsize::new(max),
+            debt: AtomicUsize::new(0),
+            last_change: Mutex::new(None),
+            changed_at: AtomicU64::new(0),
+            created: Instant::now(),
+            enabled: enabled && max > 1,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::SeqCst)
+    }
+
+    pub async fn acquire(&self) -> ConcurrencyPermit<'_> {
+        let permit = if self.max > 0 {
+            self.semaphore.acquire().await.ok()
+        } else {
+            None
+        };
+        ConcurrencyPermit {
+            permit,
+            debt: &self.debt,
+        }
+    }
+
+    // returns the new limit if it got lowered
+    pub fn throttled(&self) -> Option<usize> {
+        if !self.enabled {
+            return None;
+        }
+
+        let mut last_change = self.last_change.lock().unwrap();
+        let cooling_down = last_change.is_some_and(|last| last.elapsed() < DECREASE_COOLDOWN);
+        // even when cooling down it still counts as throttling, so the clean period starts over
+        *last_change = Some(self.mark_changed());
+        if cooling_down {
+            return None;
+        }
+
+        let current = self.limit();
+        let new = (current / 2).max(1);
+        if new == current {
+            return None;
+        }
+        self.limit.store(new, Ordering::SeqCst);
+
+        let diff = current - new;
+        let forgotten = self.semaphore.forget_permits(diff);
+        self.debt.fetch_add(diff - forgotten, Ordering::SeqCst);
+
+        Some(new)
+    }
+
+    fn since_created(&self) -> u64 {
+        self.created.elapsed().as_millis() as u64
+    }
+
+    fn mark_changed(&self) -> Instant {
+        let now = Instant::now();
+        let since = now.duration_since(self.created).as_millis() as u64;
+        self.changed_at.store(since + 1, Ordering::SeqCst);
+        now
+    }
+
+    // returns the new limit if it got raised
+    pub fn succeeded(&self) -> Option<usize> {
+        if !self.enabled || self.limit() >= self.max {
+            return None;
+        }
+
+        // nearly every success lands within the clean period, those don't need the lock
+        let changed_at = self.changed_at.load(Ordering::SeqCst);
+        if changed_at > 0 && self.since_created() < changed_at - 1 + CLEAN_PERIOD.as_millis() as u64
+        {
+            return None;
+        }
+
+        let mut last_change = self.last_change.lock().unwrap();
+        if last_change.is_some_and(|last| last.elapsed() < CLEAN_PERIOD) {
+            return None;
+        }
+        *last_change = Some(self.mark_changed());
+
+        let current = self.limit();
+        let new = (current + (current / 4).max(1)).min(self.max);
+        self.limit.store(new, Ordering::SeqCst);
+
+        // the permits still owed get cancelled out first
+        let diff = new - current;
+        let mut paid = 0;
+        let _ = self
+            .debt
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |d| {
+                paid = d.min(diff);
+                Some(d - paid)
+            });
+        self.semaphore.add_permits(diff - paid);
+
+        Some(new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{scan_budget, AdaptiveConcurrency, RateLimiter, RequestPacer, ScanBudget};
+
+    #[test]
+    fn budget() {
+        assert_eq!(
+            scan_budget(1000, None),
+            ScanBudget {
+                threads: 1000,
+                queue: 4000,
+                spill_after: None,
+            },
+            "testing no budget"
+        );
+        assert_eq!(
+            scan_budget(1000, Some(16)),
+            ScanBudget {
+                threads: 204,
+                queue: 816,
+                spill_after: Some(816),
+            },
+            "testing small budget"
+        );
+        assert_eq!(
+            scan_budget(0, Some(1)),
+            ScanBudget {
+                threads: 12,
+                queue: 48,
+                spill_after: Some(48),
+            },
+            "testing unlimited threads with a budget"
+        );
+        assert_eq!(
+            scan_budget(10, Some(1024)),
+            ScanBudget {
+                threads: 10,
+                queue: 40,
+                spill_after: Some(40),
+            },
+            "testing budget that fits"
+        );
+        assert_eq!(
+            scan_budget(0, None),
+            ScanBudget {
+                threads: 1,
+                queue: 4,
+                spill_after: None,
+            },
+            "testing no threads without a budget"
+        );
+    }
+
+    #[tokio::test]
+    async fn adaptive_concurrency() {
+        let limiter = AdaptiveConcurrency::new(8, true);
+        assert_eq!(limiter.limit(), 8, "testing initial limit");
+
+        assert_eq!(limiter.throttled(), Some(4), "testing decrease");
+        assert_eq!(limiter.throttled(), None, "testing decrease cooldown");
+        assert_eq!(limiter.succeeded(), None, "testing clean period");
+        assert_eq!(limiter.limit(), 4, "testing limit after throttling");
+
+        // permits in use get taken out of circulation once they're released
+        let permits = vec![limiter.acquire().await, limiter.acquire().await];
+        drop(permits);
+        assert!(
+            limiter.semaphore.available_permits() <= 4,
+            "testing permits after decrease"
+        );
+
+        let disabled = AdaptiveConcurrency::new(8, false);
+        assert_eq!(disabled.throttled(), None, "testing disabled limiter");
+
+        let unlimited = AdaptiveConcurrency::new(0, true);
+        let _permit = unlimited.acquire().await;
+        assert_eq!(unlimited.throttled(), None, "testing unlimited limiter");
+    }
+
+    #[test]
+    fn rate_limiter() {
+        let limiter = RateLimiter::new(2);
+        let now = Instant::now();
+
+        assert!(limiter.check_at("a", now).is_ok(), "testing first token");
+        assert!(limiter.check_at("a", now).is_ok(), "testing burst");
+        assert_eq!(
+            limiter
+                .check_at("a", now)
+                .unwrap_err()
+                .as_secs_f64()
+                .round(),
+            30.0,
+            "testing empty bucket"
+        );
+        assert!(
+            limiter.check_at("b", now).is_ok(),
+            "testing separate clients"
+        );
+        assert!(
+            limiter.check_at("a", now + Duration::from_secs(31)).is_ok(),
+            "testing refill"
+        );
+    }
+
+    #[test]
+    fn request_pacer() {
+        let pacer = RequestPacer::new(Some(10.0), true);
+        let now = Instant::now();
+        assert_eq!(
+            pacer.reserve_at(now),
+            Duration::ZERO,
+            "testing the first slot"
+        );
+        assert_eq!(
+            pacer.reserve_at(now),
+            Duration::from_millis(100),
+            "testing the spacing"
+        );
+
+        assert_eq!(pacer.throttled_at(now), None, "testing a lone 429");
+        assert_eq!(pacer.throttled_at(now), None, "testing a second 429");
+        assert_eq!(
+            pacer.throttled_at(now),
+            Some(Duration::from_secs(1)),
+            "testing a burst"
+        );
+        assert!(
+            pacer.reserve_at(now) >= Duration::from_secs(1),
+            "testing the pause"
+        );
+        assert_eq!(
+            pacer.throttled_at(now + Duration::from_millis(500)),
+            None,
+            "testing the responses during the pause"
+        );
+
+        let later = now + Duration::from_millis(1500);
+        for _ in 0..2 {
+            pacer.throttled_at(later);
+        }
+        assert_eq!(
+            pacer.throttled_at(later),
+            Some(Duration::from_secs(2)),
+            "testing the exponential backoff"
+        );
+        let much_later = later + Duration::from_secs(60);
+        for _ in 0..2 {
+            pacer.throttled_at(much_later);
+        }
+        assert_eq!(
+            pacer.throttled_at(much_later),
+            Some(Duration::from_secs(1)),
+            "testing the reset after a clean period"
+        );
+
+        let disabled = RequestPacer::new(None, false);
+        assert_eq!(disabled.reserve_at(now), Duration::ZERO, "testing no limit");
+        assert_eq!(
+            (0..5).filter_map(|_| disabled.throttled_at(now)).count(),
+            0,
+            "testing no backoff"
+        );
+    }
+}
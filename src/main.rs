@@ -1,25 +1,12 @@
-mod config;
-mod error;
-mod interface;
-mod twitch;
-mod update;
-mod util;
-
 use anyhow::Result;
-use clap::{crate_name, crate_version, Parser};
+use clap::{crate_name, crate_version};
 use crossterm::{execute, terminal::SetTitle};
 use env_logger::Env;
-use lazy_static::lazy_static;
 use log::{debug, error};
 use std::{io::stdout, panic};
 
-use config::Cli;
-use interface::main_interface;
-
-lazy_static! {
-    // HTTP client to share
-    static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::new();
-}
+use tbf::cli::{parse_cli, read_proxy_list, run, OutputFormat};
+use tbf::{HttpContext, HttpSettings};
 
 #[tokio::main]
 async fn main() {
@@ -30,14 +17,29 @@ async fn main() {
 }
 
 async fn execute() -> Result<()> {
-    execute!(
-        stdout(),
-        SetTitle(format!("{} v{}", crate_name!(), crate_version!()))
-    )?;
+    let matches = parse_cli()?;
+    // the title is an escape sequence on stdout, which only has the URLs with --urls-only
+    if !matches.urls_only {
+        execute!(
+            stdout(),
+            SetTitle(format!("{} v{}", crate_name!(), crate_version!()))
+        )?;
+    }
 
-    let matches = Cli::parse();
+    let mut settings = HttpSettings::from(&matches);
+    if let Some(path) = &matches.proxy_list {
+        settings.proxy_pool = read_proxy_list(path)?;
+    }
+    let http = HttpContext::new(settings);
 
-    let log_level = if matches.verbose { "debug" } else { "info" };
+    // the JSON document is the output, the log lines would only be noise around it
+    let log_level = if matches.verbose {
+        "debug"
+    } else if matches.output_format == OutputFormat::Json {
+        "warn"
+    } else {
+        "info"
+    };
 
     env_logger::Builder::from_env(Env::default().filter_or(
         env_logger::DEFAULT_FILTER_ENV,
@@ -58,14 +60,5 @@ async fn execute() -> Result<()> {
         }
     }));
 
-    match matches.command {
-        Some(ref sub) => {
-            if let Err(e) = sub.execute(matches.clone()).await {
-                error!("{e}");
-            }
-        }
-        None => main_interface(matches).await,
-    }
-
-    Ok(())
+    run(matches, &http).await
 }
@@ -0,0 +1,226 @@
+use colored::{ColoredString, Colorize};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::http::HttpContext;
+use crate::offline::playlist_base_url;
+
+// enough to get through every CDN and quality quickly without hammering any single one
+const MATRIX_CONCURRENCY: usize = 32;
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct MatrixCell {
+    // None when the request itself failed
+    pub status: Option<u16>,
+    pub latency_ms: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct AvailabilityMatrix {
+    pub cdns: Vec<String>,
+    pub qualities: Vec<String>,
+    // a row per CDN, a cell per quality
+    pub cells: Vec<Vec<MatrixCell>>,
+}
+
+// asks every CDN for every quality playlist of the found VOD
+pub async fn availability_matrix(
+    playlist_url: &str,
+    cdns: &[String],
+    qualities: &[String],
+    http: &HttpContext,
+) -> Option<AvailabilityMatrix> {
+    let (_, directory) = playlist_base_url(playlist_url)?;
+    let directory = &directory;
+    // index-dvr.m3u8, unless the VOD was found with an older layout
+    let file = playlist_url.rsplit('/').next()?;
+    // collected up front, borrowing iterators in the stream would keep the future from being Send
+    let requests: Vec<(usize, usize, String)> = cdns
+        .iter()
+        .enumerate()
+        .flat_map(|(c, cdn)| {
+            qualities.iter().enumerate().map(move |(q, quality)| {
+                (c, q, format!("https://{cdn}/{directory}/{quality}/{file}"))
+            })
+        })
+        .collect();
+
+    let results: Vec<(usize, usize, MatrixCell)> = stream::iter(requests)
+        .map(|(c, q, url)| async move {
+            let started = Instant::now();
+            let status = http.probe(&url).await.ok().map(|r| r.status().as_u16());
+            let cell = MatrixCell {
+                status,
+                latency_ms: started.elapsed().as_millis() as u64,
+            };
+            (c, q, cell)
+        })
+        .buffer_unordered(MATRIX_CONCURRENCY)
+        .collect()
+        .await;
+
+    let mut cells = vec![vec![MatrixCell::default(); qualities.len()]; cdns.len()];
+    for (c, q, cell) in results {
+        cells[c][q] = cell;
+    }
+    Some(AvailabilityMatrix {
+        cdns: cdns.to_vec(),
+        qualities: qualities.to_vec(),
+        cells,
+    })
+}
+
+fn cell_text(cell: &MatrixCell) -> String {
+    match cell.status {
+        Some(200) => format!("200 {}ms", cell.latency_ms),
+        Some(status) => status.to_string(),
+        None => "-".to_string(),
+    }
+}
+
+fn colorize(text: String, cell: &MatrixCell) -> ColoredString {
+    match cell.status {
+        Some(200) => text.green(),
+        Some(403) => text.yellow(),
+        Some(_) => text.red(),
+        None => text.dimmed(),
+    }
+}
+
+impl AvailabilityMatrix {
+    // the CDN serving every quality the quickest, the one to download from
+    pub fn best_cdn(&self) -> Option<&str> {
+        self.cdns
+            .iter()
+            .zip(&self.cells)
+            .filter(|(_, row)| row.iter().all(|c| c.status == Some(200)))
+            .min_by_key(|(_, row)| row.iter().map(|c| c.latency_ms).sum::<u64>())
+            .map(|(cdn, _)| cdn.as_str())
+    }
+
+    // a row per CDN, the CDNs that have nothing at all get summed up in one line at the end
+    pub fn render(&self) -> Vec<String> {
+        let (available, stale): (Vec<_>, Vec<_>) = self
+            .cdns
+            .iter()
+            .zip(&self.cells)
+            .partition(|(_, row)| row.iter().any(|c| c.status == Some(200)));
+
+        let cdn_width = available
+            .iter()
+            .map(|(cdn, _)| cdn.len())
+            .max()
+            .unwrap_or_default();
+        let widths: Vec<usize> = self
+            .qualities
+            .iter()
+            .enumerate()
+            .map(|(q, quality)| {
+                available
+                    .iter()
+                    .map(|(_, row)| cell_text(&row[q]).len())
+                    .chain([quality.len()])
+                    .max()
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let mut lines = Vec::new();
+        if !available.is_empty() {
+            let header: Vec<String> = self
+                .qualities
+                .iter()
+                .zip(&widths)
+                .map(|(quality, width)| format!("{quality:<width$}"))
+                .collect();
+            lines.push(format!("{:cdn_width$}  {}", "", header.join("  ")));
+        }
+        for (cdn, row) in &available {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| {
+                    colorize(format!("{:<width$}", cell_text(cell)), cell).to_string()
+                })
+                .collect();
+            lines.push(format!("{cdn:<cdn_width$}  {}", cells.join("  ")));
+        }
+        if !stale.is_empty() {
+            lines.push(format!(
+                "{} {}",
+                "Nothing on:".dimmed(),
+                stale
+                    .iter()
+                    .map(|(cdn, _)| cdn.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+                    .dimmed()
+            ));
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::http::{Fixtures, HttpContext};
+
+    use super::availability_matrix;
+
+    #[tokio::test]
+    async fn matrix() {
+        let cdns = vec![
+            "d1m7jfoe9zdc1j.cloudfront.net".to_string(),
+            "vod-secure.twitch.tv".to_string(),
+            "d2nvs31859zcd8.cloudfront.net".to_string(),
+        ];
+        let qualities = vec!["chunked".to_string(), "720p60".to_string()];
+        let directory = "d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217";
+        let url = |cdn: &str, quality: &str| {
+            format!("https://{cdn}/{directory}/{quality}/index-dvr.m3u8")
+        };
+        let http = HttpContext::default().with_fixtures(Arc::new(
+            Fixtures::new()
+                .respond(&url(&cdns[0], "chunked"), 200, "#EXTM3U")
+                .respond(&url(&cdns[0], "720p60"), 200, "#EXTM3U")
+                .respond(&url(&cdns[1], "chunked"), 200, "#EXTM3U")
+                .respond(&url(&cdns[1], "720p60"), 403, ""),
+        ));
+
+        let matrix = availability_matrix(&url(&cdns[0], "chunked"), &cdns, &qualities, &http)
+            .await
+            .unwrap();
+        assert_eq!(
+            matrix
+                .cells
+                .iter()
+                .map(|row| row.iter().map(|c| c.status).collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+            vec![
+                vec![Some(200), Some(200)],
+                vec![Some(200), Some(403)],
+                vec![Some(404), Some(404)],
+            ],
+            "testing the statuses"
+        );
+        assert_eq!(
+            matrix.best_cdn(),
+            Some("d1m7jfoe9zdc1j.cloudfront.net"),
+            "testing the CDN with every quality"
+        );
+
+        let lines = matrix.render();
+        assert_eq!(
+            lines.len(),
+            4,
+            "testing a header, two rows and the stale CDNs"
+        );
+        assert!(
+            lines[3].contains("d2nvs31859zcd8.cloudfront.net"),
+            "testing the stale CDN line"
+        );
+    }
+}
@@ -0,0 +1,289 @@
+use clap::CommandFactory;
+use colored::Colorize;
+use strum::{EnumMessage, IntoEnumIterator};
+
+use crate::config::{Cli, Commands, ModeCategory};
+use crate::i18n::{t, t_with, try_t};
+
+// the modes shown at once, the rest are a page away
+const PAGE_SIZE: usize = 12;
+
+// what a line typed into the menu means
+#[derive(Debug)]
+pub enum MenuInput {
+    Select(Commands),
+    Help(Commands),
+    NextPage,
+    PreviousPage,
+    Search(String),
+    ClearSearch,
+    // not a menu command, it can still be a pasted link or a search
+    Other(String),
+}
+
+pub fn parse_menu_input(input: &str) -> MenuInput {
+    let input = input.trim();
+    match input {
+        "" => return MenuInput::ClearSearch,
+        "n" | ">" => return MenuInput::NextPage,
+        "p" | "<" => return MenuInput::PreviousPage,
+        _ => (),
+    }
+    if let Some(selector) = input.strip_prefix('?') {
+        if let Some(sub) = Commands::from_selector(selector.trim().to_string()) {
+            return MenuInput::Help(sub);
+        }
+    }
+    if let Some(query) = input.strip_prefix('/') {
+        return MenuInput::Search(query.trim().to_string());
+    }
+    match Commands::from_selector(input.to_string()) {
+        Some(sub) => MenuInput::Select(sub),
+        None => MenuInput::Other(input.to_string()),
+    }
+}
+
+struct MenuEntry {
+    selector: String,
+    command: Commands,
+    // everything the search looks through, lowercased
+    haystack: String,
+}
+
+pub struct Menu {
+    entries: Vec<MenuEntry>,
+    query: Vec<String>,
+    page: usize,
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        let mut entries: Vec<MenuEntry> = Commands::iter()
+            .enumerate()
+            .map(|(i, com)| MenuEntry {
+                // the number stays the same whatever the grouping or the search shows
+                selector: com.to_selector().unwrap_or_else(|| (i + 1).to_string()),
+                haystack: format!(
+                    "{} {} {} {} {}",
+                    com.to_subcommand(),
+                    com.to_short_desc(),
+                    mode_name(&com),
+                    com.category(),
+                    com.get_documentation().unwrap_or_default()
+                )
+                .to_lowercase(),
+                command: com,
+            })
+            .collect();
+        let order = |c: ModeCategory| ModeCategory::iter().position(|x| x == c);
+        entries.sort_by_key(|e| order(e.command.category()));
+        Self {
+            entries,
+            query: Vec::new(),
+            page: 0,
+        }
+    }
+}
+
+impl Menu {
+    // every word typed so far narrows the list down further
+    pub fn search(&mut self, query: &str) {
+        self.query
+            .extend(query.split_whitespace().map(str::to_lowercase));
+        self.page = 0;
+    }
+
+    pub fn clear_search(&mut self) {
+        self.query.clear();
+        self.page = 0;
+    }
+
+    pub fn is_searching(&self) -> bool {
+        !self.query.is_empty()
+    }
+
+    fn visible(&self) -> Vec<&MenuEntry> {
+        self.entries
+            .iter()
+            .filter(|e| self.query.iter().all(|w| e.haystack.contains(w.as_str())))
+            .collect()
+    }
+
+    // every mode the search leaves, over all the pages
+    pub fn entries(&self) -> Vec<&Commands> {
+        self.visible().into_iter().map(|e| &e.command).collect()
+    }
+
+    pub fn pages(&self) -> usize {
+        self.visible().len().div_ceil(PAGE_SIZE).max(1)
+    }
+
+    pub fn next_page(&mut self) {
+        self.page = (self.page + 1).min(self.pages() - 1);
+    }
+
+    pub fn previous_page(&mut self) {
+        self.page = self.page.saturating_sub(1);
+    }
+
+    // the current page, grouped by category; the few modes left after a search get their
+    // descriptions right away
+    pub fn render(&self) -> Vec<String> {
+        let visible = self.visible();
+        if visible.is_empty() {
+            return vec![t_with("menu.no_match", &[("query", &self.query.join(" "))])];
+        }
+
+        let mut lines = Vec::new();
+        let mut category = None;
+        for entry in visible.iter().skip(self.page * PAGE_SIZE).take(PAGE_SIZE) {
+            let com = &entry.command;
+            if category != Some(com.category()) {
+                category = Some(com.category());
+                lines.push(format!("{}", category_name(com.category()).bold()));
+            }
+            let name = mode_name(com);
+            match (
+                self.is_searching() && com.show_description(),
+                com.get_documentation(),
+            ) {
+                (true, Some(doc)) => lines.push(format!(
+                    "  [{}] {} - {}",
+                    entry.selector.yellow(),
+                    name.bright_green(),
+                    doc.italic()
+                )),
+                _ => lines.push(format!(
+                    "  [{}] {}",
+                    entry.selector.yellow(),
+                    name.bright_green()
+                )),
+            }
+        }
+
+        let mut hints = vec![t("menu.hint_help"), t("menu.hint_search")];
+        if self.is_searching() {
+            hints.push(t("menu.hint_clear"));
+        }
+        let pages = self.pages();
+        if pages > 1 {
+            lines.push(t_with(
+                "menu.page",
+                &[
+                    ("page", &(self.page + 1).to_string()),
+                    ("pages", &pages.to_string()),
+                ],
+            ));
+        }
+        lines.push(hints.join(", "));
+        lines
+    }
+}
+
+// the names are in English in the code, a translation can have its own
+pub fn mode_name(com: &Commands) -> String {
+    try_t(&format!("mode.{}", com.to_subcommand()))
+        .map(str::to_string)
+        .unwrap_or_else(|| com.to_short_desc())
+}
+
+pub fn category_name(category: ModeCategory) -> String {
+    try_t(&format!("category.{category:?}").to_lowercase())
+        .map(str::to_string)
+        .unwrap_or_else(|| category.to_string())
+}
+
+// the mode's description and its arguments, the same as `tbf <mode> --help`
+pub fn mode_usage(com: &Commands) -> String {
+    match Cli::command().find_subcommand(com.to_subcommand()) {
+        Some(sub) => sub.clone().render_long_help().to_string(),
+        None => com.get_documentation().unwrap_or_default().to_string(),
+    }
+}
+
+pub fn mode_help(com: &Commands) -> String {
+    format!("{}\n{}", mode_name(com).bright_green(), mode_usage(com))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mode_help, parse_menu_input, Menu, MenuInput, PAGE_SIZE};
+    use crate::config::Commands;
+
+    #[test]
+    fn menu_inputs() {
+        assert!(
+            matches!(
+                parse_menu_input("1"),
+                MenuInput::Select(Commands::Exact { .. })
+            ),
+            "testing a selection"
+        );
+        assert!(
+            matches!(
+                parse_menu_input("?u"),
+                MenuInput::Help(Commands::Update { .. })
+            ),
+            "testing a help request"
+        );
+        assert!(
+            matches!(parse_menu_input("/chat"), MenuInput::Search(q) if q == "chat"),
+            "testing an explicit search"
+        );
+        assert!(
+            matches!(parse_menu_input(" n "), MenuInput::NextPage),
+            "testing paging"
+        );
+        assert!(
+            matches!(parse_menu_input("clip bruteforce"), MenuInput::Other(_)),
+            "testing anything else"
+        );
+    }
+
+    #[test]
+    fn menu_search() {
+        let mut menu = Menu::default();
+        let commands = |menu: &Menu| {
+            menu.visible()
+                .into_iter()
+                .map(|e| e.command.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            menu.pages(),
+            commands(&menu).len().div_ceil(PAGE_SIZE),
+            "testing the page count"
+        );
+        menu.next_page();
+        menu.next_page();
+        assert!(menu.page < menu.pages(), "testing paging past the end");
+
+        menu.search("clip");
+        let clips = commands(&menu).len();
+        assert!(
+            commands(&menu)
+                .iter()
+                .any(|c| matches!(c, Commands::ClipforceBatch { .. })),
+            "testing a search"
+        );
+        menu.search("batch");
+        assert!(
+            matches!(commands(&menu)[..], [Commands::ClipforceBatch { .. }]),
+            "testing a refined search"
+        );
+        assert!(clips > 1, "testing the search narrowing down");
+
+        menu.search("nonsense");
+        assert_eq!(menu.render().len(), 1, "testing a search without results");
+        menu.clear_search();
+        assert_eq!(menu.page, 0, "testing the reset page");
+        assert!(
+            mode_help(&Commands::Update {
+                install: false,
+                public_key: None
+            })
+            .contains("--install"),
+            "testing the inline help"
+        );
+    }
+}
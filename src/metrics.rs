@@ -0,0 +1,146 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use time::OffsetDateTime;
+
+use crate::config::Cli;
+use crate::util::data_dir;
+
+const METRICS_FILE: &str = "metrics.json";
+// bump whenever a field gets renamed or removed, new fields alone don't need it
+const SCHEMA_VERSION: u32 = 1;
+
+// counters that haven't been written to the snapshot yet
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    requests: AtomicU64,
+    bytes: AtomicU64,
+    run_counted: AtomicBool,
+}
+
+impl Counters {
+    const fn new() -> Self {
+        Self {
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            requests: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            run_counted: AtomicBool::new(false),
+        }
+    }
+
+    fn request(&self, bytes: u64) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+}
+
+// the ones of the current process
+static PENDING: Counters = Counters::new();
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub schema_version: u32,
+    pub updated_at: i64,
+    pub runs: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub requests: u64,
+    pub bytes: u64,
+}
+
+pub fn record_hit() {
+    PENDING.hits.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_miss() {
+    PENDING.misses.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn record_request(bytes: u64) {
+    PENDING.request(bytes);
+}
+
+fn metrics_path(flags: &Cli) -> Option<PathBuf> {
+    match &flags.metrics {
+        Some(path) => Some(PathBuf::from(path)),
+        None => data_dir().map(|dir| dir.join(METRICS_FILE)),
+    }
+}
+
+impl MetricsSnapshot {
+    // adds everything counted since the last write on top of the stored totals
+    fn merge(mut self, pending: &Counters) -> Self {
+        if !pending.run_counted.swap(true, Ordering::Relaxed) {
+            self.runs += 1;
+        }
+        self.hits += pending.hits.swap(0, Ordering::Relaxed);
+        self.misses += pending.misses.swap(0, Ordering::Relaxed);
+        self.requests += pending.requests.swap(0, Ordering::Relaxed);
+        self.bytes += pending.bytes.swap(0, Ordering::Relaxed);
+        self.schema_version = SCHEMA_VERSION;
+        self.updated_at = OffsetDateTime::now_utc().unix_timestamp();
+        self
+    }
+}
+
+// called at the end of a run, and periodically by the long-running modes
+pub fn write_snapshot(flags: &Cli) {
+    let Some(path) = metrics_path(flags) else {
+        return;
+    };
+    let stored: MetricsSnapshot = fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default();
+
+    match serde_json::to_string_pretty(&stored.merge(&PENDING)) {
+        Ok(s) => {
+            if let Err(e) = fs::write(&path, s) {
+                debug!("Couldn't write the metrics snapshot - {e}");
+            }
+        }
+        Err(e) => debug!("Couldn't serialize the metrics snapshot - {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::{Counters, MetricsSnapshot};
+
+    #[test]
+    fn merge_metrics() {
+        // a counter of its own, the other tests keep adding to the global one
+        let pending = Counters::new();
+        pending.hits.fetch_add(1, Ordering::Relaxed);
+        pending.request(100);
+        pending.request(50);
+
+        let merged = MetricsSnapshot {
+            runs: 2,
+            hits: 1,
+            requests: 10,
+            bytes: 1000,
+            ..Default::default()
+        }
+        .merge(&pending);
+        assert_eq!(merged.runs, 3, "testing run counter");
+        assert_eq!(merged.hits, 2, "testing hit counter");
+        assert_eq!(merged.requests, 12, "testing request counter");
+        assert_eq!(merged.bytes, 1150, "testing byte counter");
+
+        let again = MetricsSnapshot::default().merge(&pending);
+        assert_eq!(
+            (again.runs, again.hits, again.requests),
+            (0, 0, 0),
+            "testing counters only being merged once"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use reqwest::StatusCode;
+use serde::Serialize;
+use std::{collections::HashMap, fs, time::Duration};
+use time::OffsetDateTime;
+
+use crate::config::MonitorOptions;
+use crate::feed::load_history;
+use crate::http::HttpContext;
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DecayAlert {
+    pub url: String,
+    pub status: u16,
+    pub checked_at: i64,
+}
+
+// one URL per line, the lines starting with # are comments
+pub fn parse_url_list(list: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for line in list.lines().map(str::trim) {
+        if !line.is_empty() && !line.starts_with('#') && !urls.iter().any(|u| u == line) {
+            urls.push(line.to_string());
+        }
+    }
+    urls
+}
+
+// the URL list file, or every playlist in the recovery history without one
+pub fn monitor_targets(file: &Option<String>) -> Result<Vec<String>> {
+    match file {
+        Some(path) => Ok(parse_url_list(&fs::read_to_string(path)?)),
+        None => {
+            let mut urls: Vec<String> = Vec::new();
+            for entry in load_history() {
+                for url in entry.event.urls {
+                    if !urls.contains(&url.url) {
+                        urls.push(url.url);
+                    }
+                }
+            }
+            Ok(urls)
+        }
+    }
+}
+
+// Twitch answers with a 403 or a 404 once the VOD's storage window is over
+fn is_gone(status: StatusCode) -> bool {
+    status == StatusCode::FORBIDDEN || status == StatusCode::NOT_FOUND
+}
+
+async fn alert(alert: &DecayAlert, options: &MonitorOptions, http: &HttpContext) {
+    warn!(
+        "{} stopped being available (got a {})",
+        alert.url, alert.status
+    );
+    let Some(webhook) = &options.webhook else {
+        return;
+    };
+    let resp = http.send(http.client.post(webhook).json(alert)).await;
+    match resp.and_then(|r| r.error_for_status()) {
+        Ok(_) => debug!("Sent the alert for {} to {webhook}", alert.url),
+        Err(e) => warn!(
+            "Couldn't send the alert for {} to {webhook}: {e}",
+            alert.url
+        ),
+    }
+}
+
+// checks every URL once, the ones that went from available (or unchecked) to gone get alerted
+// about, a URL that's gone only gets alerted about once
+pub async fn check_urls(
+    urls: &[String],
+    state: &mut HashMap<String, bool>,
+    options: &MonitorOptions,
+    http: &HttpContext,
+) -> Vec<DecayAlert> {
+    let mut alerts = Vec::new();
+    for url in urls {
+        let status = match http.send_with_retry(http.client.head(url)).await {
+            Ok(r) => r.status(),
+            Err(e) => {
+                // a network error says nothing about the VOD, it gets checked again next round
+                debug!("Couldn't check {url} - {e}");
+                continue;
+            }
+        };
+        let gone = is_gone(status);
+        let was_gone = state.insert(url.clone(), gone).unwrap_or(false);
+        if gone && !was_gone {
+            let decay = DecayAlert {
+                url: url.clone(),
+                status: status.as_u16(),
+                checked_at: OffsetDateTime::now_utc().unix_timestamp(),
+            };
+            alert(&decay, options, http).await;
+            alerts.push(decay);
+        }
+    }
+    alerts
+}
+
+pub async fn monitor(
+    file: &Option<String>,
+    options: &MonitorOptions,
+    http: &HttpContext,
+) -> Result<()> {
+    let urls = monitor_targets(file)?;
+    if urls.is_empty() {
+        info!("There's nothing to monitor");
+        return Ok(());
+    }
+
+    let mut state: HashMap<String, bool> = HashMap::new();
+    loop {
+        let alerts = check_urls(&urls, &mut state, options, http).await;
+        if !options.report.simple {
+            let gone = state.values().filter(|gone| **gone).count();
+            info!(
+                "Checked {} playlists, {} gone ({} new)",
+                urls.len(),
+                gone,
+                alerts.len()
+            );
+        }
+        if options.once {
+            return Ok(());
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(options.interval)) => (),
+            _ = options.cancel.cancelled() => return Ok(()),
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use crate::config::MonitorOptions;
+    use crate::http::{Fixtures, HttpContext};
+
+    use super::{check_urls, parse_url_list};
+
+    #[test]
+    fn url_list() {
+        assert_eq!(
+            parse_url_list("# saved\nhttps://a/index-dvr.m3u8\n\nhttps://a/index-dvr.m3u8\n https://b/index-dvr.m3u8 \n"),
+            vec![
+                "https://a/index-dvr.m3u8".to_string(),
+                "https://b/index-dvr.m3u8".to_string()
+            ],
+            "testing the URL list"
+        );
+    }
+
+    #[tokio::test]
+    async fn availability_decay() {
+        let urls = vec![
+            "https://a.cloudfront.net/x/chunked/index-dvr.m3u8".to_string(),
+            "https://b.cloudfront.net/y/chunked/index-dvr.m3u8".to_string(),
+        ];
+        let fixtures = Arc::new(
+            Fixtures::new()
+                .respond(&urls[0], 200, "")
+                .respond(&urls[0], 403, "")
+                .respond(&urls[1], 404, ""),
+        );
+        let http = HttpContext::default().with_fixtures(fixtures);
+        let options = MonitorOptions::default();
+        let mut state = HashMap::new();
+
+        let first = check_urls(&urls, &mut state, &options, &http).await;
+        assert_eq!(
+            first.iter().map(|a| a.status).collect::<Vec<_>>(),
+            vec![404],
+            "testing a playlist that's already gone"
+        );
+        let second = check_urls(&urls, &mut state, &options, &http).await;
+        assert_eq!(
+            second
+                .iter()
+                .map(|a| (a.url.as_str(), a.status))
+                .collect::<Vec<_>>(),
+            vec![(urls[0].as_str(), 403)],
+            "testing a playlist going away, without alerting about the old one again"
+        );
+    }
+}
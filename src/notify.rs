@@ -0,0 +1,438 @@
+use anyhow::{anyhow, Result};
+use log::{debug, warn};
+use percent_encoding::percent_decode_str;
+use rand::distr::{Alphanumeric, SampleString};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::time::Duration;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+use url::{Host, Url};
+
+use crate::config::ReportOptions;
+use crate::http::HttpContext;
+use crate::twitch::models::ReturnURL;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecoveryEvent {
+    pub username: String,
+    pub broadcast_id: i64,
+    pub timestamp: Option<i64>,
+    pub urls: Vec<ReturnURL>,
+}
+
+impl RecoveryEvent {
+    fn to_text(&self) -> String {
+        let mut text = format!(
+            "VOD recovered for {} (broadcast {})",
+            self.username, self.broadcast_id
+        );
+        for url in &self.urls {
+            text.push('\n');
+            text.push_str(&url.url);
+            if url.muted {
+                text.push_str(" (muted)");
+            }
+        }
+        text
+    }
+}
+
+// what --webhook gets, the muted flag is set when any of the playlists has muted segments
+fn webhook_payload(event: &RecoveryEvent) -> serde_json::Value {
+    json!({
+        "streamer": event.username,
+        "broadcast_id": event.broadcast_id,
+        "timestamp": event.timestamp,
+        "urls": event.urls,
+        "muted": event.urls.iter().any(|u| u.muted),
+    })
+}
+
+async fn send_webhook(webhook: &str, event: &RecoveryEvent, http: &HttpContext) -> Result<()> {
+    http.send(http.client.post(webhook).json(&webhook_payload(event)))
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+// sends the event to every configured notification target, a failed notification never fails the run
+pub async fn notify_recovery(event: &RecoveryEvent, flags: &ReportOptions, http: &HttpContext) {
+    if let Some(webhook) = &flags.webhook {
+        match send_webhook(webhook, event, http).await {
+            Ok(()) => debug!("Sent the recovery of {} to {webhook}", event.broadcast_id),
+            Err(e) => warn!("Couldn't send the recovery to {webhook}: {e}"),
+        }
+    }
+    if let Some(webhook) = &flags.discord_webhook {
+        if let Err(e) = send_discord(webhook, event, http).await {
+            warn!("Couldn't send the Discord notification: {e}");
+        }
+    }
+    if let (Some(token), Some(chat_id)) = (&flags.telegram_token, &flags.telegram_chat_id) {
+        if let Err(e) = send_telegram(token, chat_id, &event.to_text(), http).await {
+            warn!("Couldn't send the Telegram notification: {e}");
+        }
+    }
+    publish_event(&event.username, "recovered", event, flags).await;
+}
+
+// publishes a JSON event to <prefix>/<channel>/<kind> when --mqtt-url is set
+pub async fn publish_event<T: Serialize>(
+    channel: &str,
+    kind: &str,
+    payload: &T,
+    flags: &ReportOptions,
+) {
+    let Some(broker) = &flags.mqtt_url else {
+        return;
+    };
+    let topic = format!(
+        "{}/{}/{kind}",
+        flags.mqtt_topic_prefix.trim_end_matches('/'),
+        channel.to_lowercase()
+    );
+    let payload = match serde_json::to_vec(payload) {
+        Ok(p) => p,
+        Err(e) => {
+            warn!("Couldn't serialize the MQTT payload: {e}");
+            return;
+        }
+    };
+    match timeout(MQTT_TIMEOUT, publish_mqtt(broker, &topic, &payload)).await {
+        Ok(Ok(())) => debug!("Published the MQTT event to {topic}"),
+        Ok(Err(e)) => warn!("Couldn't publish the MQTT event: {e}"),
+        Err(_) => warn!("Couldn't publish the MQTT event: the broker timed out"),
+    }
+}
+
+// Twitch purple
+const DISCORD_COLOR: u32 = 0x9146FF;
+// the limit of an embed's description
+const DISCORD_DESCRIPTION_LIMIT: usize = 4096;
+
+// the CDN and the quality, the full URLs are too long to read in a message
+fn discord_link(url: &ReturnURL) -> String {
+    let label = Url::parse(&url.url)
+        .ok()
+        .and_then(|u| {
+            let host = u.host_str()?.to_string();
+            let quality = u.path_segments()?.rev().nth(1)?.to_string();
+            Some(format!("{host} ({quality})"))
+        })
+        .unwrap_or_else(|| url.url.clone());
+    let mut link = format!("[{label}]({})", url.url);
+    if url.audio_only {
+        link.push_str(" - audio only");
+    }
+    if url.muted {
+        link.push_str(" - muted");
+    }
+    link
+}
+
+// an embed with the streamer, the start (shown in every reader's own timezone) and a link per URL
+fn discord_payload(event: &RecoveryEvent) -> serde_json::Value {
+    let mut description = String::new();
+    for link in event.urls.iter().map(discord_link) {
+        // the links that don't fit get dropped instead of the whole message failing
+        if description.len() + link.len() + 1 > DISCORD_DESCRIPTION_LIMIT {
+            break;
+        }
+        description.push_str(&link);
+        description.push('\n');
+    }
+
+    let mut fields = vec![json!({
+        "name": "Broadcast ID",
+        "value": event.broadcast_id.to_string(),
+        "inline": true,
+    })];
+    let mut embed = json!({
+        "title": format!("VOD recovered for {}", event.username),
+        "url": format!("https://www.twitch.tv/{}", event.username),
+        "color": DISCORD_COLOR,
+        "description": description.trim_end(),
+    });
+    if let Some(timestamp) = event.timestamp {
+        fields.push(json!({
+            "name": "Started",
+            "value": format!("<t:{timestamp}:F>"),
+            "inline": true,
+        }));
+        if let Some(date) = OffsetDateTime::from_unix_timestamp(timestamp)
+            .ok()
+            .and_then(|d| d.format(&Rfc3339).ok())
+        {
+            embed["timestamp"] = json!(date);
+        }
+    }
+    embed["fields"] = json!(fields);
+    json!({ "username": "tbf", "embeds": [embed] })
+}
+
+async fn send_discord(webhook: &str, event: &RecoveryEvent, http: &HttpContext) -> Result<()> {
+    http.send(http.client.post(webhook).json(&discord_payload(event)))
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+async fn send_telegram(
+    token: &str,
+    chat_id: &str,
+    text: &str,
+    http: &HttpContext,
+) -> reqwest::Result<()> {
+    let request = http
+        .client
+        .post(format!("https://api.telegram.org/bot{token}/sendMessage"))
+        .json(&json!({
+            "chat_id": chat_id,
+            "text": text,
+            "disable_web_page_preview": true,
+        }));
+    let resp = http.send(request).await?;
+    resp.error_for_status()?;
+    debug!("Sent the Telegram notification");
+    Ok(())
+}
+
+const MQTT_TIMEOUT: Duration = Duration::from_secs(10);
+const MQTT_KEEPALIVE: u16 = 60;
+
+fn mqtt_remaining_length(mut len: usize, buf: &mut Vec<u8>) {
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+}
+
+fn mqtt_string(s: &str, buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&(s.len() as u16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn mqtt_packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    mqtt_remaining_length(body.len(), &mut packet);
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn mqtt_connect(client_id: &str, username: Option<&str>, password: Option<&str>) -> Vec<u8> {
+    let mut body = Vec::new();
+    mqtt_string("MQTT", &mut body);
+    // protocol level 4 is MQTT 3.1.1
+    body.push(4);
+    let mut connect_flags = 0x02;
+    if username.is_some() {
+        connect_flags |= 0x80;
+    }
+    if password.is_some() {
+        connect_flags |= 0x40;
+    }
+    body.push(connect_flags);
+    body.extend_from_slice(&MQTT_KEEPALIVE.to_be_bytes());
+    mqtt_string(client_id, &mut body);
+    if let Some(username) = username {
+        mqtt_string(username, &mut body);
+    }
+    if let Some(password) = password {
+        mqtt_string(password, &mut body);
+    }
+    mqtt_packet(0x10, &body)
+}
+
+fn mqtt_publish(topic: &str, payload: &[u8]) -> Vec<u8> {
+    let mut body = Vec::new();
+    mqtt_string(topic, &mut body);
+    body.extend_from_slice(payload);
+    // QoS 0, no retain
+    mqtt_packet(0x30, &body)
+}
+
+// host_str keeps the brackets around IPv6 literals, which the resolver doesn't take
+fn mqtt_address(url: &Url) -> Result<(String, u16)> {
+    let host = match url.host() {
+        Some(Host::Domain(domain)) => domain.to_string(),
+        Some(Host::Ipv4(ip)) => ip.to_string(),
+        Some(Host::Ipv6(ip)) => ip.to_string(),
+        None => return Err(anyhow!("the MQTT URL is missing a host")),
+    };
+    Ok((host, url.port().unwrap_or(1883)))
+}
+
+// the URL keeps them percent-encoded, the broker wants them as they were typed
+fn mqtt_credentials(url: &Url) -> Result<(Option<String>, Option<String>)> {
+    let decode =
+        |s: &str| -> Result<String> { Ok(percent_decode_str(s).decode_utf8()?.into_owned()) };
+    let username = match url.username() {
+        "" => None,
+        username => Some(decode(username)?),
+    };
+    let password = url.password().map(decode).transpose()?;
+    Ok((username, password))
+}
+
+// a fire-and-forget QoS 0 publish, enough for automations listening on a local broker
+async fn publish_mqtt(broker: &str, topic: &str, payload: &[u8]) -> Result<()> {
+    let url = Url::parse(broker)?;
+    if url.scheme() != "mqtt" && url.scheme() != "tcp" {
+        return Err(anyhow!("only mqtt:// brokers are supported"));
+    }
+    let (host, port) = mqtt_address(&url)?;
+    let (username, password) = mqtt_credentials(&url)?;
+
+    let mut stream = TcpStream::connect((host.as_str(), port)).await?;
+    let client_id = format!("tbf-{}", Alphanumeric.sample_string(&mut rand::rng(), 8));
+    stream
+        .write_all(&mqtt_connect(
+            &client_id,
+            username.as_deref(),
+            password.as_deref(),
+        ))
+        .await?;
+
+    let mut connack = [0u8; 4];
+    stream.read_exact(&mut connack).await?;
+    if connack[0] != 0x20 || connack[3] != 0 {
+        return Err(anyhow!(
+            "the broker refused the connection (code {})",
+            connack[3]
+        ));
+    }
+
+    stream.write_all(&mqtt_publish(topic, payload)).await?;
+    stream.write_all(&[0xE0, 0x00]).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use std::sync::Arc;
+    use url::Url;
+
+    use crate::config::ReportOptions;
+    use crate::http::{Fixtures, HttpContext};
+    use crate::twitch::models::ReturnURL;
+
+    use super::{
+        discord_payload, mqtt_address, mqtt_connect, mqtt_credentials, mqtt_publish,
+        mqtt_remaining_length, notify_recovery, webhook_payload, RecoveryEvent,
+    };
+
+    #[tokio::test]
+    async fn webhook() {
+        let event = RecoveryEvent {
+            username: "dansgaming".to_string(),
+            broadcast_id: 42218705421,
+            timestamp: Some(1622854217),
+            urls: vec![ReturnURL {
+                url: "https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8".to_string(),
+                muted: true,
+                audio_only: false,
+            }],
+        };
+        let payload = webhook_payload(&event);
+        assert_eq!(
+            payload["streamer"],
+            json!("dansgaming"),
+            "testing the streamer"
+        );
+        assert_eq!(payload["muted"], json!(true), "testing the muted flag");
+        assert_eq!(
+            payload["urls"][0]["url"],
+            json!(event.urls[0].url),
+            "testing the URLs"
+        );
+
+        let webhook = "https://example.com/tbf";
+        let fixtures = Arc::new(Fixtures::new().respond(webhook, 200, ""));
+        let http = HttpContext::default().with_fixtures(fixtures.clone());
+        notify_recovery(&event, &ReportOptions::default().webhook(webhook), &http).await;
+        notify_recovery(&event, &ReportOptions::default(), &http).await;
+        assert_eq!(
+            fixtures.requested(),
+            vec![webhook],
+            "testing a POST per recovery with --webhook"
+        );
+
+        let embed = &discord_payload(&event)["embeds"][0];
+        assert_eq!(
+            embed["title"],
+            json!("VOD recovered for dansgaming"),
+            "testing the Discord title"
+        );
+        assert_eq!(
+            embed["description"],
+            json!(format!(
+                "[d1m7jfoe9zdc1j.cloudfront.net (chunked)]({}) - muted",
+                event.urls[0].url
+            )),
+            "testing the Discord links"
+        );
+        assert_eq!(
+            embed["fields"][1]["value"],
+            json!("<t:1622854217:F>"),
+            "testing the Discord date"
+        );
+        assert_eq!(
+            embed["timestamp"],
+            json!("2021-06-05T00:50:17Z"),
+            "testing the embed timestamp"
+        );
+    }
+
+    #[test]
+    fn mqtt_packets() {
+        let mut len = Vec::new();
+        mqtt_remaining_length(321, &mut len);
+        assert_eq!(len, vec![0xC1, 0x02], "testing multi-byte remaining length");
+
+        assert_eq!(
+            mqtt_publish("tbf/a", b"{}"),
+            vec![0x30, 0x09, 0x00, 0x05, b't', b'b', b'f', b'/', b'a', b'{', b'}'],
+            "testing publish packet"
+        );
+
+        let connect = mqtt_connect("tbf-1", Some("user"), None);
+        assert_eq!(connect[0], 0x10, "testing connect packet type");
+        assert_eq!(connect[9], 0x82, "testing connect flags with a username");
+        assert_eq!(
+            connect.len() as u8 - 2,
+            connect[1],
+            "testing connect remaining length"
+        );
+
+        let url = Url::parse("mqtt://us%40er:p%3Ass%25@[::1]:1884").unwrap();
+        assert_eq!(
+            mqtt_credentials(&url).unwrap(),
+            (Some("us@er".to_string()), Some("p:ss%".to_string())),
+            "testing encoded credentials"
+        );
+        assert_eq!(
+            mqtt_address(&url).unwrap(),
+            ("::1".to_string(), 1884),
+            "testing an IPv6 broker"
+        );
+        assert_eq!(
+            mqtt_credentials(&Url::parse("mqtt://localhost").unwrap()).unwrap(),
+            (None, None),
+            "testing no credentials"
+        );
+    }
+}
@@ -0,0 +1,543 @@
+//! The parts of tbf that don't need the network: the VOD hash and the URLs built from it,
+//! timestamp parsing and playlist rewriting. This module (and [`tracker`](crate::tracker)) builds
+//! without the default `net` feature, including for wasm32, so front ends can reuse the hashing
+//! scheme instead of re-implementing it.
+
+use anyhow::{anyhow, Result};
+use lazy_static::lazy_static;
+use log::debug;
+use regex::Regex;
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, PrimitiveDateTime,
+};
+
+lazy_static! {
+    static ref RE_UNIX: Regex = Regex::new(r"^\d*$").unwrap();
+    static ref RE_UTC: Regex = Regex::new("UTC").unwrap();
+    static ref FIX_REGEX: Regex = Regex::new(r"[^/]+").unwrap();
+    // 12.ts, 12-muted.ts, 12-unmuted.ts, 12-unmuted-1.ts, optionally followed by a query
+    static ref SEGMENT_REGEX: Regex =
+        Regex::new(r"^(?P<index>\d+)(?:-(?:un)?muted(?:-\d+)?)?\.ts(?:\?.*)?$").unwrap();
+}
+
+pub fn is_unmuted_segment(uri: &str) -> bool {
+    uri.contains("unmuted")
+}
+
+pub fn muted_segment_url(base_url: &str, uri: &str) -> String {
+    match SEGMENT_REGEX.captures(uri) {
+        Some(c) => format!("{base_url}{}-muted.ts", &c["index"]),
+        None => {
+            debug!("Couldn't parse the segment name, leaving it as is - {uri}");
+            format!("{base_url}{uri}")
+        }
+    }
+}
+
+// what goes into the SHA1 of the playlist's directory
+pub fn hash_input(username: &str, vod: i64, timestamp: i64) -> String {
+    format!("{username}_{vod}_{timestamp}")
+}
+
+pub fn full_vod_hash(username: &str, vod: i64, timestamp: i64) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(hash_input(username, vod, timestamp).as_str());
+    format!("{:x}", hasher.finalize())
+}
+
+pub fn vod_hash(username: &str, vod: i64, timestamp: i64) -> String {
+    full_vod_hash(username, vod, timestamp)[0..20].to_string()
+}
+
+// every step of building the playlist URLs, for checking them by hand or against other tools
+#[derive(Debug, PartialEq, Serialize)]
+pub struct HashBreakdown {
+    pub input: String,
+    pub sha1: String,
+    pub hash: String,
+    pub urls: Vec<String>,
+}
+
+pub fn hash_breakdown(username: &str, vod: i64, timestamp: i64, cdns: &[String]) -> HashBreakdown {
+    let sha1 = full_vod_hash(username, vod, timestamp);
+    let hash = sha1[0..20].to_string();
+    HashBreakdown {
+        input: hash_input(username, vod, timestamp),
+        urls: cdns
+            .iter()
+            .map(|cdn| playlist_url(cdn, &hash, username, vod, timestamp))
+            .collect(),
+        sha1,
+        hash,
+    }
+}
+
+// the username exactly as typed comes first, then the variants people usually get wrong:
+// the lowercase login and the legacy names with (or without) a trailing underscore
+pub fn username_variants(username: &str) -> Vec<String> {
+    let lowercase = username.to_lowercase();
+    let underscore = match lowercase.strip_suffix('_') {
+        Some(stripped) => stripped.to_string(),
+        None => format!("{lowercase}_"),
+    };
+
+    let mut variants = vec![username.to_string()];
+    for variant in [lowercase, underscore] {
+        if !variant.is_empty() && !variants.contains(&variant) {
+            variants.push(variant);
+        }
+    }
+    variants
+}
+
+// segment lines are the ones that aren't tags, comments or empty
+pub fn is_segment_line(line: &str) -> bool {
+    let line = line.trim();
+    !line.is_empty() && !line.starts_with('#')
+}
+
+// turns a segment line into an absolute URL, pointing unmuted segments to their muted versions
+pub fn fix_segment_line(base_url: &str, line: &str) -> String {
+    let uri = line.trim();
+    if is_unmuted_segment(uri) {
+        muted_segment_url(base_url, uri)
+    } else {
+        format!("{base_url}{uri}")
+    }
+}
+
+// the playlist URL on the given CDN for a hash from vod_hash
+pub fn playlist_url(cdn: &str, hash: &str, username: &str, vod: i64, timestamp: i64) -> String {
+    format!("https://{cdn}/{hash}_{username}_{vod}_{timestamp}/chunked/index-dvr.m3u8")
+}
+
+// the URL the segments of a playlist are relative to and the VOD's directory name,
+// only twitch.tv and cloudfront.net URLs are supported
+pub fn playlist_base_url(url: &str) -> Option<(String, String)> {
+    if !(url.contains("twitch.tv") || url.contains("cloudfront.net")) {
+        return None;
+    }
+
+    let parts: Vec<&str> = FIX_REGEX.find_iter(url).map(|m| m.as_str()).collect();
+    if parts.len() < 4 {
+        return None;
+    }
+
+    Some((
+        format!("https://{}/{}/{}/", parts[1], parts[2], parts[3]),
+        parts[2].to_string(),
+    ))
+}
+
+// a VOD's directory is <hash>_<username>_<id>_<timestamp>, the username can have underscores of
+// its own
+pub fn split_vod_directory(directory: &str) -> Option<(String, String, i64, i64)> {
+    let parts: Vec<&str> = directory.split('_').collect();
+    if parts.len() < 4 {
+        return None;
+    }
+    Some((
+        parts[0].to_string(),
+        parts[1..parts.len() - 2].join("_"),
+        parts[parts.len() - 2].parse().ok()?,
+        parts[parts.len() - 1].parse().ok()?,
+    ))
+}
+
+// rewrites a whole playlist at once, returning it with the amount of segments pointed to their muted versions
+pub fn rewrite_playlist(base_url: &str, body: &str) -> (String, usize) {
+    let mut muted = 0;
+    let mut playlist = String::with_capacity(body.len());
+    for line in body.lines() {
+        if is_segment_line(line) {
+            muted += is_unmuted_segment(line) as usize;
+            playlist.push_str(&fix_segment_line(base_url, line));
+        } else {
+            playlist.push_str(line);
+        }
+        playlist.push('\n');
+    }
+    (playlist, muted)
+}
+
+// 2h, 90m, 1h30m, 1d or plain seconds, None if it doesn't parse or doesn't fit into an i64
+fn parse_duration(duration: &str) -> Option<i64> {
+    if duration.is_empty() {
+        return None;
+    }
+    let (mut total, mut number) = (0i64, String::new());
+    for c in duration.chars() {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => return None,
+        };
+        let seconds = number.parse::<i64>().ok()?.checked_mul(unit)?;
+        total = total.checked_add(seconds)?;
+        number.clear();
+    }
+    if !number.is_empty() {
+        total = total.checked_add(number.parse::<i64>().ok()?)?;
+    }
+    Some(total)
+}
+
+// now, now-2h, now+15m or now-1h30m, counted from `now`
+pub fn parse_relative_timestamp(timestamp: &str, now: i64) -> Option<i64> {
+    let offset = timestamp.trim().to_lowercase();
+    let offset = offset.strip_prefix("now")?;
+    if offset.is_empty() {
+        Some(now)
+    } else if let Some(duration) = offset.strip_prefix('-') {
+        now.checked_sub(parse_duration(duration)?)
+    } else {
+        now.checked_add(parse_duration(offset.strip_prefix('+')?)?)
+    }
+}
+
+// the range of bruteforce, either the two timestamps or `from..to` in the first one
+pub fn split_range<'a>(from: &'a str, to: Option<&'a str>) -> Result<(&'a str, &'a str)> {
+    match (from.split_once(".."), to) {
+        (None, Some(to)) => Ok((from, to)),
+        (Some((from, to)), None) => Ok((from.trim(), to.trim())),
+        (Some(_), Some(_)) => Err(anyhow!(
+            "{from} is a range already, leave out the last timestamp"
+        )),
+        (None, None) => Err(anyhow!(
+            "the last timestamp is missing, or use a range like now-90m..now"
+        )),
+    }
+}
+
+pub fn parse_timestamp(timestamp: &str) -> Result<i64> {
+    // resolved when it gets parsed, the clock is only read for these. reading it panics on
+    // wasm32, so without `net` it's up to the caller to go through parse_relative_timestamp
+    #[cfg(feature = "net")]
+    if timestamp.trim().to_lowercase().starts_with("now") {
+        let now = time::OffsetDateTime::now_utc().unix_timestamp();
+        return parse_relative_timestamp(timestamp, now).ok_or_else(|| {
+            anyhow!("couldn't parse {timestamp}, relative timestamps look like now-2h or now-1h30m")
+        });
+    }
+
+    let format_with_utc = format_description!("[year]-[month]-[day] [hour]:[minute]:[second] UTC");
+    let format_wo_utc = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+    let format_wo_sec = format_description!("[day]-[month]-[year] [hour]:[minute]");
+
+    if RE_UNIX.is_match(timestamp) {
+        Ok(timestamp.parse::<i64>()?)
+    } else if RE_UTC.is_match(timestamp) {
+        let dt = PrimitiveDateTime::parse(timestamp, format_with_utc)?;
+        Ok(dt.assume_utc().unix_timestamp())
+    } else {
+        // Try parsing as RFC3339 first
+        if let Ok(result) = PrimitiveDateTime::parse(timestamp, &Rfc3339) {
+            return Ok(result.assume_utc().unix_timestamp());
+        }
+
+        // Try parsing without UTC
+        if let Ok(result) = PrimitiveDateTime::parse(timestamp, format_wo_utc) {
+            return Ok(result.assume_utc().unix_timestamp());
+        }
+
+        // Try parsing without seconds
+        let result = PrimitiveDateTime::parse(timestamp, format_wo_sec)?;
+        Ok(result.assume_utc().unix_timestamp())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IdGuess {
+    pub estimate: i64,
+    pub low: i64,
+    pub high: i64,
+    // how many broadcast IDs Twitch handed out per hour between the two reference points
+    pub per_hour: f64,
+}
+
+// broadcast IDs grow roughly linearly with time, so the ID of a stream started at `target` is
+// somewhere on the line through the two known (id, unix time) points. the further from the
+// closest reference point, the less the line can be trusted, so the range widens with
+// `tolerance` (a fraction of the IDs in between) and never gets narrower than an hour's worth
+pub fn guess_broadcast_id(
+    first: (i64, i64),
+    second: (i64, i64),
+    target: i64,
+    tolerance: f64,
+) -> Result<IdGuess> {
+    let (first, second) = if first.1 <= second.1 {
+        (first, second)
+    } else {
+        (second, first)
+    };
+    if first.1 == second.1 {
+        return Err(anyhow!("the reference points need different dates"));
+    }
+    if second.0 <= first.0 {
+        return Err(anyhow!(
+            "the newer reference point has to have the bigger broadcast ID"
+        ));
+    }
+
+    let per_second = (second.0 - first.0) as f64 / (second.1 - first.1) as f64;
+    let estimate = first.0 as f64 + per_second * (target - first.1) as f64;
+    let nearest = [first.0, second.0]
+        .iter()
+        .map(|id| (estimate - *id as f64).abs())
+        .fold(f64::MAX, f64::min);
+    // inside the reference points the error can't be bigger than the gap to the closest one
+    let margin = (nearest * tolerance.max(0.0)).max(per_second * 3600.0);
+
+    Ok(IdGuess {
+        estimate: estimate.round() as i64,
+        low: (estimate - margin).floor().max(0.0) as i64,
+        high: (estimate + margin).ceil() as i64,
+        per_hour: per_second * 3600.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fix_segment_line, guess_broadcast_id, hash_breakdown, muted_segment_url,
+        parse_relative_timestamp, parse_timestamp, playlist_base_url, playlist_url,
+        rewrite_playlist, split_range, username_variants, vod_hash,
+    };
+
+    #[test]
+    fn id_guess() {
+        let first = (40000000000, 1600000000);
+        let second = (40000360000, 1600360000);
+
+        let guess = guess_broadcast_id(second, first, 1600720000, 0.05).unwrap();
+        assert_eq!(
+            (guess.estimate, guess.low, guess.high),
+            (40000720000, 40000702000, 40000738000),
+            "testing extrapolating past the newer reference point"
+        );
+        let guess = guess_broadcast_id(first, second, 1600180000, 0.05).unwrap();
+        assert_eq!(
+            (guess.estimate, guess.low, guess.high),
+            (40000180000, 40000171000, 40000189000),
+            "testing interpolating between the reference points"
+        );
+        let guess = guess_broadcast_id(first, second, 1600000100, 0.05).unwrap();
+        assert_eq!(
+            (guess.low, guess.high),
+            (39999996500, 40000003700),
+            "testing the hour-wide minimum range"
+        );
+        assert!(
+            guess_broadcast_id((2, 1600000000), (1, 1600360000), 1600000000, 0.05).is_err(),
+            "testing IDs going down over time"
+        );
+    }
+
+    #[test]
+    fn hash() {
+        assert_eq!(
+            vod_hash("dansgaming", 42218705421, 1622854217),
+            "d3dcbaf880c9e36ed8c8",
+            "testing vod hash"
+        );
+
+        let breakdown = hash_breakdown(
+            "dansgaming",
+            42218705421,
+            1622854217,
+            &["vod-secure.twitch.tv".to_string()],
+        );
+        assert_eq!(
+            breakdown.input, "dansgaming_42218705421_1622854217",
+            "testing the hash input"
+        );
+        assert!(
+            breakdown.sha1.len() == 40 && breakdown.sha1.starts_with(&breakdown.hash),
+            "testing the full hash"
+        );
+        assert_eq!(
+            breakdown.urls,
+            vec!["https://vod-secure.twitch.tv/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8".to_string()],
+            "testing the URL per CDN"
+        );
+    }
+
+    #[test]
+    fn variants() {
+        assert_eq!(
+            username_variants("DansGaming"),
+            vec!["DansGaming", "dansgaming", "dansgaming_"],
+            "testing mixed case username"
+        );
+        assert_eq!(
+            username_variants("forsen"),
+            vec!["forsen", "forsen_"],
+            "testing lowercase username"
+        );
+        assert_eq!(
+            username_variants("Legacy_"),
+            vec!["Legacy_", "legacy_", "legacy"],
+            "testing legacy username with a trailing underscore"
+        );
+    }
+
+    #[test]
+    fn muted_segments() {
+        let base = "https://vod-secure.twitch.tv/hash_user_1_2/chunked/";
+        for (uri, expected) in [
+            ("12.ts", "12-muted.ts"),
+            ("12-unmuted.ts", "12-muted.ts"),
+            ("12-unmuted-1.ts", "12-muted.ts"),
+            ("12-muted.ts", "12-muted.ts"),
+            ("12-unmuted.ts?start_offset=0", "12-muted.ts"),
+            ("weird-name.ts", "weird-name.ts"),
+        ] {
+            assert_eq!(
+                muted_segment_url(base, uri),
+                format!("{base}{expected}"),
+                "testing segment name {uri}"
+            );
+        }
+    }
+
+    #[test]
+    fn fix_segments() {
+        let base = "https://vod-secure.twitch.tv/hash_user_1_2/chunked/";
+        assert_eq!(
+            fix_segment_line(base, "12.ts"),
+            format!("{base}12.ts"),
+            "testing unmuted segment"
+        );
+        assert_eq!(
+            fix_segment_line(base, "12-unmuted.ts"),
+            format!("{base}12-muted.ts"),
+            "testing muted segment"
+        );
+        assert_eq!(
+            fix_segment_line(base, " 12-unmuted.ts\r"),
+            format!("{base}12-muted.ts"),
+            "testing segment line with whitespace"
+        );
+    }
+    #[test]
+    fn parse_timestamps() {
+        assert_eq!(
+            parse_timestamp("1657871396").unwrap(),
+            1657871396,
+            "testing unix timestamp parsing"
+        );
+        assert_eq!(
+            parse_timestamp("2022-07-15T07:49:56+00:00").unwrap(),
+            1657871396,
+            "testing rfc parsing"
+        );
+        assert_eq!(
+            parse_timestamp("2022-07-15 07:49:56 UTC").unwrap(),
+            1657871396,
+            "testing parsing time with the UTC tag"
+        );
+        assert_eq!(
+            parse_timestamp("2022-07-15 07:49:56").unwrap(),
+            1657871396,
+            "testing parsing time w/o the UTC tag"
+        );
+        assert_eq!(
+            parse_timestamp("15-07-2022 07:49").unwrap(),
+            1657871340,
+            "testing parsing time w/o seconds"
+        );
+        assert!(
+            parse_timestamp("2022-07-15 0749").is_err(),
+            "testing parsing wrong timestamps"
+        );
+    }
+
+    #[test]
+    fn relative_timestamps() {
+        let now = 1657871396;
+        for (expression, expected) in [
+            ("now", Some(now)),
+            ("now-2h", Some(now - 7200)),
+            ("NOW-90m", Some(now - 5400)),
+            ("now-1h30m", Some(now - 5400)),
+            ("now+1d", Some(now + 86400)),
+            ("now-45", Some(now - 45)),
+            ("now-", None),
+            ("now-2y", None),
+            ("now2h", None),
+            ("now-9223372036854775807d", None),
+            ("now-9223372036854775807s1s", None),
+            ("now+9223372036854775807", None),
+            ("now-99999999999999999999", None),
+        ] {
+            assert_eq!(
+                parse_relative_timestamp(expression, now),
+                expected,
+                "testing {expression}"
+            );
+        }
+        assert!(
+            parse_timestamp("now-2x").is_err(),
+            "testing a wrong relative timestamp"
+        );
+        assert!(
+            parse_timestamp("now-9223372036854775807d").is_err(),
+            "testing an overflowing relative timestamp"
+        );
+
+        assert_eq!(
+            split_range("now-90m..now", None).unwrap(),
+            ("now-90m", "now"),
+            "testing a range"
+        );
+        assert_eq!(
+            split_range("1657871396", Some("now")).unwrap(),
+            ("1657871396", "now"),
+            "testing two timestamps"
+        );
+        assert!(split_range("now", None).is_err(), "testing a missing end");
+    }
+
+    #[test]
+    fn playlist_urls() {
+        assert_eq!(
+            playlist_url("vod-secure.twitch.tv", "d3dcbaf880c9e36ed8c8", "dansgaming", 42218705421, 1622854217),
+            "https://vod-secure.twitch.tv/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8",
+            "testing playlist url"
+        );
+        assert_eq!(
+            playlist_base_url("https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8"),
+            Some((
+                "https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/".to_string(),
+                "d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217".to_string()
+            )),
+            "testing base url"
+        );
+        assert_eq!(
+            playlist_base_url("https://example.com/a/b/c.m3u8"),
+            None,
+            "testing unsupported host"
+        );
+    }
+
+    #[test]
+    fn rewrite() {
+        let base = "https://vod-secure.twitch.tv/hash_user_1_2/chunked/";
+        assert_eq!(
+            rewrite_playlist(base, "#EXTM3U\n#EXTINF:10.000,\n0.ts\n#EXTINF:10.000,\n1-unmuted.ts\n#EXT-X-ENDLIST"),
+            (
+                format!("#EXTM3U\n#EXTINF:10.000,\n{base}0.ts\n#EXTINF:10.000,\n{base}1-muted.ts\n#EXT-X-ENDLIST\n"),
+                1
+            ),
+            "testing playlist rewrite"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use serde::Serialize;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use tokio::sync::broadcast;
+
+use crate::twitch::models::ReturnURL;
+
+// slow subscribers just miss the oldest events
+const EVENT_BUFFER: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum ProgressEvent {
+    Stage { stage: String },
+    Progress { scanned: u64, total: u64 },
+    Hit { url: ReturnURL },
+}
+
+// progress of the current scan shared with whoever started it (the server jobs for now)
+#[derive(Clone, Debug)]
+pub struct Progress {
+    scanned: Arc<AtomicU64>,
+    total: Arc<AtomicU64>,
+    events: broadcast::Sender<ProgressEvent>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ProgressSnapshot {
+    pub scanned: u64,
+    pub total: u64,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            scanned: Arc::default(),
+            total: Arc::default(),
+            events: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+}
+
+impl Progress {
+    pub fn start(&self, total: u64) {
+        self.scanned.store(0, Ordering::Relaxed);
+        self.total.store(total, Ordering::Relaxed);
+        self.stage("scanning");
+    }
+
+    // the scanned counter is polled instead of sent, there'd be an event per candidate otherwise
+    pub fn inc(&self) {
+        self.scanned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stage(&self, stage: &str) {
+        // no subscribers isn't an error
+        let _ = self.events.send(ProgressEvent::Stage {
+            stage: stage.to_string(),
+        });
+    }
+
+    pub fn hit(&self, url: &ReturnURL) {
+        let _ = self.events.send(ProgressEvent::Hit { url: url.clone() });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ProgressEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            scanned: self.scanned.load(Ordering::Relaxed),
+            total: self.total.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Progress, ProgressEvent};
+
+    #[tokio::test]
+    async fn progress_events() {
+        let progress = Progress::default();
+        let mut events = progress.subscribe();
+
+        progress.start(10);
+        progress.inc();
+        progress.inc();
+
+        assert_eq!(
+            events.recv().await.unwrap(),
+            ProgressEvent::Stage {
+                stage: "scanning".to_string()
+            },
+            "testing stage event"
+        );
+        assert_eq!(progress.snapshot().scanned, 2, "testing scanned counter");
+        assert_eq!(progress.snapshot().total, 10, "testing total counter");
+    }
+}
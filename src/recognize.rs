@@ -0,0 +1,262 @@
+use serde::Serialize;
+use url::Url;
+
+use crate::config::Commands;
+use crate::offline::{parse_timestamp, playlist_base_url, split_vod_directory};
+use crate::tracker::find_provider;
+use crate::twitch::clips::extract_slug;
+
+// broadcast IDs passed 10 digits long ago, Unix timestamps won't get there until 2286
+const MIN_ID_DIGITS: usize = 11;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputKind {
+    TrackerUrl,
+    Playlist,
+    ClipUrl,
+    ClipSlug,
+    VodUrl,
+    BroadcastId,
+    Timestamp,
+    Username,
+    Unknown,
+}
+
+impl InputKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::TrackerUrl => "a tracker stream URL",
+            Self::Playlist => "a VOD playlist URL",
+            Self::ClipUrl => "a clip URL",
+            Self::ClipSlug => "a clip slug",
+            Self::VodUrl => "a Twitch VOD URL",
+            Self::BroadcastId => "a VOD/broadcast ID",
+            Self::Timestamp => "a timestamp",
+            Self::Username => "a username (or an old-style clip slug)",
+            Self::Unknown => "nothing tbf knows",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Recognized {
+    pub kind: InputKind,
+    // the command the input would go to, with whatever else it still needs
+    pub command: Option<String>,
+    pub values: Vec<(String, String)>,
+}
+
+impl Recognized {
+    fn new(kind: InputKind, command: Option<&str>, values: Vec<(&str, String)>) -> Self {
+        Self {
+            kind,
+            command: command.map(str::to_string),
+            values: values
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        }
+    }
+
+    // the inputs that are enough to run a command on their own, for pasting them right into
+    // the interactive mode
+    pub fn to_command(&self, input: &str) -> Option<Commands> {
+        let input = input.trim().to_string();
+        match self.kind {
+            InputKind::TrackerUrl => Some(Commands::Link { url: input }),
+            InputKind::Playlist => Some(Commands::Fix {
+                url: input,
+                output: None,
+                slow: false,
+            }),
+            InputKind::ClipUrl | InputKind::ClipSlug => Some(Commands::Clip {
+                clip: input,
+                direct: false,
+            }),
+            InputKind::VodUrl => Some(Commands::Vod {
+                id: self
+                    .values
+                    .iter()
+                    .find(|(k, _)| k == "vod_id")?
+                    .1
+                    .parse()
+                    .ok()?,
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn is_username(s: &str) -> bool {
+    (3..=25).contains(&s.len()) && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn recognize_url(input: &str, url: &Url) -> Option<Recognized> {
+    let host = url.host_str()?.to_lowercase();
+
+    if let Some(provider) = find_provider(&host) {
+        let (username, broadcast_id) = provider.parse(url).ok()?;
+        return Some(Recognized::new(
+            InputKind::TrackerUrl,
+            Some("link"),
+            vec![
+                ("tracker", host.trim_start_matches("www.").to_string()),
+                ("username", username),
+                ("broadcast_id", broadcast_id),
+            ],
+        ));
+    }
+
+    if url.path().ends_with(".m3u8") {
+        let (base_url, directory) = playlist_base_url(input)?;
+        let mut values = vec![("base_url", base_url)];
+        if let Some((hash, username, broadcast_id, timestamp)) = split_vod_directory(&directory) {
+            values.push(("hash", hash));
+            values.push(("username", username));
+            values.push(("broadcast_id", broadcast_id.to_string()));
+            values.push(("timestamp", timestamp.to_string()));
+        }
+        return Some(Recognized::new(InputKind::Playlist, Some("fix"), values));
+    }
+
+    if host.ends_with("twitch.tv") {
+        let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+        if let [.., "videos", id] = segments.as_slice() {
+            if id.parse::<i64>().is_ok() {
+                return Some(Recognized::new(
+                    InputKind::VodUrl,
+                    Some("vod"),
+                    vec![("vod_id", id.to_string())],
+                ));
+            }
+        }
+        if let Ok(Some(slug)) = extract_slug(input.to_string()) {
+            return Some(Recognized::new(
+                InputKind::ClipUrl,
+                Some("clip"),
+                vec![("slug", slug)],
+            ));
+        }
+    }
+    None
+}
+
+// what a pasted string looks like to tbf, checked from the most specific to the least
+pub fn recognize(input: &str) -> Recognized {
+    let input = input.trim();
+    let unknown = Recognized::new(InputKind::Unknown, None, Vec::new());
+    if input.is_empty() {
+        return unknown;
+    }
+
+    // links copied without the scheme
+    let candidate = if !input.contains("://") && input.contains('/') {
+        format!("https://{input}")
+    } else {
+        input.to_string()
+    };
+    if let Ok(url) = Url::parse(&candidate) {
+        if url.host_str().is_some() {
+            return recognize_url(&candidate, &url).unwrap_or(unknown);
+        }
+    }
+
+    if input.chars().all(|c| c.is_ascii_digit()) && input.len() >= MIN_ID_DIGITS {
+        return Recognized::new(
+            InputKind::BroadcastId,
+            Some("exact, bruteforce, clipforce or chat"),
+            vec![("broadcast_id", input.to_string())],
+        );
+    }
+    if let Ok(timestamp) = parse_timestamp(input) {
+        return Recognized::new(
+            InputKind::Timestamp,
+            Some("exact or bruteforce"),
+            vec![("unix", timestamp.to_string())],
+        );
+    }
+    if is_username(input) {
+        return Recognized::new(
+            InputKind::Username,
+            Some("live or recover-channel"),
+            vec![("username", input.to_lowercase())],
+        );
+    }
+    if let Ok(Some(slug)) = extract_slug(input.to_string()) {
+        return Recognized::new(InputKind::ClipSlug, Some("clip"), vec![("slug", slug)]);
+    }
+    unknown
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Commands;
+
+    use super::{recognize, InputKind};
+
+    #[test]
+    fn recognized_inputs() {
+        for (input, kind, value) in [
+            (
+                "https://twitchtracker.com/forsen/streams/39619965384",
+                InputKind::TrackerUrl,
+                "39619965384",
+            ),
+            (
+                "streamscharts.com/channels/robcdee/streams/39648192487",
+                InputKind::TrackerUrl,
+                "39648192487",
+            ),
+            (
+                "https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dans_gaming_42218705421_1622854217/chunked/index-dvr.m3u8",
+                InputKind::Playlist,
+                "d3dcbaf880c9e36ed8c8",
+            ),
+            (
+                "https://www.twitch.tv/mrmouton/clip/SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx",
+                InputKind::ClipUrl,
+                "SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx",
+            ),
+            (
+                "SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx",
+                InputKind::ClipSlug,
+                "SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx",
+            ),
+            ("https://www.twitch.tv/videos/1234567890", InputKind::VodUrl, "1234567890"),
+            ("39619965384", InputKind::BroadcastId, "39619965384"),
+            ("2022-07-15 07:49:56", InputKind::Timestamp, "1657871396"),
+            ("1657871396", InputKind::Timestamp, "1657871396"),
+            ("DansGaming", InputKind::Username, "dansgaming"),
+        ] {
+            let recognized = recognize(input);
+            assert_eq!(recognized.kind, kind, "testing the kind of {input}");
+            assert!(
+                recognized.values.iter().any(|(_, v)| v == value),
+                "testing the values of {input}"
+            );
+        }
+
+        let vod = "https://www.twitch.tv/videos/1234567890";
+        assert!(
+            matches!(
+                recognize(vod).to_command(vod),
+                Some(Commands::Vod { id: 1234567890 })
+            ),
+            "testing the command of a VOD URL"
+        );
+
+        let playlist = recognize("https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dans_gaming_42218705421_1622854217/chunked/index-dvr.m3u8");
+        assert!(
+            playlist
+                .values
+                .contains(&("username".to_string(), "dans_gaming".to_string())),
+            "testing a username with an underscore"
+        );
+        assert_eq!(
+            recognize("https://google.com/search").kind,
+            InputKind::Unknown,
+            "testing an unsupported URL"
+        );
+    }
+}
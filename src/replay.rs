@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Result};
+use colored::Colorize;
+use log::info;
+use std::{io::stdin, time::Duration};
+use tokio::time::{sleep_until, Instant};
+
+use crate::twitch::chat::read_chat;
+use crate::twitch::models::ChatMessage;
+
+#[derive(Clone, Debug)]
+pub struct ReplayOptions {
+    // 2.0 plays the chat twice as fast
+    pub speed: f64,
+    // seconds into the VOD to start from
+    pub start: f64,
+    // waits for Enter before starting, so it can be started together with the player
+    pub wait: bool,
+    pub simple: bool,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            start: 0.0,
+            wait: false,
+            simple: false,
+        }
+    }
+}
+
+fn clock(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// how long after the replay started the message is due
+fn due(message: &ChatMessage, options: &ReplayOptions) -> Duration {
+    Duration::from_secs_f64(((message.offset_seconds - options.start) / options.speed).max(0.0))
+}
+
+fn line(message: &ChatMessage, simple: bool) -> String {
+    let name = if message.display_name.is_empty() {
+        &message.login
+    } else {
+        &message.display_name
+    };
+    let color = message
+        .color
+        .as_deref()
+        .and_then(|c| c.strip_prefix('#'))
+        .and_then(|c| u32::from_str_radix(c, 16).ok());
+    let name = match color {
+        Some(rgb) if !simple => name
+            .truecolor((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+            .bold()
+            .to_string(),
+        _ => name.to_string(),
+    };
+    format!(
+        "[{}] {}: {}",
+        clock(message.offset_seconds),
+        name,
+        message.message
+    )
+}
+
+pub async fn replay(path: &str, options: &ReplayOptions) -> Result<usize> {
+    if options.speed <= 0.0 {
+        return Err(anyhow!("The replay speed has to be above 0"));
+    }
+    let log = read_chat(path)?;
+    let messages: Vec<&ChatMessage> = log
+        .comments
+        .iter()
+        .filter(|m| m.offset_seconds >= options.start)
+        .collect();
+
+    if options.wait {
+        info!("Press Enter to start the chat at {}", clock(options.start));
+        let mut input = String::new();
+        stdin().read_line(&mut input)?;
+    }
+
+    let started = Instant::now();
+    for message in &messages {
+        sleep_until(started + due(message, options)).await;
+        println!("{}", line(message, options.simple));
+    }
+    Ok(messages.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::twitch::models::ChatMessage;
+
+    use super::{due, line, ReplayOptions};
+
+    #[test]
+    fn replay_timing() {
+        let message = ChatMessage {
+            id: "1".to_string(),
+            offset_seconds: 3725.0,
+            created_at: None,
+            login: "viewer".to_string(),
+            display_name: String::new(),
+            color: Some("#FF0000".to_string()),
+            message: "hello".to_string(),
+        };
+        let options = ReplayOptions {
+            speed: 2.0,
+            start: 3625.0,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            due(&message, &options),
+            Duration::from_secs(50),
+            "testing the delay with a speed multiplier"
+        );
+        assert_eq!(
+            line(&message, true),
+            "[01:02:05] viewer: hello",
+            "testing the simple line"
+        );
+    }
+}
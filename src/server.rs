@@ -0,0 +1,585 @@
+mod jobs;
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, FromRef, MatchedPath, Path, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use log::{debug, info};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::config::{BruteforceOptions, Cli, Commands, ReportOptions};
+use crate::feed::{load_history, render_atom};
+use crate::http::HttpContext;
+use crate::limiter::RateLimiter;
+use crate::offline::split_range;
+use crate::progress::{Progress, ProgressEvent};
+use crate::twitch::clips::find_bid_from_clip;
+use crate::twitch::lookup_video;
+use crate::twitch::models::VodRecovery;
+use crate::twitch::vods::{bruteforcer, exact, from_tracker};
+use crate::util::{compile_cdn_list, derive_date_from_url, get_useragent_list};
+use jobs::{JobQueue, JobRequest, JobState};
+
+// a single page without any build step so it can be baked into the binary
+const INDEX_HTML: &str = include_str!("server/index.html");
+
+// written by hand, keep it in sync with the routes below
+const OPENAPI_JSON: &str = include_str!("server/openapi.json");
+
+// how often the scanned counter gets pushed to the WebSocket clients
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Clone)]
+struct AppState {
+    queue: JobQueue,
+    // flips once the CDN list and the UA cache are loaded
+    ready: Arc<AtomicBool>,
+    rate_limit: Option<Arc<RateLimiter>>,
+    // the tokens the clients get told apart by
+    api_keys: Arc<Vec<String>>,
+    // the modes answered right away run with the same caps as the jobs
+    flags: Cli,
+    http: HttpContext,
+}
+
+impl FromRef<AppState> for JobQueue {
+    fn from_ref(state: &AppState) -> Self {
+        state.queue.clone()
+    }
+}
+
+async fn openapi() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], OPENAPI_JSON)
+}
+
+async fn healthz() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    if state.ready.load(Ordering::Relaxed) {
+        (StatusCode::OK, Json(json!({ "ready": true })))
+    } else {
+        (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "ready": false })),
+        )
+    }
+}
+
+// loads everything the first jobs would otherwise wait on
+async fn warm_up(flags: Cli, ready: Arc<AtomicBool>, http: HttpContext) {
+    let cdns = compile_cdn_list(flags.cdnfile.clone(), &http).await.len();
+    let useragents = get_useragent_list(&http).await.len();
+    debug!("Loaded {cdns} CDNs and {useragents} user agents");
+    ready.store(true, Ordering::Relaxed);
+}
+
+// compares every byte, so the time it takes doesn't give away how much of a key was right
+fn same_key(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+// clients sending one of the --api-key tokens are told apart by it (hashed, the jobs get saved to
+// disk), the rest by their IP, otherwise anyone could dodge the rate limit with a made up token
+fn client_id(headers: &HeaderMap, address: SocketAddr, api_keys: &[String]) -> String {
+    let token = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .filter(|token| api_keys.iter().any(|key| same_key(key, token)));
+    match token {
+        Some(token) => {
+            let hash = format!("{:x}", Sha1::digest(token.as_bytes()));
+            format!("token:{}", &hash[..16])
+        }
+        None => format!("ip:{}", address.ip()),
+    }
+}
+
+async fn create_job(
+    State(state): State<AppState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<JobRequest>,
+) -> Response {
+    if !request.command.is_job() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(
+                json!({ "error": format!("{} can't be run as a job", request.command.to_short_desc()) }),
+            ),
+        )
+            .into_response();
+    }
+    // anyone reaching the server could write anywhere the server can otherwise
+    let paths = request.command.path_fields();
+    if !paths.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": format!("{} can't be set on a server job", paths.join(", ")) })),
+        )
+            .into_response();
+    }
+
+    let client = client_id(&headers, address, &state.api_keys);
+    if let Some(response) = rate_limited(&state, &client) {
+        return response;
+    }
+
+    let id = state.queue.submit(request, Some(client));
+    (StatusCode::ACCEPTED, Json(json!({ "id": id }))).into_response()
+}
+
+fn rate_limited(state: &AppState, client: &str) -> Option<Response> {
+    let wait = state.rate_limit.as_ref()?.check(client).err()?;
+    let retry_after = wait.as_secs().max(1);
+    Some(
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(json!({ "error": format!("too many requests, try again in {retry_after}s") })),
+        )
+            .into_response(),
+    )
+}
+
+// the same document --output-format json prints
+#[derive(Serialize)]
+struct Recovered {
+    mode: String,
+    found: bool,
+    #[serde(flatten)]
+    recovery: Option<VodRecovery>,
+}
+
+async fn recover(
+    command: &Commands,
+    flags: &Cli,
+    http: &HttpContext,
+) -> Result<Option<VodRecovery>> {
+    let options = BruteforceOptions::from(flags);
+    match command {
+        Commands::Exact {
+            username,
+            id,
+            stamp,
+        } => exact(username, *id, stamp, &options, http).await,
+        Commands::Bruteforce {
+            username,
+            id,
+            from,
+            to,
+        } => {
+            let (from, to) = split_range(from, to.as_deref())?;
+            bruteforcer(username, *id, from, to, &options, http).await
+        }
+        Commands::Link { url } => {
+            let (proc, data) = derive_date_from_url(url, flags.clone(), http).await?;
+            from_tracker(proc, &data, &options, http).await
+        }
+        Commands::Vod { id } => {
+            let Some((username, broadcast_id, timestamp)) = lookup_video(*id, http).await? else {
+                return Err(anyhow!("Twitch doesn't know the video {id}"));
+            };
+            exact(
+                &username,
+                broadcast_id,
+                &timestamp.to_string(),
+                &options,
+                http,
+            )
+            .await
+        }
+        Commands::Clip { clip, .. } => {
+            let Some((username, vod)) =
+                find_bid_from_clip(clip.clone(), &ReportOptions::from(flags), http).await?
+            else {
+                return Ok(None);
+            };
+            let url = format!("https://twitchtracker.com/{username}/streams/{vod}");
+            let (_, data) = derive_date_from_url(&url, flags.clone(), http).await?;
+            exact(&username, vod, &data.start_date, &options, http).await
+        }
+        _ => Err(anyhow!(
+            "{} can't be run right away",
+            command.to_short_desc()
+        )),
+    }
+}
+
+// runs the mode of the route and answers once it's done, without going through the queue. the
+// body is the same as for a job, the mode can be left out. a client hanging up stops the search
+async fn recover_now(
+    State(state): State<AppState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    path: MatchedPath,
+    headers: HeaderMap,
+    Json(mut body): Json<Value>,
+) -> Response {
+    if let Some(response) = rate_limited(&state, &client_id(&headers, address, &state.api_keys)) {
+        return response;
+    }
+
+    let mode = path.as_str().trim_start_matches('/').to_string();
+    if let Some(fields) = body.as_object_mut() {
+        fields.insert("mode".to_string(), json!(mode));
+    }
+    let command: Commands = match serde_json::from_value(body) {
+        Ok(command) => command,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": e.to_string() })),
+            )
+                .into_response()
+        }
+    };
+
+    // counts against --max-jobs like a job would, the search starts once a slot is free
+    let _slot = state.queue.slot().await;
+    match recover(&command, &state.flags, &state.http).await {
+        Ok(recovery) => Json(Recovered {
+            mode,
+            found: recovery.is_some(),
+            recovery,
+        })
+        .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "error": e.to_string() })),
+        )
+            .into_response(),
+    }
+}
+
+async fn list_jobs(State(queue): State<JobQueue>) -> impl IntoResponse {
+    Json(queue.list())
+}
+
+async fn get_job(State(queue): State<JobQueue>, Path(id): Path<u64>) -> impl IntoResponse {
+    match queue.status(id) {
+        Some(status) => (StatusCode::OK, Json(json!(status))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "no job with this ID" })),
+        ),
+    }
+}
+
+async fn cancel_job(State(queue): State<JobQueue>, Path(id): Path<u64>) -> impl IntoResponse {
+    match queue.cancel(id) {
+        Some(status) => (StatusCode::OK, Json(json!(status))),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "no job with this ID" })),
+        ),
+    }
+}
+
+async fn job_ws(
+    State(queue): State<JobQueue>,
+    Path(id): Path<u64>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    match queue.progress(id) {
+        Some(progress) => ws.on_upgrade(move |socket| stream_progress(socket, queue, id, progress)),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "no job with this ID" })),
+        )
+            .into_response(),
+    }
+}
+
+async fn send_event(socket: &mut WebSocket, event: &ProgressEvent) -> bool {
+    match serde_json::to_string(event) {
+        Ok(text) => socket.send(Message::Text(text.into())).await.is_ok(),
+        Err(_) => false,
+    }
+}
+
+fn is_finished(state: JobState) -> bool {
+    matches!(
+        state,
+        JobState::Done | JobState::Failed | JobState::Cancelled
+    )
+}
+
+// sends the stage transitions and hits as they happen and the scanned counter every PROGRESS_INTERVAL,
+// the socket gets closed once the job is finished
+async fn stream_progress(mut socket: WebSocket, queue: JobQueue, id: u64, progress: Progress) {
+    let mut events = progress.subscribe();
+    let mut ticker = tokio::time::interval(PROGRESS_INTERVAL);
+    let mut last_snapshot = None;
+
+    // the current state first so late subscribers know where the job is at
+    let Some(status) = queue.status(id) else {
+        return;
+    };
+    let stage = ProgressEvent::Stage {
+        stage: status.state.to_string(),
+    };
+    if !send_event(&mut socket, &stage).await || is_finished(status.state) {
+        let _ = socket.send(Message::Close(None)).await;
+        return;
+    }
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let finished = matches!(
+                        &event,
+                        ProgressEvent::Stage { stage }
+                            if *stage == JobState::Done.to_string()
+                                || *stage == JobState::Failed.to_string()
+                                || *stage == JobState::Cancelled.to_string()
+                    );
+                    if finished {
+                        let snapshot = progress.snapshot();
+                        send_event(&mut socket, &ProgressEvent::Progress {
+                            scanned: snapshot.scanned,
+                            total: snapshot.total,
+                        })
+                        .await;
+                    }
+                    if !send_event(&mut socket, &event).await || finished {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("WebSocket client for job {id} skipped {skipped} events");
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = ticker.tick() => {
+                let snapshot = progress.snapshot();
+                if last_snapshot != Some(snapshot) {
+                    last_snapshot = Some(snapshot);
+                    let event = ProgressEvent::Progress {
+                        scanned: snapshot.scanned,
+                        total: snapshot.total,
+                    };
+                    if !send_event(&mut socket, &event).await {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    let _ = socket.send(Message::Close(None)).await;
+}
+
+async fn index() -> Html<&'static str> {
+    Html(INDEX_HTML)
+}
+
+async fn feed() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "application/atom+xml")],
+        render_atom(&load_history()),
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerLimits {
+    pub max_jobs: usize,
+    pub job_threads: Option<usize>,
+    pub max_requests: Option<usize>,
+    pub rate_limit: Option<u32>,
+    pub api_keys: Vec<String>,
+}
+
+pub async fn serve(
+    address: &str,
+    limits: ServerLimits,
+    flags: Cli,
+    http: &HttpContext,
+) -> Result<()> {
+    // without a shared cap every running job would add its own --threads worth of requests
+    let job_http = match limits.max_requests {
+        Some(max_requests) => http.clone().with_request_limit(max_requests),
+        None => http.clone(),
+    };
+    let mut job_flags = flags.clone();
+    if let Some(job_threads) = limits.job_threads {
+        job_flags.threads = job_flags.threads.min(job_threads);
+    }
+
+    let ready = Arc::new(AtomicBool::new(false));
+    tokio::spawn(warm_up(flags.clone(), ready.clone(), http.clone()));
+
+    let app = Router::new()
+        .route("/", get(index))
+        .route("/jobs", get(list_jobs).post(create_job))
+        .route("/jobs/{id}", get(get_job).delete(cancel_job))
+        .route("/jobs/{id}/ws", get(job_ws))
+        .route("/exact", post(recover_now))
+        .route("/bruteforce", post(recover_now))
+        .route("/link", post(recover_now))
+        .route("/vod", post(recover_now))
+        .route("/clip", post(recover_now))
+        .route("/feed", get(feed))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/openapi.json", get(openapi))
+        .with_state(AppState {
+            queue: JobQueue::persistent(limits.max_jobs, job_flags.clone(), job_http.clone()),
+            ready: ready.clone(),
+            rate_limit: limits
+                .rate_limit
+                .map(|per_minute| Arc::new(RateLimiter::new(per_minute))),
+            api_keys: Arc::new(limits.api_keys),
+            flags: job_flags,
+            http: job_http,
+        });
+
+    let listener = tokio::net::TcpListener::bind(address).await?;
+    if !flags.simple {
+        info!("Listening on http://{address}");
+    }
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        extract::{ConnectInfo, State},
+        http::{header, HeaderMap, StatusCode},
+        Json,
+    };
+    use serde_json::json;
+    use std::sync::{atomic::AtomicBool, Arc};
+
+    use super::{client_id, create_job, AppState, JobQueue, OPENAPI_JSON};
+    use crate::{config::Cli, http::HttpContext};
+
+    #[tokio::test]
+    async fn job_paths() {
+        let state = AppState {
+            queue: JobQueue::new(1, Cli::default(), HttpContext::default()),
+            ready: Arc::new(AtomicBool::new(true)),
+            rate_limit: None,
+            api_keys: Arc::new(Vec::new()),
+            flags: Cli::default(),
+            http: HttpContext::default(),
+        };
+        let request = serde_json::from_value(json!({
+            "mode": "clipforce",
+            "id": 1,
+            "start": 0,
+            "end": 10,
+            "state": "/etc/cron.d/tbf",
+        }))
+        .unwrap();
+
+        let response = create_job(
+            State(state.clone()),
+            ConnectInfo("10.0.0.1:4000".parse().unwrap()),
+            HeaderMap::new(),
+            Json(request),
+        )
+        .await;
+        assert_eq!(
+            response.status(),
+            StatusCode::BAD_REQUEST,
+            "testing a job with a path"
+        );
+        assert!(
+            state.queue.list().is_empty(),
+            "testing nothing being queued"
+        );
+    }
+
+    #[test]
+    fn client_ids() {
+        let address = "10.0.0.1:4000".parse().unwrap();
+        let keys = vec!["secret".to_string()];
+        let bearer = |token: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                header::AUTHORIZATION,
+                format!("Bearer {token}").parse().unwrap(),
+            );
+            headers
+        };
+
+        let known = client_id(&bearer("secret"), address, &keys);
+        assert!(known.starts_with("token:"), "testing a known token");
+        assert!(!known.contains("secret"), "testing the token being hashed");
+        assert_eq!(
+            client_id(&bearer("made-up"), address, &keys),
+            "ip:10.0.0.1",
+            "testing an unknown token"
+        );
+        assert_eq!(
+            client_id(&bearer("secret"), address, &[]),
+            "ip:10.0.0.1",
+            "testing a token without any keys set"
+        );
+        assert_eq!(
+            client_id(&HeaderMap::new(), address, &keys),
+            "ip:10.0.0.1",
+            "testing no token"
+        );
+    }
+
+    #[test]
+    fn openapi_document() {
+        let document: serde_json::Value = serde_json::from_str(OPENAPI_JSON).unwrap();
+        let paths = document["paths"].as_object().unwrap();
+        for route in [
+            "/jobs",
+            "/jobs/{id}",
+            "/jobs/{id}/ws",
+            "/exact",
+            "/bruteforce",
+            "/link",
+            "/vod",
+            "/clip",
+            "/feed",
+            "/healthz",
+            "/readyz",
+            "/openapi.json",
+        ] {
+            assert!(
+                paths.contains_key(route),
+                "testing {route} being documented"
+            );
+        }
+        assert_eq!(
+            document["info"]["version"],
+            env!("CARGO_PKG_VERSION"),
+            "testing document version"
+        );
+    }
+}
@@ -0,0 +1,695 @@
+use anyhow::Result;
+use log::{debug, warn};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use strum::{Display, EnumString};
+use time::OffsetDateTime;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{Cli, Commands};
+use crate::history::history_location;
+use crate::http::HttpContext;
+use crate::progress::{Progress, ProgressSnapshot};
+use crate::twitch::models::ReturnURL;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Display, EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+// what the clients submit, the mode fields plus the optional extras
+#[derive(Debug, Clone, Deserialize)]
+pub struct JobRequest {
+    #[serde(flatten)]
+    pub command: Commands,
+    // gets the final job status POSTed to it once the job is finished
+    pub callback: Option<String>,
+}
+
+#[derive(Debug)]
+struct Job {
+    command: Commands,
+    callback: Option<String>,
+    // whoever submitted the job, used to share the slots fairly
+    client: Option<String>,
+    state: JobState,
+    progress: Progress,
+    cancel: CancellationToken,
+    created_at: i64,
+    finished_at: Option<i64>,
+    results: Option<Vec<ReturnURL>>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub id: u64,
+    pub state: JobState,
+    pub command: Commands,
+    pub progress: ProgressSnapshot,
+    pub created_at: i64,
+    pub finished_at: Option<i64>,
+    pub results: Option<Vec<ReturnURL>>,
+    pub error: Option<String>,
+}
+
+// the jobs get saved next to the history of the results, one row per job
+struct JobStore {
+    conn: Mutex<Connection>,
+}
+
+impl JobStore {
+    fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        // the history writes to the same database through its own connection
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS jobs (
+                id INTEGER PRIMARY KEY,
+                command TEXT NOT NULL,
+                callback TEXT,
+                client TEXT,
+                state TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                finished_at INTEGER,
+                results TEXT,
+                error TEXT
+            );",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn save(&self, id: u64, job: &Job) -> Result<()> {
+        let results = job
+            .results
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO jobs
+                (id, command, callback, client, state, created_at, finished_at, results, error)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                id as i64,
+                serde_json::to_string(&job.command)?,
+                job.callback,
+                job.client,
+                job.state.to_string(),
+                job.created_at,
+                job.finished_at,
+                results,
+                job.error
+            ],
+        )?;
+        Ok(())
+    }
+
+    // a job that can't be read back (say, a mode that got removed) is skipped, not the whole store
+    fn load(&self) -> Result<HashMap<u64, Job>> {
+        let conn = self.conn.lock().unwrap();
+        let mut statement = conn.prepare(
+            "SELECT id, command, callback, client, state, created_at, finished_at, results, error
+            FROM jobs",
+        )?;
+        let mut rows = statement.query([])?;
+        let mut jobs = HashMap::new();
+        while let Some(row) = rows.next()? {
+            let id = row.get::<_, i64>(0)? as u64;
+            let job = || -> Result<Job> {
+                let results: Option<String> = row.get(7)?;
+                Ok(Job {
+                    command: serde_json::from_str(&row.get::<_, String>(1)?)?,
+                    callback: row.get(2)?,
+                    client: row.get(3)?,
+                    state: row.get::<_, String>(4)?.parse()?,
+                    progress: Progress::default(),
+                    cancel: CancellationToken::new(),
+                    created_at: row.get(5)?,
+                    finished_at: row.get(6)?,
+                    results: results.as_deref().map(serde_json::from_str).transpose()?,
+                    error: row.get(8)?,
+                })
+            };
+            match job() {
+                Ok(job) => {
+                    jobs.insert(id, job);
+                }
+                Err(e) => warn!("Skipping the saved job {id} - {e}"),
+            }
+        }
+        Ok(jobs)
+    }
+}
+
+// picks the queued job of the client with the fewest running jobs, the oldest one on ties
+fn pick_next(jobs: &HashMap<u64, Job>, max_jobs: usize) -> Option<u64> {
+    let running: Vec<&Option<String>> = jobs
+        .values()
+        .filter(|job| job.state == JobState::Running)
+        .map(|job| &job.client)
+        .collect();
+    if running.len() >= max_jobs {
+        return None;
+    }
+
+    jobs.iter()
+        .filter(|(_, job)| job.state == JobState::Queued)
+        .min_by_key(|(id, job)| (running.iter().filter(|c| ***c == job.client).count(), **id))
+        .map(|(id, _)| *id)
+}
+
+// jobs run in the background with at most `max_jobs` of them at once, the rest wait in the queue
+#[derive(Clone)]
+pub struct JobQueue {
+    jobs: Arc<Mutex<HashMap<u64, Job>>>,
+    next_id: Arc<AtomicU64>,
+    max_jobs: usize,
+    // the `max_jobs` slots, shared by the jobs and the searches answered right away
+    slots: Arc<Semaphore>,
+    flags: Cli,
+    http: HttpContext,
+    // where the jobs get saved to, None keeps them in memory only
+    store: Option<Arc<JobStore>>,
+}
+
+impl JobQueue {
+    pub fn new(max_jobs: usize, flags: Cli, http: HttpContext) -> Self {
+        Self {
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+            max_jobs: max_jobs.max(1),
+            slots: Arc::new(Semaphore::new(max_jobs.max(1))),
+            flags,
+            http,
+            store: None,
+        }
+    }
+
+    // loads the saved jobs and requeues the ones that didn't get to finish before the restart
+    pub fn persistent(max_jobs: usize, flags: Cli, http: HttpContext) -> Self {
+        let mut queue = Self::new(max_jobs, flags, http);
+        let store = match history_location(&queue.flags).and_then(|path| JobStore::open(&path)) {
+            Ok(store) => store,
+            Err(e) => {
+                warn!("Couldn't open the job store, the jobs are kept in memory only - {e}");
+                return queue;
+            }
+        };
+
+        let mut saved = store.load().unwrap_or_else(|e| {
+            warn!("Couldn't load the saved jobs - {e}");
+            HashMap::new()
+        });
+        let last_id = saved.keys().max().copied().unwrap_or_default();
+        queue.next_id.store(last_id + 1, Ordering::Relaxed);
+
+        let mut requeued = Vec::new();
+        for (id, job) in saved.iter_mut() {
+            if job.state == JobState::Running {
+                debug!("Requeueing job {id}");
+                job.state = JobState::Queued;
+                requeued.push(*id);
+            }
+        }
+        *queue.jobs.lock().unwrap() = saved;
+        queue.store = Some(Arc::new(store));
+        for id in requeued {
+            queue.save(id);
+        }
+
+        queue.dispatch();
+        queue
+    }
+
+    pub fn submit(&self, request: JobRequest, client: Option<String>) -> u64 {
+        let JobRequest { command, callback } = request;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.jobs.lock().unwrap().insert(
+            id,
+            Job {
+                command,
+                callback,
+                client,
+                state: JobState::Queued,
+                progress: Progress::default(),
+                cancel: CancellationToken::new(),
+                created_at: OffsetDateTime::now_utc().unix_timestamp(),
+                finished_at: None,
+                results: None,
+                error: None,
+            },
+        );
+        self.save(id);
+        self.dispatch();
+
+        id
+    }
+
+    // starts queued jobs while there are free slots
+    fn dispatch(&self) {
+        let mut started = Vec::new();
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            while let Some(id) = pick_next(&jobs, self.max_jobs) {
+                // the searches waiting for a slot get it before the queue does
+                let Ok(permit) = self.slots.clone().try_acquire_owned() else {
+                    break;
+                };
+                let job = jobs.get_mut(&id).unwrap();
+                job.state = JobState::Running;
+                started.push((
+                    id,
+                    job.command.clone(),
+                    job.callback.clone(),
+                    job.progress.clone(),
+                    job.cancel.clone(),
+                    permit,
+                ));
+            }
+        }
+        for (id, command, callback, progress, cancel, permit) in started {
+            self.save(id);
+            self.run(id, command, callback, progress, cancel, permit);
+        }
+    }
+
+    // waits for a free slot for a search that skips the queue, the queued jobs get it back
+    // once the slot is dropped
+    pub async fn slot(&self) -> Slot {
+        let permit = self
+            .slots
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("the job slots never get closed");
+        Slot {
+            permit: Some(permit),
+            queue: self.clone(),
+        }
+    }
+
+    fn run(
+        &self,
+        id: u64,
+        command: Commands,
+        callback: Option<String>,
+        progress: Progress,
+        cancel: CancellationToken,
+        permit: OwnedSemaphorePermit,
+    ) {
+        let queue = self.clone();
+        tokio::spawn(async move {
+            progress.stage(&JobState::Running.to_string());
+
+            let mut flags = queue.flags.clone();
+            flags.progressbar = false;
+            flags.progress = Some(progress.clone());
+            flags.cancel = cancel.clone();
+            let result = command.execute(flags, &queue.http).await;
+            let state = match result {
+                _ if cancel.is_cancelled() => JobState::Cancelled,
+                Ok(_) => JobState::Done,
+                Err(_) => JobState::Failed,
+            };
+
+            queue.update(id, |job| {
+                job.finished_at = Some(OffsetDateTime::now_utc().unix_timestamp());
+                job.state = state;
+                match result {
+                    Ok(urls) => job.results = Some(urls.unwrap_or_default()),
+                    Err(e) => job.error = Some(e.to_string()),
+                }
+            });
+            progress.stage(&state.to_string());
+            drop(permit);
+            queue.dispatch();
+
+            if let (Some(callback), Some(status)) = (callback, queue.status(id)) {
+                send_callback(&callback, &status, &queue.http).await;
+            }
+        });
+    }
+
+    // queued jobs get cancelled right away, running ones stop at their next check and keep their partial results
+    pub fn cancel(&self, id: u64) -> Option<JobStatus> {
+        let cancelled = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let job = jobs.get_mut(&id)?;
+            match job.state {
+                JobState::Queued => {
+                    job.state = JobState::Cancelled;
+                    job.finished_at = Some(OffsetDateTime::now_utc().unix_timestamp());
+                    job.progress.stage(&JobState::Cancelled.to_string());
+                    true
+                }
+                JobState::Running => {
+                    job.cancel.cancel();
+                    false
+                }
+                _ => false,
+            }
+        };
+        if cancelled {
+            self.save(id);
+        }
+        self.status(id)
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(&id) {
+            f(job);
+        }
+        self.save(id);
+    }
+
+    fn save(&self, id: u64) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        if let Some(job) = self.jobs.lock().unwrap().get(&id) {
+            if let Err(e) = store.save(id, job) {
+                warn!("Couldn't save job {id} - {e}");
+            }
+        }
+    }
+
+    pub fn status(&self, id: u64) -> Option<JobStatus> {
+        self.jobs.lock().unwrap().get(&id).map(|job| JobStatus {
+            id,
+            state: job.state,
+            command: job.command.clone(),
+            progress: job.progress.snapshot(),
+            created_at: job.created_at,
+            finished_at: job.finished_at,
+            results: job.results.clone(),
+            error: job.error.clone(),
+        })
+    }
+
+    pub fn progress(&self, id: u64) -> Option<Progress> {
+        self.jobs
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|job| job.progress.clone())
+    }
+
+    pub fn list(&self) -> Vec<JobStatus> {
+        let mut ids: Vec<u64> = self.jobs.lock().unwrap().keys().copied().collect();
+        ids.sort_unstable();
+        ids.into_iter().filter_map(|id| self.status(id)).collect()
+    }
+}
+
+pub struct Slot {
+    permit: Option<OwnedSemaphorePermit>,
+    queue: JobQueue,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.permit.take();
+        self.queue.dispatch();
+    }
+}
+
+async fn send_callback(callback: &str, status: &JobStatus, http: &HttpContext) {
+    let resp = http.send(http.client.post(callback).json(status)).await;
+    match resp.and_then(|r| r.error_for_status()) {
+        Ok(_) => debug!("Sent the result of job {} to {callback}", status.id),
+        Err(e) => warn!(
+            "Couldn't send the result of job {} to {callback}: {e}",
+            status.id
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    use super::{pick_next, Job, JobQueue, JobRequest, JobState, JobStore};
+    use crate::{
+        config::{Cli, Commands},
+        http::HttpContext,
+        progress::Progress,
+        twitch::models::ReturnURL,
+    };
+
+    fn job(client: &str, state: JobState) -> Job {
+        Job {
+            command: Commands::Live {
+                username: client.to_string(),
+                chat: false,
+                chat_output: None,
+                watch: false,
+                interval: 60,
+                keep_watching: false,
+                output: None,
+            },
+            callback: None,
+            client: Some(client.to_string()),
+            state,
+            progress: Progress::default(),
+            cancel: Default::default(),
+            created_at: 0,
+            finished_at: None,
+            results: None,
+            error: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn job_queue() {
+        // the clipforce progress would end up in the current folder otherwise
+        let dir = tempdir().unwrap();
+        let queue = JobQueue::new(1, Cli::default(), HttpContext::default());
+        let id = queue.submit(
+            JobRequest {
+                command: Commands::Clipforce {
+                    id: 1,
+                    start: 0,
+                    end: 0,
+                    state: Some(
+                        dir.path()
+                            .join("clipforce.json")
+                            .to_string_lossy()
+                            .to_string(),
+                    ),
+                    resume: false,
+                },
+                callback: None,
+            },
+            None,
+        );
+
+        assert!(queue.status(id).is_some(), "testing submitted job");
+        assert!(queue.status(id + 1).is_none(), "testing unknown job");
+
+        for _ in 0..100 {
+            if queue.status(id).unwrap().state == JobState::Done {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let status = queue.status(id).unwrap();
+        assert_eq!(status.state, JobState::Done, "testing finished job");
+        assert_eq!(status.results, Some(Vec::new()), "testing empty results");
+        assert_eq!(queue.list().len(), 1, "testing job list");
+    }
+
+    #[tokio::test]
+    async fn shared_slots() {
+        // the clipforce progress would end up in the current folder otherwise
+        let dir = tempdir().unwrap();
+        let queue = JobQueue::new(1, Cli::default(), HttpContext::default());
+        let slot = queue.slot().await;
+        let id = queue.submit(
+            JobRequest {
+                command: Commands::Clipforce {
+                    id: 1,
+                    start: 0,
+                    end: 0,
+                    state: Some(
+                        dir.path()
+                            .join("clipforce.json")
+                            .to_string_lossy()
+                            .to_string(),
+                    ),
+                    resume: false,
+                },
+                callback: None,
+            },
+            None,
+        );
+        tokio::task::yield_now().await;
+        assert_eq!(
+            queue.status(id).unwrap().state,
+            JobState::Queued,
+            "testing the job waiting for the taken slot"
+        );
+
+        drop(slot);
+        for _ in 0..100 {
+            if queue.status(id).unwrap().state == JobState::Done {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            queue.status(id).unwrap().state,
+            JobState::Done,
+            "testing the job running once the slot is free"
+        );
+    }
+
+    #[test]
+    fn cancel_job() {
+        let queue = JobQueue::new(1, Cli::default(), HttpContext::default());
+        queue.jobs.lock().unwrap().extend([
+            (1, job("a", JobState::Queued)),
+            (2, job("a", JobState::Done)),
+        ]);
+
+        let status = queue.cancel(1).unwrap();
+        assert_eq!(status.state, JobState::Cancelled, "testing queued job");
+        assert!(status.finished_at.is_some(), "testing finish time");
+        assert_eq!(
+            queue.cancel(2).unwrap().state,
+            JobState::Done,
+            "testing finished job"
+        );
+        assert!(queue.cancel(3).is_none(), "testing unknown job");
+    }
+
+    #[test]
+    fn job_request() {
+        let request: JobRequest = serde_json::from_str(
+            r#"{"mode": "link", "url": "https://twitchtracker.com/a/streams/1", "callback": "http://localhost/done"}"#,
+        )
+        .unwrap();
+        assert!(
+            matches!(request.command, Commands::Link { .. }),
+            "testing flattened command"
+        );
+        assert_eq!(
+            request.callback.as_deref(),
+            Some("http://localhost/done"),
+            "testing callback"
+        );
+
+        // only the mode and the username are required by the API document
+        let request: JobRequest =
+            serde_json::from_str(r#"{"mode": "live", "username": "mrmouton"}"#).unwrap();
+        assert!(
+            matches!(request.command, Commands::Live { chat: false, .. }),
+            "testing a live job without the optional fields"
+        );
+        let request: JobRequest = serde_json::from_str(
+            r#"{"mode": "live", "username": "mrmouton", "chat": true, "chat_output": "/tmp/chat.json"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request.command.path_fields(),
+            vec!["chat_output"],
+            "testing the chat output path"
+        );
+        let request: JobRequest = serde_json::from_str(
+            r#"{"mode": "live", "username": "mrmouton", "output": "/home/user/.bashrc"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            request.command.path_fields(),
+            vec!["output"],
+            "testing the URL list path"
+        );
+    }
+
+    #[test]
+    fn fair_scheduling() {
+        let jobs = HashMap::from([
+            (1, job("a", JobState::Running)),
+            (2, job("a", JobState::Queued)),
+            (3, job("a", JobState::Queued)),
+            (4, job("b", JobState::Queued)),
+        ]);
+        assert_eq!(
+            pick_next(&jobs, 2),
+            Some(4),
+            "testing the client without running jobs going first"
+        );
+        assert_eq!(pick_next(&jobs, 1), None, "testing full slots");
+
+        let jobs = HashMap::from([
+            (1, job("a", JobState::Done)),
+            (2, job("a", JobState::Queued)),
+            (3, job("b", JobState::Queued)),
+        ]);
+        assert_eq!(
+            pick_next(&jobs, 2),
+            Some(2),
+            "testing the oldest job on ties"
+        );
+    }
+
+    #[test]
+    fn job_store() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("tbf").join("history.db");
+        let store = JobStore::open(&path).unwrap();
+        let mut done = job("b", JobState::Done);
+        done.finished_at = Some(10);
+        done.results = Some(vec![ReturnURL {
+            url: "https://d1m7jfoe9zdc1j.cloudfront.net/b/chunked/index-dvr.m3u8".to_string(),
+            muted: false,
+            audio_only: false,
+        }]);
+        store.save(1, &job("a", JobState::Running)).unwrap();
+        store.save(2, &done).unwrap();
+        store.save(1, &job("a", JobState::Failed)).unwrap();
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO jobs (id, command, state, created_at) VALUES (3, '{}', 'done', 0)",
+                [],
+            )
+            .unwrap();
+        drop(store);
+
+        let jobs = JobStore::open(&path).unwrap().load().unwrap();
+        assert_eq!(jobs.len(), 2, "testing the broken job getting skipped");
+        assert_eq!(jobs[&1].state, JobState::Failed, "testing the updated job");
+        assert_eq!(jobs[&1].client.as_deref(), Some("a"), "testing the client");
+        assert_eq!(jobs[&2].finished_at, Some(10), "testing the finish time");
+        assert_eq!(jobs[&2].results, done.results, "testing the results");
+        assert!(
+            matches!(&jobs[&2].command, Commands::Live { username, .. } if username == "b"),
+            "testing the command"
+        );
+    }
+}
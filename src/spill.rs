@@ -0,0 +1,117 @@
+use anyhow::Result;
+use log::warn;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Seek, Write},
+    vec,
+};
+
+// the offsets a scan still has to go over, written to a temporary file once there are more of
+// them than the memory budget allows and read back one at a time as the scan gets to them
+pub enum PendingOffsets {
+    Memory(vec::IntoIter<i64>),
+    Disk {
+        reader: BufReader<File>,
+        left: usize,
+    },
+}
+
+impl PendingOffsets {
+    // everything stays in memory without a limit
+    pub fn new(offsets: impl IntoIterator<Item = i64>, spill_after: Option<usize>) -> Result<Self> {
+        let mut offsets = offsets.into_iter();
+        let Some(limit) = spill_after else {
+            return Ok(Self::Memory(offsets.collect::<Vec<_>>().into_iter()));
+        };
+        let head: Vec<i64> = offsets.by_ref().take(limit + 1).collect();
+        if head.len() <= limit {
+            return Ok(Self::Memory(head.into_iter()));
+        }
+
+        // the OS removes it once it's closed, even if the scan gets killed
+        let mut file = tempfile::tempfile()?;
+        let mut left = 0;
+        {
+            let mut writer = BufWriter::new(&mut file);
+            for offset in head.into_iter().chain(offsets) {
+                writer.write_all(&offset.to_le_bytes())?;
+                left += 1;
+            }
+            writer.flush()?;
+        }
+        file.rewind()?;
+        Ok(Self::Disk {
+            reader: BufReader::new(file),
+            left,
+        })
+    }
+
+    pub fn is_spilled(&self) -> bool {
+        matches!(self, Self::Disk { .. })
+    }
+}
+
+impl Iterator for PendingOffsets {
+    type Item = i64;
+
+    fn next(&mut self) -> Option<i64> {
+        match self {
+            Self::Memory(offsets) => offsets.next(),
+            Self::Disk { left: 0, .. } => None,
+            Self::Disk { reader, left } => {
+                let mut bytes = [0; 8];
+                match reader.read_exact(&mut bytes) {
+                    Ok(()) => {
+                        *left -= 1;
+                        Some(i64::from_le_bytes(bytes))
+                    }
+                    Err(e) => {
+                        warn!("Couldn't read the spilled offsets back - {e}");
+                        *left = 0;
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Memory(offsets) => offsets.size_hint(),
+            Self::Disk { left, .. } => (*left, Some(*left)),
+        }
+    }
+}
+
+impl ExactSizeIterator for PendingOffsets {}
+
+#[cfg(test)]
+mod tests {
+    use super::PendingOffsets;
+
+    #[test]
+    fn spill() {
+        let offsets = PendingOffsets::new(0..10, None).unwrap();
+        assert!(!offsets.is_spilled(), "testing no limit");
+        assert_eq!(offsets.len(), 10, "testing in-memory length");
+
+        let offsets = PendingOffsets::new(0..10, Some(10)).unwrap();
+        assert!(!offsets.is_spilled(), "testing offsets within the limit");
+        assert_eq!(
+            offsets.collect::<Vec<_>>(),
+            (0..10).collect::<Vec<_>>(),
+            "testing in-memory offsets"
+        );
+
+        let mut offsets = PendingOffsets::new((0..100).filter(|o| o % 3 != 0), Some(5)).unwrap();
+        assert!(offsets.is_spilled(), "testing offsets over the limit");
+        assert_eq!(offsets.len(), 66, "testing spilled length");
+        assert_eq!(offsets.next(), Some(1), "testing first spilled offset");
+        assert_eq!(offsets.len(), 65, "testing length after reading one back");
+        assert_eq!(
+            offsets.collect::<Vec<_>>(),
+            (2..100).filter(|o| o % 3 != 0).collect::<Vec<_>>(),
+            "testing spilled offsets"
+        );
+    }
+}
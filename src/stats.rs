@@ -0,0 +1,227 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::{collections::HashMap, fmt::Write, fs, path::Path};
+
+use crate::twitch::models::ChatLog;
+
+// how many entries the top lists and the peaks have
+const TOP: usize = 10;
+const PEAKS: usize = 5;
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MinuteCount {
+    pub minute: usize,
+    pub messages: usize,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ChatStats {
+    pub messages: usize,
+    pub chatters: usize,
+    // messages per minute from the start of the VOD, the empty minutes included
+    pub per_minute: Vec<MinuteCount>,
+    pub top_chatters: Vec<(String, usize)>,
+    // only the 7TV/BTTV/FFZ emotes embedded in the chat get counted
+    pub top_emotes: Vec<(String, usize)>,
+    // the busiest minutes, never two next to each other so one burst doesn't take every spot
+    pub peaks: Vec<MinuteCount>,
+}
+
+fn top(counts: HashMap<&str, usize>) -> Vec<(String, usize)> {
+    let mut counts: Vec<(String, usize)> = counts
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+    // the name as the tiebreaker keeps the output stable
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(TOP);
+    counts
+}
+
+pub fn chat_stats(log: &ChatLog) -> ChatStats {
+    let minutes = log
+        .comments
+        .iter()
+        .map(|m| (m.offset_seconds.max(0.0) / 60.0) as usize + 1)
+        .max()
+        .unwrap_or_default();
+    let mut per_minute = vec![0; minutes];
+    let mut chatters: HashMap<&str, usize> = HashMap::new();
+    let mut emotes: HashMap<&str, usize> = HashMap::new();
+
+    for message in &log.comments {
+        per_minute[(message.offset_seconds.max(0.0) / 60.0) as usize] += 1;
+        *chatters.entry(message.login.as_str()).or_default() += 1;
+        for word in message.message.split_whitespace() {
+            if let Some(emote) = log.emotes.iter().find(|e| e.name == word) {
+                *emotes.entry(emote.name.as_str()).or_default() += 1;
+            }
+        }
+    }
+
+    let mut busiest: Vec<usize> = (0..minutes).filter(|&m| per_minute[m] > 0).collect();
+    busiest.sort_by(|a, b| per_minute[*b].cmp(&per_minute[*a]).then(a.cmp(b)));
+    let mut peaks: Vec<usize> = Vec::new();
+    for minute in busiest {
+        if peaks.len() == PEAKS {
+            break;
+        }
+        if peaks.iter().all(|p| p.abs_diff(minute) > 1) {
+            peaks.push(minute);
+        }
+    }
+
+    ChatStats {
+        messages: log.comments.len(),
+        chatters: chatters.len(),
+        per_minute: per_minute
+            .iter()
+            .enumerate()
+            .map(|(minute, &messages)| MinuteCount { minute, messages })
+            .collect(),
+        top_chatters: top(chatters),
+        top_emotes: top(emotes),
+        peaks: peaks
+            .into_iter()
+            .map(|minute| MinuteCount {
+                minute,
+                messages: per_minute[minute],
+            })
+            .collect(),
+    }
+}
+
+fn clock(minute: usize) -> String {
+    format!("{:02}:{:02}:00", minute / 60, minute % 60)
+}
+
+pub fn stats_summary(stats: &ChatStats) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{} messages from {} chatters over {} minutes",
+        stats.messages,
+        stats.chatters,
+        stats.per_minute.len()
+    )];
+    let list = |items: &[(String, usize)]| {
+        items
+            .iter()
+            .map(|(name, count)| format!("{name} ({count})"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if !stats.top_chatters.is_empty() {
+        lines.push(format!("Top chatters: {}", list(&stats.top_chatters)));
+    }
+    if !stats.top_emotes.is_empty() {
+        lines.push(format!("Top emotes: {}", list(&stats.top_emotes)));
+    }
+    if !stats.peaks.is_empty() {
+        lines.push(format!(
+            "Peak moments: {}",
+            stats
+                .peaks
+                .iter()
+                .map(|p| format!("{} ({} messages)", clock(p.minute), p.messages))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    lines
+}
+
+// the messages per minute, as CSV for .csv paths and JSON for anything else
+pub fn write_time_series(path: &str, stats: &ChatStats) -> Result<()> {
+    let is_csv = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"));
+    let body = if is_csv {
+        let mut body = String::from("minute,messages\n");
+        for count in &stats.per_minute {
+            let _ = writeln!(body, "{},{}", count.minute, count.messages);
+        }
+        body
+    } else {
+        serde_json::to_string_pretty(&stats.per_minute)?
+    };
+    fs::write(path, body)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::twitch::models::{ChatLog, ChatMessage, ThirdPartyEmote};
+
+    use super::{chat_stats, MinuteCount};
+
+    fn message(login: &str, offset_seconds: f64, text: &str) -> ChatMessage {
+        ChatMessage {
+            id: offset_seconds.to_string(),
+            offset_seconds,
+            created_at: None,
+            login: login.to_string(),
+            display_name: String::new(),
+            color: None,
+            message: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn stats() {
+        let log = ChatLog {
+            vod_id: 1,
+            cursor: None,
+            complete: true,
+            comments: vec![
+                message("a", 10.0, "hi"),
+                message("b", 61.0, "catJAM catJAM"),
+                message("a", 62.0, "catJAM"),
+                message("a", 65.0, "lol"),
+                message("c", 130.0, "hi"),
+                message("c", 250.0, "bye"),
+                message("b", 251.0, "bye"),
+            ],
+            emotes: vec![ThirdPartyEmote {
+                provider: "7tv".to_string(),
+                id: "1".to_string(),
+                name: "catJAM".to_string(),
+                url: String::new(),
+            }],
+        };
+        let stats = chat_stats(&log);
+
+        assert_eq!(
+            stats
+                .per_minute
+                .iter()
+                .map(|m| m.messages)
+                .collect::<Vec<_>>(),
+            vec![1, 3, 1, 0, 2],
+            "testing the messages per minute"
+        );
+        assert_eq!(
+            stats.top_chatters[0],
+            ("a".to_string(), 3),
+            "testing the top chatter"
+        );
+        assert_eq!(
+            stats.top_emotes,
+            vec![("catJAM".to_string(), 3)],
+            "testing the emote count"
+        );
+        assert_eq!(
+            stats.peaks,
+            vec![
+                MinuteCount {
+                    minute: 1,
+                    messages: 3
+                },
+                MinuteCount {
+                    minute: 4,
+                    messages: 2
+                }
+            ],
+            "testing the peaks skipping the minutes next to each other"
+        );
+    }
+}
@@ -0,0 +1,328 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::{fmt::Write, fs, path::Path};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::twitch::chat::read_chat;
+use crate::twitch::models::{ChatLog, ChatMessage};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    #[default]
+    Srt,
+    Vtt,
+}
+
+impl SubtitleFormat {
+    // guessed from the output path, SRT for anything that isn't .vtt
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("vtt") => Self::Vtt,
+            _ => Self::Srt,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Srt => "srt",
+            Self::Vtt => "vtt",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SubtitleOptions {
+    pub format: SubtitleFormat,
+    // how many messages are on screen at most, the oldest ones scroll away first
+    pub max_lines: usize,
+    // how long a message stays on screen (in seconds)
+    pub duration: f64,
+    // messages get snapped to windows of this many seconds so bursts show up together instead of
+    // producing a cue per message, 0 disables it
+    pub group: f64,
+    // seconds added to every message, on top of the detected offset
+    pub offset: f64,
+    // the timestamp the recovered VOD starts at (the one in its URL), the chat gets shifted
+    // by how far the chat's own start is from it
+    pub vod_start: Option<i64>,
+}
+
+impl Default for SubtitleOptions {
+    fn default() -> Self {
+        Self {
+            format: SubtitleFormat::Srt,
+            max_lines: 5,
+            duration: 5.0,
+            group: 0.0,
+            offset: 0.0,
+            vod_start: None,
+        }
+    }
+}
+
+fn timestamp(seconds: f64, format: SubtitleFormat) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    let (h, m, s, ms) = (
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000,
+    );
+    match format {
+        SubtitleFormat::Srt => format!("{h:02}:{m:02}:{s:02},{ms:03}"),
+        SubtitleFormat::Vtt => format!("{h:02}:{m:02}:{s:02}.{ms:03}"),
+    }
+}
+
+fn line(message: &ChatMessage, format: SubtitleFormat) -> String {
+    let name = if message.display_name.is_empty() {
+        &message.login
+    } else {
+        &message.display_name
+    };
+    // the cue text can't have line breaks, they'd end the cue
+    let text = message.message.replace(['\r', '\n'], " ");
+    match format {
+        SubtitleFormat::Srt => match &message.color {
+            Some(color) => format!("<font color=\"{color}\">{name}</font>: {text}"),
+            None => format!("{name}: {text}"),
+        },
+        SubtitleFormat::Vtt => {
+            let escape = |s: &str| {
+                s.replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;")
+            };
+            format!("{}: {}", escape(name), escape(&text))
+        }
+    }
+}
+
+// where the chat puts the start of the stream, from the first message that has both its
+// offset and the time it was sent
+fn chat_start(log: &ChatLog) -> Option<f64> {
+    log.comments.iter().find_map(|m| {
+        let sent = OffsetDateTime::parse(m.created_at.as_deref()?, &Rfc3339).ok()?;
+        Some(sent.unix_timestamp_nanos() as f64 / 1e9 - m.offset_seconds)
+    })
+}
+
+// how far the messages need to be moved to line up with a VOD starting at vod_start
+pub fn detect_offset(log: &ChatLog, vod_start: i64) -> Option<f64> {
+    chat_start(log).map(|start| start - vod_start as f64)
+}
+
+// shifts the messages by the detected and the manual offset, anything ending up before
+// the VOD start gets dropped
+pub fn align(log: &mut ChatLog, options: &SubtitleOptions) {
+    let detected = match options.vod_start {
+        Some(vod_start) => detect_offset(log, vod_start).unwrap_or_else(|| {
+            debug!("None of the messages have a timestamp, the chat can't be aligned");
+            0.0
+        }),
+        None => 0.0,
+    };
+    let offset = detected + options.offset;
+    if offset == 0.0 {
+        return;
+    }
+    debug!("Shifting the chat by {offset:.3}s");
+    for message in &mut log.comments {
+        message.offset_seconds += offset;
+    }
+    log.comments.retain(|m| m.offset_seconds >= 0.0);
+}
+
+pub fn render(log: &ChatLog, options: &SubtitleOptions) -> String {
+    let shown_at = |m: &ChatMessage| {
+        if options.group > 0.0 {
+            (m.offset_seconds / options.group).floor() * options.group
+        } else {
+            m.offset_seconds
+        }
+    };
+    let mut messages: Vec<(f64, &ChatMessage)> =
+        log.comments.iter().map(|m| (shown_at(m), m)).collect();
+    messages.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    // the screen only changes when a message shows up or goes away
+    let mut points: Vec<f64> = messages
+        .iter()
+        .flat_map(|(start, _)| [*start, start + options.duration])
+        .collect();
+    points.sort_by(|a, b| a.total_cmp(b));
+    points.dedup();
+
+    let (mut first, mut last) = (0, 0);
+    // the start, end and visible range of every cue
+    let mut cues: Vec<(f64, f64, usize, usize)> = Vec::new();
+    for window in points.windows(2) {
+        let (start, end) = (window[0], window[1]);
+        // everything shown at or before the start that hasn't gone away yet
+        while last < messages.len() && messages[last].0 <= start {
+            last += 1;
+        }
+        while first < last && messages[first].0 + options.duration <= start {
+            first += 1;
+        }
+        if first == last {
+            continue;
+        }
+
+        let visible = first.max(last.saturating_sub(options.max_lines.max(1)));
+        match cues.last_mut() {
+            // with max_lines reached the screen doesn't change when an older message goes away
+            Some(cue) if cue.1 == start && (cue.2, cue.3) == (visible, last) => cue.1 = end,
+            _ => cues.push((start, end, visible, last)),
+        }
+    }
+
+    let mut out = String::new();
+    if options.format == SubtitleFormat::Vtt {
+        out.push_str("WEBVTT\n\n");
+    }
+    for (i, (start, end, from, to)) in cues.into_iter().enumerate() {
+        if options.format == SubtitleFormat::Srt {
+            let _ = writeln!(out, "{}", i + 1);
+        }
+        let _ = writeln!(
+            out,
+            "{} --> {}",
+            timestamp(start, options.format),
+            timestamp(end, options.format)
+        );
+        for (_, message) in &messages[from..to] {
+            let _ = writeln!(out, "{}", line(message, options.format));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// the chat JSON next to it with the subtitle extension by default
+pub fn subtitle_path(input: &str, output: &Option<String>, format: SubtitleFormat) -> String {
+    match output {
+        Some(path) => path.clone(),
+        None => Path::new(input)
+            .with_extension(format.extension())
+            .to_string_lossy()
+            .to_string(),
+    }
+}
+
+pub fn write_subtitles(input: &str, output: &str, options: &SubtitleOptions) -> Result<usize> {
+    let mut log = read_chat(input)?;
+    align(&mut log, options);
+    fs::write(output, render(&log, options))?;
+    Ok(log.comments.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::twitch::models::{ChatLog, ChatMessage};
+
+    use super::{align, detect_offset, render, SubtitleFormat, SubtitleOptions};
+
+    fn message(id: &str, offset_seconds: f64, text: &str) -> ChatMessage {
+        ChatMessage {
+            id: id.to_string(),
+            offset_seconds,
+            created_at: None,
+            login: "viewer".to_string(),
+            display_name: "Viewer".to_string(),
+            color: None,
+            message: text.to_string(),
+        }
+    }
+
+    fn log() -> ChatLog {
+        ChatLog {
+            vod_id: 1,
+            cursor: None,
+            complete: true,
+            comments: vec![
+                message("1", 1.0, "first"),
+                message("2", 2.5, "second"),
+                message("3", 3661.0, "<3 & hi"),
+            ],
+            emotes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn srt() {
+        let options = SubtitleOptions {
+            max_lines: 1,
+            duration: 2.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&log(), &options),
+            "1\n00:00:01,000 --> 00:00:02,500\nViewer: first\n\n\
+             2\n00:00:02,500 --> 00:00:04,500\nViewer: second\n\n\
+             3\n01:01:01,000 --> 01:01:03,000\nViewer: <3 & hi\n\n",
+            "testing srt with a single line"
+        );
+    }
+
+    #[test]
+    fn vtt() {
+        let options = SubtitleOptions {
+            format: SubtitleFormat::Vtt,
+            duration: 2.0,
+            group: 5.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            render(&log(), &options),
+            "WEBVTT\n\n\
+             00:00:00.000 --> 00:00:02.000\nViewer: first\nViewer: second\n\n\
+             01:01:00.000 --> 01:01:02.000\nViewer: &lt;3 &amp; hi\n\n",
+            "testing grouped vtt"
+        );
+    }
+
+    #[test]
+    fn alignment() {
+        let mut log = log();
+        // the chat thinks the stream started at 1622854217, the VOD starts 2 seconds later
+        log.comments[1].created_at = Some("2021-06-05T00:50:19.500Z".to_string());
+        assert_eq!(
+            detect_offset(&log, 1622854219),
+            Some(-2.0),
+            "testing the detected offset"
+        );
+
+        let options = SubtitleOptions {
+            offset: 0.5,
+            vod_start: Some(1622854219),
+            ..Default::default()
+        };
+        align(&mut log, &options);
+        assert_eq!(
+            log.comments
+                .iter()
+                .map(|c| c.offset_seconds)
+                .collect::<Vec<_>>(),
+            vec![1.0, 3659.5],
+            "testing the shifted offsets with the message before the VOD dropped"
+        );
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(
+            SubtitleFormat::from_path("chat.VTT"),
+            SubtitleFormat::Vtt,
+            "testing vtt"
+        );
+        assert_eq!(
+            SubtitleFormat::from_path("chat.srt"),
+            SubtitleFormat::Srt,
+            "testing srt"
+        );
+    }
+}
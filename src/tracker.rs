@@ -0,0 +1,495 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use log::info;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::DeriveDate;
+use crate::offline::parse_timestamp;
+
+#[derive(Clone, Debug, PartialEq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessingType {
+    Exact,
+    Bruteforce,
+}
+
+// reruns play an older VOD again and premieres play an uploaded video, so neither of them is
+// stored under its own start the way a live stream is
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamType {
+    #[default]
+    Live,
+    Rerun,
+    Premiere,
+}
+
+// premieres are stored under the time the video got uploaded, which can be a while before the
+// premiere starts, so the window before the start gets bruteforced instead
+pub const PREMIERE_WINDOW: i64 = 3600;
+
+impl StreamType {
+    // the type as Twitch's GQL API ("live", "rerun", "premiere") or a tracker's label has it,
+    // "vodcast" is what reruns used to be called
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.trim().to_lowercase().as_str() {
+            "live" => Some(Self::Live),
+            "rerun" | "vodcast" => Some(Self::Rerun),
+            "premiere" => Some(Self::Premiere),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct URLData {
+    pub username: String,
+    pub broadcast_id: String,
+    pub start_date: String,
+    pub end_date: Option<String>,
+    pub stream_type: StreamType,
+}
+
+// a site the stream start date can be scraped from, derive_date_from_url picks the provider by the URL's host
+pub trait TrackerProvider: Send + Sync {
+    fn hosts(&self) -> &'static [&'static str];
+
+    // pulls the username and the broadcast ID out of the URL
+    fn parse(&self, url: &Url) -> Result<(String, String)>;
+
+    // finds the timestamps on the scraped page, mode forces one of the methods if the provider has several
+    fn extract(
+        &self,
+        page: &Html,
+        mode: Option<ProcessingType>,
+        simple: bool,
+    ) -> Result<ExtractedTimestamps>;
+
+    fn stream_url(&self, username: &str, broadcast_id: &str) -> String;
+
+    // the page listing the channel's recent streams
+    fn streams_url(&self, username: &str) -> String;
+
+    // the broadcast IDs linked from the listing page, in the order they show up there
+    fn list_streams(&self, page: &Html, username: &str) -> Vec<String> {
+        let (Ok(base), Ok(selector)) = (
+            Url::parse(&self.streams_url(username)),
+            Selector::parse("a[href]"),
+        ) else {
+            return Vec::new();
+        };
+        let mut ids: Vec<String> = Vec::new();
+        for link in page.select(&selector) {
+            let Some(url) = link.value().attr("href").and_then(|h| base.join(h).ok()) else {
+                continue;
+            };
+            if url.host_str() != base.host_str() {
+                continue;
+            }
+            if let Ok((user, id)) = self.parse(&url) {
+                if user.eq_ignore_ascii_case(username) && !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
+        }
+        ids
+    }
+
+    // reruns and premieres get a label (or a data-stream-type attribute) on the stream page,
+    // only the exact label counts so a stream title mentioning a premiere doesn't
+    fn stream_type(&self, page: &Html) -> StreamType {
+        let Ok(selector) = Selector::parse(".label, .badge, [data-stream-type]") else {
+            return StreamType::Live;
+        };
+        page.select(&selector)
+            .find_map(|e| {
+                e.value()
+                    .attr("data-stream-type")
+                    .and_then(StreamType::from_label)
+                    .or_else(|| StreamType::from_label(&e.text().collect::<String>()))
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ExtractedTimestamps {
+    pub processing_type: ProcessingType,
+    pub start_date: String,
+    pub end_date: Option<String>,
+}
+
+impl ExtractedTimestamps {
+    pub fn into_url_data(self, username: String, broadcast_id: String) -> URLData {
+        URLData {
+            username,
+            broadcast_id,
+            start_date: self.start_date,
+            end_date: self.end_date,
+            stream_type: StreamType::default(),
+        }
+    }
+}
+
+// what a tracker page says about a stream, without anything getting probed
+#[derive(Debug, PartialEq, Serialize)]
+pub struct StreamAnalysis {
+    pub username: String,
+    pub broadcast_id: String,
+    // exact uses the start as is, bruteforce goes over every second between start and end
+    pub processing_type: ProcessingType,
+    pub start: i64,
+    pub end: Option<i64>,
+    // only known when the tracker had the exact start and end
+    pub duration: Option<i64>,
+    pub stream_type: StreamType,
+}
+
+pub fn analyze(processing_type: ProcessingType, data: &URLData) -> Result<StreamAnalysis> {
+    let start = parse_timestamp(&data.start_date)?;
+    let end = match &data.end_date {
+        Some(end) => Some(parse_timestamp(end)?),
+        None => None,
+    };
+    let duration = match (&processing_type, end) {
+        (ProcessingType::Exact, Some(end)) => Some(end - start),
+        _ => None,
+    };
+    Ok(StreamAnalysis {
+        username: data.username.clone(),
+        broadcast_id: data.broadcast_id.clone(),
+        processing_type,
+        start,
+        end,
+        duration,
+        stream_type: data.stream_type,
+    })
+}
+
+// new providers only need to be added here
+pub fn providers() -> Vec<Box<dyn TrackerProvider>> {
+    vec![Box::new(TwitchTracker), Box::new(StreamsCharts)]
+}
+
+pub fn find_provider(host: &str) -> Option<Box<dyn TrackerProvider>> {
+    let host = host.to_lowercase();
+    let host = host.strip_prefix("www.").unwrap_or(&host);
+    providers().into_iter().find(|p| p.hosts().contains(&host))
+}
+
+pub fn supported_hosts() -> String {
+    providers()
+        .iter()
+        .flat_map(|p| p.hosts().iter().copied())
+        .collect::<Vec<_>>()
+        .join(" and ")
+}
+
+fn path_segments(url: &Url) -> Result<Vec<&str>> {
+    Ok(url
+        .path_segments()
+        .map(|c| c.collect())
+        .ok_or(DeriveDate::SegmentMap)?)
+}
+
+pub struct TwitchTracker;
+
+impl TrackerProvider for TwitchTracker {
+    fn hosts(&self) -> &'static [&'static str] {
+        &["twitchtracker.com"]
+    }
+
+    fn parse(&self, url: &Url) -> Result<(String, String)> {
+        let segments = path_segments(url)?;
+        if segments.len() != 3 || segments[1] != "streams" {
+            return Err(DeriveDate::WrongURL(
+                "Not a valid TwitchTracker VOD URL".to_string(),
+            ))?;
+        }
+        Ok((segments[0].to_string(), segments[2].to_string()))
+    }
+
+    fn stream_url(&self, username: &str, broadcast_id: &str) -> String {
+        format!("https://twitchtracker.com/{username}/streams/{broadcast_id}")
+    }
+
+    fn streams_url(&self, username: &str) -> String {
+        format!("https://twitchtracker.com/{username}/streams")
+    }
+
+    fn extract(
+        &self,
+        page: &Html,
+        _mode: Option<ProcessingType>,
+        _simple: bool,
+    ) -> Result<ExtractedTimestamps> {
+        let selector = Selector::parse(".stream-timestamp-dt.to-dowdatetime")
+            .map_err(|_| DeriveDate::Selector)?;
+
+        let date = page
+            .select(&selector)
+            .next()
+            .ok_or(DeriveDate::ScraperElement)?
+            .text()
+            .collect::<String>();
+
+        Ok(ExtractedTimestamps {
+            processing_type: ProcessingType::Exact,
+            start_date: date,
+            end_date: None,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamsChartsTwitchClip {
+    started_at: String,
+    ended_at: String,
+}
+
+pub struct StreamsCharts;
+
+impl TrackerProvider for StreamsCharts {
+    fn hosts(&self) -> &'static [&'static str] {
+        &["streamscharts.com"]
+    }
+
+    fn parse(&self, url: &Url) -> Result<(String, String)> {
+        let segments = path_segments(url)?;
+        if segments.len() != 4 || segments[0] != "channels" || segments[2] != "streams" {
+            return Err(DeriveDate::WrongURL(
+                "Not a valid StreamsCharts VOD URL".to_string(),
+            ))?;
+        }
+        Ok((segments[1].to_string(), segments[3].to_string()))
+    }
+
+    fn stream_url(&self, username: &str, broadcast_id: &str) -> String {
+        format!("https://streamscharts.com/channels/{username}/streams/{broadcast_id}")
+    }
+
+    fn streams_url(&self, username: &str) -> String {
+        format!("https://streamscharts.com/channels/{username}/streams")
+    }
+
+    fn extract(
+        &self,
+        page: &Html,
+        mode: Option<ProcessingType>,
+        simple: bool,
+    ) -> Result<ExtractedTimestamps> {
+        let (processing_type, start, end) = match mode {
+            Some(ProcessingType::Bruteforce) => {
+                if !simple {
+                    info!("Bruteforcing for timestamps...");
+                }
+                sc_bruteforce_timestamps(page)?
+            }
+            Some(ProcessingType::Exact) => {
+                if !simple {
+                    info!("Extracting exact timestamps...");
+                }
+                sc_extract_exact_timestamps(page)?
+            }
+            None => {
+                if !simple {
+                    info!("Extracting exact timestamps...");
+                }
+                sc_extract_exact_timestamps(page).or_else(|_| {
+                    if !simple {
+                        info!("Bruteforcing for timestamps...");
+                    }
+                    sc_bruteforce_timestamps(page)
+                })?
+            }
+        };
+
+        if !simple {
+            let approximate_or_exact = match processing_type {
+                ProcessingType::Exact => "exact",
+                ProcessingType::Bruteforce => "approximate",
+            };
+            info!(
+                "Found {} timestamps for the stream. Started at {} and ended at {}.",
+                approximate_or_exact, start, end
+            );
+        }
+
+        Ok(ExtractedTimestamps {
+            processing_type,
+            start_date: start.to_string(),
+            end_date: Some(end.to_string()),
+        })
+    }
+}
+
+fn sc_extract_exact_timestamps(html_fragment: &Html) -> Result<(ProcessingType, i64, i64)> {
+    let exact_dt_selector =
+        Selector::parse("div > div[data-requests]").map_err(|_| DeriveDate::Selector)?;
+
+    let element = html_fragment
+        .select(&exact_dt_selector)
+        .next()
+        .ok_or(DeriveDate::ScraperElement)?;
+
+    let data_requests = element
+        .value()
+        .attr("data-requests")
+        .ok_or(DeriveDate::ScraperAttribute)?;
+
+    // Parse the clips_json into the struct StreamsChartsTwitchClip with serde_json
+    let clips_payloads: Vec<StreamsChartsTwitchClip> = serde_json::from_str(data_requests)?;
+
+    let first_clip = clips_payloads
+        .first()
+        .ok_or_else(|| DeriveDate::WrongURL("No clips found in data".to_string()))?;
+
+    let last_clip = clips_payloads
+        .last()
+        .ok_or_else(|| DeriveDate::WrongURL("No clips found in data".to_string()))?;
+
+    let start_dt = parse_timestamp(&first_clip.started_at)?;
+    let end_dt = parse_timestamp(&last_clip.ended_at)?;
+
+    Ok((ProcessingType::Exact, start_dt, end_dt))
+}
+
+fn sc_bruteforce_timestamps(html_fragment: &Html) -> Result<(ProcessingType, i64, i64)> {
+    let bruteforce_selector = Selector::parse("time").map_err(|_| DeriveDate::Selector)?;
+
+    let element = html_fragment
+        .select(&bruteforce_selector)
+        .next()
+        .ok_or(DeriveDate::ScraperElement)?;
+
+    let datetime_attr = element
+        .value()
+        .attr("datetime")
+        .ok_or(DeriveDate::ScraperAttribute)?;
+
+    let date_parsed = parse_timestamp(datetime_attr)?;
+
+    Ok((
+        ProcessingType::Bruteforce,
+        date_parsed - 60,
+        date_parsed + 60,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+    use url::Url;
+
+    use super::{analyze, find_provider, supported_hosts, ProcessingType, StreamType, URLData};
+
+    #[test]
+    fn provider_lookup() {
+        let parse = |url: &str| {
+            let url = Url::parse(url).unwrap();
+            find_provider(url.host_str().unwrap()).and_then(|p| p.parse(&url).ok())
+        };
+
+        assert_eq!(
+            parse("https://twitchtracker.com/forsen/streams/39619965384"),
+            Some(("forsen".to_string(), "39619965384".to_string())),
+            "testing twitchtracker"
+        );
+        assert_eq!(
+            parse("https://www.streamscharts.com/channels/robcdee/streams/39648192487"),
+            Some(("robcdee".to_string(), "39648192487".to_string())),
+            "testing streamscharts with www"
+        );
+        assert_eq!(
+            parse("https://streamscharts.com/channels/forsen/sreams/39619965384"),
+            None,
+            "testing wrong streamscharts link"
+        );
+        assert!(
+            find_provider("google.com").is_none(),
+            "testing unsupported host"
+        );
+        assert_eq!(
+            supported_hosts(),
+            "twitchtracker.com and streamscharts.com",
+            "testing the supported hosts message"
+        );
+    }
+
+    #[test]
+    fn stream_listing() {
+        let page = Html::parse_document(
+            r#"<table>
+                <tr><td><a href="/forsen/streams/39619965384">Stream</a></td></tr>
+                <tr><td><a href="https://twitchtracker.com/forsen/streams/39619965384">Again</a></td></tr>
+                <tr><td><a href="/forsen/streams/39600000000">Older</a></td></tr>
+                <tr><td><a href="/xqc/streams/39611111111">Someone else</a></td></tr>
+                <tr><td><a href="/forsen/games">Games</a></td></tr>
+            </table>"#,
+        );
+        let provider = find_provider("twitchtracker.com").unwrap();
+        assert_eq!(
+            provider.list_streams(&page, "Forsen"),
+            vec!["39619965384".to_string(), "39600000000".to_string()],
+            "testing the listed streams"
+        );
+        assert_eq!(
+            provider.stream_url("forsen", "39619965384"),
+            "https://twitchtracker.com/forsen/streams/39619965384",
+            "testing the stream URL"
+        );
+    }
+
+    #[test]
+    fn analysis() {
+        let data = URLData {
+            username: "robcdee".to_string(),
+            broadcast_id: "39648192487".to_string(),
+            start_date: "2021-06-05 00:50:17".to_string(),
+            end_date: Some("1622861417".to_string()),
+            stream_type: StreamType::Live,
+        };
+        let analysis = analyze(ProcessingType::Exact, &data).unwrap();
+        assert_eq!(
+            (analysis.start, analysis.end, analysis.duration),
+            (1622854217, Some(1622861417), Some(7200)),
+            "testing an exact analysis"
+        );
+        assert_eq!(
+            analyze(ProcessingType::Bruteforce, &data).unwrap().duration,
+            None,
+            "testing no duration for an approximate window"
+        );
+    }
+
+    #[test]
+    fn stream_types() {
+        let provider = find_provider("twitchtracker.com").unwrap();
+        let page = |html: &str| provider.stream_type(&Html::parse_document(html));
+
+        assert_eq!(
+            page(r#"<div><span class="label">Rerun</span><h1>Old stream</h1></div>"#),
+            StreamType::Rerun,
+            "testing a rerun label"
+        );
+        assert_eq!(
+            page(r#"<div data-stream-type="premiere"></div>"#),
+            StreamType::Premiere,
+            "testing the stream type attribute"
+        );
+        assert_eq!(
+            page(
+                r#"<h1>Watching the premiere of the new trailer</h1><span class="badge">Partner</span>"#
+            ),
+            StreamType::Live,
+            "testing a title that only mentions a premiere"
+        );
+        assert_eq!(
+            StreamType::from_label(" Vodcast "),
+            Some(StreamType::Rerun),
+            "testing the old rerun name"
+        );
+    }
+}
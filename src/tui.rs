@@ -0,0 +1,829 @@
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::{crate_name, crate_version, ArgAction, CommandFactory, Parser};
+use log::LevelFilter;
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Gauge, List, ListItem, ListState, Paragraph, Wrap},
+    Frame, Terminal,
+};
+use std::{
+    io::{self, stdin, stdout, Stdout, Write},
+    time::{Duration, Instant},
+};
+use strum::EnumMessage;
+use tokio::{sync::broadcast, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{Cli, Commands};
+use crate::http::HttpContext;
+use crate::i18n::{t, t_with};
+use crate::interface::run_plain;
+use crate::menu::{category_name, mode_name, mode_usage, Menu};
+use crate::offline::{parse_timestamp, split_range};
+use crate::progress::{Progress, ProgressEvent};
+use crate::recognize::recognize;
+use crate::twitch::models::ReturnURL;
+use crate::util;
+
+// how long to wait for a key before redrawing, also how often a running scan gets polled
+const TICK: Duration = Duration::from_millis(100);
+
+// one input of a form, an argument of the mode
+struct Field {
+    name: String,
+    help: String,
+    // None for the positional arguments
+    long: Option<String>,
+    required: bool,
+    // a flag without a value, toggled instead of typed
+    flag: bool,
+    // the default value, shown while nothing is typed
+    placeholder: String,
+    value: String,
+    on: bool,
+}
+
+impl Field {
+    fn label(&self) -> String {
+        let name = match &self.long {
+            Some(long) => format!("--{long}"),
+            None => self.name.clone(),
+        };
+        if self.required {
+            format!("{name} *")
+        } else {
+            name
+        }
+    }
+}
+
+// the arguments of a mode, taken from its clap definition, so every mode gets one
+struct Form {
+    command: Commands,
+    fields: Vec<Field>,
+    selected: usize,
+    error: Option<String>,
+}
+
+impl Form {
+    fn new(command: Commands) -> Self {
+        let fields = match Cli::command().find_subcommand(command.to_subcommand()) {
+            Some(sub) => {
+                let mut args: Vec<_> = sub
+                    .get_arguments()
+                    .filter(|a| {
+                        !matches!(
+                            a.get_action(),
+                            ArgAction::Help
+                                | ArgAction::HelpShort
+                                | ArgAction::HelpLong
+                                | ArgAction::Version
+                        )
+                    })
+                    .collect();
+                // the positional arguments are what a mode needs, the options come after them
+                args.sort_by_key(|a| !a.is_positional());
+                args.into_iter()
+                    .map(|a| Field {
+                        name: a.get_id().to_string(),
+                        help: a.get_help().map(|h| h.to_string()).unwrap_or_default(),
+                        long: a.get_long().map(str::to_string),
+                        required: a.is_required_set(),
+                        flag: matches!(a.get_action(), ArgAction::SetTrue),
+                        placeholder: a
+                            .get_default_values()
+                            .iter()
+                            .map(|v| v.to_string_lossy().to_string())
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                        value: String::new(),
+                        on: false,
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        Self {
+            command,
+            fields,
+            selected: 0,
+            error: None,
+        }
+    }
+
+    // the command line the form stands for, the options first and the positional arguments
+    // after a -- so values like -5 don't get taken for flags
+    fn args(&self) -> Vec<String> {
+        let mut args = vec![crate_name!().to_string(), self.command.to_subcommand()];
+        let mut positional = vec!["--".to_string()];
+        for field in &self.fields {
+            let value = field.value.trim();
+            match &field.long {
+                Some(long) if field.flag && field.on => args.push(format!("--{long}")),
+                Some(long) if !field.flag && !value.is_empty() => {
+                    args.push(format!("--{long}={value}"))
+                }
+                None if !value.is_empty() => positional.push(value.to_string()),
+                _ => (),
+            }
+        }
+        args.extend(positional);
+        args
+    }
+
+    // clap checks the types and what's required, the timestamps get parsed right away too
+    // instead of failing once the mode runs
+    fn validate(&self) -> Result<Commands> {
+        let cli = Cli::try_parse_from(self.args()).map_err(|e| {
+            let text = e.to_string();
+            anyhow!(
+                "{}",
+                text.lines()
+                    .next()
+                    .unwrap_or_default()
+                    .trim_start_matches("error: ")
+            )
+        })?;
+        let command = cli
+            .command
+            .ok_or_else(|| anyhow!("{}", t("menu.no_mode")))?;
+        match &command {
+            Commands::Exact { stamp, .. } => {
+                parse_timestamp(stamp)?;
+            }
+            Commands::Bruteforce { from, to, .. } => {
+                let (from, to) = split_range(from, to.as_deref())?;
+                parse_timestamp(from)?;
+                parse_timestamp(to)?;
+            }
+            _ => (),
+        }
+        Ok(command)
+    }
+
+    fn on_key(&mut self, key: KeyEvent) {
+        let Some(field) = self.fields.get_mut(self.selected) else {
+            return;
+        };
+        match key.code {
+            KeyCode::Tab | KeyCode::Down => {
+                self.selected = (self.selected + 1) % self.fields.len();
+            }
+            KeyCode::BackTab | KeyCode::Up => {
+                self.selected = (self.selected + self.fields.len() - 1) % self.fields.len();
+            }
+            KeyCode::Char(' ') if field.flag => field.on = !field.on,
+            KeyCode::Char(c) if !field.flag => field.value.push(c),
+            KeyCode::Backspace => {
+                field.value.pop();
+            }
+            _ => return,
+        }
+        self.error = None;
+    }
+
+    fn draw(&self, frame: &mut Frame, area: Rect) {
+        let [fields_area, help_area] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(6)]).areas(area);
+        let width = self
+            .fields
+            .iter()
+            .map(|f| f.label().len())
+            .max()
+            .unwrap_or_default();
+        let items: Vec<ListItem> = self
+            .fields
+            .iter()
+            .map(|f| {
+                let value = match (f.flag, f.value.is_empty()) {
+                    (true, _) => Span::raw(if f.on { "[x]" } else { "[ ]" }),
+                    (false, true) => Span::styled(f.placeholder.clone(), dimmed()),
+                    (false, false) => Span::raw(f.value.clone()),
+                };
+                ListItem::new(Line::from(vec![
+                    Span::styled(
+                        format!("{:<width$}  ", f.label()),
+                        Style::new().fg(Color::Yellow),
+                    ),
+                    value,
+                ]))
+            })
+            .collect();
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(mode_name(&self.command)))
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            fields_area,
+            &mut state,
+        );
+
+        let help = match (&self.error, self.fields.get(self.selected)) {
+            (Some(error), _) => Paragraph::new(error.as_str()).style(Style::new().fg(Color::Red)),
+            (None, Some(field)) => Paragraph::new(field.help.as_str()),
+            (None, None) => Paragraph::new(self.command.get_documentation().unwrap_or_default()),
+        };
+        frame.render_widget(
+            help.wrap(Wrap { trim: true }).block(Block::bordered()),
+            help_area,
+        );
+    }
+}
+
+// a mode running in the background, its progress polled every tick
+struct Run {
+    mode: String,
+    progress: Progress,
+    events: broadcast::Receiver<ProgressEvent>,
+    stage: Option<String>,
+    hits: Vec<ReturnURL>,
+    started: Instant,
+    cancel: CancellationToken,
+    task: JoinHandle<Result<Option<Vec<ReturnURL>>>>,
+}
+
+impl Run {
+    fn start(command: Commands, matches: &Cli, http: &HttpContext) -> Self {
+        let progress = Progress::default();
+        let cancel = CancellationToken::new();
+        let flags = Cli {
+            progressbar: false,
+            progress: Some(progress.clone()),
+            cancel: cancel.clone(),
+            headless: true,
+            ..matches.clone()
+        };
+        let http = http.clone();
+        Self {
+            mode: mode_name(&command),
+            events: progress.subscribe(),
+            progress,
+            stage: None,
+            hits: Vec::new(),
+            started: Instant::now(),
+            cancel,
+            task: tokio::spawn(async move { command.execute(flags, &http).await }),
+        }
+    }
+
+    fn poll_events(&mut self) {
+        loop {
+            match self.events.try_recv() {
+                Ok(ProgressEvent::Stage { stage }) => self.stage = Some(stage),
+                Ok(ProgressEvent::Hit { url }) => self.hits.push(url),
+                Ok(ProgressEvent::Progress { .. }) => (),
+                Err(broadcast::error::TryRecvError::Lagged(_)) => (),
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn draw(&self, frame: &mut Frame, area: Rect) {
+        let [gauge_area, hits_area] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(area);
+        let snapshot = self.progress.snapshot();
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let stage = if self.cancel.is_cancelled() {
+            t("tui.stopping")
+        } else {
+            self.stage.clone().unwrap_or_else(|| t("tui.starting"))
+        };
+        let (ratio, label) = match snapshot.total {
+            // nothing to count, only how long it's been going
+            0 => (0.0, format!("{elapsed:.0}s")),
+            total => {
+                let scanned = snapshot.scanned.min(total);
+                let left = match scanned {
+                    0 => String::new(),
+                    _ => format!(
+                        ", ~{:.0}s left",
+                        elapsed / scanned as f64 * (total - scanned) as f64
+                    ),
+                };
+                (
+                    scanned as f64 / total as f64,
+                    format!("{scanned}/{total}, {elapsed:.0}s{left}"),
+                )
+            }
+        };
+        frame.render_widget(
+            Gauge::default()
+                .block(Block::bordered().title(format!("{} - {stage}", self.mode)))
+                .gauge_style(Style::new().fg(Color::Green))
+                .ratio(ratio)
+                .label(label),
+            gauge_area,
+        );
+
+        let items: Vec<ListItem> = self.hits.iter().map(url_item).collect();
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title(t_with(
+                "tui.hits",
+                &[("count", &self.hits.len().to_string())],
+            ))),
+            hits_area,
+        );
+    }
+}
+
+// what a run ended with, the URLs are what the copying works on
+struct Results {
+    mode: String,
+    urls: Vec<ReturnURL>,
+    error: Option<String>,
+    state: ListState,
+}
+
+impl Results {
+    fn new(run: &Run, result: Result<Option<Vec<ReturnURL>>>) -> Self {
+        // the modes only returning what they found at the end still sent the hits on the way
+        let (urls, error) = match result {
+            Ok(Some(urls)) if !urls.is_empty() => (urls, None),
+            Ok(_) => (run.hits.clone(), None),
+            Err(e) => (run.hits.clone(), Some(e.to_string())),
+        };
+        Self {
+            mode: run.mode.clone(),
+            state: ListState::default().with_selected((!urls.is_empty()).then_some(0)),
+            urls,
+            error,
+        }
+    }
+
+    fn selected(&self) -> Option<&ReturnURL> {
+        self.urls.get(self.state.selected()?)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, area: Rect) {
+        let title = Line::from(vec![
+            Span::raw(format!("{} - ", self.mode)),
+            match &self.error {
+                Some(error) => Span::styled(
+                    t_with("tui.failed", &[("error", error)]),
+                    Style::new().fg(Color::Red),
+                ),
+                None => Span::raw(t_with(
+                    "tui.found",
+                    &[("count", &self.urls.len().to_string())],
+                )),
+            },
+        ]);
+        let block = Block::bordered().title(title);
+        if self.urls.is_empty() {
+            frame.render_widget(Paragraph::new(t("tui.nothing_found")).block(block), area);
+            return;
+        }
+        let items: Vec<ListItem> = self.urls.iter().map(url_item).collect();
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(block)
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+                .highlight_symbol("> "),
+            area,
+            &mut self.state,
+        );
+    }
+}
+
+enum Screen {
+    Menu,
+    Form(Form),
+    Running(Run),
+    Results(Results),
+}
+
+// what the loop has to do outside of the screens
+enum Action {
+    None,
+    Quit,
+    // a mode without the live progress, run on the plain terminal with its own output
+    Plain(Box<Commands>),
+}
+
+struct App {
+    matches: Cli,
+    menu: Menu,
+    search: String,
+    menu_state: ListState,
+    screen: Screen,
+    // shown in place of the keys until the next key
+    status: Option<String>,
+}
+
+impl App {
+    fn new(matches: Cli) -> Self {
+        Self {
+            matches,
+            menu: Menu::default(),
+            search: String::new(),
+            menu_state: ListState::default().with_selected(Some(0)),
+            screen: Screen::Menu,
+            status: None,
+        }
+    }
+
+    fn set_search(&mut self, search: String) {
+        self.search = search;
+        self.menu.clear_search();
+        self.menu.search(&self.search);
+        self.menu_state.select(Some(0));
+    }
+
+    // the modes with a progress to show run inside the TUI, the rest get the terminal back
+    fn run(&mut self, command: Commands, http: &HttpContext) -> Action {
+        if command.is_job() {
+            self.screen = Screen::Running(Run::start(command, &self.matches, http));
+            return Action::None;
+        }
+        self.screen = Screen::Menu;
+        Action::Plain(Box::new(command))
+    }
+
+    // the selected mode gets its form, a pasted link runs right away
+    fn on_menu_key(&mut self, key: KeyEvent, http: &HttpContext) -> Action {
+        let entries = self.menu.entries().len();
+        let selected = self.menu_state.selected().unwrap_or_default();
+        match key.code {
+            KeyCode::Esc if self.search.is_empty() => return Action::Quit,
+            KeyCode::Esc => self.set_search(String::new()),
+            KeyCode::Up => self.menu_state.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down if entries > 0 => self
+                .menu_state
+                .select(Some((selected + 1).min(entries - 1))),
+            KeyCode::PageUp => self.menu_state.select(Some(selected.saturating_sub(10))),
+            KeyCode::PageDown if entries > 0 => self
+                .menu_state
+                .select(Some((selected + 10).min(entries - 1))),
+            KeyCode::Backspace => {
+                let mut search = self.search.clone();
+                search.pop();
+                self.set_search(search);
+            }
+            KeyCode::Char(c) => self.set_search(format!("{}{c}", self.search)),
+            KeyCode::Enter => {
+                let chosen = self.menu.entries().get(selected).map(|c| (*c).clone());
+                if let Some(command) = chosen {
+                    self.screen = Screen::Form(Form::new(command));
+                } else if let Some(command) = recognize(&self.search).to_command(&self.search) {
+                    self.set_search(String::new());
+                    return self.run(command, http);
+                }
+            }
+            _ => (),
+        }
+        Action::None
+    }
+
+    fn on_key(&mut self, key: KeyEvent, http: &HttpContext) -> Action {
+        self.status = None;
+        let ctrl_c =
+            key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c');
+        match &mut self.screen {
+            Screen::Menu if ctrl_c => Action::Quit,
+            Screen::Menu => self.on_menu_key(key, http),
+            Screen::Form(_) if ctrl_c || key.code == KeyCode::Esc => {
+                self.screen = Screen::Menu;
+                Action::None
+            }
+            Screen::Form(form) if key.code == KeyCode::Enter => match form.validate() {
+                Ok(command) => self.run(command, http),
+                Err(e) => {
+                    form.error = Some(e.to_string());
+                    Action::None
+                }
+            },
+            Screen::Form(form) => {
+                form.on_key(key);
+                Action::None
+            }
+            // the scan stops on its own, the results show up once it did
+            Screen::Running(run) if ctrl_c || key.code == KeyCode::Esc => {
+                run.cancel.cancel();
+                Action::None
+            }
+            Screen::Running(_) => Action::None,
+            Screen::Results(_) if ctrl_c || key.code == KeyCode::Esc => {
+                self.screen = Screen::Menu;
+                Action::None
+            }
+            Screen::Results(results) => {
+                let last = results.urls.len().saturating_sub(1);
+                let selected = results.state.selected().unwrap_or_default();
+                match key.code {
+                    KeyCode::Up => results.state.select(Some(selected.saturating_sub(1))),
+                    KeyCode::Down => results.state.select(Some((selected + 1).min(last))),
+                    KeyCode::Enter | KeyCode::Char('c') => {
+                        if let Some(url) = results.selected() {
+                            self.status = Some(copy_status(&url.url));
+                        }
+                    }
+                    KeyCode::Char('a') if !results.urls.is_empty() => {
+                        let all: Vec<&str> = results.urls.iter().map(|u| u.url.as_str()).collect();
+                        self.status = Some(copy_status(&all.join("\n")));
+                    }
+                    _ => (),
+                }
+                Action::None
+            }
+        }
+    }
+
+    // moves a finished run over to its results
+    async fn update(&mut self) {
+        let Screen::Running(run) = &mut self.screen else {
+            return;
+        };
+        run.poll_events();
+        if !run.task.is_finished() {
+            return;
+        }
+        let result = match (&mut run.task).await {
+            Ok(result) => result,
+            Err(e) => Err(e.into()),
+        };
+        self.screen = Screen::Results(Results::new(run, result));
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [header, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        frame.render_widget(
+            Paragraph::new(format!("{} v{}", crate_name!(), crate_version!()))
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+            header,
+        );
+
+        let keys = match &mut self.screen {
+            Screen::Menu => {
+                draw_menu(frame, body, &self.menu, &self.search, &mut self.menu_state);
+                "tui.menu_keys"
+            }
+            Screen::Form(form) => {
+                form.draw(frame, body);
+                "tui.form_keys"
+            }
+            Screen::Running(run) => {
+                run.draw(frame, body);
+                "tui.running_keys"
+            }
+            Screen::Results(results) => {
+                results.draw(frame, body);
+                "tui.results_keys"
+            }
+        };
+        let footer_text = match &self.status {
+            Some(status) => Paragraph::new(status.as_str()).style(Style::new().fg(Color::Green)),
+            None => Paragraph::new(t(keys)).style(dimmed()),
+        };
+        frame.render_widget(footer_text, footer);
+    }
+}
+
+fn dimmed() -> Style {
+    Style::new().add_modifier(Modifier::DIM)
+}
+
+fn url_item(url: &ReturnURL) -> ListItem<'static> {
+    let mut spans = vec![Span::raw(url.url.clone())];
+    if url.muted {
+        spans.push(Span::styled(" muted", Style::new().fg(Color::Yellow)));
+    }
+    if url.audio_only {
+        spans.push(Span::styled(" audio only", dimmed()));
+    }
+    ListItem::new(Line::from(spans))
+}
+
+fn draw_menu(frame: &mut Frame, area: Rect, menu: &Menu, search: &str, state: &mut ListState) {
+    let [input, body] = Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(area);
+    frame.render_widget(
+        Paragraph::new(search).block(Block::bordered().title(t("tui.search"))),
+        input,
+    );
+    frame.set_cursor_position((input.x + 1 + search.chars().count() as u16, input.y + 1));
+
+    let entries = menu.entries();
+    if entries.is_empty() {
+        let recognized = recognize(search);
+        let text = match recognized.to_command(search) {
+            Some(command) => t_with(
+                "tui.recognized",
+                &[
+                    ("kind", recognized.kind.describe()),
+                    ("mode", &mode_name(&command)),
+                ],
+            ),
+            None => t_with("tui.no_match", &[("query", search)]),
+        };
+        frame.render_widget(Paragraph::new(text).wrap(Wrap { trim: true }), body);
+        return;
+    }
+
+    let [list_area, help_area] =
+        Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(body);
+    let items: Vec<ListItem> = entries
+        .iter()
+        .map(|com| {
+            ListItem::new(Line::from(vec![
+                Span::styled(mode_name(com), Style::new().fg(Color::LightGreen)),
+                Span::styled(format!("  {}", category_name(com.category())), dimmed()),
+            ]))
+        })
+        .collect();
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(Block::bordered())
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+            .highlight_symbol("> "),
+        list_area,
+        state,
+    );
+
+    let help = state
+        .selected()
+        .and_then(|i| entries.get(i))
+        .map(|com| mode_usage(com))
+        .unwrap_or_default();
+    frame.render_widget(
+        Paragraph::new(help)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered()),
+        help_area,
+    );
+}
+
+// OSC 52, the terminal puts it on the clipboard, over SSH too
+fn clipboard_sequence(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+fn copy_status(text: &str) -> String {
+    let mut out = stdout();
+    match out
+        .write_all(clipboard_sequence(text).as_bytes())
+        .and_then(|_| out.flush())
+    {
+        Ok(_) => t("tui.copied"),
+        Err(e) => t_with("tui.failed", &[("error", &e.to_string())]),
+    }
+}
+
+// puts the terminal back however the TUI ends, a panic included
+struct TerminalGuard {
+    // the scans log to stderr, which would draw all over the screen, so logging is off while
+    // the TUI is up
+    level: LevelFilter,
+}
+
+impl TerminalGuard {
+    fn enter() -> io::Result<Self> {
+        let guard = Self {
+            level: log::max_level(),
+        };
+        guard.resume()?;
+        Ok(guard)
+    }
+
+    fn resume(&self) -> io::Result<()> {
+        enable_raw_mode()?;
+        execute!(stdout(), EnterAlternateScreen)?;
+        log::set_max_level(LevelFilter::Off);
+        Ok(())
+    }
+
+    fn suspend(&self) -> io::Result<()> {
+        log::set_max_level(self.level);
+        execute!(stdout(), LeaveAlternateScreen)?;
+        disable_raw_mode()
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = self.suspend();
+    }
+}
+
+async fn run_app(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    guard: &TerminalGuard,
+    app: &mut App,
+    http: &HttpContext,
+) -> Result<()> {
+    loop {
+        app.update().await;
+        terminal.draw(|frame| app.draw(frame))?;
+        if !event::poll(TICK)? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match app.on_key(key, http) {
+            Action::None => (),
+            Action::Quit => return Ok(()),
+            Action::Plain(command) => {
+                guard.suspend()?;
+                run_plain(*command, false, app.matches.clone(), http).await;
+                util::prompt(&t("tui.press_enter"));
+                stdin().read_line(&mut String::new())?;
+                guard.resume()?;
+                terminal.clear()?;
+            }
+        }
+    }
+}
+
+pub async fn tui_interface(matches: Cli, http: &HttpContext) -> Result<()> {
+    let guard = TerminalGuard::enter()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    let mut app = App::new(matches);
+    run_app(&mut terminal, &guard, &mut app, http).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Commands;
+
+    use super::{clipboard_sequence, Form};
+
+    #[test]
+    fn forms() {
+        let mut form = Form::new(Commands::Bruteforce {
+            username: String::new(),
+            id: 0,
+            from: String::new(),
+            to: None,
+        });
+        assert_eq!(
+            form.fields.iter().map(|f| f.label()).collect::<Vec<_>>(),
+            vec!["username *", "id *", "from *", "to"],
+            "testing the fields"
+        );
+        assert!(form.validate().is_err(), "testing an empty form");
+
+        form.fields[0].value = "dansgaming".to_string();
+        form.fields[1].value = "nope".to_string();
+        form.fields[2].value = "now-90m..now".to_string();
+        assert!(
+            form.validate().is_err(),
+            "testing an ID that isn't a number"
+        );
+
+        form.fields[1].value = "42218705421".to_string();
+        assert!(
+            matches!(
+                form.validate().unwrap(),
+                Commands::Bruteforce {
+                    id: 42218705421,
+                    to: None,
+                    ..
+                }
+            ),
+            "testing a valid form"
+        );
+
+        form.fields[2].value = "yesterday".to_string();
+        assert!(
+            form.validate().is_err(),
+            "testing a timestamp that doesn't parse"
+        );
+
+        let mut form = Form::new(Commands::Clip {
+            clip: String::new(),
+            direct: false,
+        });
+        form.fields[0].value = "-GentleAthleticWombat".to_string();
+        form.fields[1].on = true;
+        assert_eq!(
+            form.args(),
+            vec!["tbf", "clip", "--direct", "--", "-GentleAthleticWombat"],
+            "testing the command line"
+        );
+    }
+
+    #[test]
+    fn clipboard() {
+        assert_eq!(
+            clipboard_sequence("https://example.com"),
+            "\x1b]52;c;aHR0cHM6Ly9leGFtcGxlLmNvbQ==\x07",
+            "testing the OSC 52 sequence"
+        );
+    }
+}
@@ -1,76 +1,336 @@
+pub mod chat;
 pub mod clips;
+pub mod emotes;
+pub mod irc;
 pub mod models;
 pub mod vods;
 
+use anyhow::{anyhow, Result};
 use futures::stream::{self, StreamExt};
+use log::debug;
+use m3u8_rs::{parse_media_playlist_res, MediaSegment};
+use reqwest::header::{HeaderMap, HeaderValue};
+use std::time::Duration;
+use tokio::time::Instant;
+use url::Url;
 
-use crate::config::Cli;
+use crate::config::ScanOptions;
+use crate::http::HttpContext;
+use crate::offline::split_vod_directory;
 use crate::util::compile_cdn_list;
-use models::{AvailabilityCheck, ReturnURL};
+use models::{
+    is_audio_only, AvailabilityCheck, MutedRange, MutedSegments, PathLayout, ReturnURL,
+    VideoInfoQuery, VideoInfoResponse, VideoInfoVars, VideoLookupResponse, VideoQuery, VideoVars,
+    VodInfo, AUDIO_ONLY, VOD_QUALITIES,
+};
+
+// what gets probed for the VOD's directory on every CDN, in every quality and layout asked for
+fn availability_checks(
+    directory: &str,
+    qualities: &[String],
+    cdns: &[String],
+    flags: &ScanOptions,
+) -> Vec<AvailabilityCheck> {
+    let mut urls: Vec<AvailabilityCheck> = Vec::new();
+    for cdn in cdns {
+        for quality in qualities {
+            for layout in &flags.layouts {
+                let playlist = layout.quality_url(cdn, directory, quality);
+                urls.push(match layout {
+                    PathLayout::Dvr => AvailabilityCheck {
+                        fragment: format!("https://{cdn}/{directory}/{quality}/1.ts"),
+                        fragment_muted: Some(format!(
+                            "https://{cdn}/{directory}/{quality}/1-muted.ts"
+                        )),
+                        playlist,
+                    },
+                    // nothing to guess the segment names from, so the playlist itself gets asked for
+                    PathLayout::Index => AvailabilityCheck {
+                        fragment: playlist.clone(),
+                        fragment_muted: None,
+                        playlist,
+                    },
+                });
+            }
+        }
+    }
+    urls
+}
 
 pub async fn check_availability(
     hash: &String,
     username: &str,
     broadcast_id: i64,
     timestamp: &i64,
-    flags: Cli,
+    flags: &ScanOptions,
+    http: &HttpContext,
 ) -> Vec<ReturnURL> {
-    let mut urls: Vec<AvailabilityCheck> = Vec::new();
-    let cdn_urls_compiled = compile_cdn_list(flags.cdnfile);
-    for cdn in cdn_urls_compiled {
-        urls.push(AvailabilityCheck {
-            fragment: (format!(
-                "https://{cdn}/{hash}_{username}_{broadcast_id}_{timestamp}/chunked/1.ts"
-            )),
-            fragment_muted: (format!(
-                "https://{cdn}/{hash}_{username}_{broadcast_id}_{timestamp}/chunked/1-muted.ts"
-            )),
-            playlist: (format!(
-                "https://{cdn}/{hash}_{username}_{broadcast_id}_{timestamp}/chunked/index-dvr.m3u8"
-            )),
-        });
+    let directory = format!("{hash}_{username}_{broadcast_id}_{timestamp}");
+    let cdns = compile_cdn_list(flags.cdnfile.clone(), http).await;
+    let found = probe_availability(
+        availability_checks(&directory, &flags.qualities, &cdns, flags),
+        flags,
+        http,
+    )
+    .await;
+
+    // the video renditions can be gone while the audio is still around
+    if found.is_empty() && flags.audio_fallback && !flags.qualities.iter().any(|q| q == AUDIO_ONLY)
+    {
+        debug!("No video left for {directory}, looking for the audio");
+        let audio = [AUDIO_ONLY.to_string()];
+        return probe_availability(
+            availability_checks(&directory, &audio, &cdns, flags),
+            flags,
+            http,
+        )
+        .await;
     }
+    found
+}
 
-    let fetches = stream::iter(urls)
-        .map(|url| async move {
-            let unmuted = match crate::HTTP_CLIENT.get(url.fragment.as_str()).send().await {
+async fn probe_availability(
+    urls: Vec<AvailabilityCheck>,
+    flags: &ScanOptions,
+    http: &HttpContext,
+) -> Vec<ReturnURL> {
+    let mut checks = stream::iter(urls.into_iter().enumerate())
+        .map(|(i, url)| async move {
+            let unmuted = match http.probe(url.fragment.as_str()).await {
                 Ok(r) => r.status(),
                 Err(_) => return None,
             };
-            let muted = match crate::HTTP_CLIENT
-                .get(url.fragment_muted.as_str())
-                .send()
-                .await
-            {
-                Ok(r) => r.status(),
-                Err(_) => return None,
+            let muted = match &url.fragment_muted {
+                Some(fragment) => match http.probe(fragment).await {
+                    Ok(r) => r.status(),
+                    Err(_) => return None,
+                },
+                None => unmuted,
             };
             if unmuted == 200 {
-                Some(ReturnURL {
-                    url: url.playlist.clone(),
-                    muted: false,
-                })
+                Some((
+                    i,
+                    ReturnURL {
+                        url: url.playlist.clone(),
+                        muted: false,
+                        audio_only: is_audio_only(&url.playlist),
+                    },
+                ))
             } else if muted == 200 {
-                Some(ReturnURL {
-                    url: url.playlist.clone(),
-                    muted: true,
-                })
+                Some((
+                    i,
+                    ReturnURL {
+                        url: url.playlist.clone(),
+                        muted: true,
+                        audio_only: is_audio_only(&url.playlist),
+                    },
+                ))
             } else {
                 None
             }
         })
-        .buffer_unordered(flags.threads)
-        .collect::<Vec<Option<ReturnURL>>>()
-        .await;
+        .buffer_unordered(flags.threads);
+
+    // once enough CDNs confirmed the VOD, the stragglers only get a short grace period
+    let mut found: Vec<(usize, ReturnURL)> = Vec::new();
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let next = match deadline {
+            Some(deadline) => match tokio::time::timeout_at(deadline, checks.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    debug!("Enough CDNs confirmed the VOD, dropping the remaining checks");
+                    break;
+                }
+            },
+            None => checks.next().await,
+        };
 
-    fetches.into_iter().flatten().collect()
+        match next {
+            Some(Some(hit)) => {
+                found.push(hit);
+                if deadline.is_none()
+                    && flags.confirmations > 0
+                    && found.len() >= flags.confirmations
+                {
+                    deadline = Some(Instant::now() + Duration::from_millis(flags.hedge_delay));
+                }
+            }
+            Some(None) => (),
+            None => break,
+        }
+    }
+
+    // keeping the CDN list order regardless of which one answered first
+    found.sort_by_key(|(i, _)| *i);
+    found.into_iter().map(|(_, url)| url).collect()
+}
+
+// goes over every quality of a found VOD and counts the segments that got muted
+pub async fn check_muted_segments(playlist_url: &str, http: &HttpContext) -> Vec<MutedSegments> {
+    // whichever quality got found, the others are next to it
+    let base_url = match playlist_url
+        .strip_suffix("/index-dvr.m3u8")
+        .and_then(|u| u.rsplit_once('/'))
+    {
+        Some((b, _)) => format!("{b}/"),
+        None => return Vec::new(),
+    };
+
+    stream::iter(VOD_QUALITIES)
+        .map(|quality| {
+            let url = format!("{base_url}{quality}/index-dvr.m3u8");
+            async move {
+                let resp = http.send_with_retry(http.client.get(url)).await.ok()?;
+                let body = resp.error_for_status().ok()?.bytes().await.ok()?;
+                let playlist = parse_media_playlist_res(&body).ok()?;
+                Some(MutedSegments {
+                    quality: quality.to_string(),
+                    muted: playlist
+                        .segments
+                        .iter()
+                        .filter(|segment| segment.uri.contains("muted"))
+                        .count(),
+                    total: playlist.segments.len(),
+                })
+            }
+        })
+        .buffered(VOD_QUALITIES.len())
+        .filter_map(|report| async move { report })
+        // boxed so the borrowed qualities don't keep the callers' futures from being Send
+        .boxed()
+        .collect()
+        .await
+}
+
+// merges consecutive muted segments into ranges
+fn collect_muted_ranges(segments: &[MediaSegment]) -> Vec<MutedRange> {
+    let mut ranges: Vec<MutedRange> = Vec::new();
+    let mut offset = 0.0;
+    let mut previous_muted = false;
+
+    for segment in segments {
+        let duration = segment.duration as f64;
+        let muted = segment.uri.contains("muted");
+        if muted {
+            match ranges.last_mut() {
+                Some(range) if previous_muted => range.end = offset + duration,
+                _ => ranges.push(MutedRange {
+                    start: offset,
+                    end: offset + duration,
+                }),
+            }
+        }
+        previous_muted = muted;
+        offset += duration;
+    }
+
+    ranges
+}
+
+pub async fn muted_ranges(playlist_url: &str, http: &HttpContext) -> Vec<MutedRange> {
+    let body = match http.send_with_retry(http.client.get(playlist_url)).await {
+        Ok(r) => match r.error_for_status() {
+            Ok(r) => r.bytes().await.ok(),
+            Err(_) => None,
+        },
+        Err(_) => None,
+    };
+
+    match body.and_then(|b| parse_media_playlist_res(&b).ok()) {
+        Some(playlist) => collect_muted_ranges(&playlist.segments),
+        None => {
+            debug!("Couldn't get the muted ranges for {playlist_url}");
+            Vec::new()
+        }
+    }
+}
+
+pub async fn vod_info(username: &str, vod: i64, http: &HttpContext) -> Result<VodInfo> {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        "client-id",
+        HeaderValue::from_static("kimne78kx3ncx6brgo4mv6wki5h1ko"),
+    );
+
+    let query = VideoInfoQuery {
+        query: "query($id:ID,$login:String){video(id: $id){title}user(login: $login){displayName}}"
+            .to_string(),
+        variables: VideoInfoVars {
+            id: vod.to_string(),
+            login: username.to_string(),
+        },
+    };
+
+    let request = http
+        .client
+        .post("https://gql.twitch.tv/gql")
+        .json(&query)
+        .headers(header_map);
+    let data: VideoInfoResponse = http.send_with_retry(request).await?.json().await?;
+
+    Ok(VodInfo {
+        display_name: data.data.user.map(|u| u.display_name),
+        title: data.data.video.and_then(|v| v.title),
+    })
+}
+
+// the streamer, the broadcast ID and the start of a VOD that's still up, None once it's deleted
+pub async fn lookup_video(video_id: i64, http: &HttpContext) -> Result<Option<(String, i64, i64)>> {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        "client-id",
+        HeaderValue::from_static("kimne78kx3ncx6brgo4mv6wki5h1ko"),
+    );
+
+    let query = VideoQuery {
+        query: "query($id:ID){video(id: $id){owner{login}seekPreviewsURL}}".to_string(),
+        variables: VideoVars {
+            id: video_id.to_string(),
+        },
+    };
+
+    let request = http
+        .client
+        .post("https://gql.twitch.tv/gql")
+        .json(&query)
+        .headers(header_map);
+    let data: VideoLookupResponse = http.send_with_retry(request).await?.json().await?;
+    let Some(video) = data.data.video else {
+        return Ok(None);
+    };
+
+    let directory = video
+        .seek_previews_url
+        .as_deref()
+        .and_then(|url| Url::parse(url).ok())
+        .and_then(|url| split_vod_directory(url.path_segments()?.next()?));
+    match directory {
+        Some((_, username, broadcast_id, timestamp)) => Ok(Some((
+            video.owner.map(|o| o.login).unwrap_or(username),
+            broadcast_id,
+            timestamp,
+        ))),
+        None => Err(anyhow!(
+            "couldn't get the broadcast ID of the video {video_id}, only past broadcasts have one"
+        )),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{config::Cli, twitch::models::ReturnURL};
+    use m3u8_rs::MediaSegment;
+    use std::sync::Arc;
 
-    use super::check_availability as ca;
+    use crate::{
+        config::ScanOptions,
+        http::{Fixtures, HttpContext},
+        twitch::models::{MutedRange, PathLayout, ReturnURL},
+    };
+
+    use super::{
+        check_availability as ca, check_muted_segments, collect_muted_ranges, lookup_video,
+    };
 
     #[tokio::test]
     async fn check_availability() {
@@ -80,16 +340,19 @@ mod tests {
             "dansgaming",
             42218705421,
             &1622854217,
-            Cli::default(),
+            &ScanOptions::default(),
+            &HttpContext::default(),
         )
         .await;
 
         let comp_working: Vec<ReturnURL> = vec![ReturnURL {
             url: "https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8".to_string(),
             muted: false,
+            audio_only: false,
         }, ReturnURL {
             url: "https://d2vjef5jvl6bfs.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8".to_string(),
             muted: false,
+            audio_only: false,
         }];
 
         assert_eq!(
@@ -103,7 +366,8 @@ mod tests {
             "forsen",
             23722143840,
             &1479745189,
-            Cli::default(),
+            &ScanOptions::default(),
+            &HttpContext::default(),
         )
         .await;
 
@@ -114,4 +378,240 @@ mod tests {
             "testing invalid vod (forsen - 2016)"
         );
     }
+
+    #[tokio::test]
+    async fn check_availability_offline() {
+        let base = "d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked";
+        let fixtures = Arc::new(
+            Fixtures::new()
+                .respond(
+                    &format!("https://d1m7jfoe9zdc1j.cloudfront.net/{base}/1.ts"),
+                    200,
+                    "",
+                )
+                .respond(
+                    &format!("https://d2vjef5jvl6bfs.cloudfront.net/{base}/1-muted.ts"),
+                    200,
+                    "",
+                ),
+        );
+        let http = HttpContext::default().with_fixtures(fixtures.clone());
+
+        let found = ca(
+            &"d3dcbaf880c9e36ed8c8".to_string(),
+            "dansgaming",
+            42218705421,
+            &1622854217,
+            &ScanOptions::default().confirmations(0),
+            &http,
+        )
+        .await;
+        assert_eq!(
+            found,
+            vec![
+                ReturnURL {
+                    url: format!("https://d1m7jfoe9zdc1j.cloudfront.net/{base}/index-dvr.m3u8"),
+                    muted: false,
+                    audio_only: false,
+                },
+                ReturnURL {
+                    url: format!("https://d2vjef5jvl6bfs.cloudfront.net/{base}/index-dvr.m3u8"),
+                    muted: true,
+                    audio_only: false,
+                }
+            ],
+            "testing unmuted and muted hits in the CDN order"
+        );
+        assert!(
+            fixtures
+                .requested()
+                .iter()
+                .all(|url| url.contains(base) && url.ends_with(".ts")),
+            "testing the probed URLs"
+        );
+    }
+
+    #[tokio::test]
+    async fn check_availability_layouts() {
+        let base = "d45bc961583725d59867_forsen_23722143840_1479745189/chunked";
+        let fixtures = Arc::new(Fixtures::new().respond(
+            &format!("https://vod-metro.twitch.tv/{base}/index.m3u8"),
+            200,
+            "#EXTM3U",
+        ));
+        let http = HttpContext::default().with_fixtures(fixtures.clone());
+
+        let found = ca(
+            &"d45bc961583725d59867".to_string(),
+            "forsen",
+            23722143840,
+            &1479745189,
+            &ScanOptions::default()
+                .confirmations(0)
+                .layouts(vec![PathLayout::Dvr, PathLayout::Index]),
+            &http,
+        )
+        .await;
+        assert_eq!(
+            found,
+            vec![ReturnURL {
+                url: format!("https://vod-metro.twitch.tv/{base}/index.m3u8"),
+                muted: false,
+                audio_only: false,
+            }],
+            "testing an older VOD with the plain index playlist"
+        );
+        assert!(
+            fixtures
+                .requested()
+                .iter()
+                .any(|url| url.ends_with("/chunked/1.ts")),
+            "testing the current layout still getting checked"
+        );
+    }
+
+    #[tokio::test]
+    async fn check_availability_qualities() {
+        let base = "d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217";
+        let fixtures = Arc::new(Fixtures::new().respond(
+            &format!("https://d1m7jfoe9zdc1j.cloudfront.net/{base}/480p30/1.ts"),
+            200,
+            "",
+        ));
+        let http = HttpContext::default().with_fixtures(fixtures.clone());
+
+        let found = ca(
+            &"d3dcbaf880c9e36ed8c8".to_string(),
+            "dansgaming",
+            42218705421,
+            &1622854217,
+            &ScanOptions::default()
+                .confirmations(0)
+                .qualities(vec!["chunked".to_string(), "480p30".to_string()]),
+            &http,
+        )
+        .await;
+        assert_eq!(
+            found,
+            vec![ReturnURL {
+                url: format!("https://d1m7jfoe9zdc1j.cloudfront.net/{base}/480p30/index-dvr.m3u8"),
+                muted: false,
+                audio_only: false,
+            }],
+            "testing a VOD only left in a transcoded quality"
+        );
+        assert!(
+            fixtures
+                .requested()
+                .iter()
+                .any(|url| url.ends_with("/chunked/1.ts")),
+            "testing the source still getting checked"
+        );
+    }
+
+    #[tokio::test]
+    async fn check_availability_audio() {
+        let base = "d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217";
+        let url = format!("https://d1m7jfoe9zdc1j.cloudfront.net/{base}/audio_only/index-dvr.m3u8");
+        let fixtures = Arc::new(Fixtures::new().respond(
+            &format!("https://d1m7jfoe9zdc1j.cloudfront.net/{base}/audio_only/1.ts"),
+            200,
+            "",
+        ));
+        let http = HttpContext::default().with_fixtures(fixtures);
+        let check = |options: ScanOptions| {
+            let http = &http;
+            async move {
+                ca(
+                    &"d3dcbaf880c9e36ed8c8".to_string(),
+                    "dansgaming",
+                    42218705421,
+                    &1622854217,
+                    &options.confirmations(0),
+                    http,
+                )
+                .await
+            }
+        };
+
+        assert!(
+            check(ScanOptions::default()).await.is_empty(),
+            "testing no audio without the fallback"
+        );
+        assert_eq!(
+            check(ScanOptions::default().audio_fallback(true)).await,
+            vec![ReturnURL {
+                url,
+                muted: false,
+                audio_only: true,
+            }],
+            "testing the audio-only fallback"
+        );
+    }
+
+    #[tokio::test]
+    async fn muted_segments() {
+        assert!(
+            check_muted_segments(
+                "https://vod-secure.twitch.tv/not/a/playlist.m3u8",
+                &HttpContext::default()
+            )
+            .await
+            .is_empty(),
+            "testing non-chunked playlist url"
+        );
+    }
+
+    #[test]
+    fn muted_ranges() {
+        let segments: Vec<MediaSegment> =
+            ["0.ts", "1-muted.ts", "2-unmuted.ts", "3.ts", "4-muted.ts"]
+                .iter()
+                .map(|uri| MediaSegment {
+                    uri: uri.to_string(),
+                    duration: 10.0,
+                    ..Default::default()
+                })
+                .collect();
+
+        assert_eq!(
+            collect_muted_ranges(&segments),
+            vec![
+                MutedRange {
+                    start: 10.0,
+                    end: 30.0
+                },
+                MutedRange {
+                    start: 40.0,
+                    end: 50.0
+                }
+            ],
+            "testing muted range merging"
+        );
+        assert!(
+            collect_muted_ranges(&segments[..1]).is_empty(),
+            "testing playlist without muted segments"
+        );
+    }
+
+    #[tokio::test]
+    async fn video_lookup() {
+        let gql = "https://gql.twitch.tv/gql";
+        let fixtures = Arc::new(
+            Fixtures::new()
+                .respond(gql, 200, r#"{"data":{"video":{"owner":{"login":"dansgaming"},"seekPreviewsURL":"https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/storyboards/1043232498-info.json"}}}"#)
+                .respond(gql, 200, r#"{"data":{"video":null}}"#),
+        );
+        let http = HttpContext::default().with_fixtures(fixtures);
+        assert_eq!(
+            lookup_video(1043232498, &http).await.unwrap(),
+            Some(("dansgaming".to_string(), 42218705421, 1622854217)),
+            "testing a VOD that's still up"
+        );
+        assert_eq!(
+            lookup_video(1043232498, &http).await.unwrap(),
+            None,
+            "testing a deleted VOD"
+        );
+    }
 }
@@ -0,0 +1,876 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use indicatif::{ProgressBar, ProgressStyle};
+use log::{debug, info, warn};
+use regex::Regex;
+use reqwest::{
+    header::{HeaderMap, HeaderValue, RETRY_AFTER},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, time::Duration};
+use time::{format_description::well_known::Rfc3339, Date, OffsetDateTime};
+
+use crate::config::ChatOptions;
+use crate::error::Chat;
+use crate::http::HttpContext;
+use crate::twitch::emotes::{resolve_emotes, user_id};
+use crate::twitch::models::{
+    ChannelLogs, ChatLog, ChatMessage, ChatVideo, ChatVideoQuery, ChatVideoResponse, ChatVideoVars,
+    CommentNode, CommentsQuery, CommentsResponse, CommentsVars, LogMessage, PersistedQuery,
+    QueryExtensions, TdChat, TdComment, TdCommenter, TdFileInfo, TdFragment, TdMessage, TdStreamer,
+    TdVersion, TdVideo,
+};
+use crate::util::sleep_with_jitter;
+
+const COMMENTS_OPERATION: &str = "VideoCommentsByOffsetOrCursor";
+const COMMENTS_HASH: &str = "b70a3591ff0f4e0313d126c6a1502d79a1c02baebb288227c582044aa76adf6a";
+// throttled pages get retried this many times before giving up (the progress is saved either way)
+const MAX_RETRIES: u32 = 5;
+// the file only gets rewritten every this many pages, long chats have thousands of them
+const SAVE_EVERY: usize = 20;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChatFormat {
+    #[default]
+    Tbf,
+    // TwitchDownloaderCLI's schema, its chatrender takes it as is
+    TwitchDownloader,
+}
+
+// what to keep from a chat, every criterion that's set has to match
+#[derive(Clone, Debug, Default)]
+pub struct ChatFilter {
+    // logins or display names, any of them
+    pub users: Vec<String>,
+    // any of these in the message, case insensitive
+    pub contains: Vec<String>,
+    pub regex: Option<Regex>,
+    // seconds into the VOD, the download starts and stops there instead of going over everything
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+}
+
+impl ChatFilter {
+    pub fn matches(&self, message: &ChatMessage) -> bool {
+        let in_range = self.start.is_none_or(|s| message.offset_seconds >= s)
+            && self.end.is_none_or(|e| message.offset_seconds <= e);
+        let user = self.users.is_empty()
+            || self.users.iter().any(|u| {
+                u.eq_ignore_ascii_case(&message.login)
+                    || u.eq_ignore_ascii_case(&message.display_name)
+            });
+        let contains = self.contains.is_empty() || {
+            let text = message.message.to_lowercase();
+            self.contains
+                .iter()
+                .any(|c| text.contains(&c.to_lowercase()))
+        };
+        let regex = self
+            .regex
+            .as_ref()
+            .is_none_or(|r| r.is_match(&message.message));
+        in_range && user && contains && regex
+    }
+}
+
+pub fn chat_path(vod: i64, output: &Option<String>) -> String {
+    match output {
+        Some(path) => path.clone(),
+        None => format!("chat_{vod}.json"),
+    }
+}
+
+// reads either of the formats the chat gets written in
+pub fn read_chat(path: &str) -> Result<ChatLog> {
+    let body = fs::read_to_string(path)?;
+    match serde_json::from_str::<ChatLog>(&body) {
+        Ok(log) => Ok(log),
+        Err(e) => match serde_json::from_str::<TdChat>(&body) {
+            Ok(chat) => Ok(from_twitch_downloader(chat)),
+            Err(_) => Err(e)?,
+        },
+    }
+}
+
+// picks up an unfinished download of the same VOD, anything else starts from scratch
+fn load_partial(path: &str, vod: i64) -> Option<ChatLog> {
+    let log = read_chat(path).ok()?;
+    (log.vod_id == vod).then_some(log)
+}
+
+fn write_json<T: Serialize>(path: &str, value: &T) -> Result<()> {
+    // written next to the file first so an interrupted write doesn't eat the progress
+    let tmp = format!("{path}.part");
+    fs::write(&tmp, serde_json::to_string(value)?).map_err(Chat::from)?;
+    fs::rename(&tmp, path).map_err(Chat::from)?;
+    Ok(())
+}
+
+pub fn save_chat(path: &str, log: &ChatLog) -> Result<()> {
+    write_json(path, log)
+}
+
+// TwitchDownloader wants a date everywhere, the messages without one get the start of the epoch
+const NO_DATE: &str = "1970-01-01T00:00:00Z";
+
+pub fn to_twitch_downloader(log: &ChatLog, channel: &str, video: Option<ChatVideo>) -> TdChat {
+    let now = OffsetDateTime::now_utc()
+        .format(&Rfc3339)
+        .unwrap_or_else(|_| NO_DATE.to_string());
+    let (title, created_at, length, owner) = match video {
+        Some(v) => (v.title, v.created_at, v.length_seconds, v.owner),
+        None => (None, None, None, None),
+    };
+    let (channel_id, channel) = match owner {
+        Some(owner) => (owner.id, owner.login),
+        None => (String::new(), channel.to_string()),
+    };
+    let length = length.unwrap_or_else(|| {
+        log.comments
+            .last()
+            .map(|m| m.offset_seconds.ceil())
+            .unwrap_or_default()
+    });
+
+    TdChat {
+        file_info: TdFileInfo {
+            version: TdVersion {
+                major: 1,
+                minor: 4,
+                patch: 0,
+            },
+            created_at: now.clone(),
+            updated_at: now,
+        },
+        streamer: TdStreamer {
+            name: channel,
+            id: channel_id.parse().unwrap_or_default(),
+        },
+        video: TdVideo {
+            title,
+            id: log.vod_id.to_string(),
+            created_at: created_at.unwrap_or_else(|| NO_DATE.to_string()),
+            start: 0.0,
+            end: length,
+            length,
+            view_count: 0,
+            chapters: Vec::new(),
+        },
+        comments: log
+            .comments
+            .iter()
+            .map(|m| TdComment {
+                id: m.id.clone(),
+                created_at: m.created_at.clone().unwrap_or_else(|| NO_DATE.to_string()),
+                channel_id: channel_id.clone(),
+                content_type: "video".to_string(),
+                content_id: log.vod_id.to_string(),
+                content_offset_seconds: m.offset_seconds,
+                commenter: TdCommenter {
+                    display_name: m.display_name.clone(),
+                    id: String::new(),
+                    name: m.login.clone(),
+                    bio: None,
+                    created_at: NO_DATE.to_string(),
+                    updated_at: NO_DATE.to_string(),
+                    logo: None,
+                },
+                message: TdMessage {
+                    body: m.message.clone(),
+                    bits_spent: 0,
+                    fragments: vec![TdFragment {
+                        text: m.message.clone(),
+                        emoticon: None,
+                    }],
+                    user_badges: Vec::new(),
+                    user_color: m.color.clone(),
+                    emoticons: Vec::new(),
+                },
+            })
+            .collect(),
+    }
+}
+
+fn from_twitch_downloader(chat: TdChat) -> ChatLog {
+    ChatLog {
+        vod_id: chat.video.id.parse().unwrap_or_default(),
+        cursor: None,
+        complete: true,
+        comments: chat
+            .comments
+            .into_iter()
+            .map(|c| ChatMessage {
+                id: c.id,
+                offset_seconds: c.content_offset_seconds,
+                created_at: (c.created_at != NO_DATE).then_some(c.created_at),
+                login: c.commenter.name,
+                display_name: c.commenter.display_name,
+                color: c.message.user_color,
+                message: c.message.body,
+            })
+            .collect(),
+        emotes: Vec::new(),
+    }
+}
+
+// the title and the owner for the TwitchDownloader header, None once the VOD is gone
+async fn chat_video(vod: i64, http: &HttpContext) -> Option<ChatVideo> {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        "client-id",
+        HeaderValue::from_static("kimne78kx3ncx6brgo4mv6wki5h1ko"),
+    );
+    let query = ChatVideoQuery {
+        query: "query($id:ID){video(id: $id){title createdAt lengthSeconds owner{id login}}}"
+            .to_string(),
+        variables: ChatVideoVars {
+            id: vod.to_string(),
+        },
+    };
+    let request = http
+        .client
+        .post("https://gql.twitch.tv/gql")
+        .json(&query)
+        .headers(header_map);
+    let resp: ChatVideoResponse = http
+        .send_with_retry(request)
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+    resp.data?.video
+}
+
+// the finished download in the requested format, unfinished ones stay in ours so they can resume
+async fn write_chat(
+    path: &str,
+    log: &ChatLog,
+    options: &ChatOptions,
+    http: &HttpContext,
+) -> Result<()> {
+    if options.format != ChatFormat::TwitchDownloader || !log.complete {
+        return Ok(());
+    }
+    let video = chat_video(log.vod_id, http).await;
+    let channel = options.channel.clone().unwrap_or_default();
+    write_json(path, &to_twitch_downloader(log, &channel, video))
+}
+
+fn to_message(node: CommentNode) -> ChatMessage {
+    let (login, display_name) = match node.commenter {
+        Some(c) => (c.login, c.display_name),
+        None => (String::new(), String::new()),
+    };
+    ChatMessage {
+        id: node.id,
+        offset_seconds: node.content_offset_seconds,
+        created_at: node.created_at,
+        login,
+        display_name,
+        color: node.message.user_color,
+        message: node.message.fragments.into_iter().map(|f| f.text).collect(),
+    }
+}
+
+async fn fetch_page(
+    vod: i64,
+    cursor: &Option<String>,
+    options: &ChatOptions,
+    http: &HttpContext,
+) -> Result<CommentsResponse> {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        "client-id",
+        HeaderValue::from_static("kimne78kx3ncx6brgo4mv6wki5h1ko"),
+    );
+
+    let query = CommentsQuery {
+        operation_name: COMMENTS_OPERATION.to_string(),
+        variables: CommentsVars {
+            video_id: vod.to_string(),
+            cursor: cursor.clone(),
+            // the first page is asked for by offset, the rest by cursor
+            content_offset_seconds: cursor
+                .is_none()
+                .then(|| options.filter.start.unwrap_or_default().max(0.0) as u64),
+        },
+        extensions: QueryExtensions {
+            persisted_query: PersistedQuery {
+                version: 1,
+                sha256_hash: COMMENTS_HASH.to_string(),
+            },
+        },
+    };
+
+    let mut delay = Duration::from_millis(options.retry_delay);
+    for attempt in 1..=MAX_RETRIES {
+        let request = http
+            .client
+            .post("https://gql.twitch.tv/gql")
+            .json(&query)
+            .headers(header_map.clone());
+        let resp = http.send(request).await?;
+
+        let status = resp.status();
+        if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+            let wait = resp
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|h| h.parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(delay);
+            warn!(
+                "Got a {status} on attempt #{attempt}, waiting {:.1}s",
+                wait.as_secs_f32()
+            );
+            sleep_with_jitter(wait).await;
+            delay *= 2;
+            continue;
+        }
+
+        return Ok(resp.error_for_status()?.json().await?);
+    }
+
+    Err(Chat::Throttled)?
+}
+
+// tries Twitch first, then the logging instances if the chat is gone and the stream's window is known
+pub async fn download_chat(vod: i64, options: &ChatOptions, http: &HttpContext) -> Result<ChatLog> {
+    let path = chat_path(vod, &options.output);
+    let result = download_from_twitch(vod, &path, options, http).await;
+
+    let mut log = match (result, &options.channel, options.window) {
+        (Err(e), Some(channel), Some((from, to)))
+            if matches!(e.downcast_ref::<Chat>(), Some(Chat::NotFound)) =>
+        {
+            if !options.report.simple {
+                info!("The chat isn't on Twitch anymore, checking the logging instances...");
+            }
+            let log = recover_from_logs(vod, channel, from, to, options, http).await?;
+            save_chat(&path, &log)?;
+            log
+        }
+        (result, _, _) => result?,
+    };
+
+    if options.emotes && log.complete && log.emotes.is_empty() {
+        let channel_id = match chat_video(vod, http).await.and_then(|v| v.owner) {
+            Some(owner) => Some(owner.id),
+            None => match &options.channel {
+                Some(channel) => user_id(channel, http).await,
+                None => None,
+            },
+        };
+        log.emotes = resolve_emotes(channel_id.as_deref(), &log, http).await;
+        if !log.emotes.is_empty() {
+            save_chat(&path, &log)?;
+        }
+    }
+    write_chat(&path, &log, options, http).await?;
+    Ok(log)
+}
+
+async fn download_from_twitch(
+    vod: i64,
+    path: &str,
+    options: &ChatOptions,
+    http: &HttpContext,
+) -> Result<ChatLog> {
+    let flags = &options.report;
+
+    let mut log = match load_partial(path, vod) {
+        Some(log) if log.complete => {
+            debug!("The chat of {vod} is already downloaded to {path}");
+            return Ok(log);
+        }
+        Some(log) => {
+            if !flags.simple {
+                info!(
+                    "Resuming the chat download with {} messages already saved",
+                    log.comments.len()
+                );
+            }
+            log
+        }
+        None => ChatLog {
+            vod_id: vod,
+            cursor: None,
+            complete: false,
+            comments: Vec::new(),
+            emotes: Vec::new(),
+        },
+    };
+
+    let pb = if flags.progressbar {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::with_template("{spinner} {msg}").unwrap());
+        pb
+    } else {
+        ProgressBar::hidden()
+    };
+
+    let mut pages = 0;
+    loop {
+        if options.cancel.is_cancelled() {
+            debug!("The chat download got cancelled");
+            break;
+        }
+
+        let comments = match fetch_page(vod, &log.cursor, options, http).await {
+            Ok(page) => page
+                .data
+                .and_then(|d| d.video)
+                .and_then(|v| v.comments)
+                .ok_or(Chat::NotFound.into()),
+            Err(e) => Err(e),
+        };
+        let comments = match comments {
+            Ok(comments) => comments,
+            Err(e) => {
+                // keeping what got downloaded so far, the next run resumes from the cursor
+                pb.finish_and_clear();
+                if !log.comments.is_empty() {
+                    save_chat(path, &log)?;
+                }
+                return Err(e);
+            }
+        };
+
+        // the pages are in order, once one goes past the end of the range the rest would too
+        let past_end = match (comments.edges.last(), options.filter.end) {
+            (Some(edge), Some(end)) => edge.node.content_offset_seconds > end,
+            _ => false,
+        };
+        let next_cursor = match comments.edges.last() {
+            Some(edge) if comments.page_info.has_next_page && !past_end => edge.cursor.clone(),
+            _ => None,
+        };
+        log.comments.extend(
+            comments
+                .edges
+                .into_iter()
+                .map(|e| to_message(e.node))
+                .filter(|m| options.filter.matches(m)),
+        );
+        pb.set_message(format!("{} messages", log.comments.len()));
+
+        pages += 1;
+        match next_cursor {
+            Some(cursor) => log.cursor = Some(cursor),
+            None => {
+                log.cursor = None;
+                log.complete = true;
+                break;
+            }
+        }
+        if pages % SAVE_EVERY == 0 {
+            save_chat(path, &log)?;
+        }
+
+        sleep_with_jitter(Duration::from_millis(options.page_delay)).await;
+    }
+
+    pb.finish_and_clear();
+    save_chat(path, &log)?;
+    Ok(log)
+}
+
+// the days (in UTC) the window touches, the instances serve the logs per day
+fn log_days(from: i64, to: i64) -> Vec<Date> {
+    let (Ok(from), Ok(to)) = (
+        OffsetDateTime::from_unix_timestamp(from),
+        OffsetDateTime::from_unix_timestamp(to),
+    ) else {
+        return Vec::new();
+    };
+    let mut days = vec![from.date()];
+    while let Some(next) = days.last().and_then(|d| d.next_day()) {
+        if next > to.date() {
+            break;
+        }
+        days.push(next);
+    }
+    days
+}
+
+async fn fetch_day(
+    instance: &str,
+    channel: &str,
+    day: Date,
+    http: &HttpContext,
+) -> Result<Vec<LogMessage>> {
+    let url = format!(
+        "{}/channel/{}/{}/{}/{}?json",
+        instance.trim_end_matches('/'),
+        channel.to_lowercase(),
+        day.year(),
+        day.month() as u8,
+        day.day()
+    );
+    let resp = http.send(http.client.get(&url)).await?;
+    // the instances answer with a 404 for channels and days they don't have
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Ok(Vec::new());
+    }
+    let logs: ChannelLogs = resp.error_for_status()?.json().await?;
+    Ok(logs.messages)
+}
+
+// collects the messages sent during the stream from every instance, the same message
+// from several instances only gets kept once
+pub async fn recover_from_logs(
+    vod: i64,
+    channel: &str,
+    from: i64,
+    to: i64,
+    options: &ChatOptions,
+    http: &HttpContext,
+) -> Result<ChatLog> {
+    let mut comments: HashMap<String, ChatMessage> = HashMap::new();
+
+    for instance in &options.log_instances {
+        for day in log_days(from, to) {
+            if options.cancel.is_cancelled() {
+                break;
+            }
+            let messages = match fetch_day(instance, channel, day, http).await {
+                Ok(messages) => messages,
+                Err(e) => {
+                    warn!("Couldn't get the logs for {day} from {instance} - {e}");
+                    continue;
+                }
+            };
+
+            for message in messages {
+                let Ok(sent) = OffsetDateTime::parse(&message.timestamp, &Rfc3339) else {
+                    continue;
+                };
+                let sent = sent.unix_timestamp_nanos() as f64 / 1e9;
+                if message.kind != 1 || sent < from as f64 || sent > to as f64 {
+                    continue;
+                }
+                let message = ChatMessage {
+                    id: message.id,
+                    offset_seconds: sent - from as f64,
+                    created_at: Some(message.timestamp),
+                    login: message.username,
+                    display_name: message.display_name,
+                    color: message.tags.get("color").filter(|c| !c.is_empty()).cloned(),
+                    message: message.text,
+                };
+                if options.filter.matches(&message) {
+                    comments.entry(message.id.clone()).or_insert(message);
+                }
+            }
+            sleep_with_jitter(Duration::from_millis(options.page_delay)).await;
+        }
+    }
+
+    if comments.is_empty() {
+        return Err(Chat::NotFound)?;
+    }
+    let mut comments: Vec<ChatMessage> = comments.into_values().collect();
+    comments.sort_by(|a, b| a.offset_seconds.total_cmp(&b.offset_seconds));
+
+    Ok(ChatLog {
+        vod_id: vod,
+        cursor: None,
+        complete: !options.cancel.is_cancelled(),
+        comments,
+        emotes: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+    use tempfile::tempdir;
+
+    use crate::config::ChatOptions;
+    use crate::http::{Fixtures, HttpContext};
+    use crate::twitch::models::{ChatLog, ChatMessage};
+
+    use super::{
+        download_chat, from_twitch_downloader, log_days, save_chat, to_twitch_downloader,
+        ChatFilter,
+    };
+
+    fn page(id: &str, offset: u32, cursor: &str, has_next_page: bool) -> String {
+        format!(
+            r##"{{"data": {{"video": {{"comments": {{"edges": [{{"cursor": "{cursor}", "node": {{"id": "{id}", "commenter": {{"login": "viewer", "displayName": "Viewer"}}, "contentOffsetSeconds": {offset}, "createdAt": "2021-06-05T00:50:17Z", "message": {{"fragments": [{{"text": "hello "}}, {{"text": "world"}}], "userColor": "#FF0000"}}}}}}], "pageInfo": {{"hasNextPage": {has_next_page}}}}}}}}}}}"##
+        )
+    }
+
+    #[tokio::test]
+    async fn chat_pagination() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("chat.json");
+        let path = path.to_str().unwrap();
+        let options = ChatOptions::default()
+            .output(path)
+            .page_delay(0)
+            .retry_delay(0)
+            .emotes(false);
+
+        let fixtures = Arc::new(
+            Fixtures::new()
+                .respond("https://gql.twitch.tv/gql", 200, page("1", 5, "c1", true))
+                .respond("https://gql.twitch.tv/gql", 429, "")
+                .respond("https://gql.twitch.tv/gql", 200, page("2", 10, "c2", false)),
+        );
+        let http = HttpContext::default().with_fixtures(fixtures.clone());
+        let log = download_chat(42218705421, &options, &http).await.unwrap();
+
+        assert!(log.complete, "testing a complete download");
+        assert_eq!(
+            log.comments
+                .iter()
+                .map(|c| (c.id.as_str(), c.offset_seconds, c.message.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("1", 5.0, "hello world"), ("2", 10.0, "hello world")],
+            "testing the pages getting merged"
+        );
+        assert_eq!(
+            fixtures.requested().len(),
+            3,
+            "testing the throttled page getting retried"
+        );
+        assert_eq!(
+            serde_json::from_str::<ChatLog>(&fs::read_to_string(path).unwrap()).unwrap(),
+            log,
+            "testing the saved chat"
+        );
+
+        // an unfinished download continues from its cursor
+        let mut partial = log.clone();
+        partial.comments.truncate(1);
+        partial.cursor = Some("c1".to_string());
+        partial.complete = false;
+        save_chat(path, &partial).unwrap();
+        let http = HttpContext::default().with_fixtures(Arc::new(Fixtures::new().respond(
+            "https://gql.twitch.tv/gql",
+            200,
+            page("2", 10, "c2", false),
+        )));
+        assert_eq!(
+            download_chat(42218705421, &options, &http).await.unwrap(),
+            log,
+            "testing a resumed download"
+        );
+
+        let http = HttpContext::default().with_fixtures(Arc::new(Fixtures::new().respond(
+            "https://gql.twitch.tv/gql",
+            200,
+            r#"{"data": {"video": null}}"#,
+        )));
+        assert!(
+            download_chat(
+                1,
+                &ChatOptions::default().output(dir.path().join("missing.json").to_str().unwrap()),
+                &http
+            )
+            .await
+            .is_err(),
+            "testing a missing VOD"
+        );
+    }
+
+    #[tokio::test]
+    async fn chat_filters() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("filtered.json");
+        let options = ChatOptions::default()
+            .output(path.to_str().unwrap())
+            .page_delay(0)
+            .emotes(false)
+            .filter(ChatFilter {
+                users: vec!["VIEWER".to_string()],
+                contains: vec!["WORLD".to_string()],
+                end: Some(7.0),
+                ..Default::default()
+            });
+
+        let fixtures = Arc::new(
+            Fixtures::new()
+                .respond("https://gql.twitch.tv/gql", 200, page("1", 5, "c1", true))
+                .respond("https://gql.twitch.tv/gql", 200, page("2", 10, "c2", true))
+                .respond("https://gql.twitch.tv/gql", 200, page("3", 15, "c3", false)),
+        );
+        let http = HttpContext::default().with_fixtures(fixtures.clone());
+        let log = download_chat(1, &options, &http).await.unwrap();
+
+        assert_eq!(
+            log.comments
+                .iter()
+                .map(|c| c.id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["1"],
+            "testing the messages in the range"
+        );
+        assert_eq!(
+            fixtures.requested().len(),
+            2,
+            "testing the download stopping past the end"
+        );
+
+        let message = &log.comments[0];
+        let filter = |filter: ChatFilter| filter.matches(message);
+        assert!(
+            !filter(ChatFilter {
+                users: vec!["someone".to_string()],
+                ..Default::default()
+            }),
+            "testing another user"
+        );
+        assert!(
+            filter(ChatFilter {
+                regex: Some(regex::Regex::new(r"^hello\s").unwrap()),
+                start: Some(5.0),
+                ..Default::default()
+            }),
+            "testing a regex"
+        );
+        assert!(
+            !filter(ChatFilter {
+                contains: vec!["bye".to_string()],
+                ..Default::default()
+            }),
+            "testing missing text"
+        );
+    }
+
+    fn log_line(id: &str, timestamp: &str, kind: u8) -> String {
+        format!(
+            r##"{{"id": "{id}", "text": "message {id}", "username": "viewer", "displayName": "Viewer", "timestamp": "{timestamp}", "type": {kind}, "tags": {{"color": "#00FF00"}}}}"##
+        )
+    }
+
+    #[tokio::test]
+    async fn chat_from_logs() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("logs.json");
+        // 2021-06-04 23:59:00 - 2021-06-05 00:10:00 UTC, so two days get asked for
+        let (from, to) = (1622851140, 1622851800);
+        let options = ChatOptions::default()
+            .output(path.to_str().unwrap())
+            .page_delay(0)
+            .emotes(false)
+            .log_instances(vec![
+                "https://logs.one".to_string(),
+                "https://logs.two/".to_string(),
+            ])
+            .fallback("DansGaming", from, to);
+
+        let fixtures = Arc::new(
+            Fixtures::new()
+                .respond(
+                    "https://gql.twitch.tv/gql",
+                    200,
+                    r#"{"data": {"video": null}}"#,
+                )
+                .respond(
+                    "https://logs.one/channel/dansgaming/2021/6/4?json",
+                    200,
+                    format!(
+                        r#"{{"messages": [{}, {}]}}"#,
+                        log_line("early", "2021-06-04T23:00:00Z", 1),
+                        log_line("a", "2021-06-04T23:59:30.500Z", 1)
+                    ),
+                )
+                .respond(
+                    "https://logs.one/channel/dansgaming/2021/6/5?json",
+                    200,
+                    format!(
+                        r#"{{"messages": [{}, {}]}}"#,
+                        log_line("b", "2021-06-05T00:05:00Z", 1),
+                        log_line("timeout", "2021-06-05T00:06:00Z", 2)
+                    ),
+                )
+                .respond(
+                    "https://logs.two/channel/dansgaming/2021/6/5?json",
+                    200,
+                    format!(
+                        r#"{{"messages": [{}, {}]}}"#,
+                        log_line("b", "2021-06-05T00:05:00Z", 1),
+                        log_line("c", "2021-06-05T00:01:00Z", 1)
+                    ),
+                ),
+        );
+        let http = HttpContext::default().with_fixtures(fixtures);
+        let log = download_chat(42218705421, &options, &http).await.unwrap();
+
+        assert_eq!(
+            log.comments
+                .iter()
+                .map(|c| (c.id.as_str(), c.offset_seconds))
+                .collect::<Vec<_>>(),
+            vec![("a", 30.5), ("c", 120.0), ("b", 360.0)],
+            "testing the merged logs"
+        );
+        assert_eq!(
+            log.comments[0].color.as_deref(),
+            Some("#00FF00"),
+            "testing the color tag"
+        );
+        assert_eq!(
+            serde_json::from_str::<ChatLog>(&fs::read_to_string(&path).unwrap()).unwrap(),
+            log,
+            "testing the saved chat"
+        );
+        assert_eq!(log_days(from, from).len(), 1, "testing a single day");
+    }
+
+    #[test]
+    fn twitch_downloader_format() {
+        let log = ChatLog {
+            vod_id: 42218705421,
+            cursor: None,
+            complete: true,
+            comments: vec![ChatMessage {
+                id: "1".to_string(),
+                offset_seconds: 5.5,
+                created_at: Some("2021-06-05T00:50:22.500Z".to_string()),
+                login: "viewer".to_string(),
+                display_name: "Viewer".to_string(),
+                color: Some("#FF0000".to_string()),
+                message: "hello world".to_string(),
+            }],
+            emotes: Vec::new(),
+        };
+
+        let chat = to_twitch_downloader(&log, "dansgaming", None);
+        let json = serde_json::to_value(&chat).unwrap();
+        assert_eq!(
+            (
+                &json["streamer"]["name"],
+                &json["video"]["id"],
+                &json["video"]["length"],
+                &json["comments"][0]["_id"],
+                &json["comments"][0]["content_offset_seconds"],
+                &json["comments"][0]["commenter"]["name"],
+                &json["comments"][0]["message"]["body"],
+                &json["comments"][0]["message"]["user_color"],
+            ),
+            (
+                &serde_json::json!("dansgaming"),
+                &serde_json::json!("42218705421"),
+                &serde_json::json!(6.0),
+                &serde_json::json!("1"),
+                &serde_json::json!(5.5),
+                &serde_json::json!("viewer"),
+                &serde_json::json!("hello world"),
+                &serde_json::json!("#FF0000"),
+            ),
+            "testing the TwitchDownloader fields"
+        );
+        assert!(
+            json["FileInfo"]["Version"]["Major"].is_number(),
+            "testing the file info"
+        );
+        assert_eq!(from_twitch_downloader(chat), log, "testing reading it back");
+    }
+}
@@ -1,55 +1,114 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use colored::*;
-use futures::StreamExt;
+use futures::{future, StreamExt};
 use indicatif::ProgressBar;
-use log::{error, info};
-use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
-use std::{collections::HashMap, str::FromStr};
+use log::{error, info, warn};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    StatusCode,
+};
+use std::{collections::HashMap, str::FromStr, sync::Mutex, time::Duration};
+use tokio::sync::mpsc;
 use url::Url;
 
-use crate::config::Cli;
+use crate::clipforce::ClipScanState;
+use crate::config::{ClipOptions, ReportOptions};
 use crate::error::Clip;
-use crate::twitch::models::{ClipQuery, ClipResponse, ClipVars, ReturnURL};
-use crate::util::info;
+use crate::http::HttpContext;
+use crate::limiter::{scan_budget, AdaptiveConcurrency};
+use crate::metrics;
+use crate::offline::parse_timestamp;
+use crate::spill::PendingOffsets;
+use crate::twitch::models::{
+    BroadcastClip, ClipPlaybackResponse, ClipQuality, ClipQuery, ClipResponse, ClipVars, ReturnURL,
+    UserClipsQuery, UserClipsResponse, UserClipsVars,
+};
+use crate::util::{self, WarningLimiter};
 
-fn extract_slug(s: String) -> Result<Option<String>> {
-    match Url::parse(&s) {
+// seconds between two throttling warnings
+const THROTTLE_WARNING_INTERVAL: u64 = 5;
+// 100 clips a page, a channel's clips are gone over from the newest until the broadcast is passed
+const MAX_CLIP_PAGES: usize = 50;
+// a clip can be cut from anywhere in the last 90 seconds before it was created
+const CLIP_BUFFER: i64 = 90;
+// the offsets gone over between two saves of the state file
+const STATE_SAVE_INTERVAL: usize = 500;
+
+enum OffsetCheck {
+    Clip(ReturnURL),
+    Nothing,
+    // throttled or the request failed, so it's not known whether there's a clip
+    Failed,
+}
+
+pub(crate) fn extract_slug(s: String) -> Result<Option<String>> {
+    let s = s.trim();
+
+    // allow for links copied without the scheme (e.g. "twitch.tv/user/clip/slug")
+    let candidate = if !s.contains("://") && s.to_lowercase().contains("twitch.tv/") {
+        format!("https://{s}")
+    } else {
+        s.to_string()
+    };
+
+    match Url::parse(&candidate) {
         Ok(resolved_url) => {
             let domain = resolved_url
                 .domain()
-                .ok_or_else(|| Clip::WrongURL("Invalid URL".to_string()))?;
+                .ok_or_else(|| Clip::WrongURL("Invalid URL".to_string()))?
+                .to_lowercase();
 
-            match domain.to_lowercase().as_str() {
-                "twitch.tv" | "www.twitch.tv" => {
-                    let segments: Vec<_> = resolved_url
-                        .path_segments()
-                        .map(|c| c.collect())
-                        .ok_or(Clip::SegmentMap)?;
+            // empty segments come from trailing or doubled slashes
+            let segments: Vec<&str> = resolved_url
+                .path_segments()
+                .map(|c| c.filter(|segment| !segment.is_empty()).collect())
+                .ok_or(Clip::SegmentMap)?;
 
-                    if segments.len() > 1 && segments[1] == "clip" {
-                        Ok(Some(segments[2].to_string()))
-                    } else {
-                        Err(Clip::WrongURL("Not a clip URL".to_string()))?
+            let slug = match domain.as_str() {
+                "twitch.tv" | "www.twitch.tv" | "m.twitch.tv" => {
+                    match segments.iter().position(|segment| *segment == "clip") {
+                        Some(i) => segments.get(i + 1).map(|slug| slug.to_string()),
+                        None => return Err(Clip::WrongURL("Not a clip URL".to_string()))?,
                     }
                 }
-                "clips.twitch.tv" => {
-                    let segments: Vec<_> = resolved_url
-                        .path_segments()
-                        .map(|c| c.collect())
-                        .ok_or(Clip::SegmentMap)?;
-
-                    Ok(Some(segments[0].to_string()))
+                "clips.twitch.tv" => match segments.first() {
+                    // clips.twitch.tv/embed?clip=<slug>
+                    Some(&"embed") => resolved_url
+                        .query_pairs()
+                        .find(|(key, _)| key == "clip")
+                        .map(|(_, value)| value.into_owned()),
+                    first => first.map(|slug| slug.to_string()),
+                },
+                _ => {
+                    return Err(Clip::WrongURL(
+                        "Only twitch.tv URLs are supported".to_string(),
+                    ))?
                 }
+            };
+
+            match slug {
+                Some(slug) if is_valid_slug(&slug) => Ok(Some(slug)),
                 _ => Err(Clip::WrongURL(
-                    "Only twitch.tv URLs are supported".to_string(),
+                    "Couldn't find the clip slug in the URL".to_string(),
                 ))?,
             }
         }
-        Err(_) => Ok(Some(s)), // Assume it's already a slug
+        Err(_) if is_valid_slug(s) => Ok(Some(s.to_string())), // Assume it's already a slug
+        Err(_) => Err(Clip::WrongURL("Not a valid clip URL or slug".to_string()))?,
     }
 }
 
-pub async fn find_bid_from_clip(s: String, flags: Cli) -> Result<Option<(String, i64)>> {
+fn is_valid_slug(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub async fn find_bid_from_clip(
+    s: String,
+    flags: &ReportOptions,
+    http: &HttpContext,
+) -> Result<Option<(String, i64)>> {
     let slug = match extract_slug(s) {
         Ok(Some(slug)) => slug,
         Ok(None) => return Ok(None),
@@ -74,12 +133,13 @@ pub async fn find_bid_from_clip(s: String, flags: Cli) -> Result<Option<(String,
         variables: ClipVars { slug },
     };
 
-    let request = crate::HTTP_CLIENT
+    let request = http
+        .client
         .post(endpoint)
         .json(&query)
         .headers(header_map.clone());
 
-    let re = request.send().await?;
+    let re = http.send_with_retry(request).await?;
     let data: ClipResponse = match re.json().await {
         Ok(d) => d,
         Err(e) => {
@@ -96,79 +156,363 @@ pub async fn find_bid_from_clip(s: String, flags: Cli) -> Result<Option<(String,
     )))
 }
 
+// 1080 at 60fps is 1080p60, like the quality names of the player
+fn quality_name(quality: &str, frame_rate: Option<f64>) -> String {
+    match frame_rate {
+        Some(fps) if fps > 30.5 => format!("{quality}p{}", fps.round() as i64),
+        _ => format!("{quality}p"),
+    }
+}
+
+// the clip's own MP4s, best quality first. the source URLs only play with the signed token of
+// the playback access token query attached
+pub async fn clip_qualities(s: String, http: &HttpContext) -> Result<Vec<ClipQuality>> {
+    let Some(slug) = extract_slug(s)? else {
+        return Ok(Vec::new());
+    };
+
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        "Client-ID",
+        HeaderValue::from_static("kimne78kx3ncx6brgo4mv6wki5h1ko"),
+    );
+
+    let query = ClipQuery {
+        query: "query($slug:ID!){clip(slug: $slug){playbackAccessToken(params:{platform:\"web\",playerBackend:\"mediaplayer\",playerType:\"site\"}){signature value}videoQualities{quality frameRate sourceURL}}}".to_string(),
+        variables: ClipVars { slug },
+    };
+
+    let request = http
+        .client
+        .post("https://gql.twitch.tv/gql")
+        .json(&query)
+        .headers(header_map);
+    let data: ClipPlaybackResponse = http.send_with_retry(request).await?.json().await?;
+    let clip = data.data.clip.ok_or(Clip::NotFound)?;
+
+    let token = &clip.playback_access_token;
+    let mut qualities = Vec::new();
+    for quality in &clip.video_qualities {
+        let url = Url::parse_with_params(
+            &quality.source_url,
+            &[("sig", &token.signature), ("token", &token.value)],
+        )?;
+        qualities.push(ClipQuality {
+            quality: quality_name(&quality.quality, quality.frame_rate),
+            url: url.to_string(),
+        });
+    }
+    Ok(qualities)
+}
+
+// every clip of the channel cut from the broadcast, in the order they come in the stream. the
+// clips come newest first, so going over them stops at the first page from older broadcasts only
+pub async fn clips_of_broadcast(
+    username: &str,
+    broadcast_id: i64,
+    http: &HttpContext,
+) -> Result<Vec<BroadcastClip>> {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        "Client-ID",
+        HeaderValue::from_static("kimne78kx3ncx6brgo4mv6wki5h1ko"),
+    );
+
+    let mut clips: Vec<BroadcastClip> = Vec::new();
+    let mut after = None;
+    for _ in 0..MAX_CLIP_PAGES {
+        let query = UserClipsQuery {
+            query: "query($login:String!,$after:Cursor){user(login: $login){clips(first: 100, after: $after, criteria: {period: ALL_TIME, sort: CREATED_AT_DESC}){pageInfo{hasNextPage}edges{cursor node{slug createdAt durationSeconds videoOffsetSeconds broadcast{id}}}}}}".to_string(),
+            variables: UserClipsVars {
+                login: username.to_lowercase(),
+                after: after.clone(),
+            },
+        };
+        let request = http
+            .client
+            .post("https://gql.twitch.tv/gql")
+            .json(&query)
+            .headers(header_map.clone());
+        let data: UserClipsResponse = http.send_with_retry(request).await?.json().await?;
+        let Some(user) = data.data.user else {
+            return Err(anyhow!("{username} doesn't exist on Twitch"));
+        };
+
+        let page = user.clips;
+        let mut older = true;
+        for edge in &page.edges {
+            let id = edge
+                .node
+                .broadcast
+                .as_ref()
+                .and_then(|b| b.id.parse::<i64>().ok());
+            match id {
+                Some(id) if id == broadcast_id => {
+                    older = false;
+                    if clips.iter().any(|c| c.slug == edge.node.slug) {
+                        continue;
+                    }
+                    clips.push(BroadcastClip {
+                        slug: edge.node.slug.clone(),
+                        created_at: parse_timestamp(&edge.node.created_at)?,
+                        duration: edge.node.duration_seconds.round() as i64,
+                        offset: edge.node.video_offset_seconds,
+                    });
+                }
+                Some(id) if id < broadcast_id => (),
+                _ => older = false,
+            }
+        }
+
+        after = page.edges.last().and_then(|e| e.cursor.clone());
+        if older || !page.page_info.has_next_page || after.is_none() {
+            break;
+        }
+    }
+
+    clips.sort_by_key(|c| (c.offset.unwrap_or(i64::MAX), c.created_at));
+    Ok(clips)
+}
+
+// the seconds the stream could have started in. a clip created at C with its start at the offset
+// O of the stream and D seconds long had the stream start somewhere between C - O - D - 90 and
+// C - O - D; every clip narrows it down, starting from the earliest one
+pub fn stream_start_window(clips: &[BroadcastClip]) -> Option<(i64, i64)> {
+    let mut windows = clips.iter().filter_map(|c| {
+        let latest = c.created_at - c.offset? - c.duration;
+        Some((latest - CLIP_BUFFER, latest))
+    });
+    let first = windows.next()?;
+    let narrowed = windows.fold(first, |(from, to), (f, t)| (from.max(f), to.min(t)));
+    // clips that don't agree with each other, only the earliest one is left to go by
+    match narrowed.0 <= narrowed.1 {
+        true => Some(narrowed),
+        false => Some(first),
+    }
+}
+
 pub async fn clip_bruteforce(
     vod: i64,
     start: i64,
     end: i64,
-    flags: Cli,
+    options: &ClipOptions,
+    http: &HttpContext,
 ) -> Result<Option<Vec<ReturnURL>>> {
-    let vod = vod.to_string();
-    let pb = ProgressBar::new((end - start) as u64);
+    let budget = scan_budget(options.scan.threads, options.scan.max_memory);
+    let concurrency = AdaptiveConcurrency::new(budget.threads, !options.scan.fixed_threads);
+    clip_bruteforce_shared(vod, start, end, options, &concurrency, http).await
+}
 
-    let fetches = futures::stream::iter((start..end).map(|number| {
-        let url = format!(
-            "https://clips-media-assets2.twitch.tv/{vod}-offset-{number}.mp4"
+// the concurrency comes from the caller, so the VODs bruteforced one after another keep
+// what the earlier ones learned about the throttling instead of starting over at full speed
+pub async fn clip_bruteforce_shared(
+    vod: i64,
+    start: i64,
+    end: i64,
+    options: &ClipOptions,
+    concurrency: &AdaptiveConcurrency,
+    http: &HttpContext,
+) -> Result<Option<Vec<ReturnURL>>> {
+    let flags = &options.report;
+    let state = match (&options.state, options.resume) {
+        (Some(path), true) => ClipScanState::load(path, vod)?,
+        _ => ClipScanState::new(vod),
+    };
+    let budget = scan_budget(options.scan.threads, options.scan.max_memory);
+    let offsets = PendingOffsets::new(
+        (start..end).filter(|&o| !state.is_checked(o)),
+        budget.spill_after,
+    )?;
+    if offsets.is_spilled() && !flags.simple {
+        info!(
+            "Keeping the {} offsets on disk to fit into the memory budget",
+            offsets.len()
         );
+    }
+    if options.resume && !flags.simple {
+        info!(
+            "Resuming with {} of {} offsets left to go over",
+            offsets.len(),
+            (end - start).max(0)
+        );
+    }
+    let earlier = state.clips.clone();
+    let state = Mutex::new(state);
+
+    let vod = vod.to_string();
+    let pb = if flags.console {
+        ProgressBar::new(offsets.len() as u64)
+    } else {
+        ProgressBar::hidden()
+    };
+    if let Some(progress) = &flags.progress {
+        progress.start(offsets.len() as u64);
+    }
+    let throttle_warning = WarningLimiter::new(Duration::from_secs(THROTTLE_WARNING_INTERVAL));
+
+    let check_offset = |number: i64| {
+        let url = format!("https://clips-media-assets2.twitch.tv/{vod}-offset-{number}.mp4");
         let pb_clone = pb.clone();
+        let throttle_warning = &throttle_warning;
         async move {
-            match crate::HTTP_CLIENT.get(url.as_str()).send().await {
+            let _permit = concurrency.acquire().await;
+            let res = http.probe(&url).await;
+            if let Some(progress) = &flags.progress {
+                progress.inc();
+            }
+            match res {
                 Ok(r) => {
                     if flags.progressbar {
                         pb_clone.inc(1);
                     }
-                    if r.status() == 200 {
+                    let status = r.status();
+                    if status == StatusCode::OK {
                         if flags.verbose {
                             pb_clone.println(format!("Got a clip! - {url}"));
                         }
-                        Some(ReturnURL {
+                        OffsetCheck::Clip(ReturnURL {
                             url,
                             muted: false,
+                            audio_only: false,
                         })
-                    } else if r.status() == 403 {
-                        if flags.verbose {
-                            pb_clone.println(format!("Still going! - {url}"));
+                    } else if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                        if let Some(limit) = concurrency.throttled() {
+                            pb_clone.println(format!("Lowering the concurrency to {limit}"));
                         }
-                        None
+                        if let Some(suppressed) = throttle_warning.check() {
+                            let suppressed = match suppressed {
+                                0 => String::new(),
+                                n => format!(" ({n} similar warnings suppressed)"),
+                            };
+                            pb_clone.println(format!(
+                                "You might be getting throttled (or your connection is dead)! Status code: {} - URL: {}{}",
+                                status,
+                                r.url(),
+                                suppressed
+                            ));
+                        }
+                        OffsetCheck::Failed
                     } else {
-                        pb_clone.println(format!(
-                            "You might be getting throttled (or your connection is dead)! Status code: {} - URL: {}",
-                            r.status(),
-                            r.url()
-                        ));
-                        None
+                        // 403s and 404s are just the offsets that don't have a clip
+                        if flags.verbose {
+                            pb_clone.println(format!("Still going! ({status}) - {url}"));
+                        }
+                        if let Some(limit) = concurrency.succeeded() {
+                            pb_clone.println(format!(
+                                "No throttling for a while, raising the concurrency to {limit}"
+                            ));
+                        }
+                        OffsetCheck::Nothing
                     }
                 }
                 Err(e) => {
                     pb_clone.println(format!("Error sending request for {}: {}", url, e));
-                    None
+                    OffsetCheck::Failed
                 }
             }
         }
-    }))
-    .buffer_unordered(flags.threads)
-    .collect::<Vec<Option<ReturnURL>>>()
-    .await;
+    };
 
-    let res: Vec<ReturnURL> = fetches.into_iter().flatten().collect();
+    // hits are pushed through the channel as soon as they're found, misses are dropped right away
+    let (hit_tx, mut hit_rx) = mpsc::unbounded_channel::<ReturnURL>();
 
-    if !res.is_empty() {
-        if !flags.simple {
-            info!("{}! Here are the URLs:", "Got some clips".green());
+    // the clips found by the earlier run come first
+    for hit in earlier {
+        let _ = hit_tx.send(hit);
+    }
+
+    let state = &state;
+    let scan = async move {
+        let check_offset = &check_offset;
+        let mut unsaved = 0;
+        futures::stream::iter(offsets)
+            .map(|number| async move { (number, check_offset(number).await) })
+            .buffer_unordered(budget.threads)
+            .take_until(options.scan.cancel.cancelled())
+            .for_each(|(number, check)| {
+                let mut state = state.lock().unwrap();
+                match check {
+                    OffsetCheck::Clip(hit) => {
+                        if let Some(progress) = &flags.progress {
+                            progress.hit(&hit);
+                        }
+                        state.clips.push(hit.clone());
+                        let _ = hit_tx.send(hit);
+                        state.mark(number);
+                        // a found clip is worth saving right away
+                        unsaved = STATE_SAVE_INTERVAL;
+                    }
+                    OffsetCheck::Nothing => {
+                        state.mark(number);
+                        unsaved += 1;
+                    }
+                    // throttled or failed, a resumed run tries it again
+                    OffsetCheck::Failed => (),
+                }
+                if let (Some(path), true) = (&options.state, unsaved >= STATE_SAVE_INTERVAL) {
+                    unsaved = 0;
+                    if let Err(e) = state.save(path) {
+                        warn!("Couldn't save the progress to {} - {e}", path.display());
+                    }
+                }
+                future::ready(())
+            })
+            .await;
+    };
+
+    let printer = async {
+        let mut res: Vec<ReturnURL> = Vec::new();
+        while let Some(hit) = hit_rx.recv().await {
+            if flags.console {
+                pb.suspend(|| {
+                    if res.is_empty() && !flags.simple {
+                        info!("{}! Here are the URLs:", "Got some clips".green());
+                    }
+                    util::url(&hit.url, flags.simple);
+                });
+            }
+            res.push(hit);
         }
-        for line in res.clone() {
-            info(line.url, flags.simple);
+        res
+    };
+
+    let ((), res) = tokio::join!(scan, printer);
+
+    if let Some(path) = &options.state {
+        match state.lock().unwrap().save(path) {
+            Ok(()) if options.scan.cancel.is_cancelled() && !flags.simple => info!(
+                "Saved the progress to {}, --resume continues from there",
+                path.display()
+            ),
+            Ok(()) => (),
+            Err(e) => warn!("Couldn't save the progress to {} - {e}", path.display()),
         }
-    } else if !flags.simple {
-        info!("{}", "Couldn't find anything :(".red());
+    }
+
+    // whatever got found before the cancellation still gets returned
+    if !res.is_empty() {
+        metrics::record_hit();
+    } else if !options.scan.cancel.is_cancelled() {
+        metrics::record_miss();
     }
     Ok(Some(res))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::config::Cli;
+    use std::sync::Arc;
+
+    use crate::{
+        config::ReportOptions,
+        http::{Fixtures, HttpContext},
+    };
+
+    use crate::twitch::models::BroadcastClip;
 
-    use super::{extract_slug as es, find_bid_from_clip as bid};
+    use super::{
+        clip_qualities, clips_of_broadcast, extract_slug as es, find_bid_from_clip as bid,
+        stream_start_window,
+    };
 
     #[test]
     fn extract_slug() {
@@ -200,6 +544,48 @@ mod tests {
             .is_err(),
             "testing twitch non-clip link 1"
         );
+        assert_eq!(
+            es("https://www.twitch.tv/mrmouton/clip/SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx/".to_string()).unwrap(),
+            Some("SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string()),
+            "testing trailing slash"
+        );
+        assert_eq!(
+            es("https://www.twitch.tv/mrmouton/clip/SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx?featured=false#t=1".to_string()).unwrap(),
+            Some("SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string()),
+            "testing query params and fragments"
+        );
+        assert_eq!(
+            es("https://www.twitch.tv/mrmouton/clip/SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx/edit".to_string()).unwrap(),
+            Some("SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string()),
+            "testing clip edit link"
+        );
+        assert_eq!(
+            es("HTTPS://WWW.TWITCH.TV/mrmouton/clip/SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string()).unwrap(),
+            Some("SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string()),
+            "testing uppercase host"
+        );
+        assert_eq!(
+            es(
+                "twitch.tv/mrmouton/clip/SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx"
+                    .to_string()
+            )
+            .unwrap(),
+            Some("SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string()),
+            "testing link without the scheme"
+        );
+        assert_eq!(
+            es("https://clips.twitch.tv/embed?clip=SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx&parent=example.com".to_string()).unwrap(),
+            Some("SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string()),
+            "testing clips.twitch.tv embed link"
+        );
+        assert!(
+            es("https://www.twitch.tv/mrmouton/clip".to_string()).is_err(),
+            "testing clip link without a slug"
+        );
+        assert!(
+            es("https://clips.twitch.tv/".to_string()).is_err(),
+            "testing clips.twitch.tv link without a slug"
+        );
     }
 
     #[tokio::test]
@@ -207,7 +593,8 @@ mod tests {
         assert_eq!(
             bid(
                 "SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string(),
-                Cli::default()
+                &ReportOptions::default(),
+                &HttpContext::default(),
             )
             .await
             .unwrap(),
@@ -217,7 +604,8 @@ mod tests {
         assert_eq!(
             bid(
                 "SpotlessCrypticStapleAMPTropPunch-H_rVu0mfGLNMlEx".to_string(),
-                Cli::default()
+                &ReportOptions::default(),
+                &HttpContext::default(),
             )
             .await
             .unwrap(),
@@ -225,4 +613,114 @@ mod tests {
             "testing invalid clip"
         );
     }
+
+    #[tokio::test]
+    async fn find_bid_from_clip_offline() {
+        let http = HttpContext::default().with_fixtures(Arc::new(Fixtures::new().respond(
+            "https://gql.twitch.tv/gql",
+            200,
+            r#"{"data": {"clip": {"broadcaster": {"login": "mrmouton"}, "broadcast": {"id": "39905263305"}}}}"#,
+        )));
+        assert_eq!(
+            bid(
+                "SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string(),
+                &ReportOptions::default(),
+                &http,
+            )
+            .await
+            .unwrap(),
+            Some(("mrmouton".to_string(), 39905263305)),
+            "testing the GQL response parsing"
+        );
+
+        let missing = HttpContext::default().with_fixtures(Arc::new(Fixtures::new()));
+        assert_eq!(
+            bid(
+                "SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string(),
+                &ReportOptions::default(),
+                &missing,
+            )
+            .await
+            .unwrap(),
+            None,
+            "testing an empty response"
+        );
+    }
+
+    #[tokio::test]
+    async fn direct_qualities() {
+        let gql = "https://gql.twitch.tv/gql";
+        let http = HttpContext::default().with_fixtures(Arc::new(
+            Fixtures::new()
+                .respond(gql, 200, r#"{"data": {"clip": {"playbackAccessToken": {"signature": "abc123", "value": "{\"clip_uri\":\"\"}"}, "videoQualities": [{"quality": "1080", "frameRate": 60, "sourceURL": "https://production.assets.clips.twitchcdn.net/AT-cm%7C123.mp4"}, {"quality": "720", "frameRate": 30, "sourceURL": "https://production.assets.clips.twitchcdn.net/AT-cm%7C123-720.mp4"}]}}}"#)
+                .respond(gql, 200, r#"{"data": {"clip": null}}"#),
+        ));
+        let slug = "SpotlessCrypticStapleAMPTropPunch-H_rVu0mGfGLNMlEx".to_string();
+
+        let qualities = clip_qualities(slug.clone(), &http).await.unwrap();
+        assert_eq!(
+            qualities
+                .iter()
+                .map(|q| q.quality.as_str())
+                .collect::<Vec<_>>(),
+            vec!["1080p60", "720p"],
+            "testing the quality names"
+        );
+        assert_eq!(
+            qualities[0].url,
+            "https://production.assets.clips.twitchcdn.net/AT-cm%7C123.mp4?sig=abc123&token=%7B%22clip_uri%22%3A%22%22%7D",
+            "testing the signed URL"
+        );
+        assert!(
+            clip_qualities(slug, &http).await.is_err(),
+            "testing a deleted clip"
+        );
+    }
+
+    #[tokio::test]
+    async fn broadcast_clips() {
+        let http = HttpContext::default().with_fixtures(Arc::new(
+            Fixtures::new().respond(
+                "https://gql.twitch.tv/gql",
+                200,
+                r#"{"data": {"user": {"clips": {"pageInfo": {"hasNextPage": true}, "edges": [
+                    {"cursor": "a", "node": {"slug": "Newer", "createdAt": "2022-07-13T12:00:00Z", "durationSeconds": 30, "videoOffsetSeconds": 60, "broadcast": {"id": "39905263306"}}},
+                    {"cursor": "b", "node": {"slug": "Later", "createdAt": "2022-07-12T13:00:00Z", "durationSeconds": 30, "videoOffsetSeconds": 3570, "broadcast": {"id": "39905263305"}}},
+                    {"cursor": "c", "node": {"slug": "Earlier", "createdAt": "2022-07-12T12:10:00Z", "durationSeconds": 26.5, "videoOffsetSeconds": 560, "broadcast": {"id": "39905263305"}}},
+                    {"cursor": "d", "node": {"slug": "Older", "createdAt": "2022-07-10T12:00:00Z", "durationSeconds": 30, "videoOffsetSeconds": 60, "broadcast": {"id": "39905263304"}}}
+                ]}}}}"#,
+            ),
+        ));
+
+        let clips = clips_of_broadcast("MrMouton", 39905263305, &http)
+            .await
+            .unwrap();
+        assert_eq!(
+            clips.iter().map(|c| c.slug.as_str()).collect::<Vec<_>>(),
+            vec!["Earlier", "Later"],
+            "testing the clips of the broadcast in stream order"
+        );
+        assert_eq!(
+            stream_start_window(&clips),
+            Some((1657627123, 1657627200)),
+            "testing the start narrowed down by both clips"
+        );
+
+        let clip = |created_at: i64, offset: Option<i64>| BroadcastClip {
+            slug: String::new(),
+            created_at,
+            duration: 30,
+            offset,
+        };
+        assert_eq!(
+            stream_start_window(&[clip(1000, Some(100)), clip(5000, None)]),
+            Some((780, 870)),
+            "testing a single clip with an offset"
+        );
+        assert_eq!(
+            stream_start_window(&[clip(1000, None)]),
+            None,
+            "testing clips without offsets"
+        );
+    }
 }
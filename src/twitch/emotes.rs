@@ -0,0 +1,226 @@
+use log::debug;
+use reqwest::header::{HeaderMap, HeaderValue};
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+
+use crate::http::HttpContext;
+use crate::twitch::models::{
+    BttvChannel, BttvEmote, ChatLog, ChatUserResponse, FfzSets, SevenTvSet, SevenTvUser,
+    ThirdPartyEmote, VodQuery, VodVars,
+};
+
+async fn get_json<T: DeserializeOwned>(url: &str, http: &HttpContext) -> Option<T> {
+    let resp = match http.send(http.client.get(url)).await {
+        Ok(r) => r,
+        Err(e) => {
+            debug!("Couldn't get the emotes from {url} - {e}");
+            return None;
+        }
+    };
+    match resp.error_for_status() {
+        Ok(r) => r.json().await.ok(),
+        // channels that never set up a provider get a 404
+        Err(e) => {
+            debug!("Couldn't get the emotes from {url} - {e}");
+            None
+        }
+    }
+}
+
+pub async fn user_id(login: &str, http: &HttpContext) -> Option<String> {
+    let mut header_map = HeaderMap::new();
+    header_map.insert(
+        "client-id",
+        HeaderValue::from_static("kimne78kx3ncx6brgo4mv6wki5h1ko"),
+    );
+    let query = VodQuery {
+        query: "query($login:String){user(login: $login){id}}".to_string(),
+        variables: VodVars {
+            login: login.to_lowercase(),
+        },
+    };
+    let request = http
+        .client
+        .post("https://gql.twitch.tv/gql")
+        .json(&query)
+        .headers(header_map);
+    let resp: ChatUserResponse = http
+        .send_with_retry(request)
+        .await
+        .ok()?
+        .json()
+        .await
+        .ok()?;
+    Some(resp.data?.user?.id)
+}
+
+fn emote(provider: &str, id: String, name: String, url: String) -> ThirdPartyEmote {
+    ThirdPartyEmote {
+        provider: provider.to_string(),
+        id,
+        name,
+        url,
+    }
+}
+
+fn seventv(set: SevenTvSet) -> Vec<ThirdPartyEmote> {
+    set.emotes
+        .into_iter()
+        .map(|e| {
+            let url = format!("https://cdn.7tv.app/emote/{}/1x.webp", e.id);
+            emote("7tv", e.id, e.name, url)
+        })
+        .collect()
+}
+
+fn bttv(emotes: Vec<BttvEmote>) -> Vec<ThirdPartyEmote> {
+    emotes
+        .into_iter()
+        .map(|e| {
+            let url = format!("https://cdn.betterttv.net/emote/{}/1x", e.id);
+            emote("bttv", e.id, e.code, url)
+        })
+        .collect()
+}
+
+fn ffz(sets: FfzSets) -> Vec<ThirdPartyEmote> {
+    let mut ids: Vec<&String> = sets.sets.keys().collect();
+    ids.sort();
+    ids.into_iter()
+        .flat_map(|id| &sets.sets[id].emoticons)
+        .map(|e| {
+            let url = format!("https://cdn.frankerfacez.com/emote/{}/1", e.id);
+            emote("ffz", e.id.to_string(), e.name.clone(), url)
+        })
+        .collect()
+}
+
+// every emote the channel could use, the channel ones first so they win over the global
+// ones with the same name
+async fn available_emotes(channel_id: Option<&str>, http: &HttpContext) -> Vec<ThirdPartyEmote> {
+    let mut emotes = Vec::new();
+    if let Some(id) = channel_id {
+        let seventv_url = format!("https://7tv.io/v3/users/twitch/{id}");
+        if let Some(set) = get_json::<SevenTvUser>(&seventv_url, http)
+            .await
+            .and_then(|u| u.emote_set)
+        {
+            emotes.extend(seventv(set));
+        }
+        let bttv_url = format!("https://api.betterttv.net/3/cached/users/twitch/{id}");
+        if let Some(channel) = get_json::<BttvChannel>(&bttv_url, http).await {
+            emotes.extend(bttv(channel.channel_emotes));
+            emotes.extend(bttv(channel.shared_emotes));
+        }
+        let ffz_url = format!("https://api.frankerfacez.com/v1/room/id/{id}");
+        if let Some(sets) = get_json::<FfzSets>(&ffz_url, http).await {
+            emotes.extend(ffz(sets));
+        }
+    }
+
+    if let Some(set) = get_json::<SevenTvSet>("https://7tv.io/v3/emote-sets/global", http).await {
+        emotes.extend(seventv(set));
+    }
+    if let Some(global) =
+        get_json::<Vec<BttvEmote>>("https://api.betterttv.net/3/cached/emotes/global", http).await
+    {
+        emotes.extend(bttv(global));
+    }
+    if let Some(sets) =
+        get_json::<FfzSets>("https://api.frankerfacez.com/v1/set/global", http).await
+    {
+        emotes.extend(ffz(sets));
+    }
+    emotes
+}
+
+// only the emotes that show up in the messages end up in the chat JSON
+fn used_emotes(log: &ChatLog, available: Vec<ThirdPartyEmote>) -> Vec<ThirdPartyEmote> {
+    let words: HashSet<&str> = log
+        .comments
+        .iter()
+        .flat_map(|m| m.message.split_whitespace())
+        .collect();
+    let mut seen: HashSet<String> = HashSet::new();
+    available
+        .into_iter()
+        .filter(|e| words.contains(e.name.as_str()))
+        .filter(|e| seen.insert(e.name.clone()))
+        .collect()
+}
+
+pub async fn resolve_emotes(
+    channel_id: Option<&str>,
+    log: &ChatLog,
+    http: &HttpContext,
+) -> Vec<ThirdPartyEmote> {
+    used_emotes(log, available_emotes(channel_id, http).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::http::{Fixtures, HttpContext};
+    use crate::twitch::models::{ChatLog, ChatMessage};
+
+    use super::resolve_emotes;
+
+    #[tokio::test]
+    async fn third_party_emotes() {
+        let log = ChatLog {
+            vod_id: 1,
+            cursor: None,
+            complete: true,
+            comments: vec![ChatMessage {
+                id: "1".to_string(),
+                offset_seconds: 1.0,
+                created_at: None,
+                login: "viewer".to_string(),
+                display_name: "Viewer".to_string(),
+                color: None,
+                message: "OMEGALUL catJAM LULW".to_string(),
+            }],
+            emotes: Vec::new(),
+        };
+        let http = HttpContext::default().with_fixtures(Arc::new(
+            Fixtures::new()
+                .respond(
+                    "https://7tv.io/v3/users/twitch/123",
+                    200,
+                    r#"{"emote_set": {"emotes": [{"id": "60ae7316", "name": "catJAM"}]}}"#,
+                )
+                .respond(
+                    "https://api.betterttv.net/3/cached/users/twitch/123",
+                    200,
+                    r#"{"channelEmotes": [], "sharedEmotes": [{"id": "5e4e7a1f", "code": "catJAM"}]}"#,
+                )
+                .respond(
+                    "https://api.frankerfacez.com/v1/set/global",
+                    200,
+                    r#"{"default_sets": [3], "sets": {"3": {"emoticons": [{"id": 128054, "name": "OMEGALUL"}, {"id": 1, "name": "unused"}]}}}"#,
+                ),
+        ));
+
+        assert_eq!(
+            resolve_emotes(Some("123"), &log, &http)
+                .await
+                .iter()
+                .map(|e| (e.provider.as_str(), e.name.as_str(), e.url.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "7tv",
+                    "catJAM",
+                    "https://cdn.7tv.app/emote/60ae7316/1x.webp"
+                ),
+                (
+                    "ffz",
+                    "OMEGALUL",
+                    "https://cdn.frankerfacez.com/emote/128054/1"
+                ),
+            ],
+            "testing the used emotes with the channel ones first"
+        );
+    }
+}
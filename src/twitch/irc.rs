@@ -0,0 +1,248 @@
+use anyhow::Result;
+use log::{debug, info, warn};
+use rand::Rng;
+use std::{collections::HashMap, time::Duration};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    time::{interval, MissedTickBehavior},
+};
+
+use crate::config::ChatOptions;
+use crate::http::HttpContext;
+use crate::twitch::chat::{chat_path, save_chat};
+use crate::twitch::emotes::{resolve_emotes, user_id};
+use crate::twitch::models::{ChatLog, ChatMessage};
+use crate::twitch::vods::current_broadcast;
+
+const IRC_ADDRESS: &str = "irc.chat.twitch.tv:6667";
+// the file only gets rewritten every this many messages, busy chats send several a second
+const SAVE_EVERY: usize = 50;
+// how often the stream gets checked for still being live
+const LIVE_CHECK: Duration = Duration::from_secs(60);
+
+#[derive(Debug, PartialEq)]
+pub struct PrivMsg {
+    pub tags: HashMap<String, String>,
+    pub login: String,
+    pub text: String,
+}
+
+// the escaping IRCv3 uses for tag values
+fn unescape_tag(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some(':') => out.push(';'),
+            Some('r') => out.push('\r'),
+            Some('n') => out.push('\n'),
+            Some(c) => out.push(c),
+            None => (),
+        }
+    }
+    out
+}
+
+// @tags :login!login@login.tmi.twitch.tv PRIVMSG #channel :text
+pub fn parse_privmsg(line: &str) -> Option<PrivMsg> {
+    let (tags, rest) = match line.strip_prefix('@') {
+        Some(line) => line.split_once(' ')?,
+        None => ("", line),
+    };
+    let (prefix, rest) = rest.strip_prefix(':')?.split_once(' ')?;
+    let (command, rest) = rest.split_once(' ')?;
+    if command != "PRIVMSG" {
+        return None;
+    }
+    let (_channel, text) = rest.split_once(" :")?;
+
+    let tags = tags
+        .split(';')
+        .filter_map(|tag| tag.split_once('='))
+        .map(|(k, v)| (k.to_string(), unescape_tag(v)))
+        .collect();
+    let login = prefix.split('!').next()?.to_string();
+    // /me messages come wrapped in a CTCP ACTION
+    let text = text
+        .strip_prefix("\u{1}ACTION ")
+        .and_then(|t| t.strip_suffix('\u{1}'))
+        .unwrap_or(text)
+        .to_string();
+
+    Some(PrivMsg { tags, login, text })
+}
+
+fn to_message(msg: PrivMsg, start: i64) -> ChatMessage {
+    let sent = msg
+        .tags
+        .get("tmi-sent-ts")
+        .and_then(|ts| ts.parse::<i64>().ok())
+        .and_then(|ts| OffsetDateTime::from_unix_timestamp_nanos(ts as i128 * 1_000_000).ok())
+        .unwrap_or_else(OffsetDateTime::now_utc);
+    let tag = |name: &str| msg.tags.get(name).filter(|v| !v.is_empty()).cloned();
+
+    ChatMessage {
+        id: tag("id").unwrap_or_else(|| format!("{}-{}", msg.login, sent.unix_timestamp_nanos())),
+        offset_seconds: (sent.unix_timestamp_nanos() as f64 / 1e9 - start as f64).max(0.0),
+        created_at: sent.format(&Rfc3339).ok(),
+        display_name: tag("display-name").unwrap_or_default(),
+        color: tag("color"),
+        login: msg.login,
+        message: msg.text,
+    }
+}
+
+async fn connect(channel: &str) -> Result<BufReader<TcpStream>> {
+    let mut stream = TcpStream::connect(IRC_ADDRESS).await?;
+    // justinfan with any number is the anonymous read-only login
+    let nick = format!("justinfan{}", rand::rng().random_range(10000..99999));
+    let handshake = format!(
+        "CAP REQ :twitch.tv/tags\r\nPASS SCHMOOPIIE\r\nNICK {nick}\r\nJOIN #{}\r\n",
+        channel.to_lowercase()
+    );
+    stream.write_all(handshake.as_bytes()).await?;
+    Ok(BufReader::new(stream))
+}
+
+// logs the chat of a running stream with the offsets counted from the stream's start, until
+// the stream goes offline or the capture gets cancelled (Ctrl+C in the CLI)
+pub async fn capture_live_chat(
+    channel: &str,
+    vod: i64,
+    start: i64,
+    options: &ChatOptions,
+    http: &HttpContext,
+) -> Result<ChatLog> {
+    let path = chat_path(vod, &options.output);
+    let flags = &options.report;
+    let mut log = ChatLog {
+        vod_id: vod,
+        cursor: None,
+        complete: false,
+        comments: Vec::new(),
+        emotes: Vec::new(),
+    };
+
+    let mut live_check = interval(LIVE_CHECK);
+    live_check.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // the first tick fires right away, the stream was just found live
+    live_check.tick().await;
+
+    if !flags.simple {
+        info!("Logging the chat of {channel} to {path}, press Ctrl+C to stop");
+    }
+    'connection: loop {
+        let mut reader = match connect(channel).await {
+            Ok(reader) => reader,
+            Err(e) => {
+                warn!("Couldn't connect to the chat - {e}");
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_millis(options.retry_delay)) => continue,
+                    _ = options.cancel.cancelled() => break,
+                    _ = tokio::signal::ctrl_c() => break,
+                }
+            }
+        };
+
+        let mut line = String::new();
+        loop {
+            // read_line isn't cancel safe, a line cut off by the other branches gets finished on
+            // the next read, so the buffer only gets cleared once a line is complete
+            tokio::select! {
+                read = reader.read_line(&mut line) => match read {
+                    Ok(0) | Err(_) => {
+                        warn!("Lost the connection to the chat, reconnecting...");
+                        continue 'connection;
+                    }
+                    Ok(_) => (),
+                },
+                _ = live_check.tick() => {
+                    match current_broadcast(channel, flags, http).await {
+                        Ok(Some((id, _))) if id == vod => (),
+                        Ok(_) => {
+                            if !flags.simple {
+                                info!("The stream is over, stopping the chat log");
+                            }
+                            log.complete = true;
+                            break 'connection;
+                        }
+                        Err(e) => debug!("Couldn't check if the stream is still live - {e}"),
+                    }
+                    continue;
+                }
+                _ = options.cancel.cancelled() => break 'connection,
+                _ = tokio::signal::ctrl_c() => break 'connection,
+            }
+
+            let complete = std::mem::take(&mut line);
+            let line = complete.trim_end();
+            if let Some(server) = line.strip_prefix("PING ") {
+                reader
+                    .get_mut()
+                    .write_all(format!("PONG {server}\r\n").as_bytes())
+                    .await?;
+            } else if line.contains(" RECONNECT") && !line.contains(" PRIVMSG ") {
+                debug!("The chat server asked for a reconnect");
+                continue 'connection;
+            } else if let Some(msg) = parse_privmsg(line) {
+                log.comments.push(to_message(msg, start));
+                if log.comments.len().is_multiple_of(SAVE_EVERY) {
+                    save_chat(&path, &log)?;
+                }
+            }
+        }
+    }
+
+    if options.emotes {
+        let channel_id = user_id(channel, http).await;
+        log.emotes = resolve_emotes(channel_id.as_deref(), &log, http).await;
+    }
+    save_chat(&path, &log)?;
+    Ok(log)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_privmsg, to_message};
+
+    #[test]
+    fn privmsg() {
+        let line = r"@color=#FF0000;display-name=Some\sViewer;id=abc;tmi-sent-ts=1622854227500 :viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #dansgaming :hello :) world";
+        let msg = parse_privmsg(line).unwrap();
+        assert_eq!(msg.login, "viewer", "testing the login");
+        assert_eq!(msg.text, "hello :) world", "testing the text");
+
+        let message = to_message(msg, 1622854217);
+        assert_eq!(
+            (
+                message.id.as_str(),
+                message.offset_seconds,
+                message.display_name.as_str(),
+                message.color.as_deref()
+            ),
+            ("abc", 10.5, "Some Viewer", Some("#FF0000")),
+            "testing the message relative to the stream start"
+        );
+
+        assert_eq!(
+            parse_privmsg(":tmi.twitch.tv 001 justinfan12345 :Welcome, GLHF!"),
+            None,
+            "testing a non-PRIVMSG line"
+        );
+        assert_eq!(
+            parse_privmsg(
+                ":viewer!viewer@viewer.tmi.twitch.tv PRIVMSG #chan :\u{1}ACTION waves\u{1}"
+            )
+            .map(|m| m.text),
+            Some("waves".to_string()),
+            "testing a /me message"
+        );
+    }
+}
@@ -1,4 +1,6 @@
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 pub static CDN_URLS: [&str; 29] = [
     "vod-secure.twitch.tv",
@@ -32,6 +34,50 @@ pub static CDN_URLS: [&str; 29] = [
     "d3fi1amfgojobc.cloudfront.net",
 ];
 
+// "chunked" is the source, the rest are the transcodes (some old VODs only have those left)
+pub static VOD_QUALITIES: [&str; 8] = [
+    "chunked", "1080p60", "720p60", "720p30", "480p30", "360p30", "160p30", AUDIO_ONLY,
+];
+
+// the rendition without any video
+pub const AUDIO_ONLY: &str = "audio_only";
+
+pub fn is_audio_only(playlist_url: &str) -> bool {
+    playlist_url.contains(&format!("/{AUDIO_ONLY}/"))
+}
+
+// where the playlist sits in the <hash>_<username>_<id>_<timestamp> directory. the VODs of the
+// last years all use index-dvr.m3u8, some older ones (mostly from before 2018) only have the
+// plain index.m3u8 and segments named after it, so the 1.ts probe misses them too
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PathLayout {
+    #[default]
+    Dvr,
+    Index,
+}
+
+impl PathLayout {
+    pub fn template(&self) -> &'static str {
+        match self {
+            Self::Dvr => "https://{cdn}/{directory}/{quality}/index-dvr.m3u8",
+            Self::Index => "https://{cdn}/{directory}/{quality}/index.m3u8",
+        }
+    }
+
+    // the source quality
+    pub fn playlist_url(&self, cdn: &str, directory: &str) -> String {
+        self.quality_url(cdn, directory, "chunked")
+    }
+
+    pub fn quality_url(&self, cdn: &str, directory: &str, quality: &str) -> String {
+        self.template()
+            .replace("{cdn}", cdn)
+            .replace("{directory}", directory)
+            .replace("{quality}", quality)
+    }
+}
+
 #[derive(Debug)]
 pub struct TwitchURL {
     pub full_url: String,
@@ -42,14 +88,84 @@ pub struct TwitchURL {
 #[derive(Debug)]
 pub struct AvailabilityCheck {
     pub fragment: String,
-    pub fragment_muted: String,
+    // only the current layout has predictable muted segment names
+    pub fragment_muted: Option<String>,
     pub playlist: String,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ReturnURL {
     pub url: String,
     pub muted: bool,
+    // only the audio is left, there's no video in this playlist
+    #[serde(default)]
+    pub audio_only: bool,
+}
+
+// a clip cut from a broadcast, the offset is None once the VOD is gone
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BroadcastClip {
+    pub slug: String,
+    pub created_at: i64,
+    pub duration: i64,
+    pub offset: Option<i64>,
+}
+
+// a playable MP4 of a clip
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ClipQuality {
+    // 1080p60, 720p and so on
+    pub quality: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MutedSegments {
+    pub quality: String,
+    pub muted: usize,
+    pub total: usize,
+}
+
+// what the VOD modes return once they find something
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VodRecovery {
+    // the username variant that matched, it can differ from the one that was asked for
+    pub username: String,
+    pub broadcast_id: i64,
+    pub timestamp: i64,
+    pub urls: Vec<ReturnURL>,
+    // the muted segment counts of every quality available on the first CDN
+    pub qualities: Vec<MutedSegments>,
+    pub muted_ranges: Vec<MutedRange>,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FixedPlaylist {
+    pub path: String,
+    pub segments: usize,
+    // the segments that got pointed to their muted versions
+    pub muted: usize,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DownloadedVod {
+    pub path: String,
+    pub segments: usize,
+    // the segments only the muted version of was left of
+    pub muted: usize,
+    pub bytes: u64,
+    // the mp4 next to the .ts, with --remux
+    pub remuxed: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+// offsets into the VOD in seconds
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MutedRange {
+    pub start: f64,
+    pub end: f64,
 }
 
 #[derive(Deserialize, Debug)]
@@ -57,6 +173,91 @@ pub struct ClipResponse {
     pub data: ClipData,
 }
 
+#[derive(Serialize, Debug)]
+pub struct UserClipsVars {
+    pub login: String,
+    pub after: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct UserClipsQuery {
+    pub query: String,
+    pub variables: UserClipsVars,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UserClipsResponse {
+    pub data: UserClipsData,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UserClipsData {
+    pub user: Option<UserClips>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UserClips {
+    pub clips: ClipConnection,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ClipConnection {
+    #[serde(alias = "pageInfo")]
+    pub page_info: PageInfo,
+    pub edges: Vec<ClipEdge>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ClipEdge {
+    pub cursor: Option<String>,
+    pub node: ClipNode,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ClipNode {
+    pub slug: String,
+    #[serde(alias = "createdAt")]
+    pub created_at: String,
+    #[serde(alias = "durationSeconds")]
+    pub duration_seconds: f64,
+    #[serde(alias = "videoOffsetSeconds")]
+    pub video_offset_seconds: Option<i64>,
+    pub broadcast: Option<Broadcast>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ClipPlaybackResponse {
+    pub data: ClipPlaybackData,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ClipPlaybackData {
+    pub clip: Option<ClipPlayback>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ClipPlayback {
+    #[serde(alias = "playbackAccessToken")]
+    pub playback_access_token: PlaybackAccessToken,
+    #[serde(alias = "videoQualities")]
+    pub video_qualities: Vec<VideoQuality>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PlaybackAccessToken {
+    pub signature: String,
+    pub value: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VideoQuality {
+    pub quality: String,
+    #[serde(alias = "frameRate")]
+    pub frame_rate: Option<f64>,
+    #[serde(alias = "sourceURL")]
+    pub source_url: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct VodResponse {
     pub data: VodData,
@@ -87,6 +288,9 @@ pub struct Stream {
     pub id: String,
     #[serde(alias = "createdAt")]
     pub created_at: String,
+    // "live", "rerun" or "premiere"
+    #[serde(rename = "type", default)]
+    pub stream_type: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -109,6 +313,12 @@ pub struct VodVars {
     pub login: String,
 }
 
+#[derive(Serialize, Debug)]
+pub struct VideoInfoVars {
+    pub id: String,
+    pub login: String,
+}
+
 #[derive(Serialize, Debug)]
 pub struct ClipQuery {
     pub query: String,
@@ -120,3 +330,407 @@ pub struct VodQuery {
     pub query: String,
     pub variables: VodVars,
 }
+
+#[derive(Serialize, Debug)]
+pub struct VideoVars {
+    pub id: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct VideoQuery {
+    pub query: String,
+    pub variables: VideoVars,
+}
+
+#[derive(Serialize, Debug)]
+pub struct VideoInfoQuery {
+    pub query: String,
+    pub variables: VideoInfoVars,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VideoInfoResponse {
+    pub data: VideoInfoData,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VideoInfoData {
+    pub video: Option<Video>,
+    pub user: Option<UserInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Video {
+    pub title: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VideoLookupResponse {
+    pub data: VideoLookupData,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VideoLookupData {
+    pub video: Option<VideoLookup>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct VideoLookup {
+    pub owner: Option<Broadcaster>,
+    // the storyboards sit in the VOD's directory, which has the broadcast ID and the start in it
+    #[serde(alias = "seekPreviewsURL")]
+    pub seek_previews_url: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct UserInfo {
+    #[serde(alias = "displayName")]
+    pub display_name: String,
+}
+
+// whatever GQL still knows about a VOD, deleted VODs usually only have the user left
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VodInfo {
+    pub display_name: Option<String>,
+    pub title: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentsVars {
+    #[serde(rename = "videoID")]
+    pub video_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_offset_seconds: Option<u64>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PersistedQuery {
+    pub version: u8,
+    #[serde(rename = "sha256Hash")]
+    pub sha256_hash: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryExtensions {
+    pub persisted_query: PersistedQuery,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentsQuery {
+    pub operation_name: String,
+    pub variables: CommentsVars,
+    pub extensions: QueryExtensions,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommentsResponse {
+    pub data: Option<CommentsData>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommentsData {
+    pub video: Option<CommentsVideo>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommentsVideo {
+    pub comments: Option<Comments>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Comments {
+    pub edges: Vec<CommentEdge>,
+    pub page_info: PageInfo,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PageInfo {
+    pub has_next_page: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommentEdge {
+    pub cursor: Option<String>,
+    pub node: CommentNode,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentNode {
+    pub id: String,
+    // null for deleted accounts
+    pub commenter: Option<Commenter>,
+    pub content_offset_seconds: f64,
+    pub created_at: Option<String>,
+    pub message: CommentMessage,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Commenter {
+    pub login: String,
+    pub display_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CommentMessage {
+    pub fragments: Vec<CommentFragment>,
+    pub user_color: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CommentFragment {
+    pub text: String,
+}
+
+// a single chat message, the same for every source so the converters don't care where it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    pub id: String,
+    // seconds since the start of the VOD
+    pub offset_seconds: f64,
+    pub created_at: Option<String>,
+    pub login: String,
+    pub display_name: String,
+    pub color: Option<String>,
+    pub message: String,
+}
+
+// what gets written to the chat JSON, saved along the way so interrupted downloads can resume
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatLog {
+    pub vod_id: i64,
+    // the cursor of the next page, None once the download is complete
+    pub cursor: Option<String>,
+    pub complete: bool,
+    pub comments: Vec<ChatMessage>,
+    // the 7TV/BTTV/FFZ emotes used in the messages, matched by name like the chat clients do
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub emotes: Vec<ThirdPartyEmote>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThirdPartyEmote {
+    pub provider: String,
+    pub id: String,
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChatUserResponse {
+    pub data: Option<ChatUserData>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChatUserData {
+    pub user: Option<ChatUser>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChatUser {
+    pub id: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BttvEmote {
+    pub id: String,
+    pub code: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BttvChannel {
+    #[serde(default)]
+    pub channel_emotes: Vec<BttvEmote>,
+    #[serde(default)]
+    pub shared_emotes: Vec<BttvEmote>,
+}
+
+// both the global and the room endpoints answer with the sets keyed by their ID
+#[derive(Deserialize, Debug)]
+pub struct FfzSets {
+    pub sets: HashMap<String, FfzSet>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FfzSet {
+    pub emoticons: Vec<FfzEmote>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FfzEmote {
+    pub id: i64,
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SevenTvSet {
+    #[serde(default)]
+    pub emotes: Vec<SevenTvEmote>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SevenTvEmote {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SevenTvUser {
+    pub emote_set: Option<SevenTvSet>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChatVideoQuery {
+    pub query: String,
+    pub variables: ChatVideoVars,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ChatVideoVars {
+    pub id: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChatVideoResponse {
+    pub data: Option<ChatVideoData>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChatVideoData {
+    pub video: Option<ChatVideo>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatVideo {
+    pub title: Option<String>,
+    pub created_at: Option<String>,
+    pub length_seconds: Option<f64>,
+    pub owner: Option<ChatVideoOwner>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ChatVideoOwner {
+    pub id: String,
+    pub login: String,
+}
+
+// the chat JSON TwitchDownloader reads and writes, so its chat renderer works on our downloads
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TdChat {
+    #[serde(rename = "FileInfo")]
+    pub file_info: TdFileInfo,
+    pub streamer: TdStreamer,
+    pub video: TdVideo,
+    pub comments: Vec<TdComment>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TdFileInfo {
+    pub version: TdVersion,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TdVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TdStreamer {
+    pub name: String,
+    pub id: i64,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TdVideo {
+    pub title: Option<String>,
+    pub id: String,
+    #[serde(rename = "created_at")]
+    pub created_at: String,
+    pub start: f64,
+    pub end: f64,
+    pub length: f64,
+    #[serde(default)]
+    pub view_count: i64,
+    #[serde(default)]
+    pub chapters: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TdComment {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub created_at: String,
+    pub channel_id: String,
+    pub content_type: String,
+    pub content_id: String,
+    pub content_offset_seconds: f64,
+    pub commenter: TdCommenter,
+    pub message: TdMessage,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TdCommenter {
+    pub display_name: String,
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub name: String,
+    pub bio: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub logo: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TdMessage {
+    pub body: String,
+    pub bits_spent: i64,
+    pub fragments: Vec<TdFragment>,
+    pub user_badges: Vec<serde_json::Value>,
+    pub user_color: Option<String>,
+    pub emoticons: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct TdFragment {
+    pub text: String,
+    pub emoticon: Option<serde_json::Value>,
+}
+
+// the JSON justlog and rustlog serve for a channel's day
+#[derive(Deserialize, Debug)]
+pub struct ChannelLogs {
+    pub messages: Vec<LogMessage>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LogMessage {
+    pub id: String,
+    pub text: String,
+    pub username: String,
+    pub display_name: String,
+    pub timestamp: String,
+    // 1 is a regular message, the rest are timeouts, subs and the like
+    #[serde(rename = "type")]
+    pub kind: u8,
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+}
@@ -1,70 +1,182 @@
-use anyhow::Result;
-use colored::*;
-use futures::stream::{self, StreamExt};
-use indicatif::{ProgressBar, ProgressIterator};
-use lazy_static::lazy_static;
-use log::{debug, error, info};
-use m3u8_rs::{parse_media_playlist_res, MediaPlaylist, MediaSegment};
-use regex::Regex;
+use anyhow::{anyhow, Result};
+use futures::{
+    future,
+    stream::{self, StreamExt},
+};
+use indicatif::ProgressBar;
+use log::{debug, error, info, warn};
+use rayon::prelude::*;
 use reqwest::StatusCode;
-use sha1::{Digest, Sha1};
+use serde_json::json;
+use std::{
+    collections::HashSet,
+    fs::File,
+    io::{BufWriter, Write},
+    pin::pin,
+    time::Duration,
+};
+use tokio::{sync::mpsc, time::Instant};
 
-use crate::config::Cli;
-use crate::error::PlaylistFix;
+use crate::config::{BruteforceOptions, DownloadOptions, FixOptions, ReportOptions};
+use crate::error::{Download, PlaylistFix};
+use crate::export::{emit_streamlink, media_server_export, remux, write_archive_metadata};
+use crate::feed::record_recovery;
+use crate::hooks::{run_hooks, HookStage};
+use crate::http::HttpContext;
+use crate::limiter::{scan_budget, AdaptiveConcurrency};
+use crate::metrics;
+use crate::notify::{notify_recovery, RecoveryEvent};
+use crate::offline::{
+    fix_segment_line, is_segment_line, is_unmuted_segment, muted_segment_url, parse_timestamp,
+    playlist_base_url, playlist_url, username_variants, vod_hash,
+};
+use crate::tracker::{ProcessingType, StreamType, URLData, PREMIERE_WINDOW};
 use crate::twitch::{
-    check_availability,
-    models::{ReturnURL, TwitchURL},
+    check_availability, check_muted_segments,
+    models::{DownloadedVod, FixedPlaylist, ReturnURL, TwitchURL, VodRecovery},
+    muted_ranges,
 };
-use crate::util::{compile_cdn_list, info, parse_timestamp};
+use crate::util::compile_cdn_list;
 
-lazy_static! {
-    static ref FIX_REGEX: Regex = Regex::new(r"[^/]+").unwrap();
+async fn recovery(
+    username: &str,
+    vod: i64,
+    timestamp: i64,
+    urls: Vec<ReturnURL>,
+    started: Instant,
+    http: &HttpContext,
+) -> VodRecovery {
+    let (qualities, muted_ranges) = tokio::join!(
+        check_muted_segments(&urls[0].url, http),
+        muted_ranges(&urls[0].url, http)
+    );
+    VodRecovery {
+        username: username.to_string(),
+        broadcast_id: vod,
+        timestamp,
+        urls,
+        qualities,
+        muted_ranges,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    }
 }
 
-pub async fn bruteforcer(
+// everything that happens after a hit besides the output, which is up to the caller
+async fn handle_hit(recovery: &VodRecovery, flags: &ReportOptions, http: &HttpContext) {
+    let (username, vod) = (&recovery.username, recovery.broadcast_id);
+
+    metrics::record_hit();
+    if let Some(progress) = &flags.progress {
+        for url in &recovery.urls {
+            progress.hit(url);
+        }
+    }
+    let event = RecoveryEvent {
+        username: username.to_string(),
+        broadcast_id: vod,
+        timestamp: Some(recovery.timestamp),
+        urls: recovery.urls.clone(),
+    };
+    run_hooks(HookStage::Hit, &event, flags).await;
+    notify_recovery(&event, flags, http).await;
+    if let Err(e) = record_recovery(&event, flags) {
+        error!("Couldn't update the feed: {e}");
+    }
+    if let Err(e) = write_archive_metadata(&event, flags, http).await {
+        error!("Couldn't write the archive metadata: {e}");
+    }
+
+    let output = match media_server_export(&event, flags, http).await {
+        Ok(Some(name)) => format!("{name}.mp4"),
+        Ok(None) => format!("{username}_{vod}.ts"),
+        Err(e) => {
+            error!("Couldn't write the NFO file: {e}");
+            format!("{username}_{vod}.ts")
+        }
+    };
+
+    // muted playlists are only worth downloading after fixing them
+    if !recovery.urls[0].muted {
+        emit_streamlink(&recovery.urls[0].url, &output, flags).await;
+    }
+}
+
+async fn bruteforce_range(
     username: &str,
     vod: i64,
-    initial_from_stamp: &str,
-    initial_to_stamp: &str,
-    flags: Cli,
-) -> Result<Option<Vec<ReturnURL>>> {
-    let number1 = parse_timestamp(initial_from_stamp)?;
-    let number2 = parse_timestamp(initial_to_stamp)?;
+    from: i64,
+    to: i64,
+    options: &BruteforceOptions,
+    http: &HttpContext,
+) -> Option<TwitchURL> {
+    let (scan, flags) = (&options.scan, &options.report);
+    let cdn_urls_compiled = compile_cdn_list(scan.cdnfile.clone(), http).await;
+    let layouts = scan.layouts.clone();
+    let qualities = scan.qualities.clone();
+    let total = (to - from + 1).max(0) as u64
+        * (cdn_urls_compiled.len() * qualities.len() * layouts.len()) as u64;
+    let pb = if flags.console {
+        ProgressBar::new(total)
+    } else {
+        ProgressBar::hidden()
+    };
+    if let Some(progress) = &flags.progress {
+        progress.start(total);
+    }
 
-    let mut all_formats_vec: Vec<TwitchURL> = Vec::new();
-    if !flags.simple {
-        info!("Starting!");
+    // hashing happens on the rayon pool and feeds the probes as it goes,
+    // so the requests start right away instead of waiting for the whole range
+    let budget = scan_budget(scan.threads, scan.max_memory);
+    if budget.threads != scan.threads {
+        debug!(
+            "Using {} threads to fit into the memory budget",
+            budget.threads
+        );
     }
-    for number in number1..number2 + 1 {
-        let mut hasher = Sha1::new();
-        hasher.update(format!("{username}_{vod}_{number}").as_str());
-        let hex_vec = hasher.finalize();
-        let hex = format!("{hex_vec:x}");
-        let cdn_urls_compiled = compile_cdn_list(flags.cdnfile.clone());
-        for cdn in cdn_urls_compiled {
-            all_formats_vec.push(TwitchURL {
-                full_url: format!(
-                    "https://{cdn}/{hex}_{username}_{vod}_{number}/chunked/index-dvr.m3u8",
-                    cdn = cdn,
-                    hex = &hex[0..20],
-                    username = username,
-                    vod = vod,
-                    number = number
-                ),
-                hash: hex[0..20].to_string(),
-                timestamp: number,
+    let (tx, rx) = mpsc::channel::<TwitchURL>(budget.queue);
+    let username_owned = username.to_string();
+    let hashing = tokio::task::spawn_blocking(move || {
+        let result = (from..=to)
+            .into_par_iter()
+            .try_for_each_with(tx, |tx, number| {
+                let hex = vod_hash(&username_owned, vod, number);
+                let directory = format!("{hex}_{username_owned}_{vod}_{number}");
+                for cdn in &cdn_urls_compiled {
+                    for quality in &qualities {
+                        for layout in &layouts {
+                            tx.blocking_send(TwitchURL {
+                                full_url: layout.quality_url(cdn, &directory, quality),
+                                hash: hex.clone(),
+                                timestamp: number,
+                            })?;
+                        }
+                    }
+                }
+                Ok::<(), mpsc::error::SendError<TwitchURL>>(())
             });
+        if result.is_ok() {
+            debug!("Finished making urls.");
         }
-    }
-    debug!("Finished making urls.");
-    let pb = ProgressBar::new(all_formats_vec.len() as u64);
+    });
 
-    let fetches = stream::iter(all_formats_vec)
+    // the unfold holds the receiver's future, so it has to be pinned before it can be polled
+    let urls = pin!(stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|url| (url, rx))
+    }));
+
+    let concurrency = AdaptiveConcurrency::new(budget.threads, !scan.fixed_threads);
+
+    // misses are dropped as soon as they come in and the scan stops at the first hit
+    let hits = urls
         .map(|url| async {
-            let res = crate::HTTP_CLIENT.get(url.full_url.clone()).send().await;
+            let _permit = concurrency.acquire().await;
+            let res = http.probe(&url.full_url).await;
             if flags.progressbar {
                 pb.inc(1);
             }
+            if let Some(progress) = &flags.progress {
+                progress.inc();
+            }
             match res {
                 Ok(res) => match res.status() {
                     StatusCode::OK => {
@@ -77,6 +189,11 @@ pub async fn bruteforcer(
                         if flags.verbose {
                             pb.println(format!("Still going - {url:?}"));
                         }
+                        if let Some(limit) = concurrency.succeeded() {
+                            pb.println(format!(
+                                "No throttling for a while, raising the concurrency to {limit}"
+                            ));
+                        }
                         None
                     }
                     _ => {
@@ -85,6 +202,9 @@ pub async fn bruteforcer(
                                 res.status(),
                                 res.url()
                             ));
+                        if let Some(limit) = concurrency.throttled() {
+                            pb.println(format!("Lowering the concurrency to {limit}"));
+                        }
                         None
                     }
                 },
@@ -94,59 +214,99 @@ pub async fn bruteforcer(
                 }
             }
         })
-        .buffer_unordered(flags.threads)
-        .collect::<Vec<Option<TwitchURL>>>()
-        .await;
+        .buffer_unordered(budget.threads)
+        .take_until(scan.cancel.cancelled())
+        .filter_map(future::ready);
+    // the cancellation future can't be moved around either
+    let hit = pin!(hits).next().await;
 
-    let final_url: Option<TwitchURL> = fetches.into_iter().flatten().next();
+    if let Err(e) = hashing.await {
+        error!("Couldn't generate the hashes: {e}");
+    }
 
-    match final_url {
-        Some(final_url) => {
-            let valid_urls = check_availability(
-                &final_url.hash,
-                username,
-                vod,
-                &final_url.timestamp,
-                flags.clone(),
-            )
-            .await;
-            if !valid_urls.is_empty() {
-                if !flags.simple {
-                    info!(
-                        "Got the URL and it {} on Twitch servers. Here are the valid URLs:",
-                        "was available".green()
-                    );
-                }
-                for url in &valid_urls {
-                    info(url.url.clone(), flags.simple);
-                }
-                Ok(Some(valid_urls))
-            } else {
-                if !flags.simple {
-                    info!(
-                        "Got the URL and it {} on Twitch servers :(",
-                        "was NOT available".red()
-                    );
-                    info!("Here's the URL for debug purposes - {}", final_url.full_url);
-                }
-                Ok(None)
-            }
+    hit
+}
+
+pub async fn bruteforcer(
+    username: &str,
+    vod: i64,
+    initial_from_stamp: &str,
+    initial_to_stamp: &str,
+    options: &BruteforceOptions,
+    http: &HttpContext,
+) -> Result<Option<VodRecovery>> {
+    let started = Instant::now();
+    let flags = &options.report;
+    let number1 = parse_timestamp(initial_from_stamp)?;
+    let number2 = parse_timestamp(initial_to_stamp)?;
+
+    if !flags.simple {
+        info!("Starting!");
+    }
+
+    let mut final_url: Option<(String, TwitchURL)> = None;
+    for (i, variant) in username_variants(username).into_iter().enumerate() {
+        if options.scan.cancel.is_cancelled() {
+            break;
         }
-        None => {
-            if !flags.simple {
-                info!("{}", "Couldn't find anything :(".red());
-            }
-            Ok(None)
+        if i > 0 && !flags.simple {
+            info!("Trying the username variant \"{variant}\"...");
+        }
+        if let Some(url) = bruteforce_range(&variant, vod, number1, number2, options, http).await {
+            final_url = Some((variant, url));
+            break;
         }
     }
+
+    let Some((matched_username, final_url)) = final_url else {
+        if options.scan.cancel.is_cancelled() {
+            debug!("The bruteforce got cancelled");
+        } else {
+            metrics::record_miss();
+        }
+        return Ok(None);
+    };
+
+    let valid_urls = check_availability(
+        &final_url.hash,
+        &matched_username,
+        vod,
+        &final_url.timestamp,
+        &options.scan,
+        http,
+    )
+    .await;
+    if valid_urls.is_empty() {
+        debug!(
+            "Found the URL, but it's not available on Twitch servers - {}",
+            final_url.full_url
+        );
+        metrics::record_miss();
+        return Ok(None);
+    }
+
+    let recovery = recovery(
+        &matched_username,
+        vod,
+        final_url.timestamp,
+        valid_urls,
+        started,
+        http,
+    )
+    .await;
+    handle_hit(&recovery, flags, http).await;
+    Ok(Some(recovery))
 }
 
 pub async fn exact(
     username: &str,
     vod: i64,
     initial_stamp: &str,
-    flags: Cli,
-) -> Result<Option<Vec<ReturnURL>>> {
+    options: &BruteforceOptions,
+    http: &HttpContext,
+) -> Result<Option<VodRecovery>> {
+    let started = Instant::now();
+    let flags = &options.report;
     let number = parse_timestamp(initial_stamp)?;
 
     if !flags.simple {
@@ -163,218 +323,447 @@ pub async fn exact(
         offsets.push(-i);
     }
 
-    for i in offsets {
-        let current_stamp = number + i;
-        let mut hasher = Sha1::new();
-        hasher.update(format!("{username}_{vod}_{current_stamp}").as_str());
-        let hex_vec = hasher.finalize();
-        let hex = format!("{hex_vec:x}");
-        let valid_urls = check_availability(
-            &hex[0..20].to_string(),
-            username,
-            vod,
-            &current_stamp,
-            flags.clone(),
-        )
-        .await;
-
-        if !valid_urls.is_empty() {
-            if !flags.simple {
-                info!(
-                    "Got the URL and it {} on Twitch servers. Here are the valid URLs:",
-                    "was available".green()
-                );
+    for (i, variant) in username_variants(username).iter().enumerate() {
+        if i > 0 && !flags.simple {
+            info!("Trying the username variant \"{variant}\"...");
+        }
+        for offset in &offsets {
+            if options.scan.cancel.is_cancelled() {
+                debug!("The exact check got cancelled");
+                return Ok(None);
             }
-            for url in &valid_urls {
-                info(url.url.clone(), flags.simple);
+            let current_stamp = number + offset;
+            let valid_urls = check_availability(
+                &vod_hash(variant, vod, current_stamp),
+                variant,
+                vod,
+                &current_stamp,
+                &options.scan,
+                http,
+            )
+            .await;
+
+            if !valid_urls.is_empty() {
+                let recovery =
+                    recovery(variant, vod, current_stamp, valid_urls, started, http).await;
+                handle_hit(&recovery, flags, http).await;
+                return Ok(Some(recovery));
             }
-            return Ok(Some(valid_urls));
         }
     }
 
-    if !flags.simple {
-        info!(
-            "Got the URL and it {} on Twitch servers :(",
-            "was NOT available".red()
-        );
-        let mut hasher = Sha1::new();
-        hasher.update(format!("{username}_{vod}_{number}").as_str());
-        let hex_vec = hasher.finalize();
-        let hex = format!("{hex_vec:x}");
-        info!("Here's the URL for debug purposes - https://vod-secure.twitch.tv/{}_{}_{}_{}/chunked/index-dvr.m3u8", &hex[0..20].to_string(), username, vod, &number);
-    }
+    debug!(
+        "Not available on Twitch servers - {}",
+        playlist_url(
+            "vod-secure.twitch.tv",
+            &vod_hash(username, vod, number),
+            username,
+            vod,
+            number
+        )
+    );
+    metrics::record_miss();
     Ok(None)
 }
 
-pub async fn fix(url: &str, output: Option<String>, old_method: bool, flags: Cli) -> Result<()> {
-    if !(url.contains("twitch.tv") || url.contains("cloudfront.net")) {
-        return Err(PlaylistFix::URL)?;
-    }
+pub async fn fix(url: &str, options: &FixOptions, http: &HttpContext) -> Result<FixedPlaylist> {
+    let started = Instant::now();
+    let flags = &options.report;
+    let (base_url, directory) = playlist_base_url(url).ok_or(PlaylistFix::URL)?;
 
-    let mut base_url_parts: Vec<String> = Vec::new();
-    for elem in FIX_REGEX.captures_iter(url) {
-        base_url_parts.push(elem[0].to_string());
-    }
+    let res = http.send_with_retry(http.client.get(url)).await?;
+    let body = res.error_for_status()?.text().await?;
 
-    if base_url_parts.len() < 4 {
-        return Err(PlaylistFix::URL)?;
+    if !body.trim_start().starts_with("#EXTM3U") {
+        return Err(PlaylistFix::Playlist)?;
     }
 
-    let base_url = format!(
-        "https://{}/{}/{}/",
-        base_url_parts[1], base_url_parts[2], base_url_parts[3]
-    );
-
-    let res = crate::HTTP_CLIENT.get(url).send().await?;
-    let body = res.text().await?;
-
-    let bytes = body.into_bytes();
+    let path = match &options.output {
+        Some(path) => path.clone(),
+        None => {
+            format!("muted_{directory}.m3u8")
+        }
+    };
 
-    let mut playlist = MediaPlaylist {
-        ..Default::default()
+    // the playlist gets rewritten line by line straight into the file,
+    // so even 48 hour VODs with tens of thousands of segments don't pile up in memory
+    let mut file = BufWriter::new(File::create(&path)?);
+    let segment_count = body.lines().filter(|line| is_segment_line(line)).count();
+    let pb = if flags.progressbar {
+        ProgressBar::new(segment_count as u64)
+    } else {
+        ProgressBar::hidden()
     };
+    let mut muted = 0;
 
-    match parse_media_playlist_res(&bytes) {
-        Ok(pl) => {
-            playlist = MediaPlaylist {
-                version: pl.version,
-                target_duration: pl.target_duration,
-                media_sequence: pl.media_sequence,
-                discontinuity_sequence: pl.discontinuity_sequence,
-                end_list: pl.end_list,
-                playlist_type: pl.playlist_type,
-                ..Default::default()
-            };
-            if old_method {
-                let initial_url_vec: Vec<String> = pl
-                    .segments
-                    .iter()
-                    .map(|segment| format!("{}{}", base_url, segment.uri))
-                    .collect();
-
-                let pb = ProgressBar::new(initial_url_vec.len() as u64);
-
-                let mut fetches = stream::iter(initial_url_vec)
-                    .map(|mut url| {
-                        let pb_clone = pb.clone();
-                        async move {
-                            let mut remove_chars = 3;
-                            let res = crate::HTTP_CLIENT
-                                .get(url.clone())
-                                .send()
-                                .await
-                                .expect("Error");
-                            if flags.progressbar {
-                                pb_clone.inc(1);
-                            }
-                            if res.status() == 403 {
-                                if url.contains("unmuted") {
-                                    remove_chars = 11;
-                                }
-                                url = format!(
-                                    "{}-muted.ts",
-                                    &url.clone()[..url.len() - remove_chars]
-                                );
-                                if flags.verbose {
-                                    pb_clone.println(format!(
-                                        "Found the muted version of this .ts file - {url:?}"
-                                    ))
-                                }
-                            } else if res.status() == 200 && flags.verbose {
-                                pb_clone.println(format!(
-                                    "Found the unmuted version of this .ts file - {url:?}"
-                                ))
-                            }
-                            url
-                        }
-                    })
-                    .buffer_unordered(flags.threads)
-                    .collect::<Vec<String>>()
-                    .await;
-
-                let initial_url_vec = &mut fetches[..];
-                alphanumeric_sort::sort_str_slice(initial_url_vec);
-                for (i, segment) in pl.segments.iter().enumerate() {
-                    playlist.segments.push(MediaSegment {
-                        uri: initial_url_vec[i].clone(),
-                        duration: segment.duration,
-                        ..Default::default()
-                    });
-                    debug!("Added this .ts file - {:?}", initial_url_vec[i])
+    if options.old_method {
+        let base_url = base_url.as_str();
+        let pb = &pb;
+        let mut lines = stream::iter(body.lines())
+            .map(|line| async move {
+                if !is_segment_line(line) {
+                    return (line.to_string(), false);
                 }
-            } else if flags.progressbar {
-                let pb = ProgressBar::new(pl.segments.len() as u64);
-                for segment in pl.segments.iter().progress_with(pb) {
-                    let url = format!("{}{}", base_url, segment.uri);
-                    if segment.uri.contains("unmuted") {
-                        let muted_url = format!("{}-muted.ts", &url.clone()[..url.len() - 11]);
-                        playlist.segments.push(MediaSegment {
-                            uri: muted_url.clone(),
-                            duration: segment.duration,
-                            ..Default::default()
-                        });
+
+                let url = format!("{base_url}{}", line.trim());
+                let res = http.send_with_retry(http.client.get(url.clone())).await;
+                pb.inc(1);
+                match res {
+                    Ok(r) if r.status() == StatusCode::FORBIDDEN => {
+                        let muted_url = muted_segment_url(base_url, line.trim());
                         if flags.verbose {
-                            println!("Found the muted version of this .ts file - {url:?}")
+                            pb.println(format!(
+                                "Found the muted version of this .ts file - {muted_url:?}"
+                            ))
                         }
-                    } else {
-                        playlist.segments.push(MediaSegment {
-                            uri: url.clone(),
-                            duration: segment.duration,
-                            ..Default::default()
-                        });
-                        if flags.verbose {
-                            println!("Found the unmuted version of this .ts file - {url:?}")
+                        (muted_url, true)
+                    }
+                    Ok(r) => {
+                        if r.status() == StatusCode::OK && flags.verbose {
+                            pb.println(format!(
+                                "Found the unmuted version of this .ts file - {url:?}"
+                            ))
                         }
+                        (url, false)
                     }
-                }
-            } else {
-                for segment in pl.segments {
-                    let url = format!("{}{}", base_url, segment.uri);
-                    if segment.uri.contains("unmuted") {
-                        let muted_url = format!("{}-muted.ts", &url.clone()[..url.len() - 11]);
-                        playlist.segments.push(MediaSegment {
-                            uri: muted_url.clone(),
-                            duration: segment.duration,
-                            ..Default::default()
-                        });
-                        debug!("Found the muted version of this .ts file - {muted_url:?}")
-                    } else {
-                        playlist.segments.push(MediaSegment {
-                            uri: url.clone(),
-                            duration: segment.duration,
-                            ..Default::default()
-                        });
-                        debug!("Found the unmuted version of this .ts file - {url:?}")
+                    Err(e) => {
+                        pb.println(format!("Couldn't check the .ts file {url:?} - {e}"));
+                        (url, false)
                     }
                 }
+            })
+            // keeping the playlist order intact
+            .buffered(scan_budget(options.scan.threads, options.scan.max_memory).threads)
+            .take_until(options.scan.cancel.cancelled())
+            // boxed to pin the cancellation future and so the borrowed lines don't keep the
+            // future from being Send
+            .boxed();
+
+        while let Some((line, is_muted)) = lines.next().await {
+            writeln!(file, "{line}")?;
+            muted += is_muted as usize;
+        }
+        if options.scan.cancel.is_cancelled() {
+            file.flush()?;
+            pb.finish_and_clear();
+            return Err(PlaylistFix::Cancelled)?;
+        }
+    } else {
+        for line in body.lines() {
+            if !is_segment_line(line) {
+                writeln!(file, "{line}")?;
+                continue;
+            }
+
+            let fixed = fix_segment_line(&base_url, line);
+            if is_unmuted_segment(line) {
+                debug!("Found the muted version of this .ts file - {fixed:?}");
+                muted += 1;
+            } else {
+                debug!("Found the unmuted version of this .ts file - {fixed:?}");
             }
+            writeln!(file, "{fixed}")?;
+            pb.inc(1);
         }
-        Err(e) => error!("Error in unmute(): {e:?}"),
     }
 
-    let path = match output {
-        Some(path) => path,
-        None => {
-            format!("muted_{}.m3u8", base_url_parts[2])
+    file.flush()?;
+    pb.finish_and_clear();
+
+    run_hooks(
+        HookStage::Fixed,
+        &json!({ "url": url, "output": path }),
+        flags,
+    )
+    .await;
+
+    let name = path.strip_suffix(".m3u8").unwrap_or(&path);
+    emit_streamlink(&path, &format!("{name}.ts"), flags).await;
+
+    Ok(FixedPlaylist {
+        path,
+        segments: segment_count,
+        muted,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+// the segment the way the playlist names it, or its muted version once that one's gone (which
+// the "-unmuted" ones usually are)
+async fn fetch_segment(
+    base_url: &str,
+    uri: &str,
+    http: &HttpContext,
+) -> Result<(Vec<u8>, bool), reqwest::Error> {
+    let url = format!("{base_url}{uri}");
+    let res = http.send_with_retry(http.client.get(&url)).await?;
+    let muted_url = muted_segment_url(base_url, uri);
+    if res.status() == StatusCode::FORBIDDEN && muted_url != url {
+        let res = http.send_with_retry(http.client.get(&muted_url)).await?;
+        return Ok((res.error_for_status()?.bytes().await?.to_vec(), true));
+    }
+    Ok((res.error_for_status()?.bytes().await?.to_vec(), false))
+}
+
+// the segments are fetched `threads` at a time but written in the playlist's order, so only
+// those few are ever in memory. a failed or cancelled download leaves what it got in the file
+pub async fn download(
+    url: &str,
+    options: &DownloadOptions,
+    http: &HttpContext,
+) -> Result<DownloadedVod> {
+    let started = Instant::now();
+    let flags = &options.report;
+    let (base_url, directory) = playlist_base_url(url).ok_or(Download::URL)?;
+
+    let res = http.send_with_retry(http.client.get(url)).await?;
+    let body = res.error_for_status()?.text().await?;
+
+    if !body.trim_start().starts_with("#EXTM3U") {
+        return Err(Download::Playlist)?;
+    }
+
+    let path = match &options.output {
+        Some(path) => path.clone(),
+        None => format!("{directory}.ts"),
+    };
+    let segments: Vec<&str> = body
+        .lines()
+        .filter(|line| is_segment_line(line))
+        .map(str::trim)
+        .collect();
+    let pb = if flags.console {
+        ProgressBar::new(segments.len() as u64)
+    } else {
+        ProgressBar::hidden()
+    };
+
+    let mut file = BufWriter::new(File::create(&path)?);
+    let base_url = base_url.as_str();
+    let pb_ref = &pb;
+    let mut fetched = stream::iter(segments.iter().copied())
+        .map(|uri| async move {
+            let segment = fetch_segment(base_url, uri, http).await;
+            pb_ref.inc(1);
+            segment
+                .map(|(data, is_muted)| (uri, data, is_muted))
+                .map_err(|e| Download::Segment(uri.to_string(), e))
+        })
+        .buffered(scan_budget(options.scan.threads, options.scan.max_memory).threads)
+        .take_until(options.scan.cancel.cancelled())
+        // boxed to pin the cancellation future and so the borrowed segments don't keep the
+        // future from being Send
+        .boxed();
+
+    let (mut muted, mut bytes) = (0, 0);
+    while let Some(segment) = fetched.next().await {
+        let (uri, data, is_muted) = segment?;
+        if is_muted && flags.verbose {
+            pb.println(format!("Only the muted version of {uri} is left"));
+        }
+        file.write_all(&data)?;
+        bytes += data.len() as u64;
+        muted += is_muted as usize;
+    }
+    file.flush()?;
+    pb.finish_and_clear();
+    if options.scan.cancel.is_cancelled() {
+        return Err(Download::Cancelled)?;
+    }
+
+    let remuxed = match &options.remux {
+        Some(ffmpeg) => {
+            let name = path.strip_suffix(".ts").unwrap_or(&path);
+            let output = format!("{name}.mp4");
+            if !flags.simple {
+                info!("Remuxing into {output}");
+            }
+            remux(&path, &output, ffmpeg).await?;
+            Some(output)
         }
+        None => None,
     };
 
-    let mut file = std::fs::File::create(&path)?;
-    playlist.write_to(&mut file)?;
+    Ok(DownloadedVod {
+        path,
+        segments: segments.len(),
+        muted,
+        bytes,
+        remuxed,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
 
-    if !flags.simple {
-        info!("Playlist fixed and saved to: {}", path);
+async fn live_stream(
+    username: &str,
+    bid: i64,
+    stamp: &str,
+    stream_type: StreamType,
+    options: &BruteforceOptions,
+    http: &HttpContext,
+) -> Result<Option<VodRecovery>> {
+    match stream_type {
+        StreamType::Rerun => Err(anyhow!(
+            "{username} is running a rerun ({bid}), reruns don't get a VOD of their own"
+        )),
+        StreamType::Premiere => {
+            premiere(username, bid, parse_timestamp(stamp)?, None, options, http).await
+        }
+        StreamType::Live => exact(username, bid, stamp, options, http).await,
     }
+}
 
-    Ok(())
+pub async fn live(
+    username: &str,
+    options: &BruteforceOptions,
+    http: &HttpContext,
+) -> Result<Option<VodRecovery>> {
+    match util::find_bid_from_username(username, &options.report, http).await? {
+        Some((bid, stamp, stream_type)) => {
+            live_stream(username, bid, &stamp, stream_type, options, http).await
+        }
+        None => Ok(None),
+    }
 }
 
-pub async fn live(username: &str, flags: Cli) -> Result<Option<Vec<ReturnURL>>> {
-    match util::find_bid_from_username(username, flags.clone()).await {
-        Ok(Some((bid, stamp))) => exact(username, bid, &stamp, flags).await,
-        Ok(None) => Ok(None),
-        Err(e) => Err(e),
+// checks whether the streamer is live every `interval` and recovers the VOD as soon as a stream
+// other than `last` shows up. a stream that has no VOD (yet) gets asked about again on the next
+// check, reruns get skipped. None once the scan gets cancelled
+pub async fn watch_live(
+    username: &str,
+    interval: Duration,
+    last: Option<i64>,
+    options: &BruteforceOptions,
+    http: &HttpContext,
+) -> Result<Option<VodRecovery>> {
+    let mut ticker = tokio::time::interval(interval);
+    // only logged once per stream
+    let mut missed = HashSet::new();
+    loop {
+        tokio::select! {
+            _ = options.scan.cancel.cancelled() => return Ok(None),
+            _ = ticker.tick() => (),
+        }
+        let stream = match util::find_bid_from_username(username, &options.report, http).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Couldn't check whether {username} is live - {e}");
+                continue;
+            }
+        };
+        let Some((bid, stamp, stream_type)) = stream.filter(|(bid, _, _)| Some(*bid) != last)
+        else {
+            continue;
+        };
+        if stream_type == StreamType::Rerun {
+            if missed.insert(bid) {
+                info!("{username} is running a rerun ({bid}), waiting for a live stream");
+            }
+            continue;
+        }
+        match live_stream(username, bid, &stamp, stream_type, options, http).await {
+            Ok(Some(recovery)) => return Ok(Some(recovery)),
+            Ok(None) => {
+                if missed.insert(bid) {
+                    info!("{username} is live ({bid}) but there's no VOD yet, trying again");
+                }
+            }
+            Err(e) => warn!("Couldn't recover the stream {bid} - {e}"),
+        }
+    }
+}
+
+async fn premiere(
+    username: &str,
+    vod: i64,
+    start: i64,
+    end: Option<i64>,
+    options: &BruteforceOptions,
+    http: &HttpContext,
+) -> Result<Option<VodRecovery>> {
+    if !options.report.simple {
+        info!("{vod} is a premiere, looking through the hour before it started");
     }
+    bruteforcer(
+        username,
+        vod,
+        &(start - PREMIERE_WINDOW).to_string(),
+        &end.unwrap_or(start).to_string(),
+        options,
+        http,
+    )
+    .await
+}
+
+// recovers a VOD with whichever method the tracker's data allows
+pub async fn from_tracker(
+    processing_type: ProcessingType,
+    data: &URLData,
+    options: &BruteforceOptions,
+    http: &HttpContext,
+) -> Result<Option<VodRecovery>> {
+    let broadcast_id = data.broadcast_id.parse::<i64>()?;
+    match data.stream_type {
+        StreamType::Rerun => {
+            return Err(anyhow!(
+                "{broadcast_id} is a rerun, reruns don't get a VOD of their own - recover the stream it replays instead"
+            ));
+        }
+        StreamType::Premiere => {
+            let end = match (&processing_type, &data.end_date) {
+                (ProcessingType::Bruteforce, Some(end)) => Some(parse_timestamp(end)?),
+                _ => None,
+            };
+            return premiere(
+                &data.username,
+                broadcast_id,
+                parse_timestamp(&data.start_date)?,
+                end,
+                options,
+                http,
+            )
+            .await;
+        }
+        StreamType::Live => (),
+    }
+    match processing_type {
+        ProcessingType::Exact => {
+            exact(
+                &data.username,
+                broadcast_id,
+                &data.start_date,
+                options,
+                http,
+            )
+            .await
+        }
+        ProcessingType::Bruteforce => {
+            let end_date = data
+                .end_date
+                .as_deref()
+                .ok_or_else(|| anyhow!("couldn't get the end date for the bruteforce method"))?;
+            bruteforcer(
+                &data.username,
+                broadcast_id,
+                &data.start_date,
+                end_date,
+                options,
+                http,
+            )
+            .await
+        }
+    }
+}
+
+// the ID and the start of the stream currently running on the channel, if there's one
+pub async fn current_broadcast(
+    username: &str,
+    flags: &ReportOptions,
+    http: &HttpContext,
+) -> Result<Option<(i64, String)>> {
+    Ok(util::find_bid_from_username(username, flags, http)
+        .await?
+        .map(|(bid, stamp, _)| (bid, stamp)))
 }
 
 mod util {
@@ -383,13 +772,16 @@ mod util {
     use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
     use std::{collections::HashMap, str::FromStr};
 
-    use crate::config::Cli;
+    use crate::config::ReportOptions;
+    use crate::http::HttpContext;
+    use crate::tracker::StreamType;
     use crate::twitch::models::{VodQuery, VodResponse, VodVars};
 
     pub async fn find_bid_from_username(
         username: &str,
-        flags: Cli,
-    ) -> Result<Option<(i64, String)>> {
+        flags: &ReportOptions,
+        http: &HttpContext,
+    ) -> Result<Option<(i64, String, StreamType)>> {
         let endpoint = "https://gql.twitch.tv/gql";
         let mut headers = HashMap::new();
         headers.insert("Client-ID", "kimne78kx3ncx6brgo4mv6wki5h1ko");
@@ -404,18 +796,20 @@ mod util {
         }
 
         let query = VodQuery {
-            query: "query($login:String){user(login: $login){stream{id createdAt}}}".to_string(),
+            query: "query($login:String){user(login: $login){stream{id createdAt type}}}"
+                .to_string(),
             variables: VodVars {
                 login: username.to_string(),
             },
         };
 
-        let request = crate::HTTP_CLIENT
+        let request = http
+            .client
             .post(endpoint)
             .json(&query)
             .headers(header_map.clone());
 
-        let re = request.send().await?;
+        let re = http.send_with_retry(request).await?;
         let data: VodResponse = match re.json().await {
             Ok(d) => d,
             Err(e) => {
@@ -426,7 +820,14 @@ mod util {
             }
         };
         match data.data.user.stream {
-            Some(d) => Ok(Some((d.id.parse::<i64>()?, d.created_at))),
+            Some(d) => {
+                let stream_type = d
+                    .stream_type
+                    .as_deref()
+                    .and_then(StreamType::from_label)
+                    .unwrap_or_default();
+                Ok(Some((d.id.parse::<i64>()?, d.created_at, stream_type)))
+            }
             None => Ok(None),
         }
     }
@@ -434,42 +835,51 @@ mod util {
 
 #[cfg(test)]
 mod tests {
-    use std::{fs::File, io::BufRead, io::BufReader};
+    use std::{fs, fs::File, io::BufRead, io::BufReader, sync::Arc, time::Duration};
 
     use tempfile::tempdir;
 
-    use crate::{config::Cli, twitch::models::ReturnURL};
+    use crate::{
+        config::{BruteforceOptions, DownloadOptions, FixOptions, ScanOptions},
+        http::{Fixtures, HttpContext},
+        twitch::models::ReturnURL,
+    };
 
-    use super::{bruteforcer, exact as ex, fix};
+    use super::{bruteforcer, download, exact as ex, fix, watch_live};
 
     #[tokio::test]
     async fn bruteforce() {
         let bf = bruteforcer(
-            &"dansgaming",
+            "dansgaming",
             42218705421,
-            &"2021-06-05 00:50:16",
-            &"2021-06-05 00:50:18",
-            Cli::default(),
+            "2021-06-05 00:50:16",
+            "2021-06-05 00:50:18",
+            &BruteforceOptions::default(),
+            &HttpContext::default(),
         )
         .await
         .unwrap()
-        .unwrap();
+        .unwrap()
+        .urls;
         let bf_comp: Vec<ReturnURL> = vec![ReturnURL {
             url: "https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8".to_string(),
             muted: false,
+            audio_only: false,
         }, ReturnURL {
             url: "https://d2vjef5jvl6bfs.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8".to_string(),
             muted: false,
+            audio_only: false,
         }];
 
         assert_eq!(bf, bf_comp, "testing bruteforce with results");
 
         let bf_wrong = bruteforcer(
-            &"dansgming",
+            "dansgming",
             42218705421,
-            &"2021-06-05 00:50:16",
-            &"2021-06-05 00:50:18",
-            Cli::default(),
+            "2021-06-05 00:50:16",
+            "2021-06-05 00:50:18",
+            &BruteforceOptions::default(),
+            &HttpContext::default(),
         )
         .await
         .unwrap();
@@ -477,11 +887,12 @@ mod tests {
         assert_eq!(bf_wrong, None, "testing bruteforce with no results");
 
         let bf_err = bruteforcer(
-            &"mrmouton",
+            "mrmouton",
             39905263305,
-            &"2022-07-12 1200",
-            &"2022-07-12 12:00:41",
-            Cli::default(),
+            "2022-07-12 1200",
+            "2022-07-12 12:00:41",
+            &BruteforceOptions::default(),
+            &HttpContext::default(),
         )
         .await;
 
@@ -491,36 +902,48 @@ mod tests {
     #[tokio::test]
     async fn exact() {
         let e = ex(
-            &"dansgaming",
+            "dansgaming",
             42218705421,
-            &"2021-06-05 00:50:17",
-            Cli::default(),
+            "2021-06-05 00:50:17",
+            &BruteforceOptions::default(),
+            &HttpContext::default(),
         )
         .await
         .unwrap()
-        .unwrap();
+        .unwrap()
+        .urls;
         let e_comp: Vec<ReturnURL> = vec![ReturnURL {
             url: "https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8".to_string(),
             muted: false,
+            audio_only: false,
         }, ReturnURL {
             url: "https://d2vjef5jvl6bfs.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8".to_string(),
             muted: false,
+            audio_only: false,
         }];
 
         assert_eq!(e, e_comp, "testing exact with results");
 
         let e_wrong = ex(
-            &"dansgming",
+            "dansgming",
             42218705421,
-            &"2021-06-.05 00:50:17",
-            Cli::default(),
+            "2021-06-.05 00:50:17",
+            &BruteforceOptions::default(),
+            &HttpContext::default(),
         )
         .await
         .unwrap();
 
         assert_eq!(e_wrong, None, "testing exact with no results");
 
-        let e_err = ex(&"mrmouton", 39905263305, &"2022-07-12 1200", Cli::default()).await;
+        let e_err = ex(
+            "mrmouton",
+            39905263305,
+            "2022-07-12 1200",
+            &BruteforceOptions::default(),
+            &HttpContext::default(),
+        )
+        .await;
 
         assert!(e_err.is_err(), "testing invalid exact");
     }
@@ -533,9 +956,8 @@ mod tests {
         // Try to run the fix function, but don't assert on the result since it depends on network
         let _result = fix(
             "https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/index-dvr.m3u8",
-            Some(path.to_str().unwrap().to_string()),
-            false,
-            Cli::default()
+            &FixOptions::default().output(path.to_str().unwrap()),
+            &HttpContext::default(),
         ).await;
 
         // If the file was created, check that it has content
@@ -547,4 +969,149 @@ mod tests {
         }
         // If the network request failed, that's okay for this test
     }
+
+    #[tokio::test]
+    async fn fix_playlist_offline() {
+        let base = "https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/";
+        let url = format!("{base}index-dvr.m3u8");
+        let playlist =
+            "#EXTM3U\n#EXTINF:10.000,\n0.ts\n#EXTINF:10.000,\n1-unmuted.ts\n#EXT-X-ENDLIST\n";
+        let expected = format!(
+            "#EXTM3U\n#EXTINF:10.000,\n{base}0.ts\n#EXTINF:10.000,\n{base}1-muted.ts\n#EXT-X-ENDLIST\n"
+        );
+        let dir = tempdir().unwrap();
+
+        let fast = dir.path().join("fast.m3u8");
+        let http = HttpContext::default()
+            .with_fixtures(Arc::new(Fixtures::new().respond(&url, 200, playlist)));
+        let fixed = fix(
+            &url,
+            &FixOptions::default().output(fast.to_str().unwrap()),
+            &http,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            (fixed.segments, fixed.muted),
+            (2, 1),
+            "testing the segment counts"
+        );
+        assert_eq!(
+            fs::read_to_string(&fast).unwrap(),
+            expected,
+            "testing the fixed playlist"
+        );
+
+        // the old method asks for every segment, the unmuted ones are forbidden
+        let slow = dir.path().join("slow.m3u8");
+        let http = HttpContext::default().with_fixtures(Arc::new(
+            Fixtures::new()
+                .respond(&url, 200, playlist)
+                .respond(&format!("{base}0.ts"), 200, "")
+                .respond(&format!("{base}1-unmuted.ts"), 403, ""),
+        ));
+        fix(
+            &url,
+            &FixOptions::default()
+                .output(slow.to_str().unwrap())
+                .old_method(true),
+            &http,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            fs::read_to_string(&slow).unwrap(),
+            expected,
+            "testing the fixed playlist with the old method"
+        );
+
+        let http = HttpContext::default().with_fixtures(Arc::new(Fixtures::new().respond(
+            &url,
+            200,
+            "<html></html>",
+        )));
+        assert!(
+            fix(&url, &FixOptions::default(), &http).await.is_err(),
+            "testing a response that isn't a playlist"
+        );
+    }
+
+    #[tokio::test]
+    async fn download_vod() {
+        let base = "https://d1m7jfoe9zdc1j.cloudfront.net/d3dcbaf880c9e36ed8c8_dansgaming_42218705421_1622854217/chunked/";
+        let url = format!("{base}index-dvr.m3u8");
+        let playlist = "#EXTM3U\n#EXTINF:10.000,\n0.ts\n#EXTINF:10.000,\n1-unmuted.ts\n#EXTINF:10.000,\n2.ts\n#EXT-X-ENDLIST\n";
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("vod.ts");
+
+        let http = HttpContext::default().with_fixtures(Arc::new(
+            Fixtures::new()
+                .respond(&url, 200, playlist)
+                .respond(&format!("{base}0.ts"), 200, "first")
+                .respond(&format!("{base}1-unmuted.ts"), 403, "")
+                .respond(&format!("{base}1-muted.ts"), 200, "second")
+                .respond(&format!("{base}2.ts"), 200, "third"),
+        ));
+        let options = DownloadOptions::default()
+            .output(output.to_str().unwrap())
+            .scan(ScanOptions::default().threads(2));
+        let downloaded = download(&url, &options, &http).await.unwrap();
+        assert_eq!(
+            (downloaded.segments, downloaded.muted, downloaded.bytes),
+            (3, 1, 16),
+            "testing the segment counts"
+        );
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "firstsecondthird",
+            "testing the segments in order"
+        );
+
+        let http = HttpContext::default()
+            .with_fixtures(Arc::new(Fixtures::new().respond(&url, 200, playlist)));
+        assert!(
+            download(&url, &options, &http).await.is_err(),
+            "testing a missing segment"
+        );
+    }
+
+    #[tokio::test]
+    async fn watch() {
+        let gql = "https://gql.twitch.tv/gql";
+        let stream = |id: &str, kind: &str| {
+            format!(
+                r#"{{"data":{{"user":{{"stream":{{"id":"{id}","createdAt":"2021-06-05T00:50:17Z","type":"{kind}"}}}}}}}}"#
+            )
+        };
+        let fixtures = Arc::new(
+            Fixtures::new()
+                .respond(gql, 200, r#"{"data":{"user":{"stream":null}}}"#)
+                .respond(gql, 200, stream("42218705420", "rerun"))
+                .respond(gql, 200, stream("42218705421", "live")),
+        );
+        let http = HttpContext::default().with_fixtures(fixtures.clone());
+        let options = BruteforceOptions::default();
+        let cancel = options.scan.cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            cancel.cancel();
+        });
+
+        let recovery = watch_live(
+            "dansgaming",
+            Duration::from_millis(10),
+            Some(42218705421),
+            &options,
+            &http,
+        )
+        .await
+        .unwrap();
+        assert!(recovery.is_none(), "testing the cancellation");
+        let requested = fixtures.requested();
+        assert!(requested.len() >= 3, "testing the polling");
+        assert!(
+            requested.iter().all(|url| url == gql),
+            "testing the rerun and the known stream getting skipped"
+        );
+    }
 }
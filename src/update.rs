@@ -1,11 +1,41 @@
 use anyhow::Result;
 use clap::crate_version;
 use guess_host_triple::guess_host_triple;
+use minisign_verify::{PublicKey, Signature};
 use reqwest::header::USER_AGENT;
 use semver::Version;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+use tempfile::TempDir;
+use time::OffsetDateTime;
 
 use crate::config::{Cli, CURL_UA};
+use crate::error::Update;
+use crate::http::HttpContext;
+use crate::i18n::t_with;
+use crate::util::{confirm, data_dir};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/vyneer/tbf/releases/latest";
+// what the release workflow uploads next to every archive
+const CHECKSUM_SUFFIX: &str = ".sha256";
+const SIGNATURE_SUFFIX: &str = ".minisig";
+// the background check happens at most once per this many seconds
+const UPDATE_CHECK_FILE: &str = "update_check.json";
+const UPDATE_CHECK_INTERVAL: i64 = 86400;
+const UPDATE_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+struct UpdateCheck {
+    checked_at: i64,
+    // the tag of the latest release, None if the check failed
+    latest: Option<String>,
+}
 
 #[derive(Debug, Deserialize)]
 struct GithubUpdate {
@@ -15,19 +45,317 @@ struct GithubUpdate {
 
 #[derive(Debug, Deserialize)]
 struct GithubAssets {
+    #[serde(default)]
+    name: String,
     browser_download_url: String,
 }
 
-pub async fn update(matches: Cli) -> Result<()> {
+// the same architecture goes by different names depending on who built it
+const ARCH_ALIASES: &[&[&str]] = &[
+    &["x86_64", "amd64", "x64"],
+    &["aarch64", "arm64"],
+    &["i686", "i386"],
+];
+const OPERATING_SYSTEMS: &[&str] = &["linux", "windows", "darwin", "freebsd", "android"];
+
+#[derive(Debug, PartialEq)]
+enum AssetMatch {
+    // the filename has the exact target triple
+    Exact,
+    // same OS and architecture, but another libc or toolchain (musl/gnu, msvc/gnu)
+    Platform,
+    // nothing fits (or the platform is unknown), every build gets listed
+    Any,
+}
+
+fn is_archive(name: &str) -> bool {
+    name.ends_with(".tar.gz") || name.ends_with(".zip")
+}
+
+// the checksums and the signatures only matter for --install
+fn is_build(asset: &GithubAssets) -> bool {
+    !asset.name.ends_with(CHECKSUM_SUFFIX) && !asset.name.ends_with(SIGNATURE_SUFFIX)
+}
+
+fn same_platform(name: &str, triple: &str) -> bool {
+    let name = name.to_lowercase();
+    let arch = triple.split('-').next().unwrap_or(triple);
+    let arch_names = ARCH_ALIASES
+        .iter()
+        .find(|a| a.contains(&arch))
+        .copied()
+        .unwrap_or(std::slice::from_ref(&arch));
+    let os = OPERATING_SYSTEMS.iter().find(|os| triple.contains(*os));
+    os.is_some_and(|os| name.contains(os)) && arch_names.iter().any(|a| name.contains(a))
+}
+
+// the builds for the target triple, the ones for the same OS and architecture if there's no
+// exact match, or all of them as the last resort
+fn match_assets<'a>(
+    assets: &'a [GithubAssets],
+    triple: Option<&str>,
+) -> (AssetMatch, Vec<&'a GithubAssets>) {
+    let builds: Vec<&GithubAssets> = assets.iter().filter(|a| is_build(a)).collect();
+    let Some(triple) = triple else {
+        return (AssetMatch::Any, builds);
+    };
+    let exact: Vec<&GithubAssets> = builds
+        .iter()
+        .copied()
+        .filter(|a| a.browser_download_url.contains(triple))
+        .collect();
+    if !exact.is_empty() {
+        return (AssetMatch::Exact, exact);
+    }
+    let platform: Vec<&GithubAssets> = builds
+        .iter()
+        .copied()
+        .filter(|a| same_platform(&a.name, triple))
+        .collect();
+    if !platform.is_empty() {
+        return (AssetMatch::Platform, platform);
+    }
+    (AssetMatch::Any, builds)
+}
+
+// the sha256sum format ("<hash>  <file>", with a "*" in front of the file in binary mode),
+// a line with only the hash counts for any file
+pub fn expected_checksum(checksums: &str, asset: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let hash = parts.next()?;
+        let file = parts.next().map(|f| f.trim_start_matches('*'));
+        let matches = file.is_none_or(|f| f == asset || f.ends_with(&format!("/{asset}")));
+        (matches && hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+            .then(|| hash.to_lowercase())
+    })
+}
+
+pub fn verify_checksum(data: &[u8], checksums: &str, asset: &str) -> Result<()> {
+    let expected = expected_checksum(checksums, asset).ok_or(Update::NoChecksum)?;
+    let actual = format!("{:x}", Sha256::digest(data));
+    if actual != expected {
+        return Err(Update::ChecksumMismatch { expected, actual }.into());
+    }
+    Ok(())
+}
+
+// the key is the base64 line of minisign's .pub file
+pub fn verify_signature(data: &[u8], signature: &str, public_key: &str) -> Result<()> {
+    let public_key = PublicKey::from_base64(public_key.trim())
+        .map_err(|e| Update::Signature(format!("bad public key: {e}")))?;
+    let signature = Signature::decode(signature).map_err(|e| Update::Signature(e.to_string()))?;
+    public_key
+        .verify(data, &signature, false)
+        .map_err(|e| Update::Signature(e.to_string()))?;
+    Ok(())
+}
+
+async fn download(url: &str, http: &HttpContext) -> Result<Vec<u8>> {
+    let request = http.client.get(url).header(USER_AGENT, CURL_UA);
+    let resp = http.send(request).await?.error_for_status()?;
+    Ok(resp.bytes().await?.to_vec())
+}
+
+// tar comes with Linux, macOS and Windows 10+ and unpacks both the .tar.gz and the .zip builds
+fn extract_binary(archive: &Path, directory: &Path) -> Result<PathBuf> {
+    let status = Command::new("tar")
+        .arg("-xf")
+        .arg(archive)
+        .arg("-C")
+        .arg(directory)
+        .status()
+        .map_err(|e| Update::Extract(e.to_string()))?;
+    if !status.success() {
+        return Err(Update::Extract(format!("tar exited with {status}")).into());
+    }
+
+    let name = if cfg!(windows) { "tbf.exe" } else { "tbf" };
+    let mut pending = vec![directory.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.file_name().is_some_and(|f| f == name) {
+                return Ok(path);
+            }
+        }
+    }
+    Err(Update::Extract(format!("there's no {name} in the archive")).into())
+}
+
+// the running binary can't be overwritten on Windows, but it can be renamed out of the way
+fn replace_binary(new: &Path) -> Result<PathBuf> {
+    let data = fs::read(new)?;
+    let current = env::current_exe()?;
+    let staged = current.with_extension("new");
+    let old = current.with_extension("old");
+    fs::write(&staged, &data)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+
+    let _ = fs::remove_file(&old);
+    fs::rename(&current, &old)?;
+    if let Err(e) = fs::rename(&staged, &current) {
+        fs::rename(&old, &current)?;
+        return Err(e.into());
+    }
+    // still in use on Windows, it gets cleaned up by the next update
+    let _ = fs::remove_file(&old);
+    Ok(current)
+}
+
+async fn install(
+    gh: &GithubUpdate,
+    triple: Option<&str>,
+    public_key: Option<&str>,
+    matches: &Cli,
+    http: &HttpContext,
+) -> Result<()> {
+    // installing a build for another platform would only leave a binary that doesn't start
+    let (kind, assets) = match_assets(&gh.assets, triple);
+    let archive = assets
+        .into_iter()
+        .find(|a| kind != AssetMatch::Any && is_archive(&a.name))
+        .ok_or(Update::NoAsset)?;
+    if kind == AssetMatch::Platform && !matches.simple {
+        println!(
+            "There's no build for {}, using {} (same OS and architecture)",
+            triple.unwrap_or_default(),
+            archive.name
+        );
+    }
+    let find = |suffix: &str| {
+        gh.assets
+            .iter()
+            .find(|a| a.name == format!("{}{suffix}", archive.name))
+    };
+
+    // nothing gets downloaded if the release can't be verified anyway
+    let checksum = find(CHECKSUM_SUFFIX).ok_or(Update::NoChecksum)?;
+    let signature = find(SIGNATURE_SUFFIX);
+    if public_key.is_some() && signature.is_none() {
+        return Err(Update::NoSignature)?;
+    }
+
+    if !confirm(
+        &t_with("question.install_update", &[("tag", &gh.tag_name)]),
+        true,
+        matches.yes,
+    ) {
+        return Ok(());
+    }
+    if !matches.simple {
+        println!("Downloading {}...", archive.name);
+    }
+    let data = download(&archive.browser_download_url, http).await?;
+    let checksums = String::from_utf8(download(&checksum.browser_download_url, http).await?)?;
+    verify_checksum(&data, &checksums, &archive.name)?;
+    if !matches.simple {
+        println!("The checksum matches");
+    }
+    match (public_key, signature) {
+        (Some(key), Some(signature)) => {
+            let signature =
+                String::from_utf8(download(&signature.browser_download_url, http).await?)?;
+            verify_signature(&data, &signature, key)?;
+            if !matches.simple {
+                println!("The signature is valid");
+            }
+        }
+        (None, Some(_)) if !matches.simple => {
+            println!("The release is signed, pass --public-key to check the signature too");
+        }
+        _ => (),
+    }
+
+    // a fresh directory only this user can get into, removed once it's dropped
+    let directory = TempDir::with_prefix("tbf-update-")?;
+    let archive_path = directory.path().join(&archive.name);
+    fs::write(&archive_path, &data)?;
+    let binary = extract_binary(&archive_path, directory.path())?;
+    let installed = replace_binary(&binary)?;
+
+    if !matches.simple {
+        println!("Installed {} to {}", gh.tag_name, installed.display());
+    }
+    Ok(())
+}
+
+// the release's version if it's newer than this build
+fn newer_version(tag: &str) -> Option<Version> {
+    let version = Version::parse(tag.strip_prefix('v').unwrap_or(tag)).ok()?;
+    (version > Version::parse(crate_version!()).ok()?).then_some(version)
+}
+
+fn load_update_check() -> Option<UpdateCheck> {
+    let path = data_dir()?.join(UPDATE_CHECK_FILE);
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn save_update_check(check: &UpdateCheck) {
+    let Some(dir) = data_dir() else {
+        return;
+    };
+    if let Ok(s) = serde_json::to_string(check) {
+        let _ = fs::write(dir.join(UPDATE_CHECK_FILE), s);
+    }
+}
+
+// a failed check counts as a check too, so GitHub being down doesn't get asked on every run
+fn needs_check(cached: Option<&UpdateCheck>, now: i64) -> bool {
+    cached.is_none_or(|c| now - c.checked_at >= UPDATE_CHECK_INTERVAL)
+}
+
+// the tag of a newer release, from the cache if it was checked during the last day; meant to
+// run in the background, nothing here is worth bothering the user with if it fails
+pub async fn background_update_check(http: HttpContext) -> Option<String> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let cached = load_update_check();
+    let latest = if needs_check(cached.as_ref(), now) {
+        let request = http
+            .client
+            .get(RELEASES_URL)
+            .header(USER_AGENT, CURL_UA)
+            .timeout(UPDATE_CHECK_TIMEOUT);
+        let latest = match http.send(request).await.and_then(|r| r.error_for_status()) {
+            Ok(r) => r.json::<GithubUpdate>().await.ok().map(|gh| gh.tag_name),
+            Err(_) => None,
+        };
+        save_update_check(&UpdateCheck {
+            checked_at: now,
+            latest: latest.clone(),
+        });
+        latest
+    } else {
+        cached.and_then(|c| c.latest)
+    };
+    latest.filter(|tag| newer_version(tag).is_some())
+}
+
+pub fn update_notice(tag: &str) -> String {
+    format!(
+        "tbf {tag} is out (this is v{}), run `tbf update --install` to get it",
+        crate_version!()
+    )
+}
+
+pub async fn update(
+    matches: Cli,
+    install_update: bool,
+    public_key: Option<&str>,
+    http: &HttpContext,
+) -> Result<()> {
     let target_triple = guess_host_triple();
     let current_version = crate_version!();
     let cur_version_parsed = Version::parse(current_version)?;
 
-    let resp = crate::HTTP_CLIENT
-        .get("https://api.github.com/repos/vyneer/tbf/releases/latest")
-        .header(USER_AGENT, CURL_UA)
-        .send()
-        .await;
+    let request = http.client.get(RELEASES_URL).header(USER_AGENT, CURL_UA);
+    let resp = http.send(request).await;
 
     let gh = match resp {
         Ok(r) if r.status().is_success() => {
@@ -59,19 +387,28 @@ pub async fn update(matches: Cli) -> Result<()> {
         match Version::parse(tag_name) {
             Ok(new_version_parsed) => {
                 if new_version_parsed > cur_version_parsed {
+                    if install_update {
+                        return install(&gh, target_triple, public_key, &matches, http).await;
+                    }
+                    let (kind, assets) = match_assets(&gh.assets, target_triple);
                     if !matches.simple {
                         println!("New version available ({}):", gh.tag_name);
-                    }
-                    for url in gh.assets {
-                        match target_triple {
-                            Some(triple) => {
-                                if url.browser_download_url.contains(triple) {
-                                    println!("{}", url.browser_download_url)
-                                }
-                            }
-                            None => println!("{}", url.browser_download_url),
+                        match (kind, target_triple) {
+                            (AssetMatch::Platform, Some(triple)) => println!(
+                                "There's no build for {triple}, these are for the same OS and architecture:"
+                            ),
+                            (AssetMatch::Any, Some(triple)) => println!(
+                                "There's no build for {triple}, pick the right one from all of them:"
+                            ),
+                            (AssetMatch::Any, None) => println!(
+                                "Couldn't detect the platform, pick the right build from all of them:"
+                            ),
+                            _ => (),
                         }
                     }
+                    for asset in assets {
+                        println!("{}", asset.browser_download_url);
+                    }
                 } else if !matches.simple {
                     println!("No updates available");
                 }
@@ -88,3 +425,146 @@ pub async fn update(matches: Cli) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        expected_checksum, match_assets, needs_check, newer_version, verify_checksum,
+        verify_signature, AssetMatch, GithubAssets, UpdateCheck,
+    };
+
+    const ARCHIVE: &str = "tbf-v0.15.0-x86_64-unknown-linux-gnu.tar.gz";
+    const DATA: &[u8] = b"tbf test archive\n";
+    const SHA256: &str = "942abe4efa4fc509a3ac248affb478f6f3990860e8a3d03264545d63646fdce1";
+    const PUBLIC_KEY: &str = "RWQBAgMEBQYHCAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQBAgMEBQYHCGKi9irC+Ab+e8sfaah+OUhez/mpsL9AXX+fMMfUKkE/IGwktr8fADT2JTgoDHxNfIspEG7OZAvxVI0iQ8U6wgw=
+trusted comment: timestamp:1700000000\tfile:tbf-v0.15.0-x86_64-unknown-linux-gnu.tar.gz
+uYO8qLqajf3RMaaPTYpOTxaaCkRfxcij8W5mXj0VBkgxcrGD+4NS24KtSYWZuVdVdUJ9gejCiSLKRKsWRpu0Cw==
+";
+
+    #[test]
+    fn checksums() {
+        let checksums = format!("{}  other.zip\n{SHA256} *{ARCHIVE}\n", "0".repeat(64));
+        assert_eq!(
+            expected_checksum(&checksums, ARCHIVE),
+            Some(SHA256.to_string()),
+            "testing the sha256sum format"
+        );
+        assert_eq!(
+            expected_checksum(SHA256, ARCHIVE),
+            Some(SHA256.to_string()),
+            "testing a lone hash"
+        );
+        assert!(
+            verify_checksum(DATA, &checksums, ARCHIVE).is_ok(),
+            "testing a matching checksum"
+        );
+        assert!(
+            verify_checksum(b"tampered", &checksums, ARCHIVE).is_err(),
+            "testing a checksum mismatch"
+        );
+        assert!(
+            verify_checksum(DATA, "", ARCHIVE).is_err(),
+            "testing a missing checksum"
+        );
+    }
+
+    #[test]
+    fn asset_matching() {
+        let assets: Vec<GithubAssets> = [
+            "tbf-v0.15.0-x86_64-unknown-linux-musl.tar.gz",
+            "tbf-v0.15.0-x86_64-unknown-linux-musl.tar.gz.sha256",
+            "tbf-v0.15.0-x86_64-pc-windows-msvc.zip",
+            "tbf-v0.15.0-aarch64-apple-darwin.tar.gz",
+        ]
+        .iter()
+        .map(|name| GithubAssets {
+            name: name.to_string(),
+            browser_download_url: format!(
+                "https://github.com/vyneer/tbf/releases/download/v0.15.0/{name}"
+            ),
+        })
+        .collect();
+        let names = |triple| {
+            let (kind, assets) = match_assets(&assets, triple);
+            (
+                kind,
+                assets.iter().map(|a| a.name.as_str()).collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(
+            names(Some("aarch64-apple-darwin")),
+            (
+                AssetMatch::Exact,
+                vec!["tbf-v0.15.0-aarch64-apple-darwin.tar.gz"]
+            ),
+            "testing an exact match"
+        );
+        assert_eq!(
+            names(Some("x86_64-unknown-linux-gnu")),
+            (
+                AssetMatch::Platform,
+                vec!["tbf-v0.15.0-x86_64-unknown-linux-musl.tar.gz"]
+            ),
+            "testing a gnu host with only a musl build"
+        );
+        assert_eq!(
+            names(Some("x86_64-pc-windows-gnu")),
+            (
+                AssetMatch::Platform,
+                vec!["tbf-v0.15.0-x86_64-pc-windows-msvc.zip"]
+            ),
+            "testing a gnu host with only an msvc build"
+        );
+        assert_eq!(
+            names(Some("aarch64-unknown-linux-gnu")).0,
+            AssetMatch::Any,
+            "testing a platform without a build"
+        );
+        assert_eq!(
+            names(None).1.len(),
+            3,
+            "testing an unknown platform, without the checksums"
+        );
+    }
+
+    #[test]
+    fn update_checks() {
+        let cached = UpdateCheck {
+            checked_at: 1700000000,
+            latest: None,
+        };
+        assert!(needs_check(None, 1700000000), "testing no cached check");
+        assert!(
+            !needs_check(Some(&cached), 1700000000 + 3600),
+            "testing a check from an hour ago"
+        );
+        assert!(
+            needs_check(Some(&cached), 1700000000 + 86400),
+            "testing a check from a day ago"
+        );
+        assert!(
+            newer_version("v999.0.0").is_some() && newer_version("v0.0.1").is_none(),
+            "testing the version comparison"
+        );
+        assert!(newer_version("nightly").is_none(), "testing a broken tag");
+    }
+
+    #[test]
+    fn signatures() {
+        assert!(
+            verify_signature(DATA, SIGNATURE, PUBLIC_KEY).is_ok(),
+            "testing a valid signature"
+        );
+        assert!(
+            verify_signature(b"tampered", SIGNATURE, PUBLIC_KEY).is_err(),
+            "testing a signature over different data"
+        );
+        assert!(
+            verify_signature(DATA, SIGNATURE, "not a key").is_err(),
+            "testing a broken public key"
+        );
+    }
+}
@@ -1,63 +1,219 @@
-use anyhow::Result;
-use lazy_static::lazy_static;
+use anyhow::{anyhow, Result};
+use clap::crate_name;
+use colored::Colorize;
 use log::{debug, info, warn};
+use once_cell::sync::OnceCell;
 use rand::prelude::*;
-use regex::Regex;
-use reqwest::{header::USER_AGENT, StatusCode};
-use scraper::{Html, Selector};
-use serde::Deserialize;
-use std::{fs::File, io::Read, path::Path, thread::sleep, time::Duration};
-use time::{
-    format_description::well_known::Rfc3339, macros::format_description, PrimitiveDateTime,
+use reqwest::{
+    header::{HeaderMap, COOKIE, SET_COOKIE, USER_AGENT},
+    StatusCode,
+};
+use scraper::Html;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{stdin, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
 };
 use url::Url;
 
 use super::config::{Cli, ProcessingType, CURL_UA};
 use crate::error::DeriveDate;
+use crate::http::HttpContext;
+use crate::tracker::{find_provider, supported_hosts, URLData};
 use crate::twitch::models::CDN_URLS;
 
-lazy_static! {
-    static ref RE_UNIX: Regex = Regex::new(r"^\d*$").unwrap();
-    static ref RE_UTC: Regex = Regex::new("UTC").unwrap();
+const SESSION_FILE: &str = "sessions.json";
+const FORBIDDEN_COOLDOWN_THRESHOLD: usize = 2;
+const FORBIDDEN_COOLDOWN_MS: (u64, u64) = (2000, 6000);
+const REMOTE_CDN_DIR: &str = "cdnfiles";
+// how long a fetched remote cdnfile gets used before it's fetched again
+const REMOTE_CDN_FILE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// a CDN in the cdnfile - just the host or a table with the settings
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum CDNEntry {
+    Host(String),
+    Settings(CDNSettings),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct CDNSettings {
+    pub host: String,
+    // the CDNs with a higher priority get checked first
+    #[serde(default)]
+    pub priority: i32,
+    // orders the CDNs of the same priority, e.g. by how often they had the VOD
+    #[serde(default = "default_cdn_weight")]
+    pub weight: u32,
+    // a dead CDN can stay in the file without getting checked
+    #[serde(default = "default_cdn_enabled")]
+    pub enabled: bool,
+}
+
+fn default_cdn_weight() -> u32 {
+    1
+}
+
+fn default_cdn_enabled() -> bool {
+    true
 }
 
-#[derive(Debug, PartialEq)]
-pub struct URLData {
-    pub username: String,
-    pub broadcast_id: String,
-    pub start_date: String,
-    pub end_date: Option<String>,
+impl CDNSettings {
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            priority: 0,
+            weight: default_cdn_weight(),
+            enabled: default_cdn_enabled(),
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
+impl From<CDNEntry> for CDNSettings {
+    fn from(entry: CDNEntry) -> Self {
+        match entry {
+            CDNEntry::Host(host) => CDNSettings::new(&host),
+            CDNEntry::Settings(settings) => settings,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct CDNFile {
-    cdns: Vec<String>,
+    pub cdns: Vec<CDNEntry>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct StreamsChartsTwitchClip {
-    started_at: String,
-    ended_at: String,
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScrapeSession {
+    useragent: String,
+    cookies: String,
 }
-#[derive(Debug, PartialEq)]
-pub struct ExtractedTimestamps {
-    processing_type: ProcessingType,
-    start_timestamp: i64,
-    end_timestamp: i64,
+
+// lets a warning through at most once per interval, counting the ones that got swallowed
+pub struct WarningLimiter {
+    interval: Duration,
+    last: Mutex<Option<Instant>>,
+    suppressed: AtomicUsize,
+}
+
+impl WarningLimiter {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            last: Mutex::new(None),
+            suppressed: AtomicUsize::new(0),
+        }
+    }
+
+    // returns the amount of warnings suppressed since the last one if this one should be shown
+    pub fn check(&self) -> Option<usize> {
+        let mut last = self.last.lock().unwrap();
+        match *last {
+            Some(instant) if instant.elapsed() < self.interval => {
+                self.suppressed.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            _ => {
+                *last = Some(Instant::now());
+                Some(self.suppressed.swap(0, Ordering::Relaxed))
+            }
+        }
+    }
+}
+
+// --urls-only, stdout only gets the URLs then and everything else goes to stderr
+static URLS_ONLY: AtomicBool = AtomicBool::new(false);
+
+pub fn set_urls_only(urls_only: bool) {
+    URLS_ONLY.store(urls_only, Ordering::Relaxed);
+}
+
+pub fn urls_only() -> bool {
+    URLS_ONLY.load(Ordering::Relaxed)
 }
 
 pub fn info(text: String, simple: bool) {
-    if simple {
+    if urls_only() {
+        eprintln!("{text}");
+    } else if simple {
         println!("{text}");
     } else {
         info!("{text}");
     }
 }
 
-pub async fn get_useragent_list() -> Vec<String> {
-    let resp = crate::HTTP_CLIENT
-        .get("https://jnrbsn.github.io/user-agents/user-agents.json")
-        .send()
+// a found URL, the one thing that still goes to stdout with --urls-only
+pub fn url(url: &str, simple: bool) {
+    if simple || urls_only() {
+        println!("{url}");
+    } else {
+        info!("{url}");
+    }
+}
+
+// the questions and prompts, kept off stdout with --urls-only so they don't end up in a pipe
+pub fn prompt(text: &str) {
+    if urls_only() {
+        eprintln!("{}", text.bright_blue());
+    } else {
+        println!("{}", text.bright_blue());
+    }
+}
+
+fn parse_answer(answer: &str, default: bool) -> bool {
+    match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" => true,
+        _ => false,
+    }
+}
+
+// a yes/no question, --yes answers it without asking and an empty answer (or a closed stdin)
+// goes with the default
+pub fn confirm(question: &str, default: bool, assume_yes: bool) -> bool {
+    let hint = if default { "(Y/n)" } else { "(y/N)" };
+    if assume_yes {
+        debug!("{question} {hint} - answered yes by --yes");
+        return true;
+    }
+    prompt(&format!("{question} {hint}"));
+    let mut answer = String::new();
+    match stdin().read_line(&mut answer) {
+        Ok(_) => parse_answer(&answer, default),
+        Err(_) => default,
+    }
+}
+
+// the list barely changes, so one successful fetch per process is enough
+static USERAGENT_CACHE: OnceCell<Vec<String>> = OnceCell::new();
+
+pub async fn get_useragent_list(http: &HttpContext) -> Vec<String> {
+    if let Some(cached) = USERAGENT_CACHE.get() {
+        return cached.clone();
+    }
+
+    let useragents = fetch_useragent_list(http).await;
+    if !useragents.is_empty() {
+        let _ = USERAGENT_CACHE.set(useragents.clone());
+    }
+    useragents
+}
+
+async fn fetch_useragent_list(http: &HttpContext) -> Vec<String> {
+    let resp = http
+        .send(
+            http.client
+                .get("https://jnrbsn.github.io/user-agents/user-agents.json"),
+        )
         .await;
 
     match resp {
@@ -75,352 +231,441 @@ pub async fn get_useragent_list() -> Vec<String> {
     }
 }
 
-pub async fn get_random_useragent() -> String {
-    let ua_vector = get_useragent_list().await;
+// waits for the given delay plus up to 50% of random jitter without blocking the runtime
+pub async fn sleep_with_jitter(base: Duration) {
+    let max_jitter = base.as_millis() as u64 / 2;
+    let jitter = if max_jitter > 0 {
+        rand::rng().random_range(0..=max_jitter)
+    } else {
+        0
+    };
+    tokio::time::sleep(base + Duration::from_millis(jitter)).await;
+}
+
+// the state that has to survive, the metrics, the feed and the update check
+pub fn data_dir() -> Option<PathBuf> {
+    app_dir(dirs::data_dir()?)
+}
+
+// what can always be fetched again, the remote cdnfiles and the scraping sessions
+pub fn cache_dir() -> Option<PathBuf> {
+    app_dir(dirs::cache_dir()?)
+}
+
+fn app_dir(base: PathBuf) -> Option<PathBuf> {
+    let dir = base.join(crate_name!());
+    fs::create_dir_all(&dir).ok()?;
+    Some(dir)
+}
+
+fn load_sessions() -> HashMap<String, ScrapeSession> {
+    cache_dir()
+        .and_then(|dir| fs::read_to_string(dir.join(SESSION_FILE)).ok())
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn write_sessions(sessions: &HashMap<String, ScrapeSession>) {
+    let Some(dir) = cache_dir() else {
+        return;
+    };
+    match serde_json::to_string_pretty(sessions) {
+        Ok(s) => {
+            if let Err(e) = fs::write(dir.join(SESSION_FILE), s) {
+                debug!("Couldn't save the scraping sessions - {e}");
+            }
+        }
+        Err(e) => debug!("Couldn't serialize the scraping sessions - {e}"),
+    }
+}
+
+fn save_session(host: &str, session: ScrapeSession) {
+    let mut sessions = load_sessions();
+    sessions.insert(host.to_string(), session);
+    write_sessions(&sessions);
+}
 
-    if !ua_vector.is_empty() {
-        if let Some(ua) = ua_vector.choose(&mut rand::rng()) {
-            return ua.clone();
+fn forget_session(host: &str) {
+    let mut sessions = load_sessions();
+    if sessions.remove(host).is_some() {
+        write_sessions(&sessions);
+    }
+}
+
+// merges the name=value pairs from the Set-Cookie headers into the existing cookie string
+fn merge_cookies(existing: &str, headers: &HeaderMap) -> String {
+    let mut cookies: Vec<(String, String)> = existing
+        .split("; ")
+        .filter_map(|c| c.split_once('='))
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    for value in headers.get_all(SET_COOKIE) {
+        let Ok(value) = value.to_str() else {
+            continue;
+        };
+        let pair = value.split(';').next().unwrap_or_default().trim();
+        if let Some((k, v)) = pair.split_once('=') {
+            match cookies.iter_mut().find(|(name, _)| name == k) {
+                Some(cookie) => cookie.1 = v.to_string(),
+                None => cookies.push((k.to_string(), v.to_string())),
+            }
         }
     }
 
-    CURL_UA.to_string()
+    cookies
+        .into_iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<_>>()
+        .join("; ")
 }
 
-async fn process_url(url: &str) -> Result<Html> {
-    let ua = get_random_useragent().await;
-    debug!("Using UA - {ua}");
+async fn process_url(url: &str, http: &HttpContext) -> Result<Html> {
+    let host = Url::parse(url)?
+        .host_str()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    // try the last session that got through first, then a fresh UA on every attempt
+    let saved_session = load_sessions().remove(&host);
+    let mut useragents = get_useragent_list(http).await;
+    useragents.shuffle(&mut rand::rng());
+    if useragents.is_empty() {
+        useragents.push(CURL_UA.to_string());
+    }
 
-    let mut attempts = 0;
-    let max_attempts = 2;
+    let policy = http.retry_policy();
+    let attempts = policy.attempts.max(1);
+    let mut consecutive_forbidden = 0;
+    let mut session = saved_session.clone().unwrap_or_default();
 
-    loop {
-        attempts += 1;
-        let resp = crate::HTTP_CLIENT
-            .get(url)
-            .header(USER_AGENT, &ua)
-            .send()
-            .await;
+    for attempt in 1..=attempts {
+        if attempt > 1 || saved_session.is_none() {
+            session.useragent = useragents[(attempt - 1) % useragents.len()].clone();
+        }
+        debug!("Using UA - {}", session.useragent);
+
+        let mut request = http.client.get(url).header(USER_AGENT, &session.useragent);
+        if !session.cookies.is_empty() {
+            request = request.header(COOKIE, &session.cookies);
+        }
 
-        match resp {
+        match http.send(request).await {
             Ok(r) => {
-                if r.status() == StatusCode::FORBIDDEN && attempts < max_attempts {
-                    warn!("Got a 403 on attempt #{attempts}");
-                    sleep(Duration::from_millis(50));
+                session.cookies = merge_cookies(&session.cookies, r.headers());
+
+                if r.status() == StatusCode::FORBIDDEN && attempt < attempts {
+                    warn!("Got a 403 on attempt #{attempt}");
+                    if attempt == 1 && saved_session.is_some() {
+                        forget_session(&host);
+                    }
+
+                    consecutive_forbidden += 1;
+                    if consecutive_forbidden >= FORBIDDEN_COOLDOWN_THRESHOLD {
+                        let cooldown = Duration::from_millis(
+                            rand::rng()
+                                .random_range(FORBIDDEN_COOLDOWN_MS.0..=FORBIDDEN_COOLDOWN_MS.1),
+                        );
+                        warn!(
+                            "Got {consecutive_forbidden} 403s in a row, cooling down for {:.1}s",
+                            cooldown.as_secs_f32()
+                        );
+                        tokio::time::sleep(cooldown).await;
+                        consecutive_forbidden = 0;
+                    } else {
+                        policy.wait(attempt).await;
+                    }
+                    continue;
+                }
+
+                let status = r.status();
+                if (status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error())
+                    && attempt < attempts
+                {
+                    warn!("Got a {status} on attempt #{attempt}");
+                    consecutive_forbidden = 0;
+                    policy.wait(attempt).await;
                     continue;
                 }
 
                 let resp = r.error_for_status()?;
                 let body = resp.text().await?;
+                save_session(&host, session);
                 return Ok(Html::parse_document(&body));
             }
             Err(e) => {
-                if attempts < max_attempts {
-                    warn!("Request failed on attempt #{attempts}: {e}");
-                    sleep(Duration::from_millis(50));
+                if attempt < attempts {
+                    warn!("Request failed on attempt #{attempt}: {e}");
+                    consecutive_forbidden = 0;
+                    policy.wait(attempt).await;
                     continue;
                 }
                 return Err(e)?;
             }
         }
     }
+
+    unreachable!("the last attempt always returns")
 }
 
-pub async fn derive_date_from_url(url: &str, flags: Cli) -> Result<(ProcessingType, URLData)> {
+pub async fn derive_date_from_url(
+    url: &str,
+    flags: Cli,
+    http: &HttpContext,
+) -> Result<(ProcessingType, URLData)> {
     let resolved_url = Url::parse(url)?;
-    let domain = resolved_url.domain().ok_or_else(|| {
-        DeriveDate::WrongURL(
-            "Only twitchtracker.com and streamscharts.com URLs are supported".to_string(),
-        )
-    })?;
-
-    match domain.to_lowercase().as_str() {
-        "twitchtracker.com" | "www.twitchtracker.com" => {
-            let segments: Vec<_> = resolved_url
-                .path_segments()
-                .map(|c| c.collect())
-                .ok_or(DeriveDate::SegmentMap)?;
-
-            if segments.len() != 3 || segments[1] != "streams" {
-                return Err(DeriveDate::WrongURL(
-                    "Not a valid TwitchTracker VOD URL".to_string(),
-                ))?;
-            }
-
-            let username = segments[0];
-            let broadcast_id = segments[2];
-            let fragment = process_url(url).await?;
-            let selector = Selector::parse(".stream-timestamp-dt.to-dowdatetime")
-                .map_err(|_| DeriveDate::Selector)?;
+    let provider = resolved_url
+        .host_str()
+        .and_then(find_provider)
+        .ok_or_else(|| {
+            DeriveDate::WrongURL(format!("Only {} URLs are supported", supported_hosts()))
+        })?;
+
+    let (username, broadcast_id) = provider.parse(&resolved_url)?;
+    let fragment = process_url(url, http).await?;
+    let extracted = provider.extract(&fragment, flags.mode.clone(), flags.simple)?;
+    let processing_type = extracted.processing_type.clone();
+    let mut data = extracted.into_url_data(username, broadcast_id);
+    data.stream_type = provider.stream_type(&fragment);
+
+    Ok((processing_type, data))
+}
 
-            let date = fragment
-                .select(&selector)
-                .next()
-                .ok_or(DeriveDate::ScraperElement)?
-                .text()
-                .collect::<String>();
+// the stream pages of the channel's recent streams on the tracker
+pub async fn list_channel_streams(
+    username: &str,
+    tracker: &str,
+    http: &HttpContext,
+) -> Result<Vec<String>> {
+    let provider = find_provider(tracker)
+        .ok_or_else(|| DeriveDate::WrongURL(format!("Only {} are supported", supported_hosts())))?;
+    let page = process_url(&provider.streams_url(username), http).await?;
+    Ok(provider
+        .list_streams(&page, username)
+        .into_iter()
+        .map(|id| provider.stream_url(username, &id))
+        .collect())
+}
 
-            Ok((
-                ProcessingType::Exact,
-                URLData {
-                    username: username.to_string(),
-                    broadcast_id: broadcast_id.to_string(),
-                    start_date: date,
-                    end_date: None,
-                },
-            ))
+// a host per line of a text file, optionally followed by priority=<n>, weight=<n> and
+// enabled=<bool> or just disabled; lines starting with # are comments
+fn parse_cdn_line(line: &str) -> Result<CDNSettings> {
+    let mut words = line.split_whitespace();
+    let mut cdn = CDNSettings::new(words.next().unwrap_or_default());
+    for word in words {
+        match word.split_once('=') {
+            Some(("priority", value)) => cdn.priority = value.parse()?,
+            Some(("weight", value)) => cdn.weight = value.parse()?,
+            Some(("enabled", value)) => cdn.enabled = value.parse()?,
+            None if word == "disabled" => cdn.enabled = false,
+            _ => return Err(anyhow!("unknown setting {word} for {}", cdn.host)),
         }
-        "streamscharts.com" | "www.streamscharts.com" => {
-            let segments: Vec<_> = resolved_url
-                .path_segments()
-                .map(|c| c.collect())
-                .ok_or(DeriveDate::SegmentMap)?;
-
-            if segments.len() != 4 || segments[0] != "channels" || segments[2] != "streams" {
-                return Err(DeriveDate::WrongURL(
-                    "Not a valid StreamsCharts VOD URL".to_string(),
-                ))?;
-            }
-
-            let username = segments[1];
-            let broadcast_id = segments[3];
-            let fragment = process_url(url).await?;
-
-            let extracted_results = match flags.mode {
-                Some(ProcessingType::Bruteforce) => {
-                    if !flags.simple {
-                        info!("Bruteforcing for timestamps...");
-                    }
-                    sc_bruteforce_timestamps(&fragment)?
-                }
-                Some(ProcessingType::Exact) => {
-                    if !flags.simple {
-                        info!("Extracting exact timestamps...");
-                    }
-                    sc_extract_exact_timestamps(&fragment)?
-                }
-                None => {
-                    if !flags.simple {
-                        info!("Extracting exact timestamps...");
-                    }
-                    sc_extract_exact_timestamps(&fragment).or_else(|_| {
-                        if !flags.simple {
-                            info!("Bruteforcing for timestamps...");
-                        }
-                        sc_bruteforce_timestamps(&fragment)
-                    })?
-                }
-            };
-
-            if !flags.simple {
-                let approximate_or_exact = match extracted_results.processing_type {
-                    ProcessingType::Exact => "exact",
-                    ProcessingType::Bruteforce => "approximate",
-                };
-                info!(
-                    "Found {} timestamps for the stream. Started at {} and ended at {}.",
-                    approximate_or_exact,
-                    extracted_results.start_timestamp,
-                    extracted_results.end_timestamp
-                );
-            }
+    }
+    Ok(cdn)
+}
 
-            Ok((
-                extracted_results.processing_type,
-                URLData {
-                    username: username.to_string(),
-                    broadcast_id: broadcast_id.to_string(),
-                    start_date: extracted_results.start_timestamp.to_string(),
-                    end_date: Some(extracted_results.end_timestamp.to_string()),
-                },
-            ))
+// every CDN in the text, the disabled ones included; the extension decides the format
+fn parse_cdn_file(cdn_string: &str, extension: Option<&str>) -> Result<Vec<CDNSettings>> {
+    let cdn_file = match extension {
+        Some("json") => serde_json::from_str::<CDNFile>(cdn_string).map_err(|e| {
+            anyhow!("Couldn't parse the CDN list file: invalid JSON - {e:#?}")
+        })?,
+        Some("toml") => toml::from_str::<CDNFile>(cdn_string).map_err(|e| {
+            anyhow!("Couldn't parse the CDN list file: invalid TOML - {e:#?}")
+        })?,
+        Some("yaml") | Some("yml") => serde_yaml::from_str::<CDNFile>(cdn_string).map_err(|e| {
+            anyhow!("Couldn't parse the CDN list file: invalid YAML - {e:#?}")
+        })?,
+        Some("txt") | None => {
+            return cdn_string
+                .lines()
+                .map(|line| line.trim())
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(|line| {
+                    parse_cdn_line(line)
+                        .map_err(|e| anyhow!("Couldn't parse the CDN list file: {e}"))
+                })
+                .collect();
         }
-        _ => Err(DeriveDate::WrongURL(
-            "Only twitchtracker.com and streamscharts.com URLs are supported".to_string(),
-        ))?,
-    }
+        _ => return Err(anyhow!("Couldn't parse the CDN list file: it must either be a text file, a JSON file, a TOML file or a YAML file.")),
+    };
+
+    Ok(cdn_file.cdns.into_iter().map(CDNSettings::from).collect())
 }
 
-pub fn parse_timestamp(timestamp: &str) -> Result<i64> {
-    let format_with_utc = format_description!("[year]-[month]-[day] [hour]:[minute]:[second] UTC");
-    let format_wo_utc = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
-    let format_wo_sec = format_description!("[day]-[month]-[year] [hour]:[minute]");
+fn cdn_file_extension(path: &str) -> Option<&str> {
+    Path::new(path).extension().and_then(|ext| ext.to_str())
+}
 
-    if RE_UNIX.is_match(timestamp) {
-        Ok(timestamp.parse::<i64>()?)
-    } else if RE_UTC.is_match(timestamp) {
-        let dt = PrimitiveDateTime::parse(timestamp, format_with_utc)?;
-        Ok(dt.assume_utc().unix_timestamp())
-    } else {
-        // Try parsing as RFC3339 first
-        if let Ok(result) = PrimitiveDateTime::parse(timestamp, &Rfc3339) {
-            return Ok(result.assume_utc().unix_timestamp());
-        }
+// every CDN in the file, the disabled ones included
+pub fn read_cdn_file(cdn_file_path: &str) -> Result<Vec<CDNSettings>> {
+    let mut file = File::open(cdn_file_path)
+        .map_err(|e| anyhow!("Couldn't open the CDN config file - {e:#?}"))?;
 
-        // Try parsing without UTC
-        if let Ok(result) = PrimitiveDateTime::parse(timestamp, format_wo_utc) {
-            return Ok(result.assume_utc().unix_timestamp());
-        }
+    let mut cdn_string = String::new();
+    file.read_to_string(&mut cdn_string)
+        .map_err(|e| anyhow!("Couldn't read the CDN config file - {e:#?}"))?;
 
-        // Try parsing without seconds
-        let result = PrimitiveDateTime::parse(timestamp, format_wo_sec)?;
-        Ok(result.assume_utc().unix_timestamp())
-    }
+    parse_cdn_file(&cdn_string, cdn_file_extension(cdn_file_path))
 }
 
-pub fn compile_cdn_list(cdn_file_path: Option<String>) -> Vec<String> {
-    let mut cdn_urls: Vec<String> = CDN_URLS.iter().map(|s| s.to_string()).collect();
+// the shared lists in gists and repos, a http(s) URL instead of a path
+pub fn remote_cdn_file(cdn_file: &str) -> Option<Url> {
+    Url::parse(cdn_file)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
 
-    let cdn_file_path = match cdn_file_path {
-        Some(path) => path,
-        None => return cdn_urls,
-    };
+// the cached copy of a remote cdnfile, named after the URL and keeping its extension so it gets
+// parsed the same way
+fn remote_cdn_cache(url: &Url) -> Option<PathBuf> {
+    let dir = cache_dir()?.join(REMOTE_CDN_DIR);
+    fs::create_dir_all(&dir).ok()?;
+    let name = format!("{:x}", Sha1::digest(url.as_str().as_bytes()));
+    Some(match cdn_file_extension(url.path()) {
+        Some(extension) => dir.join(format!("{name}.{extension}")),
+        None => dir.join(name),
+    })
+}
 
-    let file_extension = Path::new(&cdn_file_path).extension();
+fn is_fresh(path: &Path, ttl: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < ttl)
+}
 
-    let mut file = match File::open(&cdn_file_path) {
-        Ok(f) => f,
-        Err(e) => {
-            info!("Couldn't open the CDN config file - {e:#?}");
-            return cdn_urls;
+// a cached copy younger than the TTL gets used as is, otherwise the list gets fetched again and
+// the cache updated; when that fails (offline, the gist is gone) the stale copy still does
+async fn fetch_cdn_file(
+    url: &Url,
+    cache: Option<PathBuf>,
+    http: &HttpContext,
+) -> Result<Vec<CDNSettings>> {
+    let cached = cache.as_ref().filter(|path| path.exists());
+    if let Some(path) = cached.filter(|path| is_fresh(path, REMOTE_CDN_FILE_TTL)) {
+        match read_cdn_file(&path.to_string_lossy()) {
+            Ok(cdns) => return Ok(cdns),
+            Err(e) => debug!("Couldn't use the cached CDN list of {url} - {e}"),
         }
-    };
+    }
 
-    let mut cdn_string = String::new();
-    if let Err(e) = file.read_to_string(&mut cdn_string) {
-        info!("Couldn't read the CDN config file - {e:#?}");
-        return cdn_urls;
+    let fetched = async {
+        let text = http
+            .send_with_retry(http.client.get(url.as_str()))
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let cdns = parse_cdn_file(&text, cdn_file_extension(url.path()))?;
+        Ok::<_, anyhow::Error>((text, cdns))
     }
+    .await;
 
-    let new_cdns = match file_extension.and_then(|ext| ext.to_str()) {
-        Some("json") => match serde_json::from_str::<CDNFile>(&cdn_string) {
-            Ok(cdn_file) => cdn_file.cdns,
-            Err(e) => {
-                info!("Couldn't parse the CDN list file: invalid JSON - {e:#?}");
-                return cdn_urls;
-            }
-        },
-        Some("toml") => match toml::from_str::<CDNFile>(&cdn_string) {
-            Ok(cdn_file) => cdn_file.cdns,
-            Err(e) => {
-                info!("Couldn't parse the CDN list file: invalid TOML - {e:#?}");
-                return cdn_urls;
-            }
-        },
-        Some("yaml") | Some("yml") => match serde_yaml::from_str::<CDNFile>(&cdn_string) {
-            Ok(cdn_file) => cdn_file.cdns,
-            Err(e) => {
-                info!("Couldn't parse the CDN list file: invalid YAML - {e:#?}");
-                return cdn_urls;
+    match (fetched, cached) {
+        (Ok((text, cdns)), _) => {
+            if let Some(path) = &cache {
+                if let Err(e) = fs::write(path, text) {
+                    debug!("Couldn't cache the CDN list of {url} - {e}");
+                }
             }
-        },
-        Some("txt") | None => cdn_string
-            .lines()
-            .map(|line| line.trim().to_string())
-            .filter(|line| !line.is_empty())
-            .collect(),
-        _ => {
-            info!("Couldn't parse the CDN list file: it must either be a text file, a JSON file, a TOML file or a YAML file.");
-            return cdn_urls;
+            Ok(cdns)
         }
-    };
-
-    cdn_urls.extend(new_cdns);
-    cdn_urls.sort_unstable();
-    cdn_urls.dedup();
-
-    if cdn_urls.len() != CDN_URLS.len() {
-        debug!(
-            "Compiled the new CDN list - initial length: {}, new length: {}",
-            CDN_URLS.len(),
-            cdn_urls.len()
-        );
-    } else {
-        debug!(
-            "No new CDNs added - initial length: {}, new length: {}",
-            CDN_URLS.len(),
-            cdn_urls.len()
-        );
+        (Err(e), Some(path)) => {
+            info!("Couldn't fetch the CDN list from {url}, using the cached copy - {e:#}");
+            read_cdn_file(&path.to_string_lossy())
+        }
+        (Err(e), None) => Err(anyhow!("Couldn't fetch the CDN list from {url} - {e:#}")),
     }
-
-    cdn_urls
-}
-
-fn sc_extract_exact_timestamps(html_fragment: &Html) -> Result<ExtractedTimestamps> {
-    let exact_dt_selector =
-        Selector::parse("div > div[data-requests]").map_err(|_| DeriveDate::Selector)?;
-
-    let element = html_fragment
-        .select(&exact_dt_selector)
-        .next()
-        .ok_or(DeriveDate::ScraperElement)?;
-
-    let data_requests = element
-        .value()
-        .attr("data-requests")
-        .ok_or(DeriveDate::ScraperAttribute)?;
-
-    // Parse the clips_json into the struct StreamsChartsTwitchClip with serde_json
-    let clips_payloads: Vec<StreamsChartsTwitchClip> = serde_json::from_str(data_requests)?;
-
-    let first_clip = clips_payloads
-        .first()
-        .ok_or_else(|| DeriveDate::WrongURL("No clips found in data".to_string()))?;
-
-    let last_clip = clips_payloads
-        .last()
-        .ok_or_else(|| DeriveDate::WrongURL("No clips found in data".to_string()))?;
-
-    let start_dt = parse_timestamp(&first_clip.started_at)?;
-    let end_dt = parse_timestamp(&last_clip.ended_at)?;
-
-    Ok(ExtractedTimestamps {
-        processing_type: ProcessingType::Exact,
-        start_timestamp: start_dt,
-        end_timestamp: end_dt,
-    })
 }
 
-fn sc_bruteforce_timestamps(html_fragment: &Html) -> Result<ExtractedTimestamps> {
-    let bruteforce_selector = Selector::parse("time").map_err(|_| DeriveDate::Selector)?;
-
-    let element = html_fragment
-        .select(&bruteforce_selector)
-        .next()
-        .ok_or(DeriveDate::ScraperElement)?;
-
-    let datetime_attr = element
-        .value()
-        .attr("datetime")
-        .ok_or(DeriveDate::ScraperAttribute)?;
-
-    let date_parsed = parse_timestamp(datetime_attr)?;
+// the built-in CDNs and the ones from the file or URL in the order they should be checked - by
+// priority, then weight, then host; a file entry for a built-in CDN overrides its settings
+pub async fn compile_cdn_list(cdn_file: Option<String>, http: &HttpContext) -> Vec<String> {
+    let mut cdns: Vec<CDNSettings> = CDN_URLS.iter().map(|s| CDNSettings::new(s)).collect();
+
+    if let Some(cdn_file) = cdn_file {
+        let entries = match remote_cdn_file(&cdn_file) {
+            Some(url) => fetch_cdn_file(&url, remote_cdn_cache(&url), http).await,
+            None => read_cdn_file(&cdn_file),
+        };
+        match entries {
+            Ok(entries) => {
+                for entry in entries {
+                    match cdns.iter_mut().find(|cdn| cdn.host == entry.host) {
+                        Some(cdn) => *cdn = entry,
+                        None => cdns.push(entry),
+                    }
+                }
+            }
+            Err(e) => info!("{e}"),
+        }
+    }
 
-    Ok(ExtractedTimestamps {
-        processing_type: ProcessingType::Bruteforce,
-        start_timestamp: date_parsed - 60,
-        end_timestamp: date_parsed + 60,
-    })
+    let total = cdns.len();
+    cdns.retain(|cdn| cdn.enabled);
+    cdns.sort_by(|a, b| {
+        b.priority
+            .cmp(&a.priority)
+            .then(b.weight.cmp(&a.weight))
+            .then(a.host.cmp(&b.host))
+    });
+
+    debug!(
+        "Compiled the CDN list - built-in: {}, total: {}, disabled: {}",
+        CDN_URLS.len(),
+        total,
+        total - cdns.len()
+    );
+
+    cdns.into_iter().map(|cdn| cdn.host).collect()
 }
 
 #[cfg(test)]
 mod tests {
     use reqwest::header::USER_AGENT;
-    use std::fs::File;
+    use std::fs::{self, File};
     use std::io::Write;
-    use std::thread::sleep;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
     use tempfile::tempdir;
+    use url::Url;
 
     use crate::config::Cli;
+    use crate::http::{Fixtures, HttpContext};
+    use crate::tracker::StreamType;
     use crate::twitch::models::CDN_URLS;
 
     use super::{
-        compile_cdn_list, derive_date_from_url, get_useragent_list, parse_timestamp,
-        ProcessingType, URLData,
+        compile_cdn_list, derive_date_from_url, fetch_cdn_file, get_useragent_list, parse_answer,
+        read_cdn_file, remote_cdn_file, ProcessingType, URLData, WarningLimiter,
     };
 
     #[test]
-    fn compile_cdns() {
+    fn answers() {
+        assert!(parse_answer("\n", true), "testing the default yes");
+        assert!(!parse_answer("", false), "testing the default no");
+        assert!(parse_answer(" Yes\r\n", false), "testing a yes");
+        assert!(!parse_answer("n\n", true), "testing a no");
+        assert!(!parse_answer("maybe", true), "testing anything else");
+    }
+
+    #[test]
+    fn warning_limiter() {
+        let limiter = WarningLimiter::new(Duration::from_secs(60));
+        assert_eq!(limiter.check(), Some(0), "testing first warning");
+        assert_eq!(limiter.check(), None, "testing suppressed warning");
+        assert_eq!(limiter.check(), None, "testing suppressed warning");
+
+        let limiter = WarningLimiter::new(Duration::ZERO);
+        assert_eq!(limiter.check(), Some(0), "testing zero interval");
+        assert_eq!(limiter.check(), Some(0), "testing zero interval");
+    }
+
+    #[tokio::test]
+    async fn compile_cdns() {
+        let http = HttpContext::default();
         let dir = tempdir().unwrap();
         let mut cdn_urls_string: Vec<String> = CDN_URLS.iter().map(|s| s.to_string()).collect();
         cdn_urls_string.push("test.cloudflare.net".to_string());
@@ -431,7 +676,8 @@ mod tests {
 
         writeln!(file_txt, "test.cloudflare.net").unwrap();
 
-        let mut res_txt = compile_cdn_list(Some(path_txt.to_str().unwrap().to_string()));
+        let mut res_txt =
+            compile_cdn_list(Some(path_txt.to_str().unwrap().to_string()), &http).await;
         res_txt.sort();
 
         assert_eq!(res_txt, cdn_urls_string, "testing txt file");
@@ -441,7 +687,8 @@ mod tests {
 
         writeln!(file_json, "{{\n\"cdns\": [\"test.cloudflare.net\"]\n}}").unwrap();
 
-        let mut res_json = compile_cdn_list(Some(path_json.to_str().unwrap().to_string()));
+        let mut res_json =
+            compile_cdn_list(Some(path_json.to_str().unwrap().to_string()), &http).await;
         res_json.sort();
 
         assert_eq!(res_json, cdn_urls_string, "testing json file");
@@ -451,7 +698,8 @@ mod tests {
 
         writeln!(file_toml, "cdns = [\"test.cloudflare.net\"]").unwrap();
 
-        let mut res_toml = compile_cdn_list(Some(path_toml.to_str().unwrap().to_string()));
+        let mut res_toml =
+            compile_cdn_list(Some(path_toml.to_str().unwrap().to_string()), &http).await;
         res_toml.sort();
 
         assert_eq!(res_toml, cdn_urls_string, "testing toml file");
@@ -466,19 +714,22 @@ mod tests {
 
         writeln!(file_yaml2, "cdns: [\"test.cloudflare.net\"]").unwrap();
 
-        let mut res_yaml1 = compile_cdn_list(Some(path_yaml1.to_str().unwrap().to_string()));
+        let mut res_yaml1 =
+            compile_cdn_list(Some(path_yaml1.to_str().unwrap().to_string()), &http).await;
         res_yaml1.sort();
 
         assert_eq!(res_yaml1, cdn_urls_string, "testing yaml file");
 
-        let mut res_yaml2 = compile_cdn_list(Some(path_yaml2.to_str().unwrap().to_string()));
+        let mut res_yaml2 =
+            compile_cdn_list(Some(path_yaml2.to_str().unwrap().to_string()), &http).await;
         res_yaml2.sort();
 
         assert_eq!(res_yaml2, cdn_urls_string, "testing yml file");
 
         let path_png = dir.path().join("cdn_test.png");
 
-        let mut res_png = compile_cdn_list(Some(path_png.to_str().unwrap().to_string()));
+        let mut res_png =
+            compile_cdn_list(Some(path_png.to_str().unwrap().to_string()), &http).await;
         res_png.sort();
 
         assert_ne!(
@@ -496,36 +747,207 @@ mod tests {
         );
     }
 
-    #[test]
-    fn parse_timestamps() {
+    #[tokio::test]
+    async fn cdn_priorities() {
+        let http = HttpContext::default();
+        let dir = tempdir().unwrap();
+        let path_toml = dir.path().join("cdns.toml");
+        fs::write(
+            &path_toml,
+            r#"cdns = [
+    "test.cloudflare.net",
+    { host = "fast.cloudflare.net", priority = 10 },
+    { host = "busy.cloudflare.net", priority = 10, weight = 5 },
+    { host = "vod-secure.twitch.tv", enabled = false },
+]"#,
+        )
+        .unwrap();
+        let res_toml = compile_cdn_list(Some(path_toml.to_str().unwrap().to_string()), &http).await;
         assert_eq!(
-            parse_timestamp("1657871396").unwrap(),
-            1657871396,
-            "testing unix timestamp parsing"
+            res_toml[..2],
+            ["busy.cloudflare.net", "fast.cloudflare.net"],
+            "testing the priority and the weight"
         );
-        assert_eq!(
-            parse_timestamp("2022-07-15T07:49:56+00:00").unwrap(),
-            1657871396,
-            "testing rfc parsing"
+        assert!(
+            res_toml.contains(&"test.cloudflare.net".to_string()),
+            "testing a plain host next to the tables"
+        );
+        assert!(
+            !res_toml.contains(&"vod-secure.twitch.tv".to_string()),
+            "testing a disabled built-in CDN"
         );
         assert_eq!(
-            parse_timestamp("2022-07-15 07:49:56 UTC").unwrap(),
-            1657871396,
-            "testing parsing time with the UTC tag"
+            res_toml.len(),
+            CDN_URLS.len() + 2,
+            "testing the length without the disabled CDN"
+        );
+
+        let path_txt = dir.path().join("cdns.txt");
+        fs::write(
+            &path_txt,
+            "# the fast ones\nfast.cloudflare.net priority=5 weight=2\ndead.cloudflare.net disabled\n",
+        )
+        .unwrap();
+        let res_txt = compile_cdn_list(Some(path_txt.to_str().unwrap().to_string()), &http).await;
+        assert_eq!(res_txt[0], "fast.cloudflare.net", "testing txt settings");
+        assert!(
+            !res_txt.contains(&"dead.cloudflare.net".to_string()),
+            "testing a disabled CDN in a txt file"
+        );
+        assert!(
+            read_cdn_file(path_txt.to_str().unwrap())
+                .unwrap()
+                .iter()
+                .any(|cdn| cdn.host == "dead.cloudflare.net" && !cdn.enabled),
+            "testing that the disabled CDN stays in the file"
+        );
+
+        fs::write(&path_txt, "fast.cloudflare.net speed=9\n").unwrap();
+        assert!(
+            read_cdn_file(path_txt.to_str().unwrap()).is_err(),
+            "testing an unknown setting"
+        );
+    }
+
+    #[tokio::test]
+    async fn remote_cdns() {
+        assert!(
+            remote_cdn_file("https://gist.example.com/cdns.txt").is_some(),
+            "testing a remote cdnfile"
         );
+        assert!(
+            remote_cdn_file("cdns.txt").is_none() && remote_cdn_file("C:\\cdns.txt").is_none(),
+            "testing a local cdnfile"
+        );
+
+        let dir = tempdir().unwrap();
+        let cache = dir.path().join("cdns.txt");
+        let url = Url::parse("https://gist.example.com/cdns.txt").unwrap();
+        let online = Arc::new(Fixtures::new().respond(
+            url.as_str(),
+            200,
+            "fast.cloudflare.net priority=5\n",
+        ));
+        let http = HttpContext::default().with_fixtures(online.clone());
+        let cdns = fetch_cdn_file(&url, Some(cache.clone()), &http)
+            .await
+            .unwrap();
         assert_eq!(
-            parse_timestamp("2022-07-15 07:49:56").unwrap(),
-            1657871396,
-            "testing parsing time w/o the UTC tag"
+            cdns[0].host, "fast.cloudflare.net",
+            "testing the fetched list"
         );
         assert_eq!(
-            parse_timestamp("15-07-2022 07:49").unwrap(),
-            1657871340,
-            "testing parsing time w/o seconds"
+            fs::read_to_string(&cache).unwrap(),
+            "fast.cloudflare.net priority=5\n",
+            "testing the cached copy"
         );
+
+        fetch_cdn_file(&url, Some(cache.clone()), &http)
+            .await
+            .unwrap();
+        assert_eq!(online.requested().len(), 1, "testing a fresh cached copy");
+
+        let offline = Arc::new(Fixtures::new());
+        let http = HttpContext::default().with_fixtures(offline.clone());
+        File::options()
+            .write(true)
+            .open(&cache)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(2 * 24 * 60 * 60))
+            .unwrap();
+        let stale = fetch_cdn_file(&url, Some(cache.clone()), &http)
+            .await
+            .unwrap();
+        assert_eq!(offline.requested().len(), 1, "testing a stale cached copy");
+        assert_eq!(stale, cdns, "testing the fallback to the cached copy");
+
+        fs::remove_file(&cache).unwrap();
         assert!(
-            parse_timestamp("2022-07-15 0749").is_err(),
-            "testing parsing wrong timestamps"
+            fetch_cdn_file(&url, Some(cache), &http).await.is_err(),
+            "testing a failed fetch without a cached copy"
+        );
+    }
+
+    #[tokio::test]
+    async fn derive_date_offline() {
+        let http = HttpContext::default().with_fixtures(Arc::new(
+            Fixtures::new()
+                .respond(
+                    "https://twitchtracker.com/forsen/streams/39619965384",
+                    200,
+                    r#"<html><body><div class="stream-timestamp-dt to-dowdatetime">2022-07-12 17:05:08</div></body></html>"#,
+                )
+                .respond(
+                    "https://streamscharts.com/channels/robcdee/streams/39648192487",
+                    200,
+                    r#"<html><body><div><div data-requests='[{"started_at": "1662523601", "ended_at": "1662530000"}, {"started_at": "1662539000", "ended_at": "1662540600"}]'></div></div></body></html>"#,
+                )
+                .respond(
+                    "https://streamscharts.com/channels/robcdee/streams/39648192488",
+                    200,
+                    r#"<html><body><time datetime="1662523601"></time></body></html>"#,
+                ),
+        ));
+
+        assert_eq!(
+            derive_date_from_url(
+                "https://twitchtracker.com/forsen/streams/39619965384",
+                Cli::default(),
+                &http
+            )
+            .await
+            .unwrap(),
+            (
+                ProcessingType::Exact,
+                URLData {
+                    username: "forsen".to_string(),
+                    broadcast_id: "39619965384".to_string(),
+                    start_date: "2022-07-12 17:05:08".to_string(),
+                    end_date: None,
+                    stream_type: StreamType::Live
+                }
+            ),
+            "testing the twitchtracker selector"
+        );
+        assert_eq!(
+            derive_date_from_url(
+                "https://streamscharts.com/channels/robcdee/streams/39648192487",
+                Cli::default(),
+                &http
+            )
+            .await
+            .unwrap(),
+            (
+                ProcessingType::Exact,
+                URLData {
+                    username: "robcdee".to_string(),
+                    broadcast_id: "39648192487".to_string(),
+                    start_date: "1662523601".to_string(),
+                    end_date: Some("1662540600".to_string()),
+                    stream_type: StreamType::Live
+                }
+            ),
+            "testing the streamscharts clip data"
+        );
+        assert_eq!(
+            derive_date_from_url(
+                "https://streamscharts.com/channels/robcdee/streams/39648192488",
+                Cli::default(),
+                &http
+            )
+            .await
+            .unwrap(),
+            (
+                ProcessingType::Bruteforce,
+                URLData {
+                    username: "robcdee".to_string(),
+                    broadcast_id: "39648192488".to_string(),
+                    start_date: "1662523541".to_string(),
+                    end_date: Some("1662523661".to_string()),
+                    stream_type: StreamType::Live
+                }
+            ),
+            "testing the streamscharts bruteforce fallback"
         );
     }
 
@@ -541,6 +963,7 @@ mod tests {
         match derive_date_from_url(
             "https://twitchtracker.com/forsen/streams/39619965384",
             Cli::default(),
+            &HttpContext::default(),
         )
         .await
         {
@@ -553,7 +976,8 @@ mod tests {
                             username: "forsen".to_string(),
                             broadcast_id: "39619965384".to_string(),
                             start_date: "2022-07-12 17:05:08".to_string(),
-                            end_date: None
+                            end_date: None,
+                            stream_type: StreamType::Live
                         }
                     ),
                     "testing twitchtracker - https://twitchtracker.com/forsen/streams/39619965384"
@@ -569,6 +993,7 @@ mod tests {
         match derive_date_from_url(
             "https://streamscharts.com/channels/robcdee/streams/39648192487",
             Cli::default(),
+            &HttpContext::default(),
         )
         .await
         {
@@ -581,7 +1006,8 @@ mod tests {
                             username: "robcdee".to_string(),
                             broadcast_id: "39648192487".to_string(),
                             start_date: "1662523601".to_string(),
-                            end_date: Some("1662540600".to_string())
+                            end_date: Some("1662540600".to_string()),
+                            stream_type: StreamType::Live
                         }
                     ),
                     "testing streamscharts (exact with bruteforce fallback) - https://streamscharts.com/channels/robcdee/streams/39648192487"
@@ -595,31 +1021,35 @@ mod tests {
 
         // Test error cases (these don't require network)
         assert!(
-            derive_date_from_url("https://google.com", Cli::default())
-                .await
-                .is_err(),
+            derive_date_from_url(
+                "https://google.com",
+                Cli::default(),
+                &HttpContext::default()
+            )
+            .await
+            .is_err(),
             "testing wrong link - https://google.com"
         );
         assert!(
-            derive_date_from_url("https://twitchtracker.com/forsen/streams/3961965384", Cli::default())
+            derive_date_from_url("https://twitchtracker.com/forsen/streams/3961965384", Cli::default(), &HttpContext::default())
                 .await
                 .is_err(),
             "testing wrong twitchtracker link 1 - https://twitchtracker.com/forsen/streams/3961965384"
         );
         assert!(
-            derive_date_from_url("https://streamscharts.com/channels/forsen/streams/3961965384", Cli::default())
+            derive_date_from_url("https://streamscharts.com/channels/forsen/streams/3961965384", Cli::default(), &HttpContext::default())
                 .await
                 .is_err(),
             "testing wrong streamscharts link 1 - https://streamscharts.com/channels/forsen/streams/3961965384"
         );
         assert!(
-            derive_date_from_url("https://twitchtracker.com/forsen/sreams/39619965384", Cli::default())
+            derive_date_from_url("https://twitchtracker.com/forsen/sreams/39619965384", Cli::default(), &HttpContext::default())
                 .await
                 .is_err(),
             "testing wrong twitchtracker link 2 - https://twitchtracker.com/forsen/sreams/39619965384"
         );
         assert!(
-            derive_date_from_url("https://streamscharts.com/channels/forsen/sreams/39619965384", Cli::default())
+            derive_date_from_url("https://streamscharts.com/channels/forsen/sreams/39619965384", Cli::default(), &HttpContext::default())
                 .await
                 .is_err(),
             "testing wrong streamscharts link 2 - https://streamscharts.com/channels/forsen/sreams/39619965384"
@@ -630,16 +1060,18 @@ mod tests {
     #[ignore]
     async fn streamscharts_useragent_check() {
         let url = "https://streamscharts.com/channels/robcdee/streams/39648192487";
-        let ua_vec = get_useragent_list().await;
+        let http = HttpContext::default();
+        let ua_vec = get_useragent_list(&http).await;
 
         for ua in ua_vec {
-            let init_resp = crate::HTTP_CLIENT
+            let init_resp = http
+                .client
                 .get(url)
                 .header(USER_AGENT, &ua)
                 .send()
                 .await
                 .unwrap();
-            sleep(std::time::Duration::from_secs(2));
+            tokio::time::sleep(Duration::from_secs(2)).await;
             assert_eq!(
                 init_resp.status(),
                 200,